@@ -0,0 +1,205 @@
+use std::net::{Ipv4Addr, Ipv6Addr};
+
+use crate::ubus_jsonrpc_public_ip_service::{Ipv4AddressInfo, Ipv6AddressInfo};
+
+#[derive(Clone, Debug, Default)]
+pub struct AddressPolicy {
+    pub mask: Option<u8>,
+    pub allow_non_public: bool,
+}
+
+impl AddressPolicy {
+    pub fn select_ipv4(&self, addresses: &[Ipv4AddressInfo]) -> Option<Ipv4Addr> {
+        let candidates: Vec<Ipv4Addr> = addresses
+            .iter()
+            .filter(|a| self.mask.is_none() || a.mask == self.mask)
+            .map(|a| a.address)
+            .collect();
+
+        // Prefer a globally routable address, but fall back to whatever the interface has so
+        // that the caller can report what was refused instead of "no address".
+        candidates
+            .iter()
+            .find(|ip| is_public_ipv4(**ip))
+            .or_else(|| candidates.first())
+            .copied()
+    }
+
+    pub fn select_ipv6(&self, addresses: &[Ipv6AddressInfo]) -> Option<Ipv6Addr> {
+        // A preferred lifetime of zero marks a deprecated address that is only kept around for
+        // existing connections.
+        let candidates: Vec<Ipv6Addr> = addresses
+            .iter()
+            .filter(|a| self.mask.is_none() || a.mask == self.mask)
+            .filter(|a| a.preferred != Some(0))
+            .map(|a| a.address)
+            .collect();
+
+        candidates
+            .iter()
+            .find(|ip| is_public_ipv6(**ip))
+            .or_else(|| candidates.first())
+            .copied()
+    }
+
+    pub fn permits_ipv4(&self, ip: Ipv4Addr) -> bool {
+        self.allow_non_public || is_public_ipv4(ip)
+    }
+
+    pub fn permits_ipv6(&self, ip: Ipv6Addr) -> bool {
+        self.allow_non_public || is_public_ipv6(ip)
+    }
+}
+
+pub fn is_public_ipv4(ip: Ipv4Addr) -> bool {
+    let [a, b, c, _] = ip.octets();
+
+    let this_network = a == 0;
+    let shared = a == 100 && (b & 0b1100_0000) == 64;
+    let ietf_protocol = a == 192 && b == 0 && c == 0;
+    let benchmarking = a == 198 && (b & 0xfe) == 18;
+    let reserved = a >= 240;
+
+    !(ip.is_private()
+        || ip.is_loopback()
+        || ip.is_link_local()
+        || ip.is_documentation()
+        || ip.is_multicast()
+        || ip.is_broadcast()
+        || ip.is_unspecified()
+        || this_network
+        || shared
+        || ietf_protocol
+        || benchmarking
+        || reserved)
+}
+
+pub fn is_public_ipv6(ip: Ipv6Addr) -> bool {
+    let segments = ip.segments();
+
+    let unique_local = (segments[0] & 0xfe00) == 0xfc00;
+    let link_local = (segments[0] & 0xffc0) == 0xfe80;
+    let documentation = segments[0] == 0x2001 && segments[1] == 0x0db8;
+    let ipv4_mapped = ip.to_ipv4_mapped().is_some();
+    let discard = segments[0] == 0x0100 && segments[1..4] == [0, 0, 0];
+
+    !(ip.is_loopback()
+        || ip.is_unspecified()
+        || ip.is_multicast()
+        || unique_local
+        || link_local
+        || documentation
+        || ipv4_mapped
+        || discard)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ipv4(address: &str, mask: u8) -> Ipv4AddressInfo {
+        Ipv4AddressInfo {
+            address: address.parse().unwrap(),
+            mask: Some(mask),
+        }
+    }
+
+    fn ipv6(address: &str, preferred: u32) -> Ipv6AddressInfo {
+        Ipv6AddressInfo {
+            address: address.parse().unwrap(),
+            mask: Some(64),
+            preferred: Some(preferred),
+            valid: Some(preferred),
+        }
+    }
+
+    #[test]
+    fn refuses_bogons() {
+        for ip in [
+            "10.1.2.3",
+            "172.16.0.1",
+            "192.168.1.100",
+            "100.64.0.1",
+            "100.127.255.254",
+            "169.254.1.1",
+            "192.0.2.1",
+            "198.51.100.1",
+            "203.0.113.1",
+            "127.0.0.1",
+            "0.0.0.0",
+            "240.0.0.1",
+        ] {
+            assert!(!is_public_ipv4(ip.parse().unwrap()), "{} is not public", ip);
+        }
+        assert!(is_public_ipv4("100.128.0.1".parse().unwrap()));
+        assert!(is_public_ipv4("93.184.216.34".parse().unwrap()));
+    }
+
+    #[test]
+    fn refuses_ipv6_bogons() {
+        for ip in ["fd00::1", "fe80::1", "2001:db8::1", "::1", "::ffff:1.2.3.4"] {
+            assert!(!is_public_ipv6(ip.parse().unwrap()), "{} is not public", ip);
+        }
+        assert!(is_public_ipv6("2a01:4f8::1".parse().unwrap()));
+    }
+
+    #[test]
+    fn select_ipv4_prefers_global_address() {
+        let policy = AddressPolicy::default();
+        let addresses = vec![ipv4("100.64.1.2", 10), ipv4("93.184.216.34", 32)];
+
+        assert_eq!(
+            policy.select_ipv4(&addresses),
+            Some("93.184.216.34".parse().unwrap())
+        );
+    }
+
+    #[test]
+    fn select_ipv4_matches_mask() {
+        let policy = AddressPolicy {
+            mask: Some(24),
+            ..Default::default()
+        };
+        let addresses = vec![ipv4("93.184.216.34", 32), ipv4("93.184.217.1", 24)];
+
+        assert_eq!(
+            policy.select_ipv4(&addresses),
+            Some("93.184.217.1".parse().unwrap())
+        );
+    }
+
+    #[test]
+    fn select_ipv4_falls_back_to_non_public_address() {
+        let policy = AddressPolicy::default();
+        let addresses = vec![ipv4("100.64.1.2", 10)];
+
+        let ip = policy.select_ipv4(&addresses).unwrap();
+        assert_eq!(ip, "100.64.1.2".parse::<Ipv4Addr>().unwrap());
+        assert!(!policy.permits_ipv4(ip));
+    }
+
+    #[test]
+    fn select_ipv4_returns_none_without_addresses() {
+        assert_eq!(AddressPolicy::default().select_ipv4(&[]), None);
+    }
+
+    #[test]
+    fn select_ipv6_skips_deprecated_addresses() {
+        let policy = AddressPolicy::default();
+        let addresses = vec![ipv6("2a01:4f8::1", 0), ipv6("2a01:4f8::2", 3600)];
+
+        assert_eq!(
+            policy.select_ipv6(&addresses),
+            Some("2a01:4f8::2".parse().unwrap())
+        );
+    }
+
+    #[test]
+    fn permits_non_public_when_allowed() {
+        let policy = AddressPolicy {
+            allow_non_public: true,
+            ..Default::default()
+        };
+        assert!(policy.permits_ipv4("100.64.1.2".parse().unwrap()));
+    }
+}
@@ -0,0 +1,226 @@
+use async_trait::async_trait;
+use log::{debug, error};
+use reqwest::{Client, Method, RequestBuilder, Response, StatusCode};
+use serde::{Deserialize, Serialize};
+use std::net::{IpAddr, Ipv4Addr};
+use thiserror::Error;
+
+use crate::authoritative_dns::fqdn;
+use crate::dns_service::{DnsService, DnsServiceError};
+use crate::local_file::{is_ipv6_record, parse_address};
+
+#[derive(Clone, Deserialize, Serialize, Debug, Default, PartialEq, Eq)]
+pub struct AdGuardRewrite {
+    pub domain: String,
+    pub answer: String,
+}
+
+impl AdGuardRewrite {
+    fn address(&self, name: &str, ipv6: bool) -> Option<IpAddr> {
+        if !self.domain.eq_ignore_ascii_case(name) {
+            return None;
+        }
+        self.answer
+            .parse::<IpAddr>()
+            .ok()
+            .filter(|ip| ip.is_ipv6() == ipv6)
+    }
+}
+
+/// Client for AdGuard Home DNS rewrites, authenticated with the admin user's credentials.
+pub struct AdGuardHomeClient {
+    api_url: String,
+    username: String,
+    password: String,
+    client: Client,
+}
+
+impl AdGuardHomeClient {
+    pub fn new(api_url: &str, username: &str, password: &str) -> Self {
+        let client = Client::new();
+        Self {
+            api_url: String::from(api_url.trim_end_matches('/')),
+            username: String::from(username),
+            password: String::from(password),
+            client,
+        }
+    }
+
+    pub async fn list_rewrites(&self) -> Result<Vec<AdGuardRewrite>, AdGuardError> {
+        let request = self.request(Method::GET, "/control/rewrite/list");
+        Ok(self.send(request).await?.json().await?)
+    }
+
+    pub async fn add_rewrite(&self, rewrite: &AdGuardRewrite) -> Result<(), AdGuardError> {
+        let request = self
+            .request(Method::POST, "/control/rewrite/add")
+            .json(rewrite);
+        self.send(request).await?;
+        Ok(())
+    }
+
+    pub async fn delete_rewrite(&self, rewrite: &AdGuardRewrite) -> Result<(), AdGuardError> {
+        let request = self
+            .request(Method::POST, "/control/rewrite/delete")
+            .json(rewrite);
+        self.send(request).await?;
+        Ok(())
+    }
+
+    pub async fn get_address(
+        &self,
+        name: &str,
+        ipv6: bool,
+    ) -> Result<Option<IpAddr>, AdGuardError> {
+        let rewrites = self.list_rewrites().await?;
+        Ok(rewrites
+            .iter()
+            .find_map(|rewrite| rewrite.address(name, ipv6)))
+    }
+
+    /// Points `name` at `ip`, replacing its rewrites of the same address family. The new
+    /// rewrite is added before the old ones are deleted, so the name keeps resolving in between.
+    pub async fn set_address(&self, name: &str, ip: IpAddr) -> Result<(), AdGuardError> {
+        let rewrites = self.list_rewrites().await?;
+        let current: Vec<&AdGuardRewrite> = rewrites
+            .iter()
+            .filter(|rewrite| rewrite.address(name, ip.is_ipv6()).is_some())
+            .collect();
+
+        if !current
+            .iter()
+            .any(|rewrite| rewrite.address(name, ip.is_ipv6()) == Some(ip))
+        {
+            self.add_rewrite(&AdGuardRewrite {
+                domain: String::from(name),
+                answer: ip.to_string(),
+            })
+            .await?;
+        }
+        for rewrite in current
+            .into_iter()
+            .filter(|rewrite| rewrite.address(name, ip.is_ipv6()) != Some(ip))
+        {
+            self.delete_rewrite(rewrite).await?;
+        }
+        Ok(())
+    }
+
+    fn request(&self, method: Method, path: &str) -> RequestBuilder {
+        self.client
+            .request(method, format!("{}{}", self.api_url, path))
+            .basic_auth(&self.username, Some(&self.password))
+    }
+
+    async fn send(&self, request: RequestBuilder) -> Result<Response, AdGuardError> {
+        let response = request.send().await?;
+        let status = response.status();
+
+        if status.is_success() {
+            return Ok(response);
+        }
+
+        let message = response.text().await.unwrap_or_default().trim().to_string();
+        error!("AdGuard Home API returned {}: {}", status, message);
+        Err(match status {
+            StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => AdGuardError::InvalidCredentials,
+            StatusCode::BAD_REQUEST => AdGuardError::Rejected { message },
+            _ => AdGuardError::Api {
+                status: status.as_u16(),
+                message,
+            },
+        })
+    }
+}
+
+#[async_trait]
+impl DnsService for AdGuardHomeClient {
+    async fn resolve_ip(
+        &self,
+        subdomain: &str,
+        domain: &str,
+    ) -> Result<Option<Ipv4Addr>, DnsServiceError> {
+        debug!(
+            "Resolve ip for domain {} and subdomain {}.",
+            domain, subdomain
+        );
+        match self.get_address(&fqdn(subdomain, domain), false).await? {
+            Some(IpAddr::V4(ip)) => Ok(Some(ip)),
+            _ => Ok(None),
+        }
+    }
+
+    async fn update_dns(
+        &self,
+        subdomain: &str,
+        domain: &str,
+        ip: Ipv4Addr,
+    ) -> Result<(), DnsServiceError> {
+        debug!(
+            "Update dns for domain {} and subdomain {} with IP {}.",
+            domain, subdomain, ip
+        );
+        self.set_address(&fqdn(subdomain, domain), IpAddr::V4(ip))
+            .await?;
+        Ok(())
+    }
+
+    fn is_local(&self) -> bool {
+        true
+    }
+
+    fn supports_record_type(&self, record_type: &str) -> bool {
+        is_ipv6_record(record_type).is_ok()
+    }
+
+    async fn resolve_record(
+        &self,
+        subdomain: &str,
+        domain: &str,
+        record_type: &str,
+        _prefix: &str,
+    ) -> Result<Option<String>, DnsServiceError> {
+        let ipv6 = is_ipv6_record(record_type)?;
+        let ip = self.get_address(&fqdn(subdomain, domain), ipv6).await?;
+        Ok(ip.map(|ip| ip.to_string()))
+    }
+
+    async fn update_record(
+        &self,
+        subdomain: &str,
+        domain: &str,
+        record_type: &str,
+        _prefix: &str,
+        value: &str,
+    ) -> Result<(), DnsServiceError> {
+        let ip = parse_address(record_type, value)?;
+        self.set_address(&fqdn(subdomain, domain), ip).await?;
+        Ok(())
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum AdGuardError {
+    #[error("The username or password is not valid.")]
+    InvalidCredentials,
+    #[error("AdGuard Home rejected the change: {message}")]
+    Rejected { message: String },
+    #[error("API request failed with status {status}: {message}")]
+    Api { status: u16, message: String },
+    #[error("request failed")]
+    RequestFailed {
+        #[from]
+        source: reqwest::Error,
+    },
+}
+
+impl From<AdGuardError> for DnsServiceError {
+    fn from(e: AdGuardError) -> Self {
+        match e {
+            AdGuardError::Rejected { .. } => DnsServiceError::UpdateRejected {
+                record: e.to_string(),
+            },
+            _ => DnsServiceError::ClientError,
+        }
+    }
+}
@@ -0,0 +1,210 @@
+use log::{debug, info, warn};
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::time::{Duration, Instant};
+use thiserror::Error;
+use uuid::Uuid;
+
+use crate::dns_message::{
+    exchange_udp, DnsMessageError, Message, RCODE_NOERROR, RCODE_NXDOMAIN, TYPE_A, TYPE_NS,
+};
+
+pub const HETZNER_NAME_SERVERS: [&str; 3] = [
+    "hydrogen.ns.hetzner.com:53",
+    "oxygen.ns.hetzner.com:53",
+    "helium.ns.hetzner.de:53",
+];
+
+const QUERY_TIMEOUT: Duration = Duration::from_secs(3);
+const POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+#[derive(Clone, Debug)]
+pub struct AuthoritativeResolver {
+    name_servers: Vec<String>,
+    poll_interval: Duration,
+}
+
+impl AuthoritativeResolver {
+    pub fn new(name_servers: &[&str]) -> Self {
+        Self {
+            name_servers: name_servers.iter().map(|s| with_port(s)).collect(),
+            poll_interval: POLL_INTERVAL,
+        }
+    }
+
+    pub fn hetzner() -> Self {
+        Self::new(&HETZNER_NAME_SERVERS)
+    }
+
+    pub fn with_poll_interval(mut self, poll_interval: Duration) -> Self {
+        self.poll_interval = poll_interval;
+        self
+    }
+
+    pub async fn query_a(
+        &self,
+        server: &str,
+        fqdn: &str,
+    ) -> Result<Vec<Ipv4Addr>, AuthoritativeDnsError> {
+        let response = self.query(server, fqdn, TYPE_A).await?;
+        Ok(response
+            .answers
+            .iter()
+            .filter_map(|r| r.as_ipv4())
+            .collect())
+    }
+
+    pub async fn resolve_a(&self, fqdn: &str) -> Result<Vec<Ipv4Addr>, AuthoritativeDnsError> {
+        let mut last_error = AuthoritativeDnsError::NoNameServers;
+        for server in &self.name_servers {
+            match self.query_a(server, fqdn).await {
+                Ok(ips) => return Ok(ips),
+                Err(e) => {
+                    debug!("Name server {} did not answer for {}: {}", server, fqdn, e);
+                    last_error = e;
+                }
+            }
+        }
+        Err(last_error)
+    }
+
+    pub async fn name_servers_for_zone(&self, zone: &str) -> Vec<String> {
+        for server in &self.name_servers {
+            if let Ok(response) = self.query(server, zone, TYPE_NS).await {
+                let servers: Vec<String> = response
+                    .answers
+                    .iter()
+                    .filter_map(|r| r.as_name())
+                    .map(|name| with_port(&name))
+                    .collect();
+                if !servers.is_empty() {
+                    return servers;
+                }
+            }
+        }
+        self.name_servers.clone()
+    }
+
+    pub async fn wait_for(
+        &self,
+        zone: &str,
+        fqdn: &str,
+        expected: Ipv4Addr,
+        deadline: Duration,
+    ) -> Result<Duration, AuthoritativeDnsError> {
+        let started = Instant::now();
+        let servers = self.name_servers_for_zone(zone).await;
+        let mut pending = servers.clone();
+
+        loop {
+            let mut still_pending = Vec::new();
+            for server in pending {
+                match self.query_a(&server, fqdn).await {
+                    Ok(ips) if ips.contains(&expected) => {
+                        debug!("{} serves {} for {}.", server, expected, fqdn);
+                    }
+                    Ok(ips) => {
+                        debug!("{} still serves {:?} for {}.", server, ips, fqdn);
+                        still_pending.push(server);
+                    }
+                    Err(e) => {
+                        warn!("Failed to query {} for {}: {}", server, fqdn, e);
+                        still_pending.push(server);
+                    }
+                }
+            }
+
+            let elapsed = started.elapsed();
+            if still_pending.is_empty() {
+                info!(
+                    "{} is served as {} by all {} authoritative name servers after {}ms.",
+                    fqdn,
+                    expected,
+                    servers.len(),
+                    elapsed.as_millis()
+                );
+                return Ok(elapsed);
+            }
+            if elapsed + self.poll_interval > deadline {
+                return Err(AuthoritativeDnsError::NotPropagated {
+                    fqdn: fqdn.to_string(),
+                    pending: still_pending,
+                });
+            }
+
+            pending = still_pending;
+            tokio::time::sleep(self.poll_interval).await;
+        }
+    }
+
+    async fn query(
+        &self,
+        server: &str,
+        name: &str,
+        qtype: u16,
+    ) -> Result<Message, AuthoritativeDnsError> {
+        let id = u16::from_be_bytes(Uuid::new_v4().as_bytes()[..2].try_into().unwrap());
+        let response =
+            exchange_udp(server, &Message::query(id, name, qtype), QUERY_TIMEOUT).await?;
+
+        match response.rcode() {
+            RCODE_NOERROR | RCODE_NXDOMAIN => Ok(response),
+            rcode => Err(AuthoritativeDnsError::ServerFailure { rcode }),
+        }
+    }
+}
+
+fn with_port(server: &str) -> String {
+    let server = server.trim_end_matches('.');
+    if server.parse::<SocketAddr>().is_ok() {
+        return server.to_string();
+    }
+    if let Ok(ip) = server.parse::<IpAddr>() {
+        return SocketAddr::new(ip, 53).to_string();
+    }
+    if server.contains(':') {
+        server.to_string()
+    } else {
+        format!("{}:53", server)
+    }
+}
+
+pub fn fqdn(subdomain: &str, domain: &str) -> String {
+    match subdomain {
+        "@" | "" => domain.to_string(),
+        _ => format!("{}.{}", subdomain, domain),
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum AuthoritativeDnsError {
+    #[error("no name servers configured")]
+    NoNameServers,
+    #[error("name server answered with rcode {rcode}")]
+    ServerFailure { rcode: u8 },
+    #[error("{fqdn} was not served by {pending:?} before the deadline")]
+    NotPropagated { fqdn: String, pending: Vec<String> },
+    #[error("query failed")]
+    QueryFailed {
+        #[from]
+        source: DnsMessageError,
+    },
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn with_port_appends_default_port() {
+        assert_eq!(with_port("ns1.example.com."), "ns1.example.com:53");
+        assert_eq!(with_port("127.0.0.1:5353"), "127.0.0.1:5353");
+        assert_eq!(with_port("[::1]:53"), "[::1]:53");
+        assert_eq!(with_port("::1"), "[::1]:53");
+    }
+
+    #[test]
+    fn fqdn_handles_apex() {
+        assert_eq!(fqdn("@", "example.com"), "example.com");
+        assert_eq!(fqdn("www", "example.com"), "www.example.com");
+    }
+}
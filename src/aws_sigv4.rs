@@ -0,0 +1,302 @@
+use hmac::{Hmac, Mac};
+use reqwest::Url;
+use sha2::{Digest, Sha256};
+use std::path::PathBuf;
+
+use crate::time::utc_date;
+
+const ALGORITHM: &str = "AWS4-HMAC-SHA256";
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AwsCredentials {
+    pub access_key_id: String,
+    pub secret_access_key: String,
+    pub session_token: Option<String>,
+}
+
+impl AwsCredentials {
+    pub fn new(access_key_id: &str, secret_access_key: &str) -> Self {
+        Self {
+            access_key_id: String::from(access_key_id),
+            secret_access_key: String::from(secret_access_key),
+            session_token: None,
+        }
+    }
+
+    pub fn with_session_token(mut self, session_token: &str) -> Self {
+        self.session_token = Some(String::from(session_token));
+        self
+    }
+
+    /// Reads `AWS_ACCESS_KEY_ID`, `AWS_SECRET_ACCESS_KEY` and `AWS_SESSION_TOKEN`.
+    pub fn from_env() -> Option<Self> {
+        let access_key_id = std::env::var("AWS_ACCESS_KEY_ID").ok()?;
+        let secret_access_key = std::env::var("AWS_SECRET_ACCESS_KEY").ok()?;
+        let credentials = Self::new(&access_key_id, &secret_access_key);
+        Some(match std::env::var("AWS_SESSION_TOKEN") {
+            Ok(token) => credentials.with_session_token(&token),
+            Err(_) => credentials,
+        })
+    }
+
+    /// Reads a profile from the contents of a shared credentials file (`~/.aws/credentials`).
+    pub fn from_profile(contents: &str, profile: &str) -> Option<Self> {
+        let mut in_profile = false;
+        let mut access_key_id = None;
+        let mut secret_access_key = None;
+        let mut session_token = None;
+
+        for line in contents.lines().map(str::trim) {
+            if line.is_empty() || line.starts_with('#') || line.starts_with(';') {
+                continue;
+            }
+            if let Some(section) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+                let section = section.trim();
+                in_profile = section == profile || section == format!("profile {}", profile);
+                continue;
+            }
+            if !in_profile {
+                continue;
+            }
+            if let Some((key, value)) = line.split_once('=') {
+                let value = Some(value.trim().to_string());
+                match key.trim() {
+                    "aws_access_key_id" => access_key_id = value,
+                    "aws_secret_access_key" => secret_access_key = value,
+                    "aws_session_token" => session_token = value,
+                    _ => {}
+                }
+            }
+        }
+
+        Some(Self {
+            access_key_id: access_key_id?,
+            secret_access_key: secret_access_key?,
+            session_token,
+        })
+    }
+
+    /// Looks for credentials in the environment first and then in the shared credentials
+    /// file, using `AWS_SHARED_CREDENTIALS_FILE` and `AWS_PROFILE` like the AWS CLI does.
+    pub fn load() -> Option<Self> {
+        if let Some(credentials) = Self::from_env() {
+            return Some(credentials);
+        }
+
+        let path = match std::env::var("AWS_SHARED_CREDENTIALS_FILE") {
+            Ok(path) => PathBuf::from(path),
+            Err(_) => PathBuf::from(std::env::var("HOME").ok()?)
+                .join(".aws")
+                .join("credentials"),
+        };
+        let profile = std::env::var("AWS_PROFILE").unwrap_or_else(|_| String::from("default"));
+        let contents = std::fs::read_to_string(path).ok()?;
+        Self::from_profile(&contents, &profile)
+    }
+}
+
+/// Signs a request with AWS Signature Version 4 and returns the headers to add to it.
+/// `headers` are additional headers that are sent and should be signed; `host` and
+/// `x-amz-date` are always signed.
+#[allow(clippy::too_many_arguments)]
+pub fn sign(
+    credentials: &AwsCredentials,
+    region: &str,
+    service: &str,
+    method: &str,
+    url: &Url,
+    headers: &[(&str, &str)],
+    payload: &[u8],
+    time: u64,
+) -> Vec<(String, String)> {
+    let (date, datetime) = amz_date(time);
+
+    let host = match url.port() {
+        Some(port) => format!("{}:{}", url.host_str().unwrap_or_default(), port),
+        None => url.host_str().unwrap_or_default().to_string(),
+    };
+    let mut signed: Vec<(String, String)> = headers
+        .iter()
+        .map(|(name, value)| (name.to_lowercase(), value.trim().to_string()))
+        .collect();
+    signed.push((String::from("host"), host));
+    signed.push((String::from("x-amz-date"), datetime.clone()));
+    if let Some(token) = &credentials.session_token {
+        signed.push((String::from("x-amz-security-token"), token.clone()));
+    }
+    signed.sort();
+
+    let canonical_headers: String = signed
+        .iter()
+        .map(|(name, value)| format!("{}:{}\n", name, value))
+        .collect();
+    let signed_headers = signed
+        .iter()
+        .map(|(name, _)| name.as_str())
+        .collect::<Vec<_>>()
+        .join(";");
+
+    let mut query: Vec<(String, String)> = url
+        .query_pairs()
+        .map(|(key, value)| (uri_encode(&key), uri_encode(&value)))
+        .collect();
+    query.sort();
+    let canonical_query = query
+        .iter()
+        .map(|(key, value)| format!("{}={}", key, value))
+        .collect::<Vec<_>>()
+        .join("&");
+
+    let canonical_request = format!(
+        "{}\n{}\n{}\n{}\n{}\n{}",
+        method,
+        url.path(),
+        canonical_query,
+        canonical_headers,
+        signed_headers,
+        hex(&Sha256::digest(payload))
+    );
+    let scope = format!("{}/{}/{}/aws4_request", date, region, service);
+    let string_to_sign = format!(
+        "{}\n{}\n{}\n{}",
+        ALGORITHM,
+        datetime,
+        scope,
+        hex(&Sha256::digest(canonical_request.as_bytes()))
+    );
+
+    let key = hmac(
+        format!("AWS4{}", credentials.secret_access_key).as_bytes(),
+        date.as_bytes(),
+    );
+    let key = hmac(&key, region.as_bytes());
+    let key = hmac(&key, service.as_bytes());
+    let key = hmac(&key, b"aws4_request");
+    let signature = hex(&hmac(&key, string_to_sign.as_bytes()));
+
+    let mut out = vec![(String::from("x-amz-date"), datetime)];
+    if let Some(token) = &credentials.session_token {
+        out.push((String::from("x-amz-security-token"), token.clone()));
+    }
+    out.push((
+        String::from("authorization"),
+        format!(
+            "{} Credential={}/{}, SignedHeaders={}, Signature={}",
+            ALGORITHM, credentials.access_key_id, scope, signed_headers, signature
+        ),
+    ));
+    out
+}
+
+fn hmac(key: &[u8], data: &[u8]) -> Vec<u8> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC accepts keys of any length");
+    mac.update(data);
+    mac.finalize().into_bytes().to_vec()
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn uri_encode(s: &str) -> String {
+    s.bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                (b as char).to_string()
+            }
+            _ => format!("%{:02X}", b),
+        })
+        .collect()
+}
+
+/// Formats a unix timestamp as the `YYYYMMDD` date and `YYYYMMDDTHHMMSSZ` time SigV4 uses.
+fn amz_date(time: u64) -> (String, String) {
+    let (year, month, day) = utc_date(time);
+    let seconds = time % 86400;
+
+    let date = format!("{:04}{:02}{:02}", year, month, day);
+    let datetime = format!(
+        "{}T{:02}{:02}{:02}Z",
+        date,
+        seconds / 3600,
+        seconds % 3600 / 60,
+        seconds % 60
+    );
+    (date, datetime)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn format_amz_date() {
+        assert_eq!(
+            amz_date(1440938160),
+            (String::from("20150830"), String::from("20150830T123600Z"))
+        );
+        assert_eq!(
+            amz_date(951782400),
+            (String::from("20000229"), String::from("20000229T000000Z"))
+        );
+    }
+
+    #[test]
+    fn sign_documented_example() {
+        // The GET ListUsers example from the AWS Signature Version 4 documentation.
+        let credentials =
+            AwsCredentials::new("AKIDEXAMPLE", "wJalrXUtnFEMI/K7MDENG+bPxRfiCYEXAMPLEKEY");
+        let url =
+            Url::parse("https://iam.amazonaws.com/?Action=ListUsers&Version=2010-05-08").unwrap();
+        let headers = sign(
+            &credentials,
+            "us-east-1",
+            "iam",
+            "GET",
+            &url,
+            &[(
+                "Content-Type",
+                "application/x-www-form-urlencoded; charset=utf-8",
+            )],
+            b"",
+            1440938160,
+        );
+
+        assert_eq!(
+            headers,
+            vec![
+                (String::from("x-amz-date"), String::from("20150830T123600Z")),
+                (
+                    String::from("authorization"),
+                    String::from(
+                        "AWS4-HMAC-SHA256 Credential=AKIDEXAMPLE/20150830/us-east-1/iam/aws4_request, \
+                         SignedHeaders=content-type;host;x-amz-date, \
+                         Signature=5d672d79c15b13162d9279b0855cfba6789a8edb4c82c400e06b5924a6f2b5d7"
+                    )
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn read_credentials_from_profile() {
+        let contents = "[default]\n\
+                        aws_access_key_id = AKIDDEFAULT\n\
+                        aws_secret_access_key = default-secret\n\
+                        \n\
+                        [dyndnsd]\n\
+                        aws_access_key_id=AKIDDYNDNSD\n\
+                        aws_secret_access_key=dyndnsd-secret\n\
+                        aws_session_token=token\n";
+
+        assert_eq!(
+            AwsCredentials::from_profile(contents, "default"),
+            Some(AwsCredentials::new("AKIDDEFAULT", "default-secret"))
+        );
+        assert_eq!(
+            AwsCredentials::from_profile(contents, "dyndnsd"),
+            Some(AwsCredentials::new("AKIDDYNDNSD", "dyndnsd-secret").with_session_token("token"))
+        );
+        assert_eq!(AwsCredentials::from_profile(contents, "missing"), None);
+    }
+}
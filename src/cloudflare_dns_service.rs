@@ -0,0 +1,513 @@
+use async_trait::async_trait;
+use log::{debug, error};
+use reqwest::{Client, Method, RequestBuilder, StatusCode};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::collections::HashMap;
+use std::net::Ipv4Addr;
+use std::sync::Mutex;
+use thiserror::Error;
+
+use crate::authoritative_dns::fqdn;
+use crate::dns_service::{DnsService, DnsServiceError};
+use crate::record_template::{api_value, is_managed, managed_value};
+
+const API_URL: &str = "https://api.cloudflare.com/client/v4";
+const PER_PAGE: u32 = 50;
+/// Cloudflare uses a TTL of 1 for "automatic".
+const AUTOMATIC_TTL: u32 = 1;
+
+#[derive(Clone, Deserialize, Serialize, Debug, Default, PartialEq, Eq)]
+pub struct CloudflareZone {
+    pub id: String,
+    pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub status: Option<String>,
+}
+
+#[derive(Clone, Deserialize, Serialize, Debug, Default, PartialEq, Eq)]
+pub struct DnsRecord {
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub id: String,
+    pub name: String,
+    pub r#type: String,
+    pub content: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ttl: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub proxied: Option<bool>,
+}
+
+/// The fields of a record that are changed with PATCH, everything else is left as it is.
+#[derive(Clone, Deserialize, Serialize, Debug, Default, PartialEq, Eq)]
+pub struct PatchRecordRequest {
+    pub content: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ttl: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub proxied: Option<bool>,
+}
+
+#[derive(Clone, Deserialize, Serialize, Debug, Default, PartialEq, Eq)]
+pub struct CloudflareMessage {
+    pub code: u32,
+    pub message: String,
+}
+
+#[derive(Clone, Deserialize, Serialize, Debug, Default, PartialEq, Eq)]
+pub struct ResultInfo {
+    pub page: u32,
+    pub per_page: u32,
+    #[serde(default)]
+    pub total_pages: Option<u32>,
+    #[serde(default)]
+    pub count: Option<u32>,
+    #[serde(default)]
+    pub total_count: Option<u32>,
+}
+
+#[derive(Deserialize, Serialize, Debug)]
+pub struct CloudflareResponse<T> {
+    pub success: bool,
+    #[serde(default)]
+    pub errors: Vec<CloudflareMessage>,
+    #[serde(default)]
+    pub messages: Vec<CloudflareMessage>,
+    pub result: Option<T>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub result_info: Option<ResultInfo>,
+}
+
+pub struct CloudflareDnsClient {
+    api_url: String,
+    api_token: String,
+    proxied: Option<bool>,
+    zone_ids: Mutex<HashMap<String, String>>,
+    client: Client,
+}
+
+impl CloudflareDnsClient {
+    pub fn new(api_token: &str) -> Self {
+        Self::new_with_url(api_token, API_URL)
+    }
+
+    pub fn new_with_url(api_token: &str, api_url: &str) -> Self {
+        let client = Client::new();
+        Self {
+            api_url: String::from(api_url),
+            api_token: String::from(api_token),
+            proxied: None,
+            zone_ids: Mutex::new(HashMap::new()),
+            client,
+        }
+    }
+
+    /// Sets the `proxied` flag of updated and created records. Without it existing records
+    /// keep their flag and new records are created unproxied.
+    pub fn with_proxied(mut self, proxied: bool) -> Self {
+        self.proxied = Some(proxied);
+        self
+    }
+
+    pub async fn list_zones(&self) -> Result<Vec<CloudflareZone>, CloudflareDnsClientError> {
+        let mut zones = Vec::new();
+        let mut page = 1;
+        loop {
+            let request = self
+                .request(Method::GET, "/zones")
+                .query(&[("page", page), ("per_page", PER_PAGE)]);
+            let response: CloudflareResponse<Vec<CloudflareZone>> = self.send(request).await?;
+            zones.extend(response.result.unwrap_or_default());
+            match response.result_info.and_then(|info| info.total_pages) {
+                Some(total_pages) if page < total_pages => page += 1,
+                _ => break,
+            }
+        }
+        Ok(zones)
+    }
+
+    pub async fn find_zone(
+        &self,
+        zone: &str,
+    ) -> Result<Option<CloudflareZone>, CloudflareDnsClientError> {
+        let request = self.request(Method::GET, "/zones").query(&[("name", zone)]);
+        let response: CloudflareResponse<Vec<CloudflareZone>> = self.send(request).await?;
+        Ok(response
+            .result
+            .unwrap_or_default()
+            .into_iter()
+            .find(|z| z.name == zone))
+    }
+
+    /// Looks up the zone id, asking the API only the first time a zone is used.
+    pub async fn zone_id(&self, zone: &str) -> Result<String, CloudflareDnsClientError> {
+        if let Some(id) = self.zone_ids.lock().unwrap().get(zone) {
+            return Ok(id.clone());
+        }
+
+        let id = self
+            .find_zone(zone)
+            .await?
+            .ok_or_else(|| CloudflareDnsClientError::ZoneNotFound {
+                zone: zone.to_string(),
+            })?
+            .id;
+        self.zone_ids
+            .lock()
+            .unwrap()
+            .insert(zone.to_string(), id.clone());
+        Ok(id)
+    }
+
+    /// Drops the cached id of `zone` when the API answers 404, so that a zone that was
+    /// deleted and created again is looked up on the next call.
+    fn forget_zone_if_gone<T>(
+        &self,
+        zone: &str,
+        result: Result<T, CloudflareDnsClientError>,
+    ) -> Result<T, CloudflareDnsClientError> {
+        if let Err(CloudflareDnsClientError::NotFound) = result {
+            debug!("Zone {} is gone, looking up its id again next time.", zone);
+            self.zone_ids.lock().unwrap().remove(zone);
+        }
+        result
+    }
+
+    async fn zone_records(
+        &self,
+        domain: &str,
+        name: &str,
+        record_type: &str,
+    ) -> Result<(String, Vec<DnsRecord>), CloudflareDnsClientError> {
+        let zone_id = self.zone_id(domain).await?;
+        let records = self.find_records(&zone_id, name, record_type).await;
+        Ok((zone_id, self.forget_zone_if_gone(domain, records)?))
+    }
+
+    pub async fn find_record(
+        &self,
+        zone_id: &str,
+        name: &str,
+        record_type: &str,
+    ) -> Result<Option<DnsRecord>, CloudflareDnsClientError> {
+        Ok(self
+            .find_records(zone_id, name, record_type)
+            .await?
+            .into_iter()
+            .next())
+    }
+
+    pub async fn find_records(
+        &self,
+        zone_id: &str,
+        name: &str,
+        record_type: &str,
+    ) -> Result<Vec<DnsRecord>, CloudflareDnsClientError> {
+        let mut records = Vec::new();
+        let mut page = 1;
+        loop {
+            let request = self
+                .request(Method::GET, &format!("/zones/{}/dns_records", zone_id))
+                .query(&[("name", name), ("type", record_type)])
+                .query(&[("page", page), ("per_page", PER_PAGE)]);
+            let response: CloudflareResponse<Vec<DnsRecord>> = self.send(request).await?;
+            records.extend(
+                response
+                    .result
+                    .unwrap_or_default()
+                    .into_iter()
+                    .filter(|r| r.name.eq_ignore_ascii_case(name) && r.r#type == record_type),
+            );
+            match response.result_info.and_then(|info| info.total_pages) {
+                Some(total_pages) if page < total_pages => page += 1,
+                _ => break,
+            }
+        }
+        Ok(records)
+    }
+
+    pub async fn create_record(
+        &self,
+        zone_id: &str,
+        record: &DnsRecord,
+    ) -> Result<DnsRecord, CloudflareDnsClientError> {
+        let request = self
+            .request(Method::POST, &format!("/zones/{}/dns_records", zone_id))
+            .json(record);
+        self.send_result(request).await
+    }
+
+    pub async fn patch_record(
+        &self,
+        zone_id: &str,
+        record_id: &str,
+        patch: &PatchRecordRequest,
+    ) -> Result<DnsRecord, CloudflareDnsClientError> {
+        let request = self
+            .request(
+                Method::PATCH,
+                &format!("/zones/{}/dns_records/{}", zone_id, record_id),
+            )
+            .json(patch);
+        self.send_result(request).await
+    }
+
+    async fn set_record(
+        &self,
+        domain: &str,
+        subdomain: &str,
+        record_type: &str,
+        prefix: &str,
+        content: String,
+        ttl: Option<u32>,
+    ) -> Result<(), CloudflareDnsClientError> {
+        let name = fqdn(subdomain, domain);
+        let (zone_id, records) = self.zone_records(domain, &name, record_type).await?;
+
+        let managed = records
+            .into_iter()
+            .find(|record| is_managed(record_type, &record.content, prefix));
+        let result = match managed {
+            Some(record) => {
+                // Proxied records always use the automatic TTL.
+                let proxied = self.proxied.or(record.proxied).unwrap_or(false);
+                let patch = PatchRecordRequest {
+                    content,
+                    ttl: ttl.filter(|_| !proxied),
+                    proxied: self.proxied,
+                };
+                self.patch_record(&zone_id, &record.id, &patch).await
+            }
+            None => {
+                let proxied = self.proxied.unwrap_or(false);
+                let record = DnsRecord {
+                    name,
+                    r#type: record_type.to_string(),
+                    content,
+                    ttl: Some(ttl.filter(|_| !proxied).unwrap_or(AUTOMATIC_TTL)),
+                    proxied: self.proxied,
+                    ..Default::default()
+                };
+                self.create_record(&zone_id, &record).await
+            }
+        };
+        self.forget_zone_if_gone(domain, result)?;
+        Ok(())
+    }
+
+    fn request(&self, method: Method, path: &str) -> RequestBuilder {
+        self.client
+            .request(method, format!("{}{}", self.api_url, path))
+            .bearer_auth(&self.api_token)
+    }
+
+    async fn send<T: DeserializeOwned>(
+        &self,
+        request: RequestBuilder,
+    ) -> Result<CloudflareResponse<T>, CloudflareDnsClientError> {
+        let response = request.send().await?;
+        let status = response.status();
+        let body = response.text().await?;
+
+        let parsed = serde_json::from_str::<CloudflareResponse<T>>(&body);
+        if let Ok(response) = parsed {
+            if status.is_success() && response.success {
+                return Ok(response);
+            }
+            return Err(api_error(status, response.errors));
+        }
+
+        Err(api_error(
+            status,
+            vec![CloudflareMessage {
+                code: 0,
+                message: body,
+            }],
+        ))
+    }
+
+    async fn send_result<T: DeserializeOwned>(
+        &self,
+        request: RequestBuilder,
+    ) -> Result<T, CloudflareDnsClientError> {
+        self.send(request)
+            .await?
+            .result
+            .ok_or(CloudflareDnsClientError::EmptyResult)
+    }
+}
+
+fn api_error(status: StatusCode, errors: Vec<CloudflareMessage>) -> CloudflareDnsClientError {
+    let code = errors.first().map(|e| e.code).unwrap_or_default();
+    let message = errors
+        .iter()
+        .map(|e| format!("{}: {}", e.code, e.message))
+        .collect::<Vec<_>>()
+        .join(", ");
+    error!("Cloudflare API returned {}: {}", status, message);
+
+    match status {
+        StatusCode::UNAUTHORIZED => CloudflareDnsClientError::InvalidApiToken,
+        StatusCode::FORBIDDEN => CloudflareDnsClientError::PermissionDenied { message },
+        StatusCode::NOT_FOUND => CloudflareDnsClientError::NotFound,
+        StatusCode::TOO_MANY_REQUESTS => CloudflareDnsClientError::RateLimited,
+        _ => CloudflareDnsClientError::Api {
+            status: status.as_u16(),
+            code,
+            message,
+        },
+    }
+}
+
+#[async_trait]
+impl DnsService for CloudflareDnsClient {
+    async fn resolve_ip(
+        &self,
+        subdomain: &str,
+        domain: &str,
+    ) -> Result<Option<Ipv4Addr>, DnsServiceError> {
+        debug!(
+            "Resolve ip for domain {} and subdomain {}.",
+            domain, subdomain
+        );
+        let (_, records) = self
+            .zone_records(domain, &fqdn(subdomain, domain), "A")
+            .await?;
+
+        match records.into_iter().next() {
+            Some(record) => {
+                let ip = record
+                    .content
+                    .parse()
+                    .map_err(|_| DnsServiceError::UnknownError)?;
+                Ok(Some(ip))
+            }
+            None => Ok(None),
+        }
+    }
+
+    async fn resolve_ttl(
+        &self,
+        subdomain: &str,
+        domain: &str,
+    ) -> Result<Option<u32>, DnsServiceError> {
+        let (_, records) = self
+            .zone_records(domain, &fqdn(subdomain, domain), "A")
+            .await?;
+        Ok(records.into_iter().next().and_then(|record| record.ttl))
+    }
+
+    async fn update_dns(
+        &self,
+        subdomain: &str,
+        domain: &str,
+        ip: Ipv4Addr,
+    ) -> Result<(), DnsServiceError> {
+        self.update_dns_with_ttl(subdomain, domain, ip, None).await
+    }
+
+    async fn update_dns_with_ttl(
+        &self,
+        subdomain: &str,
+        domain: &str,
+        ip: Ipv4Addr,
+        ttl: Option<u32>,
+    ) -> Result<(), DnsServiceError> {
+        debug!(
+            "Update dns for domain {} and subdomain {} with IP {}.",
+            domain, subdomain, ip
+        );
+        self.set_record(domain, subdomain, "A", "", ip.to_string(), ttl)
+            .await?;
+        Ok(())
+    }
+
+    async fn list_zones(&self) -> Result<Vec<String>, DnsServiceError> {
+        let zones = CloudflareDnsClient::list_zones(self).await?;
+        let mut zone_ids = self.zone_ids.lock().unwrap();
+        Ok(zones
+            .into_iter()
+            .map(|zone| {
+                zone_ids.insert(zone.name.clone(), zone.id);
+                zone.name
+            })
+            .collect())
+    }
+
+    fn supports_record_type(&self, _record_type: &str) -> bool {
+        true
+    }
+
+    async fn resolve_record(
+        &self,
+        subdomain: &str,
+        domain: &str,
+        record_type: &str,
+        prefix: &str,
+    ) -> Result<Option<String>, DnsServiceError> {
+        let (_, records) = self
+            .zone_records(domain, &fqdn(subdomain, domain), record_type)
+            .await?;
+        let values: Vec<String> = records.into_iter().map(|record| record.content).collect();
+        Ok(managed_value(record_type, &values, prefix))
+    }
+
+    async fn update_record(
+        &self,
+        subdomain: &str,
+        domain: &str,
+        record_type: &str,
+        prefix: &str,
+        value: &str,
+    ) -> Result<(), DnsServiceError> {
+        self.set_record(
+            domain,
+            subdomain,
+            record_type,
+            prefix,
+            api_value(record_type, value),
+            None,
+        )
+        .await?;
+        Ok(())
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum CloudflareDnsClientError {
+    #[error("We could not authenticate against the API.")]
+    InvalidApiToken,
+    #[error("The API token is not permitted to do this: {message}")]
+    PermissionDenied { message: String },
+    #[error("The zone {zone} is not visible to the API token.")]
+    ZoneNotFound { zone: String },
+    #[error("The zone or record does not exist.")]
+    NotFound,
+    #[error("The API rate limit is exceeded.")]
+    RateLimited,
+    #[error("API request failed with status {status} and code {code}: {message}")]
+    Api {
+        status: u16,
+        code: u32,
+        message: String,
+    },
+    #[error("The API response did not contain a result.")]
+    EmptyResult,
+    #[error("request failed")]
+    RequestFailed {
+        #[from]
+        source: reqwest::Error,
+    },
+}
+
+impl From<CloudflareDnsClientError> for DnsServiceError {
+    fn from(e: CloudflareDnsClientError) -> Self {
+        match e {
+            CloudflareDnsClientError::ZoneNotFound { .. } => DnsServiceError::UnknownZone,
+            CloudflareDnsClientError::NotFound => DnsServiceError::UnknownRecord,
+            CloudflareDnsClientError::Api { status: 400, .. } => DnsServiceError::UpdateRejected {
+                record: e.to_string(),
+            },
+            _ => DnsServiceError::ClientError,
+        }
+    }
+}
@@ -1,13 +1,165 @@
 use envconfig::Envconfig;
+use std::fmt;
+use std::net::SocketAddr;
+use std::str::FromStr;
+
+use crate::hosts_file_dns_service::HostsFormat;
+use crate::openwrt_dns_service::OpenWrtDnsMode;
+use crate::tsig::TsigAlgorithm;
+use crate::ttl::TtlPolicy;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DnsProvider {
+    Hetzner,
+    HetznerCloud,
+    Rfc2136,
+    Cloudflare,
+    Route53,
+    PowerDns,
+    Desec,
+    Dyndns2,
+    Ovh,
+    Gandi,
+    Inwx,
+    GoogleCloud,
+    ZoneFile,
+    HostsFile,
+    Pihole,
+    AdGuardHome,
+    OpenWrt,
+}
+
+impl FromStr for DnsProvider {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "hetzner" => Ok(DnsProvider::Hetzner),
+            "hetzner-cloud" => Ok(DnsProvider::HetznerCloud),
+            "rfc2136" => Ok(DnsProvider::Rfc2136),
+            "cloudflare" => Ok(DnsProvider::Cloudflare),
+            "route53" => Ok(DnsProvider::Route53),
+            "powerdns" => Ok(DnsProvider::PowerDns),
+            "desec" => Ok(DnsProvider::Desec),
+            "dyndns2" => Ok(DnsProvider::Dyndns2),
+            "ovh" => Ok(DnsProvider::Ovh),
+            "gandi" => Ok(DnsProvider::Gandi),
+            "inwx" => Ok(DnsProvider::Inwx),
+            "google-cloud" => Ok(DnsProvider::GoogleCloud),
+            "zone-file" => Ok(DnsProvider::ZoneFile),
+            "hosts-file" => Ok(DnsProvider::HostsFile),
+            "pihole" => Ok(DnsProvider::Pihole),
+            "adguard-home" => Ok(DnsProvider::AdGuardHome),
+            "openwrt" => Ok(DnsProvider::OpenWrt),
+            _ => Err(format!("unknown dns provider: {}", s)),
+        }
+    }
+}
+
+impl fmt::Display for DnsProvider {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            DnsProvider::Hetzner => "hetzner",
+            DnsProvider::HetznerCloud => "hetzner-cloud",
+            DnsProvider::Rfc2136 => "rfc2136",
+            DnsProvider::Cloudflare => "cloudflare",
+            DnsProvider::Route53 => "route53",
+            DnsProvider::PowerDns => "powerdns",
+            DnsProvider::Desec => "desec",
+            DnsProvider::Dyndns2 => "dyndns2",
+            DnsProvider::Ovh => "ovh",
+            DnsProvider::Gandi => "gandi",
+            DnsProvider::Inwx => "inwx",
+            DnsProvider::GoogleCloud => "google-cloud",
+            DnsProvider::ZoneFile => "zone-file",
+            DnsProvider::HostsFile => "hosts-file",
+            DnsProvider::Pihole => "pihole",
+            DnsProvider::AdGuardHome => "adguard-home",
+            DnsProvider::OpenWrt => "openwrt",
+        })
+    }
+}
 
 #[derive(Envconfig)]
 pub struct CliConfig {
+    #[envconfig(from = "DYNDNSD_DNS_PROVIDER", default = "hetzner")]
+    pub dns_provider: DnsProvider,
     #[envconfig(from = "DYNDNSD_HETZNER_API_TOKEN")]
-    pub api_token: String,
+    pub api_token: Option<String>,
+    #[envconfig(from = "DYNDNSD_CLOUDFLARE_API_TOKEN")]
+    pub cloudflare_api_token: Option<String>,
+    #[envconfig(from = "DYNDNSD_CLOUDFLARE_PROXIED")]
+    pub cloudflare_proxied: Option<bool>,
+    #[envconfig(from = "DYNDNSD_RFC2136_SERVER")]
+    pub rfc2136_server: Option<String>,
+    #[envconfig(from = "DYNDNSD_TSIG_KEY_NAME")]
+    pub tsig_key_name: Option<String>,
+    #[envconfig(from = "DYNDNSD_TSIG_ALGORITHM", default = "hmac-sha256")]
+    pub tsig_algorithm: TsigAlgorithm,
+    #[envconfig(from = "DYNDNSD_TSIG_SECRET")]
+    pub tsig_secret: Option<String>,
+    #[envconfig(from = "DYNDNSD_POWERDNS_URL")]
+    pub powerdns_url: Option<String>,
+    #[envconfig(from = "DYNDNSD_POWERDNS_API_KEY")]
+    pub powerdns_api_key: Option<String>,
+    #[envconfig(from = "DYNDNSD_POWERDNS_SERVER_ID")]
+    pub powerdns_server_id: Option<String>,
+    #[envconfig(from = "DYNDNSD_DESEC_TOKEN")]
+    pub desec_token: Option<String>,
+    #[envconfig(from = "DYNDNSD_DYNDNS2_URL")]
+    pub dyndns2_url: Option<String>,
+    #[envconfig(from = "DYNDNSD_DYNDNS2_USERNAME")]
+    pub dyndns2_username: Option<String>,
+    #[envconfig(from = "DYNDNSD_DYNDNS2_PASSWORD")]
+    pub dyndns2_password: Option<String>,
+    #[envconfig(from = "DYNDNSD_DYNDNS2_STATE_FILE")]
+    pub dyndns2_state_file: Option<String>,
+    #[envconfig(from = "DYNDNSD_OVH_ENDPOINT", default = "ovh-eu")]
+    pub ovh_endpoint: String,
+    #[envconfig(from = "DYNDNSD_OVH_APPLICATION_KEY")]
+    pub ovh_application_key: Option<String>,
+    #[envconfig(from = "DYNDNSD_OVH_APPLICATION_SECRET")]
+    pub ovh_application_secret: Option<String>,
+    #[envconfig(from = "DYNDNSD_OVH_CONSUMER_KEY")]
+    pub ovh_consumer_key: Option<String>,
+    #[envconfig(from = "DYNDNSD_GANDI_TOKEN")]
+    pub gandi_token: Option<String>,
+    #[envconfig(from = "DYNDNSD_INWX_USERNAME")]
+    pub inwx_username: Option<String>,
+    #[envconfig(from = "DYNDNSD_INWX_PASSWORD")]
+    pub inwx_password: Option<String>,
+    #[envconfig(from = "DYNDNSD_GOOGLE_CREDENTIALS_FILE")]
+    pub google_credentials_file: Option<String>,
+    #[envconfig(from = "DYNDNSD_GOOGLE_PROJECT")]
+    pub google_project: Option<String>,
+    #[envconfig(from = "DYNDNSD_ZONE_FILE")]
+    pub zone_file: Option<String>,
+    #[envconfig(from = "DYNDNSD_HOSTS_FILE")]
+    pub hosts_file: Option<String>,
+    #[envconfig(from = "DYNDNSD_HOSTS_FORMAT", default = "hosts")]
+    pub hosts_format: HostsFormat,
+    #[envconfig(from = "DYNDNSD_RELOAD_COMMAND")]
+    pub reload_command: Option<String>,
+    #[envconfig(from = "DYNDNSD_PIHOLE_URL")]
+    pub pihole_url: Option<String>,
+    #[envconfig(from = "DYNDNSD_PIHOLE_PASSWORD")]
+    pub pihole_password: Option<String>,
+    #[envconfig(from = "DYNDNSD_ADGUARD_URL")]
+    pub adguard_url: Option<String>,
+    #[envconfig(from = "DYNDNSD_ADGUARD_USERNAME")]
+    pub adguard_username: Option<String>,
+    #[envconfig(from = "DYNDNSD_ADGUARD_PASSWORD")]
+    pub adguard_password: Option<String>,
+    #[envconfig(from = "DYNDNSD_OPENWRT_DNS_MODE", default = "domain")]
+    pub openwrt_dns_mode: OpenWrtDnsMode,
+    #[envconfig(from = "DYNDNSD_NAME")]
+    pub name: Option<String>,
     #[envconfig(from = "DYNDNSD_DOMAIN")]
-    pub domain: String,
+    pub domain: Option<String>,
     #[envconfig(from = "DYNDNSD_SUBDOMAIN")]
-    pub subdomain: String,
+    pub subdomain: Option<String>,
+    #[envconfig(from = "DYNDNSD_DERIVED_RECORDS")]
+    pub derived_records: Option<String>,
     #[envconfig(from = "DYNDNSD_POLL_INTERVAL")]
     pub interval: u32,
     #[envconfig(from = "DYNDNSD_UBUS_URL")]
@@ -16,4 +168,42 @@ pub struct CliConfig {
     pub ubus_user: String,
     #[envconfig(from = "DYNDNSD_UBUS_SECRET")]
     pub ubus_secret: String,
+    #[envconfig(from = "DYNDNSD_UBUS_INTERFACE", default = "wan")]
+    pub ubus_interface: String,
+    #[envconfig(from = "DYNDNSD_WAN_MASK")]
+    pub wan_mask: Option<u8>,
+    #[envconfig(from = "DYNDNSD_ALLOW_NON_PUBLIC_IP", default = "false")]
+    pub allow_non_public_ip: bool,
+    #[envconfig(from = "DYNDNSD_OBSERVED_IP_URL")]
+    pub observed_ip_url: Option<String>,
+    #[envconfig(from = "DYNDNSD_OBSERVED_IP_STUN_SERVER")]
+    pub observed_ip_stun_server: Option<String>,
+    #[envconfig(from = "DYNDNSD_DAMPENING_MIN_OBSERVATIONS", default = "1")]
+    pub dampening_min_observations: u32,
+    #[envconfig(from = "DYNDNSD_DAMPENING_MIN_STABLE_SECONDS", default = "0")]
+    pub dampening_min_stable_seconds: u64,
+    #[envconfig(from = "DYNDNSD_MAX_UPDATES_PER_HOUR")]
+    pub max_updates_per_hour: Option<u32>,
+    #[envconfig(from = "DYNDNSD_VERIFY_UPDATE", default = "false")]
+    pub verify_update: bool,
+    #[envconfig(from = "DYNDNSD_VERIFY_TIMEOUT_SECONDS", default = "120")]
+    pub verify_timeout_seconds: u64,
+    #[envconfig(from = "DYNDNSD_HETZNER_ZONE_ID")]
+    pub zone_id: Option<String>,
+    #[envconfig(from = "DYNDNSD_CACHE_RECORD_VALUES", default = "false")]
+    pub cache_record_values: bool,
+    #[envconfig(from = "DYNDNSD_CACHE_MAX_AGE_SECONDS", default = "3600")]
+    pub cache_max_age_seconds: u64,
+    #[envconfig(from = "DYNDNSD_RESOLVE_VIA_DNS", default = "false")]
+    pub resolve_via_dns: bool,
+    #[envconfig(from = "DYNDNSD_NAME_SERVERS")]
+    pub name_servers: Option<String>,
+    #[envconfig(from = "DYNDNSD_TTL", default = "keep")]
+    pub ttl: TtlPolicy,
+    #[envconfig(from = "DYNDNSD_RECORD_TTLS")]
+    pub record_ttls: Option<String>,
+    #[envconfig(from = "DYNDNSD_DRY_RUN", default = "false")]
+    pub dry_run: bool,
+    #[envconfig(from = "DYNDNSD_METRICS_ADDR")]
+    pub metrics_addr: Option<SocketAddr>,
 }
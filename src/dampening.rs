@@ -0,0 +1,249 @@
+use std::collections::{HashMap, VecDeque};
+use std::net::Ipv4Addr;
+use std::time::{Duration, Instant};
+
+const RATE_WINDOW: Duration = Duration::from_secs(3600);
+
+#[derive(Clone, Debug, Default)]
+pub struct DampeningPolicy {
+    pub min_observations: u32,
+    pub min_stable: Duration,
+    pub max_updates_per_hour: Option<u32>,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum DampeningDecision {
+    Update,
+    Unconfirmed {
+        observations: u32,
+        stable_for: Duration,
+    },
+    RateLimited {
+        updates_last_hour: u32,
+    },
+}
+
+#[derive(Debug)]
+struct RecordState {
+    candidate: Option<Ipv4Addr>,
+    first_seen: Instant,
+    observations: u32,
+    updates: VecDeque<Instant>,
+}
+
+#[derive(Debug)]
+pub struct Dampener {
+    policy: DampeningPolicy,
+    records: HashMap<String, RecordState>,
+}
+
+impl Dampener {
+    pub fn new(policy: DampeningPolicy) -> Self {
+        Self {
+            policy,
+            records: HashMap::new(),
+        }
+    }
+
+    pub fn observe(&mut self, record: &str, ip: Ipv4Addr, now: Instant) -> DampeningDecision {
+        let state = self
+            .records
+            .entry(record.to_string())
+            .or_insert_with(|| RecordState {
+                candidate: None,
+                first_seen: now,
+                observations: 0,
+                updates: VecDeque::new(),
+            });
+
+        if state.candidate != Some(ip) {
+            state.candidate = Some(ip);
+            state.first_seen = now;
+            state.observations = 0;
+        }
+        state.observations += 1;
+
+        // A new address is confirmed once it was seen often enough or for long enough,
+        // whichever of the configured thresholds is reached first.
+        let stable_for = now.saturating_duration_since(state.first_seen);
+        let by_observations = self.policy.min_observations > 1;
+        let by_time = !self.policy.min_stable.is_zero();
+        let confirmed = (!by_observations && !by_time)
+            || (by_observations && state.observations >= self.policy.min_observations)
+            || (by_time && stable_for >= self.policy.min_stable);
+        if !confirmed {
+            return DampeningDecision::Unconfirmed {
+                observations: state.observations,
+                stable_for,
+            };
+        }
+
+        while matches!(state.updates.front(), Some(t) if now.saturating_duration_since(*t) >= RATE_WINDOW)
+        {
+            state.updates.pop_front();
+        }
+        if let Some(max) = self.policy.max_updates_per_hour {
+            if state.updates.len() as u32 >= max {
+                return DampeningDecision::RateLimited {
+                    updates_last_hour: state.updates.len() as u32,
+                };
+            }
+        }
+
+        DampeningDecision::Update
+    }
+
+    pub fn record_update(&mut self, record: &str, now: Instant) {
+        if let Some(state) = self.records.get_mut(record) {
+            state.candidate = None;
+            state.updates.push_back(now);
+        }
+    }
+
+    pub fn reset(&mut self, record: &str) {
+        if let Some(state) = self.records.get_mut(record) {
+            state.candidate = None;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RECORD: &str = "test.example.com";
+
+    fn ip(last: u8) -> Ipv4Addr {
+        Ipv4Addr::new(93, 184, 216, last)
+    }
+
+    #[test]
+    fn update_immediately_by_default() {
+        let mut dampener = Dampener::new(DampeningPolicy::default());
+        assert_eq!(
+            dampener.observe(RECORD, ip(1), Instant::now()),
+            DampeningDecision::Update
+        );
+    }
+
+    #[test]
+    fn require_observations() {
+        let mut dampener = Dampener::new(DampeningPolicy {
+            min_observations: 3,
+            ..Default::default()
+        });
+        let now = Instant::now();
+
+        assert!(matches!(
+            dampener.observe(RECORD, ip(1), now),
+            DampeningDecision::Unconfirmed {
+                observations: 1,
+                ..
+            }
+        ));
+        assert!(matches!(
+            dampener.observe(RECORD, ip(1), now),
+            DampeningDecision::Unconfirmed {
+                observations: 2,
+                ..
+            }
+        ));
+        assert_eq!(
+            dampener.observe(RECORD, ip(1), now),
+            DampeningDecision::Update
+        );
+    }
+
+    #[test]
+    fn restart_confirmation_when_ip_flaps() {
+        let mut dampener = Dampener::new(DampeningPolicy {
+            min_observations: 2,
+            ..Default::default()
+        });
+        let now = Instant::now();
+
+        dampener.observe(RECORD, ip(1), now);
+        assert!(matches!(
+            dampener.observe(RECORD, ip(2), now),
+            DampeningDecision::Unconfirmed {
+                observations: 1,
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn require_stable_duration() {
+        let mut dampener = Dampener::new(DampeningPolicy {
+            min_stable: Duration::from_secs(60),
+            ..Default::default()
+        });
+        let now = Instant::now();
+
+        assert!(matches!(
+            dampener.observe(RECORD, ip(1), now),
+            DampeningDecision::Unconfirmed { .. }
+        ));
+        assert_eq!(
+            dampener.observe(RECORD, ip(1), now + Duration::from_secs(60)),
+            DampeningDecision::Update
+        );
+    }
+
+    #[test]
+    fn confirm_when_either_threshold_is_met() {
+        let mut dampener = Dampener::new(DampeningPolicy {
+            min_observations: 3,
+            min_stable: Duration::from_secs(60),
+            ..Default::default()
+        });
+        let now = Instant::now();
+
+        assert!(matches!(
+            dampener.observe(RECORD, ip(1), now),
+            DampeningDecision::Unconfirmed { .. }
+        ));
+        // Seen only twice, but for long enough.
+        assert_eq!(
+            dampener.observe(RECORD, ip(1), now + Duration::from_secs(60)),
+            DampeningDecision::Update
+        );
+
+        // Seen three times before the time threshold is reached.
+        dampener.observe(RECORD, ip(2), now);
+        dampener.observe(RECORD, ip(2), now);
+        assert_eq!(
+            dampener.observe(RECORD, ip(2), now + Duration::from_secs(1)),
+            DampeningDecision::Update
+        );
+    }
+
+    #[test]
+    fn cap_updates_per_hour() {
+        let mut dampener = Dampener::new(DampeningPolicy {
+            max_updates_per_hour: Some(1),
+            ..Default::default()
+        });
+        let now = Instant::now();
+
+        assert_eq!(
+            dampener.observe(RECORD, ip(1), now),
+            DampeningDecision::Update
+        );
+        dampener.record_update(RECORD, now);
+
+        let later = now + Duration::from_secs(60);
+        assert_eq!(
+            dampener.observe(RECORD, ip(2), later),
+            DampeningDecision::RateLimited {
+                updates_last_hour: 1
+            }
+        );
+
+        let next_hour = now + RATE_WINDOW;
+        assert_eq!(
+            dampener.observe(RECORD, ip(2), next_hour),
+            DampeningDecision::Update
+        );
+    }
+}
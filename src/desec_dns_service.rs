@@ -0,0 +1,312 @@
+use async_trait::async_trait;
+use log::{debug, error};
+use reqwest::{Client, Method, RequestBuilder, Response, StatusCode};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::net::Ipv4Addr;
+use thiserror::Error;
+
+use crate::dns_service::{DnsService, DnsServiceError};
+use crate::record_template::{managed_value, replace_managed};
+
+const API_URL: &str = "https://desec.io/api/v1";
+/// deSEC does not accept TTLs below one hour unless the account is allowed to.
+const DEFAULT_TTL: u32 = 3600;
+
+#[derive(Clone, Deserialize, Serialize, Debug, Default, PartialEq, Eq)]
+pub struct DesecDomain {
+    pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub minimum_ttl: Option<u32>,
+}
+
+#[derive(Clone, Deserialize, Serialize, Debug, Default, PartialEq, Eq)]
+pub struct DesecRRSet {
+    pub subname: String,
+    pub r#type: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ttl: Option<u32>,
+    pub records: Vec<String>,
+}
+
+/// Client for the deSEC API. Records are changed by replacing whole RRsets with a bulk
+/// `PATCH`, which also creates missing RRsets.
+pub struct DesecClient {
+    api_url: String,
+    api_token: String,
+    client: Client,
+}
+
+impl DesecClient {
+    pub fn new(api_token: &str) -> Self {
+        Self::new_with_url(api_token, API_URL)
+    }
+
+    pub fn new_with_url(api_token: &str, api_url: &str) -> Self {
+        let client = Client::new();
+        Self {
+            api_url: String::from(api_url),
+            api_token: String::from(api_token),
+            client,
+        }
+    }
+
+    pub async fn list_domains(&self) -> Result<Vec<DesecDomain>, DesecError> {
+        let request = self.request(Method::GET, "/domains/");
+        self.send_json(request).await
+    }
+
+    pub async fn get_rrset(
+        &self,
+        domain: &str,
+        subname: &str,
+        record_type: &str,
+    ) -> Result<Option<DesecRRSet>, DesecError> {
+        let request = self.request(
+            Method::GET,
+            &format!(
+                "/domains/{}/rrsets/{}/{}/",
+                domain,
+                url_subname(subname),
+                record_type
+            ),
+        );
+        match self.send_json(request).await {
+            Ok(rrset) => Ok(Some(rrset)),
+            Err(DesecError::NotFound) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    pub async fn patch_rrsets(
+        &self,
+        domain: &str,
+        rrsets: &[DesecRRSet],
+    ) -> Result<(), DesecError> {
+        let request = self
+            .request(Method::PATCH, &format!("/domains/{}/rrsets/", domain))
+            .json(rrsets);
+        self.send(request).await?;
+        Ok(())
+    }
+
+    /// Writes `values` through the bulk endpoint. deSEC rejects RRsets without a TTL, so the
+    /// current one is read first unless `ttl` is given.
+    pub async fn replace_rrset(
+        &self,
+        domain: &str,
+        subname: &str,
+        record_type: &str,
+        values: &[String],
+        ttl: Option<u32>,
+    ) -> Result<(), DesecError> {
+        let ttl = match ttl {
+            Some(ttl) => ttl,
+            None => self
+                .get_rrset(domain, subname, record_type)
+                .await?
+                .and_then(|rrset| rrset.ttl)
+                .unwrap_or(DEFAULT_TTL),
+        };
+
+        let rrset = DesecRRSet {
+            subname: api_subname(subname),
+            r#type: record_type.to_string(),
+            ttl: Some(ttl),
+            records: values.to_vec(),
+        };
+        self.patch_rrsets(domain, &[rrset]).await
+    }
+
+    fn request(&self, method: Method, path: &str) -> RequestBuilder {
+        self.client
+            .request(method, format!("{}{}", self.api_url, path))
+            .header("Authorization", format!("Token {}", self.api_token))
+    }
+
+    async fn send(&self, request: RequestBuilder) -> Result<Response, DesecError> {
+        let response = request.send().await?;
+        let status = response.status();
+
+        if status.is_success() {
+            return Ok(response);
+        }
+        if status == StatusCode::UNAUTHORIZED {
+            return Err(DesecError::InvalidApiToken);
+        }
+        if status == StatusCode::NOT_FOUND {
+            return Err(DesecError::NotFound);
+        }
+        if status == StatusCode::TOO_MANY_REQUESTS {
+            let retry_after = response
+                .headers()
+                .get("retry-after")
+                .and_then(|value| value.to_str().ok())
+                .and_then(|value| value.parse().ok());
+            return Err(DesecError::RateLimited { retry_after });
+        }
+
+        let message = response.text().await.unwrap_or_default();
+        error!("deSEC API returned {}: {}", status, message);
+        Err(match status {
+            StatusCode::BAD_REQUEST => DesecError::Rejected { message },
+            _ => DesecError::Api {
+                status: status.as_u16(),
+                message,
+            },
+        })
+    }
+
+    async fn send_json<T: DeserializeOwned>(
+        &self,
+        request: RequestBuilder,
+    ) -> Result<T, DesecError> {
+        Ok(self.send(request).await?.json().await?)
+    }
+}
+
+/// The apex is `@` in URLs and an empty subname in request bodies.
+fn url_subname(subdomain: &str) -> &str {
+    match subdomain {
+        "" | "@" => "@",
+        _ => subdomain,
+    }
+}
+
+fn api_subname(subdomain: &str) -> String {
+    match subdomain {
+        "@" => String::new(),
+        _ => subdomain.to_string(),
+    }
+}
+
+#[async_trait]
+impl DnsService for DesecClient {
+    async fn resolve_ip(
+        &self,
+        subdomain: &str,
+        domain: &str,
+    ) -> Result<Option<Ipv4Addr>, DnsServiceError> {
+        debug!(
+            "Resolve ip for domain {} and subdomain {}.",
+            domain, subdomain
+        );
+        let rrset = self.get_rrset(domain, subdomain, "A").await?;
+
+        match rrset.and_then(|rrset| rrset.records.into_iter().next()) {
+            Some(value) => {
+                let ip = value.parse().map_err(|_| DnsServiceError::UnknownError)?;
+                Ok(Some(ip))
+            }
+            None => Ok(None),
+        }
+    }
+
+    async fn resolve_ttl(
+        &self,
+        subdomain: &str,
+        domain: &str,
+    ) -> Result<Option<u32>, DnsServiceError> {
+        let rrset = self.get_rrset(domain, subdomain, "A").await?;
+        Ok(rrset.and_then(|rrset| rrset.ttl))
+    }
+
+    async fn update_dns(
+        &self,
+        subdomain: &str,
+        domain: &str,
+        ip: Ipv4Addr,
+    ) -> Result<(), DnsServiceError> {
+        self.update_dns_with_ttl(subdomain, domain, ip, None).await
+    }
+
+    async fn update_dns_with_ttl(
+        &self,
+        subdomain: &str,
+        domain: &str,
+        ip: Ipv4Addr,
+        ttl: Option<u32>,
+    ) -> Result<(), DnsServiceError> {
+        debug!(
+            "Update dns for domain {} and subdomain {} with IP {}.",
+            domain, subdomain, ip
+        );
+        self.replace_rrset(domain, subdomain, "A", &[ip.to_string()], ttl)
+            .await?;
+        Ok(())
+    }
+
+    async fn list_zones(&self) -> Result<Vec<String>, DnsServiceError> {
+        let domains = self.list_domains().await?;
+        Ok(domains.into_iter().map(|domain| domain.name).collect())
+    }
+
+    fn supports_record_type(&self, _record_type: &str) -> bool {
+        true
+    }
+
+    async fn resolve_record(
+        &self,
+        subdomain: &str,
+        domain: &str,
+        record_type: &str,
+        prefix: &str,
+    ) -> Result<Option<String>, DnsServiceError> {
+        let rrset = self.get_rrset(domain, subdomain, record_type).await?;
+        Ok(rrset.and_then(|rrset| managed_value(record_type, &rrset.records, prefix)))
+    }
+
+    async fn update_record(
+        &self,
+        subdomain: &str,
+        domain: &str,
+        record_type: &str,
+        prefix: &str,
+        value: &str,
+    ) -> Result<(), DnsServiceError> {
+        let rrset = self.get_rrset(domain, subdomain, record_type).await?;
+        let current = rrset
+            .as_ref()
+            .map(|rrset| rrset.records.clone())
+            .unwrap_or_default();
+        self.replace_rrset(
+            domain,
+            subdomain,
+            record_type,
+            &replace_managed(record_type, &current, prefix, value),
+            Some(rrset.and_then(|rrset| rrset.ttl).unwrap_or(DEFAULT_TTL)),
+        )
+        .await?;
+        Ok(())
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum DesecError {
+    #[error("We could not authenticate against the API.")]
+    InvalidApiToken,
+    #[error("The domain or RRset does not exist.")]
+    NotFound,
+    #[error("The API rate limit is exceeded, retry after {retry_after:?} seconds.")]
+    RateLimited { retry_after: Option<u64> },
+    #[error("deSEC rejected the change: {message}")]
+    Rejected { message: String },
+    #[error("API request failed with status {status}: {message}")]
+    Api { status: u16, message: String },
+    #[error("request failed")]
+    RequestFailed {
+        #[from]
+        source: reqwest::Error,
+    },
+}
+
+impl From<DesecError> for DnsServiceError {
+    fn from(e: DesecError) -> Self {
+        match e {
+            DesecError::NotFound => DnsServiceError::UnknownZone,
+            DesecError::Rejected { .. } => DnsServiceError::UpdateRejected {
+                record: e.to_string(),
+            },
+            _ => DnsServiceError::ClientError,
+        }
+    }
+}
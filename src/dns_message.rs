@@ -0,0 +1,468 @@
+use std::io;
+use std::net::{Ipv4Addr, SocketAddr};
+use std::time::Duration;
+use thiserror::Error;
+use tokio::net::{lookup_host, UdpSocket};
+use tokio::time::timeout;
+
+pub const TYPE_A: u16 = 1;
+pub const TYPE_NS: u16 = 2;
+pub const TYPE_CNAME: u16 = 5;
+pub const TYPE_SOA: u16 = 6;
+pub const TYPE_TXT: u16 = 16;
+pub const TYPE_AAAA: u16 = 28;
+pub const TYPE_TSIG: u16 = 250;
+pub const TYPE_ANY: u16 = 255;
+
+pub const CLASS_IN: u16 = 1;
+pub const CLASS_NONE: u16 = 254;
+pub const CLASS_ANY: u16 = 255;
+
+pub const OPCODE_QUERY: u8 = 0;
+pub const OPCODE_UPDATE: u8 = 5;
+
+pub const RCODE_NOERROR: u8 = 0;
+pub const RCODE_NXDOMAIN: u8 = 3;
+pub const RCODE_REFUSED: u8 = 5;
+pub const RCODE_YXDOMAIN: u8 = 6;
+pub const RCODE_YXRRSET: u8 = 7;
+pub const RCODE_NXRRSET: u8 = 8;
+pub const RCODE_NOTAUTH: u8 = 9;
+
+const FLAG_RESPONSE: u16 = 0x8000;
+const FLAG_TRUNCATED: u16 = 0x0200;
+const HEADER_LEN: usize = 12;
+const MAX_UDP_MESSAGE: usize = 4096;
+const POINTER_MASK: u8 = 0xc0;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Question {
+    pub name: String,
+    pub qtype: u16,
+    pub qclass: u16,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ResourceRecord {
+    pub name: String,
+    pub rtype: u16,
+    pub class: u16,
+    pub ttl: u32,
+    pub rdata: Vec<u8>,
+}
+
+impl ResourceRecord {
+    pub fn a(name: &str, ttl: u32, ip: Ipv4Addr) -> Self {
+        Self {
+            name: name.to_string(),
+            rtype: TYPE_A,
+            class: CLASS_IN,
+            ttl,
+            rdata: ip.octets().to_vec(),
+        }
+    }
+
+    pub fn as_ipv4(&self) -> Option<Ipv4Addr> {
+        match (self.rtype, self.rdata.len()) {
+            (TYPE_A, 4) => Some(Ipv4Addr::new(
+                self.rdata[0],
+                self.rdata[1],
+                self.rdata[2],
+                self.rdata[3],
+            )),
+            _ => None,
+        }
+    }
+
+    pub fn as_name(&self) -> Option<String> {
+        match self.rtype {
+            TYPE_NS | TYPE_CNAME => decode_name(&self.rdata, 0).ok().map(|(name, _)| name),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Message {
+    pub id: u16,
+    pub flags: u16,
+    pub questions: Vec<Question>,
+    pub answers: Vec<ResourceRecord>,
+    pub authorities: Vec<ResourceRecord>,
+    pub additionals: Vec<ResourceRecord>,
+}
+
+impl Message {
+    pub fn query(id: u16, name: &str, qtype: u16) -> Self {
+        Self {
+            id,
+            questions: vec![Question {
+                name: name.to_string(),
+                qtype,
+                qclass: CLASS_IN,
+            }],
+            ..Default::default()
+        }
+    }
+
+    pub fn response_to(request: &Message) -> Self {
+        Self {
+            id: request.id,
+            flags: FLAG_RESPONSE | (request.flags & 0x7800),
+            questions: request.questions.clone(),
+            ..Default::default()
+        }
+    }
+
+    pub fn opcode(&self) -> u8 {
+        ((self.flags >> 11) & 0x0f) as u8
+    }
+
+    pub fn set_opcode(&mut self, opcode: u8) {
+        self.flags = (self.flags & !0x7800) | ((opcode as u16 & 0x0f) << 11);
+    }
+
+    pub fn rcode(&self) -> u8 {
+        (self.flags & 0x000f) as u8
+    }
+
+    pub fn set_rcode(&mut self, rcode: u8) {
+        self.flags = (self.flags & !0x000f) | (rcode as u16 & 0x0f);
+    }
+
+    pub fn is_response(&self) -> bool {
+        self.flags & FLAG_RESPONSE != 0
+    }
+
+    pub fn is_truncated(&self) -> bool {
+        self.flags & FLAG_TRUNCATED != 0
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(512);
+        out.extend_from_slice(&self.id.to_be_bytes());
+        out.extend_from_slice(&self.flags.to_be_bytes());
+        for count in [
+            self.questions.len(),
+            self.answers.len(),
+            self.authorities.len(),
+            self.additionals.len(),
+        ] {
+            out.extend_from_slice(&(count as u16).to_be_bytes());
+        }
+
+        for question in &self.questions {
+            encode_name(&question.name, &mut out);
+            out.extend_from_slice(&question.qtype.to_be_bytes());
+            out.extend_from_slice(&question.qclass.to_be_bytes());
+        }
+        for record in self
+            .answers
+            .iter()
+            .chain(&self.authorities)
+            .chain(&self.additionals)
+        {
+            encode_record(record, &mut out);
+        }
+
+        out
+    }
+
+    pub fn decode(buf: &[u8]) -> Result<Self, DnsMessageError> {
+        if buf.len() < HEADER_LEN {
+            return Err(DnsMessageError::Truncated);
+        }
+
+        let read_u16 = |offset: usize| u16::from_be_bytes([buf[offset], buf[offset + 1]]);
+        let mut message = Message {
+            id: read_u16(0),
+            flags: read_u16(2),
+            ..Default::default()
+        };
+        let counts = [read_u16(4), read_u16(6), read_u16(8), read_u16(10)];
+
+        let mut offset = HEADER_LEN;
+        for _ in 0..counts[0] {
+            let (name, next) = decode_name(buf, offset)?;
+            let fixed = buf.get(next..next + 4).ok_or(DnsMessageError::Truncated)?;
+            message.questions.push(Question {
+                name,
+                qtype: u16::from_be_bytes([fixed[0], fixed[1]]),
+                qclass: u16::from_be_bytes([fixed[2], fixed[3]]),
+            });
+            offset = next + 4;
+        }
+
+        for (section, count) in counts[1..].iter().enumerate() {
+            for _ in 0..*count {
+                let (record, next) = decode_record(buf, offset)?;
+                match section {
+                    0 => message.answers.push(record),
+                    1 => message.authorities.push(record),
+                    _ => message.additionals.push(record),
+                }
+                offset = next;
+            }
+        }
+
+        Ok(message)
+    }
+}
+
+/// Returns the offset of the last resource record in `buf`, which is where a TSIG record
+/// starts in a signed message.
+pub fn last_record_offset(buf: &[u8]) -> Result<usize, DnsMessageError> {
+    if buf.len() < HEADER_LEN {
+        return Err(DnsMessageError::Truncated);
+    }
+    let read_u16 = |offset: usize| u16::from_be_bytes([buf[offset], buf[offset + 1]]) as usize;
+    let records = read_u16(6) + read_u16(8) + read_u16(10);
+    if records == 0 {
+        return Err(DnsMessageError::Truncated);
+    }
+
+    let mut offset = HEADER_LEN;
+    for _ in 0..read_u16(4) {
+        let (_, next) = decode_name(buf, offset)?;
+        offset = next + 4;
+    }
+    for _ in 0..records - 1 {
+        let (_, next) = decode_record(buf, offset)?;
+        offset = next;
+    }
+    Ok(offset)
+}
+
+pub fn encode_name(name: &str, out: &mut Vec<u8>) {
+    for label in name.trim_end_matches('.').split('.') {
+        if label.is_empty() {
+            continue;
+        }
+        out.push(label.len() as u8);
+        out.extend_from_slice(label.as_bytes());
+    }
+    out.push(0);
+}
+
+fn encode_record(record: &ResourceRecord, out: &mut Vec<u8>) {
+    encode_name(&record.name, out);
+    out.extend_from_slice(&record.rtype.to_be_bytes());
+    out.extend_from_slice(&record.class.to_be_bytes());
+    out.extend_from_slice(&record.ttl.to_be_bytes());
+    out.extend_from_slice(&(record.rdata.len() as u16).to_be_bytes());
+    out.extend_from_slice(&record.rdata);
+}
+
+fn decode_record(buf: &[u8], offset: usize) -> Result<(ResourceRecord, usize), DnsMessageError> {
+    let (name, next) = decode_name(buf, offset)?;
+    let fixed = buf.get(next..next + 10).ok_or(DnsMessageError::Truncated)?;
+    let rtype = u16::from_be_bytes([fixed[0], fixed[1]]);
+    let class = u16::from_be_bytes([fixed[2], fixed[3]]);
+    let ttl = u32::from_be_bytes([fixed[4], fixed[5], fixed[6], fixed[7]]);
+    let rdlength = u16::from_be_bytes([fixed[8], fixed[9]]) as usize;
+
+    let rdata_start = next + 10;
+    let rdata_end = rdata_start + rdlength;
+    let raw = buf
+        .get(rdata_start..rdata_end)
+        .ok_or(DnsMessageError::Truncated)?;
+
+    // Names inside RDATA may point back into the message, so they are expanded here to keep
+    // the record usable on its own.
+    let rdata = match rtype {
+        TYPE_NS | TYPE_CNAME => {
+            let (target, _) = decode_name(buf, rdata_start)?;
+            let mut rdata = Vec::new();
+            encode_name(&target, &mut rdata);
+            rdata
+        }
+        TYPE_SOA => {
+            let (mname, next) = decode_name(buf, rdata_start)?;
+            let (rname, next) = decode_name(buf, next)?;
+            let mut rdata = Vec::new();
+            encode_name(&mname, &mut rdata);
+            encode_name(&rname, &mut rdata);
+            rdata.extend_from_slice(buf.get(next..rdata_end).ok_or(DnsMessageError::Truncated)?);
+            rdata
+        }
+        _ => raw.to_vec(),
+    };
+
+    Ok((
+        ResourceRecord {
+            name,
+            rtype,
+            class,
+            ttl,
+            rdata,
+        },
+        rdata_end,
+    ))
+}
+
+pub fn decode_name(buf: &[u8], offset: usize) -> Result<(String, usize), DnsMessageError> {
+    let mut labels: Vec<String> = Vec::new();
+    let mut position = offset;
+    let mut end = None;
+    let mut jumps = 0;
+
+    loop {
+        let len = *buf.get(position).ok_or(DnsMessageError::Truncated)?;
+
+        if len & POINTER_MASK == POINTER_MASK {
+            let low = *buf.get(position + 1).ok_or(DnsMessageError::Truncated)?;
+            end.get_or_insert(position + 2);
+            position = (((len & !POINTER_MASK) as usize) << 8) | low as usize;
+            jumps += 1;
+            if jumps > 32 {
+                return Err(DnsMessageError::InvalidName);
+            }
+            continue;
+        }
+
+        if len == 0 {
+            end.get_or_insert(position + 1);
+            break;
+        }
+
+        let label = buf
+            .get(position + 1..position + 1 + len as usize)
+            .ok_or(DnsMessageError::Truncated)?;
+        labels.push(String::from_utf8_lossy(label).into_owned());
+        position += 1 + len as usize;
+    }
+
+    Ok((labels.join("."), end.unwrap()))
+}
+
+pub async fn exchange_udp(
+    server: &str,
+    request: &Message,
+    wait: Duration,
+) -> Result<Message, DnsMessageError> {
+    let (response, _) = exchange_udp_raw(server, request.id, &request.encode(), wait).await?;
+    Ok(response)
+}
+
+/// Like `exchange_udp`, but sends an already encoded request and also returns the response
+/// as received, which signed messages need to verify the signature.
+pub async fn exchange_udp_raw(
+    server: &str,
+    id: u16,
+    request: &[u8],
+    wait: Duration,
+) -> Result<(Message, Vec<u8>), DnsMessageError> {
+    let server: SocketAddr = lookup_host(server)
+        .await?
+        .next()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "name server not found"))?;
+    let bind = if server.is_ipv4() {
+        "0.0.0.0:0"
+    } else {
+        "[::]:0"
+    };
+
+    let socket = UdpSocket::bind(bind).await?;
+    socket.connect(server).await?;
+    socket.send(request).await?;
+
+    let mut buf = vec![0u8; MAX_UDP_MESSAGE];
+    loop {
+        let len = timeout(wait, socket.recv(&mut buf))
+            .await
+            .map_err(|_| DnsMessageError::Timeout)??;
+        let response = Message::decode(&buf[..len])?;
+
+        // Late answers to earlier attempts are dropped instead of being mistaken for ours.
+        if response.id == id && response.is_response() {
+            return Ok((response, buf[..len].to_vec()));
+        }
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum DnsMessageError {
+    #[error("message truncated")]
+    Truncated,
+    #[error("invalid name")]
+    InvalidName,
+    #[error("timed out waiting for a response")]
+    Timeout,
+    #[error("network error")]
+    IoError {
+        #[from]
+        source: io::Error,
+    },
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encode_decode_roundtrip() {
+        let mut response = Message::response_to(&Message::query(42, "test.example.com", TYPE_A));
+        response.answers.push(ResourceRecord::a(
+            "test.example.com",
+            60,
+            Ipv4Addr::new(93, 184, 216, 34),
+        ));
+
+        let decoded = Message::decode(&response.encode()).unwrap();
+        assert_eq!(decoded, response);
+        assert!(decoded.is_response());
+        assert_eq!(
+            decoded.answers[0].as_ipv4(),
+            Some(Ipv4Addr::new(93, 184, 216, 34))
+        );
+    }
+
+    #[test]
+    fn decode_compressed_names() {
+        let mut buf = Message {
+            id: 1,
+            flags: FLAG_RESPONSE,
+            questions: vec![Question {
+                name: String::from("example.com"),
+                qtype: TYPE_NS,
+                qclass: CLASS_IN,
+            }],
+            ..Default::default()
+        }
+        .encode();
+        buf[7] = 1;
+
+        // NS record with its owner pointing at the question name and a target that reuses the
+        // "example.com" suffix.
+        buf.extend_from_slice(&[0xc0, 0x0c]);
+        buf.extend_from_slice(&TYPE_NS.to_be_bytes());
+        buf.extend_from_slice(&CLASS_IN.to_be_bytes());
+        buf.extend_from_slice(&3600u32.to_be_bytes());
+        buf.extend_from_slice(&6u16.to_be_bytes());
+        buf.extend_from_slice(&[3, b'n', b's', b'1', 0xc0, 0x0c]);
+
+        let decoded = Message::decode(&buf).unwrap();
+        assert_eq!(decoded.answers[0].name, "example.com");
+        assert_eq!(
+            decoded.answers[0].as_name().as_deref(),
+            Some("ns1.example.com")
+        );
+    }
+
+    #[test]
+    fn decode_rejects_pointer_loops() {
+        let mut buf = Message::query(1, "example.com", TYPE_A).encode();
+        buf[12] = 0xc0;
+        buf[13] = 0x0c;
+        assert!(Message::decode(&buf).is_err());
+    }
+
+    #[test]
+    fn opcode_and_rcode() {
+        let mut message = Message::query(1, "example.com", TYPE_SOA);
+        message.set_opcode(OPCODE_UPDATE);
+        message.set_rcode(RCODE_NXDOMAIN);
+        assert_eq!(message.opcode(), OPCODE_UPDATE);
+        assert_eq!(message.rcode(), RCODE_NXDOMAIN);
+    }
+}
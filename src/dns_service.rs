@@ -1,7 +1,10 @@
 use async_trait::async_trait;
-use log::debug;
+use log::{debug, warn};
 use mockall_double::double;
+use std::collections::HashMap;
 use std::net::Ipv4Addr;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
 use thiserror::Error;
 
 #[cfg(test)]
@@ -10,11 +13,19 @@ use mockall::automock;
 #[double]
 use crate::hetzner_dns_client::HetznerDnsClient;
 
-use crate::hetzner_dns_client::HetznerDnsClientError;
+use crate::authoritative_dns::{fqdn, AuthoritativeDnsError, AuthoritativeResolver};
+use crate::hetzner_dns_client::{BulkRecordUpdate, HetznerDnsClientError, RecordRequest};
+use crate::record_template::{api_value, is_managed, managed_value};
+
+/// Record types the Hetzner DNS API accepts.
+const HETZNER_RECORD_TYPES: [&str; 14] = [
+    "A", "AAAA", "NS", "MX", "CNAME", "RP", "TXT", "SOA", "HINFO", "SRV", "DANE", "TLSA", "DS",
+    "CAA",
+];
 
 #[cfg_attr(test, automock)]
 #[async_trait]
-pub trait DnsService {
+pub trait DnsService: Send + Sync {
     async fn resolve_ip(
         &self,
         subdomain: &str,
@@ -26,6 +37,91 @@ pub trait DnsService {
         domain: &str,
         ip: Ipv4Addr,
     ) -> Result<(), DnsServiceError>;
+    async fn update_dns_with_ttl(
+        &self,
+        subdomain: &str,
+        domain: &str,
+        ip: Ipv4Addr,
+        ttl: Option<u32>,
+    ) -> Result<(), DnsServiceError> {
+        if ttl.is_some() {
+            debug!("This DNS service keeps the TTL of {}.", subdomain);
+        }
+        self.update_dns(subdomain, domain, ip).await
+    }
+    /// Returns the TTL the A record is published with, or `None` if it is not known.
+    async fn resolve_ttl(
+        &self,
+        subdomain: &str,
+        domain: &str,
+    ) -> Result<Option<u32>, DnsServiceError> {
+        debug!("Cannot resolve the TTL of {}.{}", subdomain, domain);
+        Ok(None)
+    }
+    /// Whether the records are only served to the local network, like the local DNS of a
+    /// router, where private addresses are expected.
+    fn is_local(&self) -> bool {
+        false
+    }
+    /// Whether records of `record_type` can be managed, checked when the configuration is
+    /// loaded.
+    fn supports_record_type(&self, record_type: &str) -> bool {
+        record_type == "A"
+    }
+    /// Returns the value starting with `prefix` among the values of the record set.
+    async fn resolve_record(
+        &self,
+        subdomain: &str,
+        domain: &str,
+        record_type: &str,
+        prefix: &str,
+    ) -> Result<Option<String>, DnsServiceError> {
+        debug!(
+            "Cannot resolve {} records of {}.{} starting with {}",
+            record_type, subdomain, domain, prefix
+        );
+        Err(DnsServiceError::Unsupported {
+            record_type: record_type.to_string(),
+        })
+    }
+    /// Replaces the value starting with `prefix` and keeps the other values of the record set.
+    async fn update_record(
+        &self,
+        subdomain: &str,
+        domain: &str,
+        record_type: &str,
+        _prefix: &str,
+        value: &str,
+    ) -> Result<(), DnsServiceError> {
+        debug!(
+            "Cannot update {} record of {}.{} to {}",
+            record_type, subdomain, domain, value
+        );
+        Err(DnsServiceError::Unsupported {
+            record_type: record_type.to_string(),
+        })
+    }
+    async fn list_zones(&self) -> Result<Vec<String>, DnsServiceError> {
+        Err(DnsServiceError::ZoneListingUnsupported)
+    }
+    async fn update_dns_batch(&self, updates: &[DnsUpdate]) -> Vec<Result<(), DnsServiceError>> {
+        let mut results = Vec::with_capacity(updates.len());
+        for update in updates {
+            results.push(
+                self.update_dns_with_ttl(&update.subdomain, &update.domain, update.ip, update.ttl)
+                    .await,
+            );
+        }
+        results
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DnsUpdate {
+    pub subdomain: String,
+    pub domain: String,
+    pub ip: Ipv4Addr,
+    pub ttl: Option<u32>,
 }
 
 #[derive(Debug, Error)]
@@ -36,6 +132,14 @@ pub enum DnsServiceError {
     UnknownRecord,
     #[error("client error")]
     ClientError,
+    #[error("update was rejected: {record}")]
+    UpdateRejected { record: String },
+    #[error("update was not served by the authoritative name servers: {record}")]
+    NotPropagated { record: String },
+    #[error("{record_type} records are not supported by this DNS service")]
+    Unsupported { record_type: String },
+    #[error("this DNS service cannot list zones")]
+    ZoneListingUnsupported,
     #[error("unknown error")]
     UnknownError,
 }
@@ -46,8 +150,30 @@ impl From<HetznerDnsClientError> for DnsServiceError {
     }
 }
 
+const DEFAULT_CACHE_MAX_AGE: Duration = Duration::from_secs(3600);
+
+#[derive(Default)]
+struct HetznerCache {
+    zone_ids: HashMap<String, String>,
+    records: HashMap<(String, String), CachedRecord>,
+}
+
+#[derive(Clone)]
+struct CachedRecord {
+    id: String,
+    value: String,
+    ttl: Option<u32>,
+    fetched_at: Instant,
+}
+
 pub struct HetznerDnsService {
     client: HetznerDnsClient,
+    verifier: Option<AuthoritativeResolver>,
+    verify_timeout: Duration,
+    live_resolver: Option<AuthoritativeResolver>,
+    cache: Mutex<HetznerCache>,
+    cache_values: bool,
+    cache_max_age: Duration,
 }
 
 impl HetznerDnsService {
@@ -57,7 +183,161 @@ impl HetznerDnsService {
     }
 
     pub fn from_client(client: HetznerDnsClient) -> Self {
-        Self { client }
+        Self {
+            client,
+            verifier: None,
+            verify_timeout: Duration::ZERO,
+            live_resolver: None,
+            cache: Mutex::new(HetznerCache::default()),
+            cache_values: false,
+            cache_max_age: DEFAULT_CACHE_MAX_AGE,
+        }
+    }
+
+    pub fn with_live_dns(mut self, resolver: AuthoritativeResolver) -> Self {
+        self.live_resolver = Some(resolver);
+        self
+    }
+
+    pub fn with_verification(mut self, verifier: AuthoritativeResolver, timeout: Duration) -> Self {
+        self.verifier = Some(verifier);
+        self.verify_timeout = timeout;
+        self
+    }
+
+    pub fn with_zone_id(self, domain: &str, zone_id: &str) -> Self {
+        self.cache
+            .lock()
+            .unwrap()
+            .zone_ids
+            .insert(domain.to_string(), zone_id.to_string());
+        self
+    }
+
+    pub fn with_cached_values(mut self, cache_values: bool) -> Self {
+        self.cache_values = cache_values;
+        self
+    }
+
+    /// Cached record values older than `cache_max_age` are read from the API again, so records
+    /// edited in the console are picked up.
+    pub fn with_cache_max_age(mut self, cache_max_age: Duration) -> Self {
+        self.cache_max_age = cache_max_age;
+        self
+    }
+
+    async fn zone_id(&self, domain: &str) -> Result<String, DnsServiceError> {
+        if let Some(zone_id) = self.cache.lock().unwrap().zone_ids.get(domain) {
+            return Ok(zone_id.clone());
+        }
+
+        let zone = self
+            .client
+            .find_zone(domain)
+            .await?
+            .ok_or(DnsServiceError::UnknownZone)?;
+        self.cache
+            .lock()
+            .unwrap()
+            .zone_ids
+            .insert(domain.to_string(), zone.id.clone());
+        Ok(zone.id)
+    }
+
+    async fn find_record(
+        &self,
+        zone_id: &str,
+        subdomain: &str,
+    ) -> Result<Option<CachedRecord>, DnsServiceError> {
+        let key = (zone_id.to_string(), subdomain.to_string());
+        let record = match self.client.find_record(zone_id, subdomain).await {
+            Ok(record) => record,
+            Err(HetznerDnsClientError::NotFound { .. }) => {
+                self.invalidate_zone(zone_id);
+                return Err(DnsServiceError::UnknownZone);
+            }
+            Err(e) => return Err(e.into()),
+        };
+
+        let mut cache = self.cache.lock().unwrap();
+        match record {
+            Some(record) => {
+                let cached = CachedRecord {
+                    id: record.id,
+                    value: record.value,
+                    ttl: record.ttl,
+                    fetched_at: Instant::now(),
+                };
+                cache.records.insert(key, cached.clone());
+                Ok(Some(cached))
+            }
+            None => {
+                cache.records.remove(&key);
+                Ok(None)
+            }
+        }
+    }
+
+    async fn record(
+        &self,
+        zone_id: &str,
+        subdomain: &str,
+    ) -> Result<CachedRecord, DnsServiceError> {
+        let key = (zone_id.to_string(), subdomain.to_string());
+        if let Some(record) = self.cache.lock().unwrap().records.get(&key) {
+            return Ok(record.clone());
+        }
+
+        self.find_record(zone_id, subdomain)
+            .await?
+            .ok_or(DnsServiceError::UnknownRecord)
+    }
+
+    fn invalidate_zone(&self, zone_id: &str) {
+        let mut cache = self.cache.lock().unwrap();
+        cache.zone_ids.retain(|_, id| id != zone_id);
+        cache.records.retain(|(id, _), _| id != zone_id);
+    }
+
+    fn invalidate_record(&self, zone_id: &str, subdomain: &str) {
+        self.cache
+            .lock()
+            .unwrap()
+            .records
+            .remove(&(zone_id.to_string(), subdomain.to_string()));
+    }
+
+    async fn verify(
+        &self,
+        zone_id: &str,
+        subdomain: &str,
+        domain: &str,
+        ip: Ipv4Addr,
+    ) -> Result<(), DnsServiceError> {
+        let verifier = match &self.verifier {
+            Some(verifier) => verifier,
+            None => return Ok(()),
+        };
+
+        let record = fqdn(subdomain, domain);
+        match verifier
+            .wait_for(domain, &record, ip, self.verify_timeout)
+            .await
+        {
+            Ok(_) => Ok(()),
+            Err(AuthoritativeDnsError::NotPropagated { .. }) => {
+                warn!(
+                    "{} is not served as {} after {}s, it may still propagate.",
+                    record,
+                    ip,
+                    self.verify_timeout.as_secs()
+                );
+                // The cached value is what we sent, not what is served; read it again next cycle.
+                self.invalidate_record(zone_id, subdomain);
+                Err(DnsServiceError::NotPropagated { record })
+            }
+            Err(_) => Err(DnsServiceError::UnknownError),
+        }
     }
 }
 
@@ -72,21 +352,59 @@ impl DnsService for HetznerDnsService {
             "Resolve ip for domain {} and subdomain {}.",
             domain, subdomain
         );
-        let zone = self.client.find_zone(domain).await?;
 
-        if let Some(zone) = zone {
-            let record = self.client.find_record(&zone.id, subdomain).await?;
+        if let Some(resolver) = &self.live_resolver {
+            let ips = resolver
+                .resolve_a(&fqdn(subdomain, domain))
+                .await
+                .map_err(|_| DnsServiceError::ClientError)?;
+            return Ok(ips.into_iter().next());
+        }
 
-            if let Some(record) = record {
-                let ip_str = record.value;
-                let ip: Ipv4Addr = ip_str.parse().map_err(|_| DnsServiceError::UnknownError)?;
-                return Ok(Some(ip));
-            }
+        let zone_id = self.zone_id(domain).await?;
+
+        let cached = if self.cache_values {
+            let key = (zone_id.clone(), subdomain.to_string());
+            self.cache
+                .lock()
+                .unwrap()
+                .records
+                .get(&key)
+                .filter(|record| record.fetched_at.elapsed() < self.cache_max_age)
+                .cloned()
+        } else {
+            None
+        };
+        let record = match cached {
+            Some(record) => Some(record),
+            None => self.find_record(&zone_id, subdomain).await?,
+        };
 
-            return Ok(None);
+        match record {
+            Some(record) => {
+                let ip: Ipv4Addr = record
+                    .value
+                    .parse()
+                    .map_err(|_| DnsServiceError::UnknownError)?;
+                Ok(Some(ip))
+            }
+            None => Ok(None),
         }
+    }
 
-        Err(DnsServiceError::UnknownZone)
+    async fn resolve_ttl(
+        &self,
+        subdomain: &str,
+        domain: &str,
+    ) -> Result<Option<u32>, DnsServiceError> {
+        let zone_id = self.zone_id(domain).await?;
+        let key = (zone_id.clone(), subdomain.to_string());
+        let cached = self.cache.lock().unwrap().records.get(&key).cloned();
+        let record = match cached {
+            Some(record) => Some(record),
+            None => self.find_record(&zone_id, subdomain).await?,
+        };
+        Ok(record.and_then(|record| record.ttl))
     }
 
     async fn update_dns(
@@ -94,31 +412,217 @@ impl DnsService for HetznerDnsService {
         subdomain: &str,
         domain: &str,
         ip: Ipv4Addr,
+    ) -> Result<(), DnsServiceError> {
+        self.update_dns_with_ttl(subdomain, domain, ip, None).await
+    }
+
+    async fn update_dns_with_ttl(
+        &self,
+        subdomain: &str,
+        domain: &str,
+        ip: Ipv4Addr,
+        ttl: Option<u32>,
     ) -> Result<(), DnsServiceError> {
         debug!(
             "Update dns for domain {} and subdomain {} with IP {}.",
             domain, subdomain, ip
         );
-        let zone = self.client.find_zone(domain).await?;
 
-        if let Some(zone) = zone {
-            let record = self.client.find_record(&zone.id, subdomain).await?;
+        // Cached ids can go stale when records are recreated in the console, so a 404 drops them
+        // and the update is retried once with fresh lookups.
+        let mut retried = false;
+        loop {
+            let zone_id = self.zone_id(domain).await?;
+            let record = self.record(&zone_id, subdomain).await?;
 
-            if let Some(record) = record {
-                println!(
-                    "Updating record {} in zone {} tp ip {}",
-                    record.id, zone.id, ip,
-                );
-                self.client
-                    .update_ip(subdomain, &zone.id, &record.id, ip)
-                    .await?;
-                return Ok(());
+            debug!(
+                "Updating record {} in zone {} to ip {}",
+                record.id, zone_id, ip
+            );
+            match self
+                .client
+                .update_ip(subdomain, &zone_id, &record.id, ip, ttl.or(record.ttl))
+                .await
+            {
+                Ok(updated) => {
+                    let key = (zone_id.clone(), subdomain.to_string());
+                    self.cache.lock().unwrap().records.insert(
+                        key,
+                        CachedRecord {
+                            id: updated.id,
+                            value: updated.value,
+                            ttl: updated.ttl,
+                            fetched_at: Instant::now(),
+                        },
+                    );
+                    return self.verify(&zone_id, subdomain, domain, ip).await;
+                }
+                Err(HetznerDnsClientError::NotFound { .. }) if !retried => {
+                    debug!("Record {} not found, refreshing cached ids.", record.id);
+                    self.invalidate_record(&zone_id, subdomain);
+                    self.invalidate_zone(&zone_id);
+                    retried = true;
+                }
+                Err(e) => return Err(e.into()),
             }
+        }
+    }
 
-            return Err(DnsServiceError::UnknownRecord);
+    fn supports_record_type(&self, record_type: &str) -> bool {
+        HETZNER_RECORD_TYPES.contains(&record_type)
+    }
+
+    async fn resolve_record(
+        &self,
+        subdomain: &str,
+        domain: &str,
+        record_type: &str,
+        prefix: &str,
+    ) -> Result<Option<String>, DnsServiceError> {
+        let zone_id = self.zone_id(domain).await?;
+        let records = self
+            .client
+            .find_records_of_type(&zone_id, subdomain, record_type)
+            .await?;
+        let values: Vec<String> = records.into_iter().map(|record| record.value).collect();
+        Ok(managed_value(record_type, &values, prefix))
+    }
+
+    async fn update_record(
+        &self,
+        subdomain: &str,
+        domain: &str,
+        record_type: &str,
+        prefix: &str,
+        value: &str,
+    ) -> Result<(), DnsServiceError> {
+        let zone_id = self.zone_id(domain).await?;
+        let request = RecordRequest {
+            name: subdomain.to_string(),
+            ttl: None,
+            r#type: record_type.to_string(),
+            value: api_value(record_type, value),
+            zone_id: zone_id.clone(),
+        };
+
+        debug!(
+            "Update {} record {} in zone {} to {}.",
+            record_type, subdomain, zone_id, value
+        );
+        // Each value is a record of its own, only the one managed by the template is changed.
+        let managed = self
+            .client
+            .find_records_of_type(&zone_id, subdomain, record_type)
+            .await?
+            .into_iter()
+            .find(|record| is_managed(record_type, &record.value, prefix));
+        match managed {
+            Some(record) => {
+                let request = RecordRequest {
+                    ttl: record.ttl,
+                    ..request
+                };
+                self.client.update_record(&record.id, &request).await?;
+            }
+            None => {
+                self.client.create_record(&request).await?;
+            }
         }
+        Ok(())
+    }
 
-        Err(DnsServiceError::UnknownZone)
+    async fn list_zones(&self) -> Result<Vec<String>, DnsServiceError> {
+        let zones = self.client.list_zones().await?;
+        let mut cache = self.cache.lock().unwrap();
+        Ok(zones
+            .into_iter()
+            .map(|zone| {
+                cache.zone_ids.insert(zone.name.clone(), zone.id);
+                zone.name
+            })
+            .collect())
+    }
+
+    async fn update_dns_batch(&self, updates: &[DnsUpdate]) -> Vec<Result<(), DnsServiceError>> {
+        debug!("Update dns for {} records in bulk.", updates.len());
+
+        let mut results: Vec<Option<Result<(), DnsServiceError>>> =
+            updates.iter().map(|_| None).collect();
+        let mut bulk = Vec::new();
+        let mut positions = HashMap::new();
+
+        for (position, update) in updates.iter().enumerate() {
+            let lookup = async {
+                let zone_id = self.zone_id(&update.domain).await?;
+                let record = self.record(&zone_id, &update.subdomain).await?;
+                Ok::<_, DnsServiceError>((zone_id, record))
+            };
+            match lookup.await {
+                Ok((_, record)) if positions.contains_key(&record.id) => {
+                    debug!("Record {} appears more than once in the batch.", record.id);
+                    results[position] = Some(Err(DnsServiceError::UpdateRejected {
+                        record: fqdn(&update.subdomain, &update.domain),
+                    }));
+                }
+                Ok((zone_id, record)) => {
+                    positions.insert(
+                        record.id.clone(),
+                        (position, zone_id.clone(), update.ttl.or(record.ttl)),
+                    );
+                    bulk.push(BulkRecordUpdate {
+                        id: record.id,
+                        name: update.subdomain.clone(),
+                        ttl: update.ttl.or(record.ttl),
+                        r#type: String::from("A"),
+                        value: update.ip.to_string(),
+                        zone_id,
+                    });
+                }
+                Err(e) => results[position] = Some(Err(e)),
+            }
+        }
+
+        if !bulk.is_empty() {
+            match self.client.bulk_update_records(&bulk).await {
+                Ok(response) => {
+                    for failed in response.failed_records {
+                        if let Some((position, zone_id, _)) = positions.remove(&failed.id) {
+                            self.invalidate_record(&zone_id, &failed.name);
+                            results[position] = Some(Err(DnsServiceError::UpdateRejected {
+                                record: fqdn(&failed.name, &updates[position].domain),
+                            }));
+                        }
+                    }
+                    for (id, (position, zone_id, ttl)) in positions {
+                        let update = &updates[position];
+                        self.cache.lock().unwrap().records.insert(
+                            (zone_id.clone(), update.subdomain.clone()),
+                            CachedRecord {
+                                id,
+                                value: update.ip.to_string(),
+                                ttl,
+                                fetched_at: Instant::now(),
+                            },
+                        );
+                        results[position] = Some(
+                            self.verify(&zone_id, &update.subdomain, &update.domain, update.ip)
+                                .await,
+                        );
+                    }
+                }
+                Err(e) => {
+                    debug!("Bulk update failed: {}", e);
+                    for (_, (position, _, _)) in positions {
+                        results[position] = Some(Err(DnsServiceError::ClientError));
+                    }
+                }
+            }
+        }
+
+        results
+            .into_iter()
+            .map(|r| r.unwrap_or(Err(DnsServiceError::UnknownError)))
+            .collect()
     }
 }
 
@@ -127,7 +631,7 @@ mod tests {
     use mockall::*;
 
     use super::*;
-    use crate::hetzner_dns_client::{Record, Zone};
+    use crate::hetzner_dns_client::{BulkUpdateRecordsResponse, Record, Zone};
 
     const ZONE: &str = "example.com";
     const SUBDOMAIN: &str = "example.com";
@@ -143,6 +647,7 @@ mod tests {
         let zone = Zone {
             name: String::from(ZONE),
             id: String::from(ZONE_ID),
+            ..Default::default()
         };
         let record = Record {
             id: String::from(RECORD_ID),
@@ -150,6 +655,7 @@ mod tests {
             name: String::from(SUBDOMAIN),
             r#type: String::from("A"),
             value: String::from(OLD_IP),
+            ..Default::default()
         };
 
         client
@@ -198,6 +704,7 @@ mod tests {
         let zone = Zone {
             name: String::from(ZONE),
             id: String::from(ZONE_ID),
+            ..Default::default()
         };
         client
             .expect_find_zone()
@@ -222,6 +729,7 @@ mod tests {
         let zone = Zone {
             name: String::from(ZONE),
             id: String::from(ZONE_ID),
+            ..Default::default()
         };
         let record = Record {
             id: String::from(RECORD_ID),
@@ -229,6 +737,7 @@ mod tests {
             name: String::from(SUBDOMAIN),
             r#type: String::from("A"),
             value: String::from(OLD_IP),
+            ..Default::default()
         };
         let new_record = Record {
             id: String::from(RECORD_ID),
@@ -236,6 +745,7 @@ mod tests {
             name: String::from(SUBDOMAIN),
             r#type: String::from("A"),
             value: String::from(NEW_IP),
+            ..Default::default()
         };
 
         client
@@ -256,9 +766,10 @@ mod tests {
                 predicate::eq(ZONE_ID),
                 predicate::eq(RECORD_ID),
                 predicate::eq(new_ip_addr),
+                predicate::eq(None),
             )
             .times(1)
-            .returning(move |_, _, _, _| Ok(new_record.clone()));
+            .returning(move |_, _, _, _, _| Ok(new_record.clone()));
 
         let svc = HetznerDnsService::from_client(client);
         let result = svc
@@ -266,4 +777,374 @@ mod tests {
             .await;
         assert!(result.is_ok());
     }
+
+    fn record(value: &str) -> Record {
+        Record {
+            id: String::from(RECORD_ID),
+            zone_id: String::from(ZONE_ID),
+            name: String::from(SUBDOMAIN),
+            r#type: String::from("A"),
+            value: String::from(value),
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn update_dns_keeps_existing_ttl() {
+        let mut client = HetznerDnsClient::default();
+        client.expect_find_record().times(1).returning(|_, _| {
+            Ok(Some(Record {
+                ttl: Some(600),
+                ..record(OLD_IP)
+            }))
+        });
+        client
+            .expect_update_ip()
+            .withf(|_, _, _, _, ttl| *ttl == Some(600))
+            .times(1)
+            .returning(|_, _, _, _, _| Ok(record(NEW_IP)));
+
+        let svc = HetznerDnsService::from_client(client).with_zone_id(ZONE, ZONE_ID);
+        svc.update_dns(SUBDOMAIN, ZONE, NEW_IP.parse().unwrap())
+            .await
+            .expect("update failed");
+    }
+
+    #[tokio::test]
+    async fn update_dns_with_ttl_overrides_existing_ttl() {
+        let mut client = HetznerDnsClient::default();
+        client.expect_find_record().times(1).returning(|_, _| {
+            Ok(Some(Record {
+                ttl: Some(600),
+                ..record(OLD_IP)
+            }))
+        });
+        client
+            .expect_update_ip()
+            .withf(|_, _, _, _, ttl| *ttl == Some(60))
+            .times(1)
+            .returning(|_, _, _, _, _| Ok(record(NEW_IP)));
+
+        let svc = HetznerDnsService::from_client(client).with_zone_id(ZONE, ZONE_ID);
+        svc.update_dns_with_ttl(SUBDOMAIN, ZONE, NEW_IP.parse().unwrap(), Some(60))
+            .await
+            .expect("update failed");
+    }
+
+    #[tokio::test]
+    async fn update_record_quotes_txt_values() {
+        let mut client = HetznerDnsClient::default();
+        client
+            .expect_find_records_of_type()
+            .with(
+                predicate::eq(ZONE_ID),
+                predicate::eq("mail"),
+                predicate::eq("TXT"),
+            )
+            .times(1)
+            .returning(|_, _, _| Ok(Vec::new()));
+        client
+            .expect_create_record()
+            .withf(|request| {
+                request.name == "mail"
+                    && request.r#type == "TXT"
+                    && request.value == "\"v=spf1 ip4:127.0.0.3 -all\""
+            })
+            .times(1)
+            .returning(|_| Ok(record(NEW_IP)));
+
+        let svc = HetznerDnsService::from_client(client).with_zone_id(ZONE, ZONE_ID);
+        svc.update_record(
+            "mail",
+            ZONE,
+            "TXT",
+            "v=spf1 ip4:",
+            "v=spf1 ip4:127.0.0.3 -all",
+        )
+        .await
+        .expect("update failed");
+    }
+
+    #[tokio::test]
+    async fn resolve_record_unquotes_txt_values() {
+        let mut client = HetznerDnsClient::default();
+        client.expect_find_records_of_type().returning(|_, _, _| {
+            Ok(vec![
+                Record {
+                    r#type: String::from("TXT"),
+                    ..record("\"google-site-verification=abc\"")
+                },
+                Record {
+                    r#type: String::from("TXT"),
+                    ..record("\"v=spf1 -all\"")
+                },
+            ])
+        });
+
+        let svc = HetznerDnsService::from_client(client).with_zone_id(ZONE, ZONE_ID);
+        let value = svc
+            .resolve_record("mail", ZONE, "TXT", "v=spf1 ")
+            .await
+            .expect("resolve failed");
+        assert_eq!(value.as_deref(), Some("v=spf1 -all"));
+    }
+
+    #[tokio::test]
+    async fn update_record_keeps_other_txt_records() {
+        let mut client = HetznerDnsClient::default();
+        client.expect_find_records_of_type().returning(|_, _, _| {
+            Ok(vec![
+                Record {
+                    id: String::from("verification"),
+                    r#type: String::from("TXT"),
+                    ..record("\"google-site-verification=abc\"")
+                },
+                Record {
+                    id: String::from("spf"),
+                    r#type: String::from("TXT"),
+                    ..record("\"v=spf1 ip4:127.0.0.2 -all\"")
+                },
+            ])
+        });
+        client
+            .expect_update_record()
+            .withf(|id, request| id == "spf" && request.value == "\"v=spf1 ip4:127.0.0.3 -all\"")
+            .times(1)
+            .returning(|_, _| Ok(record(NEW_IP)));
+        client.expect_create_record().never();
+
+        let svc = HetznerDnsService::from_client(client).with_zone_id(ZONE, ZONE_ID);
+        svc.update_record(
+            "mail",
+            ZONE,
+            "TXT",
+            "v=spf1 ip4:",
+            "v=spf1 ip4:127.0.0.3 -all",
+        )
+        .await
+        .expect("update failed");
+    }
+
+    #[test]
+    fn reject_record_types_the_api_does_not_serve() {
+        let svc = HetznerDnsService::from_client(HetznerDnsClient::default());
+        assert!(svc.supports_record_type("TXT"));
+        assert!(!svc.supports_record_type("HTTPS"));
+        assert!(!svc.supports_record_type("SVCB"));
+    }
+
+    #[tokio::test]
+    async fn resolve_then_update_reuses_cached_ids() {
+        let mut client = HetznerDnsClient::default();
+
+        let zone = Zone {
+            name: String::from(ZONE),
+            id: String::from(ZONE_ID),
+            ..Default::default()
+        };
+        client
+            .expect_find_zone()
+            .with(predicate::eq(ZONE))
+            .times(1)
+            .returning(move |_| Ok(Some(zone.clone())));
+        client
+            .expect_find_record()
+            .with(predicate::eq(ZONE_ID), predicate::eq(SUBDOMAIN))
+            .times(1)
+            .returning(|_, _| Ok(Some(record(OLD_IP))));
+        client
+            .expect_update_ip()
+            .times(1)
+            .returning(|_, _, _, _, _| Ok(record(NEW_IP)));
+
+        let svc = HetznerDnsService::from_client(client);
+        svc.resolve_ip(SUBDOMAIN, ZONE).await.unwrap();
+        svc.update_dns(SUBDOMAIN, ZONE, NEW_IP.parse().unwrap())
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn resolve_ip_uses_cached_value() {
+        let mut client = HetznerDnsClient::default();
+
+        client.expect_find_zone().never();
+        client
+            .expect_find_record()
+            .times(1)
+            .returning(|_, _| Ok(Some(record(OLD_IP))));
+
+        let svc = HetznerDnsService::from_client(client)
+            .with_zone_id(ZONE, ZONE_ID)
+            .with_cached_values(true);
+        for _ in 0..2 {
+            let ip = svc.resolve_ip(SUBDOMAIN, ZONE).await.unwrap();
+            assert_eq!(ip, Some(OLD_IP.parse().unwrap()));
+        }
+    }
+
+    #[tokio::test]
+    async fn resolve_ip_refreshes_cached_value_after_max_age() {
+        let mut client = HetznerDnsClient::default();
+        let mut seq = Sequence::new();
+
+        client
+            .expect_find_record()
+            .times(1)
+            .in_sequence(&mut seq)
+            .returning(|_, _| Ok(Some(record(OLD_IP))));
+        client
+            .expect_find_record()
+            .times(1)
+            .in_sequence(&mut seq)
+            .returning(|_, _| Ok(Some(record(NEW_IP))));
+
+        let svc = HetznerDnsService::from_client(client)
+            .with_zone_id(ZONE, ZONE_ID)
+            .with_cached_values(true)
+            .with_cache_max_age(Duration::ZERO);
+        let ip = svc.resolve_ip(SUBDOMAIN, ZONE).await.unwrap();
+        assert_eq!(ip, Some(OLD_IP.parse().unwrap()));
+        let ip = svc.resolve_ip(SUBDOMAIN, ZONE).await.unwrap();
+        assert_eq!(ip, Some(NEW_IP.parse().unwrap()));
+    }
+
+    #[tokio::test]
+    async fn update_dns_refreshes_ids_on_not_found() {
+        let mut client = HetznerDnsClient::default();
+        let mut seq = Sequence::new();
+
+        let zone = Zone {
+            name: String::from(ZONE),
+            id: String::from(ZONE_ID),
+            ..Default::default()
+        };
+        client
+            .expect_find_zone()
+            .times(1)
+            .returning(move |_| Ok(Some(zone.clone())));
+        client
+            .expect_find_record()
+            .times(2)
+            .returning(|_, _| Ok(Some(record(OLD_IP))));
+        client
+            .expect_update_ip()
+            .times(1)
+            .in_sequence(&mut seq)
+            .returning(|_, _, _, _, _| {
+                Err(HetznerDnsClientError::NotFound {
+                    status: 404,
+                    message: String::from("record not found"),
+                })
+            });
+        client
+            .expect_update_ip()
+            .times(1)
+            .in_sequence(&mut seq)
+            .returning(|_, _, _, _, _| Ok(record(NEW_IP)));
+
+        // The configured zone id is stale and gets replaced by the looked up one.
+        let svc = HetznerDnsService::from_client(client).with_zone_id(ZONE, ZONE_ID);
+        svc.update_dns(SUBDOMAIN, ZONE, NEW_IP.parse().unwrap())
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn update_dns_batch_reports_per_record() {
+        let mut client = HetznerDnsClient::default();
+
+        client.expect_find_zone().never();
+        client
+            .expect_find_record()
+            .with(predicate::eq(ZONE_ID), predicate::in_iter(["www", "mail"]))
+            .times(2)
+            .returning(|_, name| {
+                Ok(Some(Record {
+                    id: format!("rid-{}", name),
+                    name: name.to_string(),
+                    ..record(OLD_IP)
+                }))
+            });
+        client
+            .expect_find_record()
+            .with(predicate::eq(ZONE_ID), predicate::eq("missing"))
+            .times(1)
+            .returning(|_, _| Ok(None));
+        client
+            .expect_bulk_update_records()
+            .withf(|updates| {
+                updates.len() == 2 && updates.iter().all(|u| u.value == NEW_IP && u.r#type == "A")
+            })
+            .times(1)
+            .returning(|updates| {
+                Ok(BulkUpdateRecordsResponse {
+                    records: vec![],
+                    failed_records: vec![updates[1].clone()],
+                })
+            });
+        client.expect_update_ip().never();
+
+        let svc = HetznerDnsService::from_client(client).with_zone_id(ZONE, ZONE_ID);
+        let updates: Vec<DnsUpdate> = ["www", "mail", "missing"]
+            .iter()
+            .map(|subdomain| DnsUpdate {
+                subdomain: subdomain.to_string(),
+                domain: String::from(ZONE),
+                ip: NEW_IP.parse().unwrap(),
+                ttl: None,
+            })
+            .collect();
+
+        let results = svc.update_dns_batch(&updates).await;
+        assert!(results[0].is_ok());
+        assert!(matches!(
+            &results[1],
+            Err(DnsServiceError::UpdateRejected { record }) if record == "mail.example.com"
+        ));
+        assert!(matches!(results[2], Err(DnsServiceError::UnknownRecord)));
+    }
+
+    #[tokio::test]
+    async fn update_dns_batch_rejects_duplicate_records() {
+        let mut client = HetznerDnsClient::default();
+
+        client
+            .expect_find_record()
+            .with(predicate::eq(ZONE_ID), predicate::eq("www"))
+            .returning(|_, name| {
+                Ok(Some(Record {
+                    name: name.to_string(),
+                    ..record(OLD_IP)
+                }))
+            });
+        client
+            .expect_bulk_update_records()
+            .withf(|updates| updates.len() == 1 && updates[0].value == NEW_IP)
+            .times(1)
+            .returning(|_| {
+                Ok(BulkUpdateRecordsResponse {
+                    records: vec![],
+                    failed_records: vec![],
+                })
+            });
+
+        let svc = HetznerDnsService::from_client(client).with_zone_id(ZONE, ZONE_ID);
+        let updates: Vec<DnsUpdate> = [NEW_IP, OLD_IP]
+            .iter()
+            .map(|ip| DnsUpdate {
+                subdomain: String::from("www"),
+                domain: String::from(ZONE),
+                ip: ip.parse().unwrap(),
+                ttl: None,
+            })
+            .collect();
+
+        let results = svc.update_dns_batch(&updates).await;
+        assert!(results[0].is_ok());
+        assert!(matches!(
+            &results[1],
+            Err(DnsServiceError::UpdateRejected { record }) if record == "www.example.com"
+        ));
+    }
 }
@@ -0,0 +1,354 @@
+use async_trait::async_trait;
+use log::{debug, error, warn};
+use reqwest::{Client, StatusCode};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::net::Ipv4Addr;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use thiserror::Error;
+
+use crate::authoritative_dns::{fqdn, AuthoritativeResolver};
+use crate::dns_service::{DnsService, DnsServiceError};
+use crate::local_file::write_atomically;
+
+const USER_AGENT: &str = concat!("dyndnsd/", env!("CARGO_PKG_VERSION"));
+/// The protocol asks clients to wait at least 30 minutes after `911` or `dnserr`.
+const RETRY_INTERVAL: Duration = Duration::from_secs(30 * 60);
+
+#[derive(Default)]
+struct Dyndns2State {
+    /// Set after a reply that must not be retried without changing the configuration.
+    blocked: Option<String>,
+    retry_at: Option<Instant>,
+    rejected_hosts: HashSet<String>,
+    addresses: HashMap<String, Ipv4Addr>,
+}
+
+/// The part of the state that survives a restart. It only applies while the server and
+/// credentials it was written for are configured.
+#[derive(Default, Deserialize, Serialize)]
+struct PersistedState {
+    configuration: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    blocked: Option<String>,
+    #[serde(default)]
+    rejected_hosts: Vec<String>,
+}
+
+/// Client for the dyndns2 `/nic/update` protocol spoken by No-IP, Dynu, OVH DynHost and
+/// others. The protocol cannot read records, so the current address is the one last
+/// confirmed by the server unless a resolver is configured.
+pub struct Dyndns2Client {
+    server_url: String,
+    username: String,
+    password: String,
+    live_resolver: Option<AuthoritativeResolver>,
+    retry_interval: Duration,
+    state_file: Option<PathBuf>,
+    state: Mutex<Dyndns2State>,
+    client: Client,
+}
+
+impl Dyndns2Client {
+    /// `server_url` is the address of the update server without the `/nic/update` path, for
+    /// example `https://dynupdate.no-ip.com`.
+    pub fn new(server_url: &str, username: &str, password: &str) -> Self {
+        let client = Client::builder()
+            .user_agent(USER_AGENT)
+            .build()
+            .expect("failed to build HTTP client");
+        Self {
+            server_url: String::from(server_url.trim_end_matches('/')),
+            username: String::from(username),
+            password: String::from(password),
+            live_resolver: None,
+            retry_interval: RETRY_INTERVAL,
+            state_file: None,
+            state: Mutex::new(Dyndns2State::default()),
+            client,
+        }
+    }
+
+    pub fn with_live_dns(mut self, resolver: AuthoritativeResolver) -> Self {
+        self.live_resolver = Some(resolver);
+        self
+    }
+
+    pub fn with_retry_interval(mut self, retry_interval: Duration) -> Self {
+        self.retry_interval = retry_interval;
+        self
+    }
+
+    /// Keeps the stop after `badauth`, `abuse` and rejected host names in `path`, so a restart
+    /// does not contact the server again until the server or the credentials are changed.
+    pub fn with_state_file(mut self, path: impl AsRef<Path>) -> Self {
+        let path = path.as_ref().to_path_buf();
+        match fs::read_to_string(&path) {
+            Ok(contents) => match serde_json::from_str::<PersistedState>(&contents) {
+                Ok(persisted) if persisted.configuration == self.configuration() => {
+                    let mut state = self.state.lock().unwrap();
+                    if let Some(reason) = &persisted.blocked {
+                        warn!(
+                            "dyndns2 updates are stopped since an earlier run: {}",
+                            reason
+                        );
+                    }
+                    state.blocked = persisted.blocked;
+                    state.rejected_hosts = persisted.rejected_hosts.into_iter().collect();
+                }
+                Ok(_) => debug!("Ignoring dyndns2 state of another configuration."),
+                Err(e) => warn!("Ignoring invalid dyndns2 state in {:?}: {}", path, e),
+            },
+            Err(e) => debug!("No dyndns2 state in {:?}: {}", path, e),
+        }
+        self.state_file = Some(path);
+        self
+    }
+
+    /// Sends `/nic/update` for `hostname`, unless an earlier reply forbids contacting the
+    /// server again.
+    pub async fn update(&self, hostname: &str, ip: Ipv4Addr) -> Result<(), Dyndns2Error> {
+        self.check_allowed(hostname)?;
+
+        let response = self
+            .client
+            .get(format!("{}/nic/update", self.server_url))
+            .basic_auth(&self.username, Some(&self.password))
+            .query(&[("hostname", hostname), ("myip", &ip.to_string())])
+            .send()
+            .await?;
+        let status = response.status();
+        let body = response.text().await.unwrap_or_default();
+
+        let result = if status == StatusCode::UNAUTHORIZED {
+            Err(Dyndns2Error::BadAuth)
+        } else if status.is_server_error() {
+            Err(Dyndns2Error::ServerError {
+                code: status.as_u16().to_string(),
+            })
+        } else {
+            parse_reply(&body)
+        };
+        if let Err(e) = &result {
+            error!("dyndns2 server returned {}: {}", status, body.trim());
+            self.record_failure(hostname, e);
+        } else {
+            debug!("dyndns2 server confirmed {} for {}.", ip, hostname);
+            self.state
+                .lock()
+                .unwrap()
+                .addresses
+                .insert(hostname.to_string(), ip);
+        }
+        result
+    }
+
+    fn check_allowed(&self, hostname: &str) -> Result<(), Dyndns2Error> {
+        let mut state = self.state.lock().unwrap();
+        if let Some(reason) = &state.blocked {
+            return Err(Dyndns2Error::Blocked {
+                reason: reason.clone(),
+            });
+        }
+        if state.rejected_hosts.contains(hostname) {
+            return Err(Dyndns2Error::Blocked {
+                reason: format!("{} was rejected", hostname),
+            });
+        }
+        if let Some(retry_at) = state.retry_at {
+            let now = Instant::now();
+            if now < retry_at {
+                return Err(Dyndns2Error::BackingOff {
+                    seconds: (retry_at - now).as_secs(),
+                });
+            }
+            state.retry_at = None;
+        }
+        Ok(())
+    }
+
+    fn record_failure(&self, hostname: &str, e: &Dyndns2Error) {
+        let mut state = self.state.lock().unwrap();
+        match e {
+            Dyndns2Error::BadAuth
+            | Dyndns2Error::Abuse
+            | Dyndns2Error::BadAgent
+            | Dyndns2Error::NotDonator => {
+                warn!(
+                    "Stopping dyndns2 updates until the configuration is changed: {}",
+                    e
+                );
+                state.blocked = Some(e.to_string());
+            }
+            Dyndns2Error::Rejected { .. } => {
+                warn!("Stopping dyndns2 updates of {}: {}", hostname, e);
+                state.rejected_hosts.insert(hostname.to_string());
+            }
+            Dyndns2Error::ServerError { .. } => {
+                warn!(
+                    "Not contacting the dyndns2 server for {} seconds.",
+                    self.retry_interval.as_secs()
+                );
+                state.retry_at = Some(Instant::now() + self.retry_interval);
+            }
+            _ => return,
+        }
+        self.save_state(&state);
+    }
+
+    fn save_state(&self, state: &Dyndns2State) {
+        let Some(path) = &self.state_file else {
+            return;
+        };
+        let mut rejected_hosts: Vec<String> = state.rejected_hosts.iter().cloned().collect();
+        rejected_hosts.sort();
+        let persisted = PersistedState {
+            configuration: self.configuration(),
+            blocked: state.blocked.clone(),
+            rejected_hosts,
+        };
+        let result = serde_json::to_string(&persisted)
+            .map_err(std::io::Error::from)
+            .and_then(|contents| write_atomically(path, &contents));
+        if let Err(e) = result {
+            warn!("Could not write dyndns2 state to {:?}: {}", path, e);
+        }
+    }
+
+    /// Identifies the server and credentials without storing the password.
+    fn configuration(&self) -> String {
+        let digest = Sha256::new()
+            .chain_update(self.server_url.as_bytes())
+            .chain_update([0])
+            .chain_update(self.username.as_bytes())
+            .chain_update([0])
+            .chain_update(self.password.as_bytes())
+            .finalize();
+        digest.iter().map(|b| format!("{:02x}", b)).collect()
+    }
+}
+
+fn parse_reply(body: &str) -> Result<(), Dyndns2Error> {
+    let code = body.split_whitespace().next().unwrap_or_default();
+    match code {
+        "good" | "nochg" => Ok(()),
+        "badauth" => Err(Dyndns2Error::BadAuth),
+        "abuse" => Err(Dyndns2Error::Abuse),
+        "badagent" => Err(Dyndns2Error::BadAgent),
+        "!donator" => Err(Dyndns2Error::NotDonator),
+        "notfqdn" | "nohost" | "numhost" | "!yours" => Err(Dyndns2Error::Rejected {
+            code: code.to_string(),
+        }),
+        "911" | "dnserr" => Err(Dyndns2Error::ServerError {
+            code: code.to_string(),
+        }),
+        _ => Err(Dyndns2Error::UnexpectedReply {
+            reply: body.trim().to_string(),
+        }),
+    }
+}
+
+#[async_trait]
+impl DnsService for Dyndns2Client {
+    async fn resolve_ip(
+        &self,
+        subdomain: &str,
+        domain: &str,
+    ) -> Result<Option<Ipv4Addr>, DnsServiceError> {
+        debug!(
+            "Resolve ip for domain {} and subdomain {}.",
+            domain, subdomain
+        );
+        let hostname = fqdn(subdomain, domain);
+
+        if let Some(resolver) = &self.live_resolver {
+            let ips = resolver
+                .resolve_a(&hostname)
+                .await
+                .map_err(|_| DnsServiceError::ClientError)?;
+            return Ok(ips.into_iter().next());
+        }
+        Ok(self.state.lock().unwrap().addresses.get(&hostname).copied())
+    }
+
+    async fn update_dns(
+        &self,
+        subdomain: &str,
+        domain: &str,
+        ip: Ipv4Addr,
+    ) -> Result<(), DnsServiceError> {
+        debug!(
+            "Update dns for domain {} and subdomain {} with IP {}.",
+            domain, subdomain, ip
+        );
+        self.update(&fqdn(subdomain, domain), ip).await?;
+        Ok(())
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum Dyndns2Error {
+    #[error("The username or password is wrong (badauth).")]
+    BadAuth,
+    #[error("The account is blocked for abuse (abuse).")]
+    Abuse,
+    #[error("The user agent is blocked (badagent).")]
+    BadAgent,
+    #[error("The feature needs a paid account (!donator).")]
+    NotDonator,
+    #[error("The host name was rejected ({code}).")]
+    Rejected { code: String },
+    #[error("The server has a problem ({code}).")]
+    ServerError { code: String },
+    #[error("Updates are stopped: {reason}")]
+    Blocked { reason: String },
+    #[error("Backing off for another {seconds} seconds after a server error.")]
+    BackingOff { seconds: u64 },
+    #[error("unexpected reply: {reply}")]
+    UnexpectedReply { reply: String },
+    #[error("request failed")]
+    RequestFailed {
+        #[from]
+        source: reqwest::Error,
+    },
+}
+
+impl From<Dyndns2Error> for DnsServiceError {
+    fn from(e: Dyndns2Error) -> Self {
+        match e {
+            Dyndns2Error::Rejected { .. } => DnsServiceError::UpdateRejected {
+                record: e.to_string(),
+            },
+            _ => DnsServiceError::ClientError,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_reply() {
+        assert!(parse_reply("good 93.184.216.34").is_ok());
+        assert!(parse_reply("nochg 93.184.216.34\n").is_ok());
+        assert!(matches!(parse_reply("badauth"), Err(Dyndns2Error::BadAuth)));
+        assert!(matches!(parse_reply("abuse"), Err(Dyndns2Error::Abuse)));
+        assert!(matches!(
+            parse_reply("nohost"),
+            Err(Dyndns2Error::Rejected { .. })
+        ));
+        assert!(matches!(
+            parse_reply("911"),
+            Err(Dyndns2Error::ServerError { .. })
+        ));
+        assert!(matches!(
+            parse_reply("<html>"),
+            Err(Dyndns2Error::UnexpectedReply { .. })
+        ));
+    }
+}
@@ -1,15 +1,119 @@
+use log::{error, info, warn};
+use std::net::Ipv4Addr;
+use std::sync::Mutex;
+use std::time::Instant;
 use thiserror::Error;
 
 use crate::{
-    dns_service::{DnsService, DnsServiceError},
+    address_policy::{is_public_ipv4, AddressPolicy},
+    authoritative_dns::fqdn,
+    dampening::{Dampener, DampeningDecision, DampeningPolicy},
+    dns_service::{DnsService, DnsServiceError, DnsUpdate},
     public_ip_service::{PublicIpService, PublicIpServiceError},
+    record_template::RecordTemplate,
+    status::Status,
+    ttl::{TtlManager, TtlPolicy},
+    zone_detection::ZoneDetectionError,
 };
 
 pub struct DynDnsService {
     domain: String,
-    subdomain: String,
+    subdomains: Vec<String>,
+    derived_records: Vec<RecordTemplate>,
     dns_service: Box<dyn DnsService>,
     public_ip_service: Box<dyn PublicIpService>,
+    address_policy: AddressPolicy,
+    observed_ip_service: Option<Box<dyn PublicIpService>>,
+    dampener: Mutex<Dampener>,
+    ttl_manager: Mutex<TtlManager>,
+    dry_run: bool,
+    status: Status,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PlannedChange {
+    pub subdomain: String,
+    pub domain: String,
+    pub record_type: String,
+    pub old: Option<Ipv4Addr>,
+    pub new: Ipv4Addr,
+    pub ttl: Option<u32>,
+}
+
+impl PlannedChange {
+    pub fn name(&self) -> String {
+        fqdn(&self.subdomain, &self.domain)
+    }
+
+    pub fn is_change(&self) -> bool {
+        self.address_changed() || self.ttl.is_some()
+    }
+
+    pub fn address_changed(&self) -> bool {
+        self.old != Some(self.new)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DerivedChange {
+    pub subdomain: String,
+    pub domain: String,
+    pub record_type: String,
+    pub prefix: String,
+    pub old: Option<String>,
+    pub new: String,
+}
+
+impl DerivedChange {
+    pub fn name(&self) -> String {
+        fqdn(&self.subdomain, &self.domain)
+    }
+
+    pub fn is_change(&self) -> bool {
+        self.old.as_deref() != Some(self.new.as_str())
+    }
+}
+
+pub fn render_plan(plan: &[PlannedChange], derived: &[DerivedChange]) -> String {
+    let changes: Vec<&PlannedChange> = plan.iter().filter(|c| c.is_change()).collect();
+    let derived: Vec<&DerivedChange> = derived.iter().filter(|c| c.is_change()).collect();
+    if changes.is_empty() && derived.is_empty() {
+        return String::from("No changes.\n");
+    }
+
+    let mut rendered = format!(
+        "{:<40} {:<6} {:<40} {:<40} {}\n",
+        "NAME", "TYPE", "OLD", "NEW", "TTL"
+    );
+    for change in changes {
+        let old = change
+            .old
+            .map(|ip| ip.to_string())
+            .unwrap_or_else(|| String::from("(none)"));
+        let ttl = change
+            .ttl
+            .map(|ttl| ttl.to_string())
+            .unwrap_or_else(|| String::from("(keep)"));
+        rendered.push_str(&format!(
+            "{:<40} {:<6} {:<40} {:<40} {}\n",
+            change.name(),
+            change.record_type,
+            old,
+            change.new.to_string(),
+            ttl
+        ));
+    }
+    for change in derived {
+        rendered.push_str(&format!(
+            "{:<40} {:<6} {:<40} {:<40} {}\n",
+            change.name(),
+            change.record_type,
+            change.old.as_deref().unwrap_or("(none)"),
+            change.new,
+            "(keep)"
+        ));
+    }
+    rendered
 }
 
 impl DynDnsService {
@@ -21,48 +125,409 @@ impl DynDnsService {
     ) -> Self {
         Self {
             domain: String::from(domain),
-            subdomain: String::from(subdomain),
+            subdomains: vec![String::from(subdomain)],
+            derived_records: Vec::new(),
             dns_service,
             public_ip_service,
+            address_policy: AddressPolicy::default(),
+            observed_ip_service: None,
+            dampener: Mutex::new(Dampener::new(DampeningPolicy::default())),
+            ttl_manager: Mutex::new(TtlManager::new(TtlPolicy::default())),
+            dry_run: false,
+            status: Status::new(),
         }
     }
 
-    pub async fn update_dns_if_required(&self) -> Result<(), DynDnsServiceError> {
-        let current_dns_ip = self
-            .dns_service
-            .resolve_ip(&self.subdomain, &self.domain)
-            .await?;
+    pub fn with_subdomains(mut self, subdomains: &[&str]) -> Self {
+        self.subdomains = subdomains.iter().map(|s| s.to_string()).collect();
+        self
+    }
+
+    pub fn with_derived_records(mut self, derived_records: Vec<RecordTemplate>) -> Self {
+        self.derived_records = derived_records;
+        self
+    }
+
+    pub fn with_address_policy(mut self, address_policy: AddressPolicy) -> Self {
+        self.address_policy = address_policy;
+        self
+    }
+
+    pub fn with_observed_ip_service(
+        mut self,
+        observed_ip_service: Box<dyn PublicIpService>,
+    ) -> Self {
+        self.observed_ip_service = Some(observed_ip_service);
+        self
+    }
+
+    pub fn with_dampening_policy(mut self, dampening_policy: DampeningPolicy) -> Self {
+        self.dampener = Mutex::new(Dampener::new(dampening_policy));
+        self
+    }
+
+    pub fn with_ttl_manager(mut self, ttl_manager: TtlManager) -> Self {
+        self.ttl_manager = Mutex::new(ttl_manager);
+        self
+    }
+
+    pub fn with_dry_run(mut self, dry_run: bool) -> Self {
+        self.dry_run = dry_run;
+        self
+    }
+
+    pub fn with_status(mut self, status: Status) -> Self {
+        self.status = status;
+        self
+    }
+
+    pub fn status(&self) -> &Status {
+        &self.status
+    }
+
+    pub async fn plan(&self) -> Result<Vec<PlannedChange>, DynDnsServiceError> {
+        let mut current_dns_ips = Vec::with_capacity(self.subdomains.len());
+        for subdomain in &self.subdomains {
+            current_dns_ips.push(self.dns_service.resolve_ip(subdomain, &self.domain).await?);
+        }
+        let unseeded: Vec<&String> = {
+            let ttl_manager = self.ttl_manager.lock().unwrap();
+            self.subdomains
+                .iter()
+                .filter(|subdomain| ttl_manager.needs_current_ttl(subdomain))
+                .collect()
+        };
+        for subdomain in unseeded {
+            let ttl = self
+                .dns_service
+                .resolve_ttl(subdomain, &self.domain)
+                .await?;
+            self.ttl_manager
+                .lock()
+                .unwrap()
+                .seed_ttl(subdomain, ttl, Instant::now());
+        }
         let current_local_ip = self.public_ip_service.get_ip().await?;
+        self.check_nat(current_local_ip).await;
 
-        if current_dns_ip.is_none() || current_dns_ip.unwrap() != current_local_ip {
+        if !self.address_policy.permits_ipv4(current_local_ip) && !self.dns_service.is_local() {
+            warn!(
+                "Refusing to publish non-public address {} for {}.",
+                current_local_ip,
+                self.record_names()
+            );
+            return Err(DynDnsServiceError::NonPublicIp {
+                ip: current_local_ip,
+            });
+        }
+
+        let now = Instant::now();
+        let mut ttl_manager = self.ttl_manager.lock().unwrap();
+        Ok(self
+            .subdomains
+            .iter()
+            .zip(current_dns_ips)
+            .map(|(subdomain, current_dns_ip)| {
+                let address_changed = current_dns_ip != Some(current_local_ip);
+                PlannedChange {
+                    subdomain: subdomain.clone(),
+                    domain: self.domain.clone(),
+                    record_type: String::from("A"),
+                    old: current_dns_ip,
+                    new: current_local_ip,
+                    ttl: ttl_manager.planned_ttl(subdomain, address_changed, now),
+                }
+            })
+            .collect())
+    }
+
+    /// Fails if a derived record has a type the DNS service cannot manage.
+    pub fn check_derived_records(&self) -> Result<(), DynDnsServiceError> {
+        match self
+            .derived_records
+            .iter()
+            .find(|template| !self.dns_service.supports_record_type(&template.record_type))
+        {
+            Some(template) => Err(DnsServiceError::Unsupported {
+                record_type: template.record_type.clone(),
+            }
+            .into()),
+            None => Ok(()),
+        }
+    }
+
+    pub async fn plan_derived(
+        &self,
+        ip: Ipv4Addr,
+    ) -> Result<Vec<DerivedChange>, DynDnsServiceError> {
+        let mut changes = Vec::with_capacity(self.derived_records.len());
+        for template in &self.derived_records {
+            let old = self
+                .dns_service
+                .resolve_record(
+                    &template.subdomain,
+                    &self.domain,
+                    &template.record_type,
+                    template.prefix(),
+                )
+                .await?;
+            changes.push(DerivedChange {
+                subdomain: template.subdomain.clone(),
+                domain: self.domain.clone(),
+                record_type: template.record_type.clone(),
+                prefix: template.prefix().to_string(),
+                old,
+                new: template.render(&self.domain, ip),
+            });
+        }
+        Ok(changes)
+    }
+
+    pub async fn plan_with_derived(
+        &self,
+    ) -> Result<(Vec<PlannedChange>, Vec<DerivedChange>), DynDnsServiceError> {
+        let plan = self.plan().await?;
+        let derived = match plan.first() {
+            Some(change) => self.plan_derived(change.new).await?,
+            None => Vec::new(),
+        };
+        Ok((plan, derived))
+    }
+
+    pub async fn update_dns_if_required(&self) -> Result<(), DynDnsServiceError> {
+        let (plan, derived) = self.plan_with_derived().await?;
+
+        if self.dry_run {
+            print!("{}", render_plan(&plan, &derived));
+            return Ok(());
+        }
+
+        let mut confirmed = Vec::new();
+        let mut held_back = false;
+        for change in &plan {
+            if self.confirm(change) {
+                confirmed.push(change);
+            } else if change.address_changed() {
+                held_back = true;
+            }
+        }
+
+        self.apply(&confirmed).await?;
+
+        // Derived records follow the published address, so they wait while it is held back.
+        if held_back {
+            return Ok(());
+        }
+        for change in derived.iter().filter(|change| change.is_change()) {
             self.dns_service
-                .update_dns(&self.subdomain, &self.domain, current_local_ip)
+                .update_record(
+                    &change.subdomain,
+                    &change.domain,
+                    &change.record_type,
+                    &change.prefix,
+                    &change.new,
+                )
                 .await?;
         }
         Ok(())
     }
+
+    async fn apply(&self, confirmed: &[&PlannedChange]) -> Result<(), DynDnsServiceError> {
+        match confirmed {
+            [] => Ok(()),
+            [change] => {
+                match change.ttl {
+                    Some(ttl) => {
+                        self.dns_service
+                            .update_dns_with_ttl(
+                                &change.subdomain,
+                                &change.domain,
+                                change.new,
+                                Some(ttl),
+                            )
+                            .await?
+                    }
+                    None => {
+                        self.dns_service
+                            .update_dns(&change.subdomain, &change.domain, change.new)
+                            .await?
+                    }
+                }
+                self.record_update(change);
+                Ok(())
+            }
+            changes => self.apply_batch(changes).await,
+        }
+    }
+
+    async fn apply_batch(&self, changes: &[&PlannedChange]) -> Result<(), DynDnsServiceError> {
+        let updates: Vec<DnsUpdate> = changes
+            .iter()
+            .map(|change| DnsUpdate {
+                subdomain: change.subdomain.clone(),
+                domain: change.domain.clone(),
+                ip: change.new,
+                ttl: change.ttl,
+            })
+            .collect();
+
+        let mut first_error = None;
+        let results = self.dns_service.update_dns_batch(&updates).await;
+        for (change, result) in changes.iter().zip(results) {
+            match result {
+                Ok(()) => self.record_update(change),
+                Err(e) => {
+                    error!("Failed to update {}: {}", change.name(), e);
+                    first_error.get_or_insert(e);
+                }
+            }
+        }
+
+        match first_error {
+            Some(e) => Err(e.into()),
+            None => Ok(()),
+        }
+    }
+
+    fn confirm(&self, change: &PlannedChange) -> bool {
+        let record = change.name();
+
+        if !change.is_change() {
+            self.dampener.lock().unwrap().reset(&record);
+            return false;
+        }
+        // Only the TTL changes, there is no new address to dampen.
+        if !change.address_changed() {
+            return true;
+        }
+
+        let decision = self
+            .dampener
+            .lock()
+            .unwrap()
+            .observe(&record, change.new, Instant::now());
+
+        match decision {
+            DampeningDecision::Update => return true,
+            DampeningDecision::Unconfirmed {
+                observations,
+                stable_for,
+            } => {
+                info!(
+                    "Holding back update of {} to {}: seen {} time(s) over {}s, waiting for the address to settle.",
+                    record,
+                    change.new,
+                    observations,
+                    stable_for.as_secs()
+                );
+            }
+            DampeningDecision::RateLimited { updates_last_hour } => {
+                warn!(
+                    "Holding back update of {} to {}: already updated {} time(s) in the last hour.",
+                    record, change.new, updates_last_hour
+                );
+            }
+        }
+        self.status.update(|s| s.updates_held_back_total += 1);
+        false
+    }
+
+    fn record_update(&self, change: &PlannedChange) {
+        let now = Instant::now();
+        if change.address_changed() {
+            self.dampener
+                .lock()
+                .unwrap()
+                .record_update(&change.name(), now);
+        }
+        self.ttl_manager.lock().unwrap().record_update(
+            &change.subdomain,
+            change.ttl,
+            change.address_changed(),
+            now,
+        );
+    }
+
+    fn record_names(&self) -> String {
+        self.subdomains
+            .iter()
+            .map(|subdomain| fqdn(subdomain, &self.domain))
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+
+    async fn check_nat(&self, router_ip: Ipv4Addr) {
+        self.status.update(|s| s.router_ip = Some(router_ip));
+
+        let observed_ip_service = match &self.observed_ip_service {
+            Some(service) => service,
+            None => return,
+        };
+
+        let observed_ip = match observed_ip_service.get_ip().await {
+            Ok(ip) => ip,
+            Err(e) => {
+                warn!("Could not observe the external address: {}", e);
+                self.status.update(|s| {
+                    s.observed_ip = None;
+                    s.behind_nat = None;
+                });
+                return;
+            }
+        };
+
+        let behind_nat = observed_ip != router_ip;
+        self.status.update(|s| {
+            s.observed_ip = Some(observed_ip);
+            s.behind_nat = Some(behind_nat);
+            if behind_nat {
+                s.nat_mismatches_total += 1;
+            }
+        });
+
+        if behind_nat {
+            let diagnosis = if is_public_ipv4(router_ip) {
+                "the router address is public, so the upstream network translates it again"
+            } else {
+                "the router address is private or carrier-grade NAT space"
+            };
+            warn!(
+                "You are behind another NAT: the router reports WAN address {} but the address observed from outside is {} ({}). Records for {} will not be reachable from the internet.",
+                router_ip,
+                observed_ip,
+                diagnosis,
+                self.record_names()
+            );
+        }
+    }
 }
 
 #[derive(Debug, Error)]
 pub enum DynDnsServiceError {
     #[error("unknown error")]
     UnknownError,
-    #[error("public ip service error")]
+    #[error("refusing to publish non-public address {ip}")]
+    NonPublicIp { ip: Ipv4Addr },
+    #[error("public ip service error: {source}")]
     PublicIpServiceError {
         #[from]
         source: PublicIpServiceError,
     },
-    #[error("dns service error")]
+    #[error("dns service error: {source}")]
     DnsServiceError {
         #[from]
         source: DnsServiceError,
     },
+    #[error("zone detection failed")]
+    ZoneDetectionError {
+        #[from]
+        source: ZoneDetectionError,
+    },
 }
 
 #[cfg(test)]
 mod tests {
     use mockall::*;
-    use std::net::Ipv4Addr;
 
     use super::*;
     use crate::dns_service::*;
@@ -70,7 +535,7 @@ mod tests {
 
     #[tokio::test]
     async fn do_nothing_when_dns_matches_ip() {
-        let local_ip = Ipv4Addr::new(127, 0, 0, 2);
+        let local_ip = Ipv4Addr::new(93, 184, 216, 34);
 
         let mut dns_svc_mock = Box::new(MockDnsService::new());
         dns_svc_mock
@@ -103,14 +568,14 @@ mod tests {
 
     #[tokio::test]
     async fn initialize_when_dns_not_matches_ip() {
-        let local_ip = Ipv4Addr::new(127, 0, 0, 2);
+        let local_ip = Ipv4Addr::new(93, 184, 216, 34);
 
         let mut dns_svc_mock = Box::new(MockDnsService::new());
         dns_svc_mock
             .expect_resolve_ip()
             .with(predicate::eq("test"), predicate::eq("example.com"))
             .times(1)
-            .returning(|_, _| Ok(Some(Ipv4Addr::new(127, 0, 0, 3))));
+            .returning(|_, _| Ok(Some(Ipv4Addr::new(93, 184, 216, 35))));
         dns_svc_mock
             .expect_update_dns()
             .with(
@@ -136,7 +601,7 @@ mod tests {
 
     #[tokio::test]
     async fn error_on_initialize_during_dns_service_lookup() {
-        let local_ip = Ipv4Addr::new(127, 0, 0, 2);
+        let local_ip = Ipv4Addr::new(93, 184, 216, 34);
 
         let mut dns_svc_mock = Box::new(MockDnsService::new());
         dns_svc_mock
@@ -167,7 +632,7 @@ mod tests {
 
     #[tokio::test]
     async fn error_on_initialize_during_public_ip_lookup() {
-        let local_ip = Ipv4Addr::new(127, 0, 0, 2);
+        let local_ip = Ipv4Addr::new(93, 184, 216, 34);
 
         let mut dns_svc_mock = Box::new(MockDnsService::new());
         dns_svc_mock
@@ -202,8 +667,8 @@ mod tests {
 
     #[tokio::test]
     async fn error_on_initialize_during_dns_update() {
-        let local_ip = Ipv4Addr::new(127, 0, 0, 2);
-        let remote_ip = Ipv4Addr::new(127, 0, 0, 3);
+        let local_ip = Ipv4Addr::new(93, 184, 216, 34);
+        let remote_ip = Ipv4Addr::new(93, 184, 216, 35);
 
         let mut dns_svc_mock = Box::new(MockDnsService::new());
         dns_svc_mock
@@ -235,4 +700,566 @@ mod tests {
             })
         ));
     }
+
+    #[tokio::test]
+    async fn refuse_non_public_ip() {
+        let cgnat_ip = Ipv4Addr::new(100, 64, 0, 2);
+
+        let mut dns_svc_mock = Box::new(MockDnsService::new());
+        dns_svc_mock
+            .expect_resolve_ip()
+            .with(predicate::eq("test"), predicate::eq("example.com"))
+            .times(1)
+            .returning(|_, _| Ok(Some(Ipv4Addr::new(93, 184, 216, 35))));
+        dns_svc_mock.expect_is_local().return_const(false);
+        dns_svc_mock.expect_update_dns().never();
+
+        let mut netlink_svc_mock = Box::new(MockPublicIpService::new());
+        netlink_svc_mock
+            .expect_get_ip()
+            .times(1)
+            .returning(move || Ok(cgnat_ip));
+
+        let kernel = DynDnsService::new("example.com", "test", dns_svc_mock, netlink_svc_mock);
+        assert!(matches!(
+            kernel.update_dns_if_required().await,
+            Err(DynDnsServiceError::NonPublicIp { ip }) if ip == cgnat_ip
+        ));
+    }
+
+    #[tokio::test]
+    async fn flag_nat_when_observed_ip_differs() {
+        let router_ip = Ipv4Addr::new(93, 184, 216, 34);
+        let observed_ip = Ipv4Addr::new(93, 184, 216, 99);
+
+        let mut dns_svc_mock = Box::new(MockDnsService::new());
+        dns_svc_mock
+            .expect_resolve_ip()
+            .times(1)
+            .returning(move |_, _| Ok(Some(router_ip)));
+        dns_svc_mock.expect_update_dns().never();
+
+        let mut router_mock = Box::new(MockPublicIpService::new());
+        router_mock
+            .expect_get_ip()
+            .times(1)
+            .returning(move || Ok(router_ip));
+
+        let mut observed_mock = Box::new(MockPublicIpService::new());
+        observed_mock
+            .expect_get_ip()
+            .times(1)
+            .returning(move || Ok(observed_ip));
+
+        let kernel = DynDnsService::new("example.com", "test", dns_svc_mock, router_mock)
+            .with_observed_ip_service(observed_mock);
+        kernel
+            .update_dns_if_required()
+            .await
+            .expect("calling failed");
+
+        let status = kernel.status().snapshot();
+        assert_eq!(status.behind_nat, Some(true));
+        assert_eq!(status.nat_mismatches_total, 1);
+        assert_eq!(status.observed_ip, Some(observed_ip));
+    }
+
+    #[tokio::test]
+    async fn clear_nat_flag_when_observed_ip_matches() {
+        let router_ip = Ipv4Addr::new(93, 184, 216, 34);
+
+        let mut dns_svc_mock = Box::new(MockDnsService::new());
+        dns_svc_mock
+            .expect_resolve_ip()
+            .times(1)
+            .returning(move |_, _| Ok(Some(router_ip)));
+
+        let mut router_mock = Box::new(MockPublicIpService::new());
+        router_mock
+            .expect_get_ip()
+            .times(1)
+            .returning(move || Ok(router_ip));
+
+        let mut observed_mock = Box::new(MockPublicIpService::new());
+        observed_mock
+            .expect_get_ip()
+            .times(1)
+            .returning(move || Ok(router_ip));
+
+        let status = Status::new();
+        status.update(|s| s.behind_nat = Some(true));
+
+        let kernel = DynDnsService::new("example.com", "test", dns_svc_mock, router_mock)
+            .with_observed_ip_service(observed_mock)
+            .with_status(status.clone());
+        kernel
+            .update_dns_if_required()
+            .await
+            .expect("calling failed");
+
+        assert_eq!(status.snapshot().behind_nat, Some(false));
+    }
+
+    #[tokio::test]
+    async fn forget_nat_state_when_observation_fails() {
+        let router_ip = Ipv4Addr::new(93, 184, 216, 34);
+
+        let mut dns_svc_mock = Box::new(MockDnsService::new());
+        dns_svc_mock
+            .expect_resolve_ip()
+            .times(1)
+            .returning(move |_, _| Ok(Some(router_ip)));
+
+        let mut router_mock = Box::new(MockPublicIpService::new());
+        router_mock
+            .expect_get_ip()
+            .times(1)
+            .returning(move || Ok(router_ip));
+
+        let mut observed_mock = Box::new(MockPublicIpService::new());
+        observed_mock
+            .expect_get_ip()
+            .times(1)
+            .returning(|| Err(PublicIpServiceError::InvalidIpResponse));
+
+        let status = Status::new();
+        status.update(|s| {
+            s.observed_ip = Some(Ipv4Addr::new(93, 184, 216, 99));
+            s.behind_nat = Some(true);
+        });
+
+        let kernel = DynDnsService::new("example.com", "test", dns_svc_mock, router_mock)
+            .with_observed_ip_service(observed_mock)
+            .with_status(status.clone());
+        kernel
+            .update_dns_if_required()
+            .await
+            .expect("calling failed");
+
+        let status = status.snapshot();
+        assert_eq!(status.behind_nat, None);
+        assert_eq!(status.observed_ip, None);
+    }
+
+    #[tokio::test]
+    async fn hold_back_update_until_ip_is_confirmed() {
+        let local_ip = Ipv4Addr::new(93, 184, 216, 34);
+
+        let mut dns_svc_mock = Box::new(MockDnsService::new());
+        dns_svc_mock
+            .expect_resolve_ip()
+            .times(2)
+            .returning(|_, _| Ok(Some(Ipv4Addr::new(93, 184, 216, 35))));
+        dns_svc_mock
+            .expect_update_dns()
+            .with(
+                predicate::eq("test"),
+                predicate::eq("example.com"),
+                predicate::eq(local_ip),
+            )
+            .times(1)
+            .returning(|_, _, _| Ok(()));
+
+        let mut netlink_svc_mock = Box::new(MockPublicIpService::new());
+        netlink_svc_mock
+            .expect_get_ip()
+            .times(2)
+            .returning(move || Ok(local_ip));
+
+        let kernel = DynDnsService::new("example.com", "test", dns_svc_mock, netlink_svc_mock)
+            .with_dampening_policy(DampeningPolicy {
+                min_observations: 2,
+                ..Default::default()
+            });
+        kernel
+            .update_dns_if_required()
+            .await
+            .expect("calling failed");
+        assert_eq!(kernel.status().snapshot().updates_held_back_total, 1);
+
+        kernel
+            .update_dns_if_required()
+            .await
+            .expect("calling failed");
+    }
+
+    #[tokio::test]
+    async fn dry_run_never_updates() {
+        let local_ip = Ipv4Addr::new(93, 184, 216, 34);
+        let remote_ip = Ipv4Addr::new(93, 184, 216, 35);
+
+        let mut dns_svc_mock = Box::new(MockDnsService::new());
+        dns_svc_mock
+            .expect_resolve_ip()
+            .with(predicate::eq("test"), predicate::eq("example.com"))
+            .times(1)
+            .returning(move |_, _| Ok(Some(remote_ip)));
+        dns_svc_mock.expect_update_dns().never();
+
+        let mut netlink_svc_mock = Box::new(MockPublicIpService::new());
+        netlink_svc_mock
+            .expect_get_ip()
+            .times(1)
+            .returning(move || Ok(local_ip));
+
+        let kernel = DynDnsService::new("example.com", "test", dns_svc_mock, netlink_svc_mock)
+            .with_dry_run(true);
+        kernel
+            .update_dns_if_required()
+            .await
+            .expect("calling failed");
+    }
+
+    #[tokio::test]
+    async fn plan_reports_old_and_new_value() {
+        let local_ip = Ipv4Addr::new(93, 184, 216, 34);
+
+        let mut dns_svc_mock = Box::new(MockDnsService::new());
+        dns_svc_mock
+            .expect_resolve_ip()
+            .times(1)
+            .returning(|_, _| Ok(None));
+        dns_svc_mock.expect_update_dns().never();
+
+        let mut netlink_svc_mock = Box::new(MockPublicIpService::new());
+        netlink_svc_mock
+            .expect_get_ip()
+            .times(1)
+            .returning(move || Ok(local_ip));
+
+        let kernel = DynDnsService::new("example.com", "test", dns_svc_mock, netlink_svc_mock);
+        let plan = kernel.plan().await.expect("calling failed");
+
+        assert_eq!(
+            plan,
+            vec![PlannedChange {
+                subdomain: String::from("test"),
+                domain: String::from("example.com"),
+                record_type: String::from("A"),
+                old: None,
+                new: local_ip,
+                ttl: None,
+            }]
+        );
+        let rendered = render_plan(&plan, &[]);
+        assert!(rendered.contains("test.example.com"));
+        assert!(rendered.contains("(none)"));
+        assert!(rendered.contains("93.184.216.34"));
+    }
+
+    fn spf() -> RecordTemplate {
+        RecordTemplate::new("mail", "TXT", "v=spf1 ip4:{ipv4} -all")
+    }
+
+    #[tokio::test]
+    async fn update_derived_records_with_address() {
+        let dns_ip = Ipv4Addr::new(93, 184, 216, 34);
+        let local_ip = Ipv4Addr::new(93, 184, 216, 35);
+
+        let mut dns_svc_mock = Box::new(MockDnsService::new());
+        dns_svc_mock
+            .expect_resolve_ip()
+            .returning(move |_, _| Ok(Some(dns_ip)));
+        dns_svc_mock
+            .expect_resolve_record()
+            .with(
+                predicate::eq("mail"),
+                predicate::eq("example.com"),
+                predicate::eq("TXT"),
+                predicate::eq("v=spf1 ip4:"),
+            )
+            .returning(|_, _, _, _| Ok(Some(String::from("v=spf1 ip4:93.184.216.34 -all"))));
+        dns_svc_mock
+            .expect_update_dns()
+            .times(1)
+            .returning(|_, _, _| Ok(()));
+        dns_svc_mock
+            .expect_update_record()
+            .with(
+                predicate::eq("mail"),
+                predicate::eq("example.com"),
+                predicate::eq("TXT"),
+                predicate::eq("v=spf1 ip4:"),
+                predicate::eq("v=spf1 ip4:93.184.216.35 -all"),
+            )
+            .times(1)
+            .returning(|_, _, _, _, _| Ok(()));
+
+        let mut netlink_svc_mock = Box::new(MockPublicIpService::new());
+        netlink_svc_mock
+            .expect_get_ip()
+            .returning(move || Ok(local_ip));
+
+        let kernel = DynDnsService::new("example.com", "test", dns_svc_mock, netlink_svc_mock)
+            .with_derived_records(vec![spf()]);
+        kernel
+            .update_dns_if_required()
+            .await
+            .expect("calling failed");
+    }
+
+    #[tokio::test]
+    async fn hold_back_derived_records_with_address() {
+        let dns_ip = Ipv4Addr::new(93, 184, 216, 34);
+        let local_ip = Ipv4Addr::new(93, 184, 216, 35);
+
+        let mut dns_svc_mock = Box::new(MockDnsService::new());
+        dns_svc_mock
+            .expect_resolve_ip()
+            .returning(move |_, _| Ok(Some(dns_ip)));
+        dns_svc_mock
+            .expect_resolve_record()
+            .returning(|_, _, _, _| Ok(Some(String::from("v=spf1 ip4:93.184.216.34 -all"))));
+        dns_svc_mock.expect_update_dns().never();
+        dns_svc_mock.expect_update_record().never();
+
+        let mut netlink_svc_mock = Box::new(MockPublicIpService::new());
+        netlink_svc_mock
+            .expect_get_ip()
+            .returning(move || Ok(local_ip));
+
+        let kernel = DynDnsService::new("example.com", "test", dns_svc_mock, netlink_svc_mock)
+            .with_derived_records(vec![spf()])
+            .with_dampening_policy(DampeningPolicy {
+                min_observations: 2,
+                ..Default::default()
+            });
+        kernel
+            .update_dns_if_required()
+            .await
+            .expect("calling failed");
+    }
+
+    #[test]
+    fn reject_derived_records_of_unsupported_type() {
+        let mut dns_svc_mock = Box::new(MockDnsService::new());
+        dns_svc_mock
+            .expect_supports_record_type()
+            .returning(|record_type| record_type == "TXT");
+
+        let kernel = DynDnsService::new(
+            "example.com",
+            "test",
+            dns_svc_mock,
+            Box::new(MockPublicIpService::new()),
+        )
+        .with_derived_records(vec![
+            spf(),
+            RecordTemplate::new("@", "HTTPS", "1 . ipv4hint={ipv4}"),
+        ]);
+        assert!(matches!(
+            kernel.check_derived_records(),
+            Err(DynDnsServiceError::DnsServiceError {
+                source: DnsServiceError::Unsupported { record_type }
+            }) if record_type == "HTTPS"
+        ));
+    }
+
+    #[test]
+    fn render_plan_with_derived_records() {
+        let ip = Ipv4Addr::new(93, 184, 216, 34);
+        let plan = vec![PlannedChange {
+            subdomain: String::from("test"),
+            domain: String::from("example.com"),
+            record_type: String::from("A"),
+            old: Some(ip),
+            new: ip,
+            ttl: None,
+        }];
+        let derived = vec![DerivedChange {
+            subdomain: String::from("mail"),
+            domain: String::from("example.com"),
+            record_type: String::from("TXT"),
+            prefix: String::from("v=spf1 ip4:"),
+            old: None,
+            new: String::from("v=spf1 ip4:93.184.216.34 -all"),
+        }];
+
+        let rendered = render_plan(&plan, &derived);
+        assert!(rendered.contains("mail.example.com"));
+        assert!(rendered.contains("v=spf1 ip4:93.184.216.34 -all"));
+        assert!(!rendered.contains("test.example.com"));
+    }
+
+    #[test]
+    fn render_plan_without_changes() {
+        let ip = Ipv4Addr::new(93, 184, 216, 34);
+        let plan = vec![PlannedChange {
+            subdomain: String::from("test"),
+            domain: String::from("example.com"),
+            record_type: String::from("A"),
+            old: Some(ip),
+            new: ip,
+            ttl: None,
+        }];
+        assert_eq!(render_plan(&plan, &[]), "No changes.\n");
+    }
+
+    #[tokio::test]
+    async fn update_multiple_records_in_batch() {
+        let local_ip = Ipv4Addr::new(93, 184, 216, 34);
+        let remote_ip = Ipv4Addr::new(93, 184, 216, 35);
+
+        let mut dns_svc_mock = Box::new(MockDnsService::new());
+        dns_svc_mock
+            .expect_resolve_ip()
+            .with(predicate::eq("@"), predicate::eq("example.com"))
+            .times(1)
+            .returning(move |_, _| Ok(Some(remote_ip)));
+        dns_svc_mock
+            .expect_resolve_ip()
+            .with(predicate::eq("www"), predicate::eq("example.com"))
+            .times(1)
+            .returning(move |_, _| Ok(Some(remote_ip)));
+        dns_svc_mock
+            .expect_resolve_ip()
+            .with(predicate::eq("mail"), predicate::eq("example.com"))
+            .times(1)
+            .returning(move |_, _| Ok(Some(local_ip)));
+        dns_svc_mock.expect_update_dns().never();
+        dns_svc_mock
+            .expect_update_dns_batch()
+            .withf(move |updates| {
+                updates
+                    .iter()
+                    .map(|u| u.subdomain.as_str())
+                    .collect::<Vec<_>>()
+                    == ["@", "www"]
+                    && updates.iter().all(|u| u.ip == local_ip)
+            })
+            .times(1)
+            .returning(|_| vec![Ok(()), Err(DnsServiceError::UnknownRecord)]);
+
+        let mut netlink_svc_mock = Box::new(MockPublicIpService::new());
+        netlink_svc_mock
+            .expect_get_ip()
+            .times(1)
+            .returning(move || Ok(local_ip));
+
+        let kernel = DynDnsService::new("example.com", "@", dns_svc_mock, netlink_svc_mock)
+            .with_subdomains(&["@", "www", "mail"]);
+        assert!(matches!(
+            kernel.update_dns_if_required().await,
+            Err(DynDnsServiceError::DnsServiceError {
+                source: DnsServiceError::UnknownRecord
+            })
+        ));
+    }
+
+    #[tokio::test]
+    async fn send_configured_ttl_with_address_change() {
+        let dns_ip = Ipv4Addr::new(93, 184, 216, 34);
+        let local_ip = Ipv4Addr::new(93, 184, 216, 35);
+
+        let mut dns_svc_mock = Box::new(MockDnsService::new());
+        dns_svc_mock
+            .expect_resolve_ip()
+            .returning(move |_, _| Ok(Some(dns_ip)));
+        dns_svc_mock
+            .expect_resolve_ttl()
+            .times(1)
+            .returning(|_, _| Ok(Some(300)));
+        dns_svc_mock.expect_update_dns().never();
+        dns_svc_mock
+            .expect_update_dns_with_ttl()
+            .with(
+                predicate::eq("test"),
+                predicate::eq("example.com"),
+                predicate::eq(local_ip),
+                predicate::eq(Some(300)),
+            )
+            .times(1)
+            .returning(|_, _, _, _| Ok(()));
+
+        let mut netlink_svc_mock = Box::new(MockPublicIpService::new());
+        netlink_svc_mock
+            .expect_get_ip()
+            .returning(move || Ok(local_ip));
+
+        let kernel = DynDnsService::new("example.com", "test", dns_svc_mock, netlink_svc_mock)
+            .with_ttl_manager(
+                TtlManager::new(TtlPolicy::Keep).with_record("test", TtlPolicy::Fixed(300)),
+            );
+        kernel
+            .update_dns_if_required()
+            .await
+            .expect("calling failed");
+    }
+
+    #[tokio::test]
+    async fn keep_configured_ttl_that_is_already_published() {
+        let ip = Ipv4Addr::new(93, 184, 216, 34);
+
+        let mut dns_svc_mock = Box::new(MockDnsService::new());
+        dns_svc_mock
+            .expect_resolve_ip()
+            .returning(move |_, _| Ok(Some(ip)));
+        dns_svc_mock
+            .expect_resolve_ttl()
+            .times(1)
+            .returning(|_, _| Ok(Some(300)));
+        dns_svc_mock.expect_update_dns_with_ttl().never();
+
+        let mut netlink_svc_mock = Box::new(MockPublicIpService::new());
+        netlink_svc_mock.expect_get_ip().returning(move || Ok(ip));
+
+        let kernel = DynDnsService::new("example.com", "test", dns_svc_mock, netlink_svc_mock)
+            .with_ttl_manager(TtlManager::new(TtlPolicy::Fixed(300)));
+        kernel
+            .update_dns_if_required()
+            .await
+            .expect("calling failed");
+    }
+
+    #[tokio::test]
+    async fn raise_ttl_once_address_is_stable() {
+        let ip = Ipv4Addr::new(93, 184, 216, 34);
+
+        let mut dns_svc_mock = Box::new(MockDnsService::new());
+        dns_svc_mock
+            .expect_resolve_ip()
+            .times(2)
+            .returning(move |_, _| Ok(Some(ip)));
+        dns_svc_mock
+            .expect_resolve_ttl()
+            .times(1)
+            .returning(|_, _| Ok(Some(60)));
+        dns_svc_mock
+            .expect_update_dns_with_ttl()
+            .with(
+                predicate::eq("test"),
+                predicate::eq("example.com"),
+                predicate::eq(ip),
+                predicate::eq(Some(3600)),
+            )
+            .times(1)
+            .returning(|_, _, _, _| Ok(()));
+
+        let mut netlink_svc_mock = Box::new(MockPublicIpService::new());
+        netlink_svc_mock
+            .expect_get_ip()
+            .times(2)
+            .returning(move || Ok(ip));
+
+        let kernel = DynDnsService::new("example.com", "test", dns_svc_mock, netlink_svc_mock)
+            .with_dampening_policy(DampeningPolicy {
+                min_observations: 3,
+                ..Default::default()
+            })
+            .with_ttl_manager(TtlManager::new(TtlPolicy::Adaptive {
+                min: 60,
+                max: 3600,
+                stable_after: std::time::Duration::ZERO,
+            }));
+        // The first cycle raises the TTL without waiting for dampening, the second has
+        // nothing left to do.
+        kernel
+            .update_dns_if_required()
+            .await
+            .expect("calling failed");
+        kernel
+            .update_dns_if_required()
+            .await
+            .expect("calling failed");
+    }
 }
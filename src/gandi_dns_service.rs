@@ -0,0 +1,304 @@
+use async_trait::async_trait;
+use log::{debug, error};
+use reqwest::{Client, Method, RequestBuilder, Response, StatusCode};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::net::Ipv4Addr;
+use thiserror::Error;
+
+use crate::dns_service::{DnsService, DnsServiceError};
+use crate::record_template::{managed_value, replace_managed};
+
+const API_URL: &str = "https://api.gandi.net/v5/livedns";
+const DEFAULT_TTL: u32 = 10800;
+
+#[derive(Clone, Deserialize, Serialize, Debug, Default, PartialEq, Eq)]
+pub struct GandiDomain {
+    pub fqdn: String,
+}
+
+#[derive(Clone, Deserialize, Serialize, Debug, Default, PartialEq, Eq)]
+pub struct GandiRRSet {
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub rrset_name: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub rrset_type: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rrset_ttl: Option<u32>,
+    pub rrset_values: Vec<String>,
+}
+
+#[derive(Deserialize)]
+struct ErrorResponse {
+    message: String,
+}
+
+/// Client for Gandi LiveDNS, authenticated with a personal access token.
+pub struct GandiClient {
+    api_url: String,
+    token: String,
+    client: Client,
+}
+
+impl GandiClient {
+    pub fn new(token: &str) -> Self {
+        Self::new_with_url(token, API_URL)
+    }
+
+    pub fn new_with_url(token: &str, api_url: &str) -> Self {
+        let client = Client::new();
+        Self {
+            api_url: String::from(api_url),
+            token: String::from(token),
+            client,
+        }
+    }
+
+    pub async fn list_domains(&self) -> Result<Vec<GandiDomain>, GandiError> {
+        let request = self.request(Method::GET, "/domains");
+        self.send_json(request).await
+    }
+
+    pub async fn get_rrset(
+        &self,
+        domain: &str,
+        name: &str,
+        record_type: &str,
+    ) -> Result<Option<GandiRRSet>, GandiError> {
+        let request = self.request(
+            Method::GET,
+            &format!(
+                "/domains/{}/records/{}/{}",
+                domain,
+                rrset_name(name),
+                record_type
+            ),
+        );
+        match self.send_json(request).await {
+            Ok(rrset) => Ok(Some(rrset)),
+            Err(GandiError::NotFound) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Replaces the RRset with `values`, creating it if needed. Without a TTL the RRset keeps
+    /// its current one.
+    pub async fn replace_rrset(
+        &self,
+        domain: &str,
+        name: &str,
+        record_type: &str,
+        values: &[String],
+        ttl: Option<u32>,
+    ) -> Result<(), GandiError> {
+        let ttl = match ttl {
+            Some(ttl) => ttl,
+            None => self
+                .get_rrset(domain, name, record_type)
+                .await?
+                .and_then(|rrset| rrset.rrset_ttl)
+                .unwrap_or(DEFAULT_TTL),
+        };
+
+        let rrset = GandiRRSet {
+            rrset_ttl: Some(ttl),
+            rrset_values: values.to_vec(),
+            ..Default::default()
+        };
+        let request = self
+            .request(
+                Method::PUT,
+                &format!(
+                    "/domains/{}/records/{}/{}",
+                    domain,
+                    rrset_name(name),
+                    record_type
+                ),
+            )
+            .json(&rrset);
+        self.send(request).await?;
+        Ok(())
+    }
+
+    fn request(&self, method: Method, path: &str) -> RequestBuilder {
+        self.client
+            .request(method, format!("{}{}", self.api_url, path))
+            .bearer_auth(&self.token)
+    }
+
+    async fn send(&self, request: RequestBuilder) -> Result<Response, GandiError> {
+        let response = request.send().await?;
+        let status = response.status();
+
+        if status.is_success() {
+            return Ok(response);
+        }
+        if status == StatusCode::NOT_FOUND {
+            return Err(GandiError::NotFound);
+        }
+
+        let body = response.text().await.unwrap_or_default();
+        let message = serde_json::from_str::<ErrorResponse>(&body)
+            .map(|e| e.message)
+            .unwrap_or(body);
+        error!("Gandi API returned {}: {}", status, message);
+        Err(match status {
+            StatusCode::UNAUTHORIZED => GandiError::InvalidToken,
+            StatusCode::FORBIDDEN => GandiError::PermissionDenied { message },
+            StatusCode::BAD_REQUEST | StatusCode::CONFLICT => GandiError::Rejected { message },
+            _ => GandiError::Api {
+                status: status.as_u16(),
+                message,
+            },
+        })
+    }
+
+    async fn send_json<T: DeserializeOwned>(
+        &self,
+        request: RequestBuilder,
+    ) -> Result<T, GandiError> {
+        Ok(self.send(request).await?.json().await?)
+    }
+}
+
+fn rrset_name(subdomain: &str) -> &str {
+    match subdomain {
+        "" => "@",
+        _ => subdomain,
+    }
+}
+
+#[async_trait]
+impl DnsService for GandiClient {
+    async fn resolve_ip(
+        &self,
+        subdomain: &str,
+        domain: &str,
+    ) -> Result<Option<Ipv4Addr>, DnsServiceError> {
+        debug!(
+            "Resolve ip for domain {} and subdomain {}.",
+            domain, subdomain
+        );
+        let rrset = self.get_rrset(domain, subdomain, "A").await?;
+
+        match rrset.and_then(|rrset| rrset.rrset_values.into_iter().next()) {
+            Some(value) => {
+                let ip = value.parse().map_err(|_| DnsServiceError::UnknownError)?;
+                Ok(Some(ip))
+            }
+            None => Ok(None),
+        }
+    }
+
+    async fn resolve_ttl(
+        &self,
+        subdomain: &str,
+        domain: &str,
+    ) -> Result<Option<u32>, DnsServiceError> {
+        let rrset = self.get_rrset(domain, subdomain, "A").await?;
+        Ok(rrset.and_then(|rrset| rrset.rrset_ttl))
+    }
+
+    async fn update_dns(
+        &self,
+        subdomain: &str,
+        domain: &str,
+        ip: Ipv4Addr,
+    ) -> Result<(), DnsServiceError> {
+        self.update_dns_with_ttl(subdomain, domain, ip, None).await
+    }
+
+    async fn update_dns_with_ttl(
+        &self,
+        subdomain: &str,
+        domain: &str,
+        ip: Ipv4Addr,
+        ttl: Option<u32>,
+    ) -> Result<(), DnsServiceError> {
+        debug!(
+            "Update dns for domain {} and subdomain {} with IP {}.",
+            domain, subdomain, ip
+        );
+        self.replace_rrset(domain, subdomain, "A", &[ip.to_string()], ttl)
+            .await?;
+        Ok(())
+    }
+
+    async fn list_zones(&self) -> Result<Vec<String>, DnsServiceError> {
+        let domains = self.list_domains().await?;
+        Ok(domains.into_iter().map(|domain| domain.fqdn).collect())
+    }
+
+    fn supports_record_type(&self, _record_type: &str) -> bool {
+        true
+    }
+
+    async fn resolve_record(
+        &self,
+        subdomain: &str,
+        domain: &str,
+        record_type: &str,
+        prefix: &str,
+    ) -> Result<Option<String>, DnsServiceError> {
+        let rrset = self.get_rrset(domain, subdomain, record_type).await?;
+        Ok(rrset.and_then(|rrset| managed_value(record_type, &rrset.rrset_values, prefix)))
+    }
+
+    async fn update_record(
+        &self,
+        subdomain: &str,
+        domain: &str,
+        record_type: &str,
+        prefix: &str,
+        value: &str,
+    ) -> Result<(), DnsServiceError> {
+        let rrset = self.get_rrset(domain, subdomain, record_type).await?;
+        let current = rrset
+            .as_ref()
+            .map(|rrset| rrset.rrset_values.clone())
+            .unwrap_or_default();
+        self.replace_rrset(
+            domain,
+            subdomain,
+            record_type,
+            &replace_managed(record_type, &current, prefix, value),
+            Some(
+                rrset
+                    .and_then(|rrset| rrset.rrset_ttl)
+                    .unwrap_or(DEFAULT_TTL),
+            ),
+        )
+        .await?;
+        Ok(())
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum GandiError {
+    #[error("The personal access token is not valid.")]
+    InvalidToken,
+    #[error("The personal access token is not permitted to do this: {message}")]
+    PermissionDenied { message: String },
+    #[error("The domain or record does not exist.")]
+    NotFound,
+    #[error("Gandi rejected the change: {message}")]
+    Rejected { message: String },
+    #[error("API request failed with status {status}: {message}")]
+    Api { status: u16, message: String },
+    #[error("request failed")]
+    RequestFailed {
+        #[from]
+        source: reqwest::Error,
+    },
+}
+
+impl From<GandiError> for DnsServiceError {
+    fn from(e: GandiError) -> Self {
+        match e {
+            GandiError::NotFound => DnsServiceError::UnknownZone,
+            GandiError::Rejected { .. } => DnsServiceError::UpdateRejected {
+                record: e.to_string(),
+            },
+            _ => DnsServiceError::ClientError,
+        }
+    }
+}
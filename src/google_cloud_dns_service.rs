@@ -0,0 +1,608 @@
+use async_trait::async_trait;
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use log::{debug, error};
+use reqwest::{Client, Method, RequestBuilder, StatusCode};
+use rsa::pkcs1v15::SigningKey;
+use rsa::pkcs8::DecodePrivateKey;
+use rsa::signature::{SignatureEncoding, Signer};
+use rsa::RsaPrivateKey;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::json;
+use sha2::Sha256;
+use std::collections::HashMap;
+use std::net::Ipv4Addr;
+use std::sync::Mutex;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use thiserror::Error;
+
+use crate::authoritative_dns::fqdn;
+use crate::dns_service::{DnsService, DnsServiceError};
+use crate::record_template::{managed_value, replace_managed};
+
+const API_URL: &str = "https://dns.googleapis.com/dns/v1";
+const TOKEN_URI: &str = "https://oauth2.googleapis.com/token";
+const SCOPE: &str = "https://www.googleapis.com/auth/ndev.clouddns.readwrite";
+const GRANT_TYPE: &str = "urn:ietf:params:oauth:grant-type:jwt-bearer";
+const ASSERTION_LIFETIME: u64 = 3600;
+/// Tokens are replaced this long before they expire.
+const TOKEN_MARGIN: Duration = Duration::from_secs(60);
+const DEFAULT_TTL: u32 = 300;
+const CHANGE_POLL_INTERVAL: Duration = Duration::from_secs(2);
+const CHANGE_POLL_ATTEMPTS: u32 = 60;
+
+/// The parts of a service-account JSON key that are needed to mint access tokens.
+pub struct ServiceAccountKey {
+    pub client_email: String,
+    pub project_id: Option<String>,
+    pub token_uri: String,
+    private_key: RsaPrivateKey,
+}
+
+#[derive(Deserialize)]
+struct ServiceAccountKeyFile {
+    client_email: String,
+    private_key: String,
+    #[serde(default)]
+    project_id: Option<String>,
+    #[serde(default)]
+    token_uri: Option<String>,
+}
+
+impl ServiceAccountKey {
+    pub fn from_json(json: &str) -> Result<Self, GoogleCloudDnsError> {
+        let file: ServiceAccountKeyFile =
+            serde_json::from_str(json).map_err(|e| GoogleCloudDnsError::InvalidKey {
+                message: e.to_string(),
+            })?;
+        let private_key = RsaPrivateKey::from_pkcs8_pem(&file.private_key).map_err(|e| {
+            GoogleCloudDnsError::InvalidKey {
+                message: e.to_string(),
+            }
+        })?;
+        Ok(Self {
+            client_email: file.client_email,
+            project_id: file.project_id,
+            token_uri: file.token_uri.unwrap_or_else(|| String::from(TOKEN_URI)),
+            private_key,
+        })
+    }
+
+    pub fn from_file(path: &str) -> Result<Self, GoogleCloudDnsError> {
+        let json = std::fs::read_to_string(path).map_err(|e| GoogleCloudDnsError::InvalidKey {
+            message: format!("{}: {}", path, e),
+        })?;
+        Self::from_json(&json)
+    }
+
+    /// Builds the RS256 signed JWT that is exchanged for an access token.
+    pub fn assertion(&self, issued_at: u64) -> String {
+        let header = json!({ "alg": "RS256", "typ": "JWT" });
+        let claims = json!({
+            "iss": self.client_email,
+            "scope": SCOPE,
+            "aud": self.token_uri,
+            "iat": issued_at,
+            "exp": issued_at + ASSERTION_LIFETIME,
+        });
+        let message = format!(
+            "{}.{}",
+            URL_SAFE_NO_PAD.encode(header.to_string()),
+            URL_SAFE_NO_PAD.encode(claims.to_string())
+        );
+        let signature =
+            SigningKey::<Sha256>::new(self.private_key.clone()).sign(message.as_bytes());
+        format!(
+            "{}.{}",
+            message,
+            URL_SAFE_NO_PAD.encode(signature.to_bytes())
+        )
+    }
+}
+
+#[derive(Deserialize)]
+struct TokenResponse {
+    access_token: String,
+    expires_in: u64,
+}
+
+struct AccessToken {
+    token: String,
+    expires_at: Instant,
+}
+
+#[derive(Clone, Deserialize, Serialize, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct ManagedZone {
+    pub name: String,
+    pub dns_name: String,
+}
+
+#[derive(Deserialize, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+struct ManagedZonesResponse {
+    #[serde(default)]
+    managed_zones: Vec<ManagedZone>,
+    #[serde(default)]
+    next_page_token: Option<String>,
+}
+
+#[derive(Clone, Deserialize, Serialize, Debug, Default, PartialEq, Eq)]
+pub struct ResourceRecordSet {
+    pub name: String,
+    pub r#type: String,
+    pub ttl: u32,
+    pub rrdatas: Vec<String>,
+}
+
+#[derive(Deserialize, Debug, Default)]
+struct ResourceRecordSetsResponse {
+    #[serde(default)]
+    rrsets: Vec<ResourceRecordSet>,
+}
+
+#[derive(Clone, Deserialize, Serialize, Debug, Default, PartialEq, Eq)]
+pub struct Change {
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub id: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub status: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub additions: Vec<ResourceRecordSet>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub deletions: Vec<ResourceRecordSet>,
+}
+
+#[derive(Deserialize)]
+struct ErrorResponse {
+    error: ErrorDetail,
+}
+
+#[derive(Deserialize)]
+struct ErrorDetail {
+    message: String,
+}
+
+/// Client for Google Cloud DNS. Access tokens are minted from a service-account key and
+/// reused until shortly before they expire.
+pub struct GoogleCloudDnsClient {
+    api_url: String,
+    key: ServiceAccountKey,
+    project: String,
+    token: Mutex<Option<AccessToken>>,
+    change_poll_interval: Duration,
+    zone_names: Mutex<HashMap<String, String>>,
+    client: Client,
+}
+
+impl GoogleCloudDnsClient {
+    /// Uses `project`, or the project the service account belongs to if it is `None`.
+    pub fn new(key: ServiceAccountKey, project: Option<&str>) -> Result<Self, GoogleCloudDnsError> {
+        Self::new_with_url(key, project, API_URL)
+    }
+
+    pub fn new_with_url(
+        key: ServiceAccountKey,
+        project: Option<&str>,
+        api_url: &str,
+    ) -> Result<Self, GoogleCloudDnsError> {
+        let project = project
+            .map(String::from)
+            .or_else(|| key.project_id.clone())
+            .filter(|project| !project.is_empty())
+            .ok_or(GoogleCloudDnsError::MissingProject)?;
+        let client = Client::new();
+        Ok(Self {
+            api_url: String::from(api_url.trim_end_matches('/')),
+            project,
+            key,
+            token: Mutex::new(None),
+            change_poll_interval: CHANGE_POLL_INTERVAL,
+            zone_names: Mutex::new(HashMap::new()),
+            client,
+        })
+    }
+
+    pub fn with_change_poll_interval(mut self, change_poll_interval: Duration) -> Self {
+        self.change_poll_interval = change_poll_interval;
+        self
+    }
+
+    /// Returns the cached access token, or mints a new one when it is about to expire.
+    pub async fn access_token(&self) -> Result<String, GoogleCloudDnsError> {
+        if let Some(token) = &*self.token.lock().unwrap() {
+            if Instant::now() + TOKEN_MARGIN < token.expires_at {
+                return Ok(token.token.clone());
+            }
+        }
+
+        let issued_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        let assertion = self.key.assertion(issued_at);
+        let response = self
+            .client
+            .post(&self.key.token_uri)
+            .form(&[("grant_type", GRANT_TYPE), ("assertion", &assertion)])
+            .send()
+            .await?;
+        let status = response.status();
+        if !status.is_success() {
+            let message = response.text().await.unwrap_or_default();
+            error!("Google token endpoint returned {}: {}", status, message);
+            return Err(GoogleCloudDnsError::TokenRequestFailed { message });
+        }
+
+        let response: TokenResponse = response.json().await?;
+        debug!(
+            "Minted an access token for {} valid for {} seconds.",
+            self.key.client_email, response.expires_in
+        );
+        *self.token.lock().unwrap() = Some(AccessToken {
+            token: response.access_token.clone(),
+            expires_at: Instant::now() + Duration::from_secs(response.expires_in),
+        });
+        Ok(response.access_token)
+    }
+
+    pub async fn list_managed_zones(
+        &self,
+        dns_name: Option<&str>,
+    ) -> Result<Vec<ManagedZone>, GoogleCloudDnsError> {
+        let mut zones = Vec::new();
+        let mut page_token: Option<String> = None;
+        loop {
+            let mut query = vec![];
+            if let Some(dns_name) = dns_name {
+                query.push(("dnsName", canonical(dns_name)));
+            }
+            if let Some(page_token) = page_token {
+                query.push(("pageToken", page_token));
+            }
+            let response: ManagedZonesResponse = self
+                .send(self.request(Method::GET, "/managedZones").query(&query))
+                .await?;
+            zones.extend(response.managed_zones);
+
+            page_token = response.next_page_token;
+            if page_token.is_none() {
+                break;
+            }
+        }
+        Ok(zones)
+    }
+
+    /// Looks up the managed zone name, asking the API only the first time a zone is used.
+    pub async fn zone_name(&self, zone: &str) -> Result<String, GoogleCloudDnsError> {
+        if let Some(name) = self.zone_names.lock().unwrap().get(zone) {
+            return Ok(name.clone());
+        }
+
+        let dns_name = canonical(zone);
+        let name = self
+            .list_managed_zones(Some(zone))
+            .await?
+            .into_iter()
+            .find(|z| z.dns_name.eq_ignore_ascii_case(&dns_name))
+            .ok_or_else(|| GoogleCloudDnsError::ZoneNotFound {
+                zone: zone.to_string(),
+            })?
+            .name;
+        self.zone_names
+            .lock()
+            .unwrap()
+            .insert(zone.to_string(), name.clone());
+        Ok(name)
+    }
+
+    pub async fn find_rrset(
+        &self,
+        zone_name: &str,
+        name: &str,
+        record_type: &str,
+    ) -> Result<Option<ResourceRecordSet>, GoogleCloudDnsError> {
+        let name = canonical(name);
+        let request = self
+            .request(Method::GET, &format!("/managedZones/{}/rrsets", zone_name))
+            .query(&[("name", name.as_str()), ("type", record_type)]);
+        let response: ResourceRecordSetsResponse = self.send(request).await?;
+        Ok(response
+            .rrsets
+            .into_iter()
+            .find(|r| r.name.eq_ignore_ascii_case(&name) && r.r#type == record_type))
+    }
+
+    pub async fn create_change(
+        &self,
+        zone_name: &str,
+        change: &Change,
+    ) -> Result<Change, GoogleCloudDnsError> {
+        let request = self
+            .request(
+                Method::POST,
+                &format!("/managedZones/{}/changes", zone_name),
+            )
+            .json(change);
+        self.send(request).await
+    }
+
+    pub async fn get_change(
+        &self,
+        zone_name: &str,
+        id: &str,
+    ) -> Result<Change, GoogleCloudDnsError> {
+        let request = self.request(
+            Method::GET,
+            &format!("/managedZones/{}/changes/{}", zone_name, id),
+        );
+        self.send(request).await
+    }
+
+    pub async fn wait_for_change(
+        &self,
+        zone_name: &str,
+        change: Change,
+    ) -> Result<Change, GoogleCloudDnsError> {
+        let mut change = change;
+        for _ in 0..CHANGE_POLL_ATTEMPTS {
+            if change.status == "done" {
+                return Ok(change);
+            }
+            debug!("Waiting for change {} ({}).", change.id, change.status);
+            tokio::time::sleep(self.change_poll_interval).await;
+            change = self.get_change(zone_name, &change.id).await?;
+        }
+        Err(GoogleCloudDnsError::ChangeTimeout { change: change.id })
+    }
+
+    /// Replaces the RRset with `values` in one change that deletes the current RRset, so a
+    /// concurrent edit makes the change fail instead of being overwritten.
+    pub async fn replace_rrset(
+        &self,
+        zone: &str,
+        name: &str,
+        record_type: &str,
+        values: &[String],
+        ttl: Option<u32>,
+    ) -> Result<(), GoogleCloudDnsError> {
+        let zone_name = self.zone_name(zone).await?;
+        let existing = self.find_rrset(&zone_name, name, record_type).await?;
+
+        let addition = ResourceRecordSet {
+            name: canonical(name),
+            r#type: record_type.to_string(),
+            ttl: ttl
+                .or_else(|| existing.as_ref().map(|r| r.ttl))
+                .unwrap_or(DEFAULT_TTL),
+            rrdatas: values.to_vec(),
+        };
+        let change = Change {
+            additions: vec![addition],
+            deletions: existing.into_iter().collect(),
+            ..Default::default()
+        };
+        let change = self.create_change(&zone_name, &change).await?;
+        self.wait_for_change(&zone_name, change).await?;
+        Ok(())
+    }
+
+    fn request(&self, method: Method, path: &str) -> RequestBuilder {
+        self.client.request(
+            method,
+            format!("{}/projects/{}{}", self.api_url, self.project, path),
+        )
+    }
+
+    async fn send<T: DeserializeOwned>(
+        &self,
+        request: RequestBuilder,
+    ) -> Result<T, GoogleCloudDnsError> {
+        let token = self.access_token().await?;
+        let retry = request.try_clone();
+        let mut response = request.bearer_auth(token).send().await?;
+
+        // A token revoked before its expiry is replaced once.
+        if let (StatusCode::UNAUTHORIZED, Some(retry)) = (response.status(), retry) {
+            debug!("Access token was rejected, minting a new one.");
+            *self.token.lock().unwrap() = None;
+            let token = self.access_token().await?;
+            response = retry.bearer_auth(token).send().await?;
+        }
+
+        let status = response.status();
+        if status.is_success() {
+            return Ok(response.json().await?);
+        }
+
+        let body = response.text().await.unwrap_or_default();
+        let message = serde_json::from_str::<ErrorResponse>(&body)
+            .map(|e| e.error.message)
+            .unwrap_or(body);
+        error!("Google Cloud DNS API returned {}: {}", status, message);
+        Err(match status {
+            StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => {
+                GoogleCloudDnsError::PermissionDenied { message }
+            }
+            StatusCode::NOT_FOUND => GoogleCloudDnsError::NotFound,
+            StatusCode::BAD_REQUEST | StatusCode::CONFLICT | StatusCode::PRECONDITION_FAILED => {
+                GoogleCloudDnsError::Rejected { message }
+            }
+            _ => GoogleCloudDnsError::Api {
+                status: status.as_u16(),
+                message,
+            },
+        })
+    }
+}
+
+/// Cloud DNS names are absolute, with a trailing dot.
+fn canonical(name: &str) -> String {
+    format!("{}.", name.trim_end_matches('.'))
+}
+
+#[async_trait]
+impl DnsService for GoogleCloudDnsClient {
+    async fn resolve_ip(
+        &self,
+        subdomain: &str,
+        domain: &str,
+    ) -> Result<Option<Ipv4Addr>, DnsServiceError> {
+        debug!(
+            "Resolve ip for domain {} and subdomain {}.",
+            domain, subdomain
+        );
+        let zone_name = self.zone_name(domain).await?;
+        let rrset = self
+            .find_rrset(&zone_name, &fqdn(subdomain, domain), "A")
+            .await?;
+
+        match rrset.and_then(|rrset| rrset.rrdatas.into_iter().next()) {
+            Some(value) => {
+                let ip = value.parse().map_err(|_| DnsServiceError::UnknownError)?;
+                Ok(Some(ip))
+            }
+            None => Ok(None),
+        }
+    }
+
+    async fn resolve_ttl(
+        &self,
+        subdomain: &str,
+        domain: &str,
+    ) -> Result<Option<u32>, DnsServiceError> {
+        let zone_name = self.zone_name(domain).await?;
+        let rrset = self
+            .find_rrset(&zone_name, &fqdn(subdomain, domain), "A")
+            .await?;
+        Ok(rrset.map(|rrset| rrset.ttl))
+    }
+
+    async fn update_dns(
+        &self,
+        subdomain: &str,
+        domain: &str,
+        ip: Ipv4Addr,
+    ) -> Result<(), DnsServiceError> {
+        self.update_dns_with_ttl(subdomain, domain, ip, None).await
+    }
+
+    async fn update_dns_with_ttl(
+        &self,
+        subdomain: &str,
+        domain: &str,
+        ip: Ipv4Addr,
+        ttl: Option<u32>,
+    ) -> Result<(), DnsServiceError> {
+        debug!(
+            "Update dns for domain {} and subdomain {} with IP {}.",
+            domain, subdomain, ip
+        );
+        self.replace_rrset(
+            domain,
+            &fqdn(subdomain, domain),
+            "A",
+            &[ip.to_string()],
+            ttl,
+        )
+        .await?;
+        Ok(())
+    }
+
+    async fn list_zones(&self) -> Result<Vec<String>, DnsServiceError> {
+        let zones = self.list_managed_zones(None).await?;
+        let mut zone_names = self.zone_names.lock().unwrap();
+        Ok(zones
+            .into_iter()
+            .map(|zone| {
+                let dns_name = zone.dns_name.trim_end_matches('.').to_string();
+                zone_names.insert(dns_name.clone(), zone.name);
+                dns_name
+            })
+            .collect())
+    }
+
+    fn supports_record_type(&self, _record_type: &str) -> bool {
+        true
+    }
+
+    async fn resolve_record(
+        &self,
+        subdomain: &str,
+        domain: &str,
+        record_type: &str,
+        prefix: &str,
+    ) -> Result<Option<String>, DnsServiceError> {
+        let zone_name = self.zone_name(domain).await?;
+        let rrset = self
+            .find_rrset(&zone_name, &fqdn(subdomain, domain), record_type)
+            .await?;
+        Ok(rrset.and_then(|rrset| managed_value(record_type, &rrset.rrdatas, prefix)))
+    }
+
+    async fn update_record(
+        &self,
+        subdomain: &str,
+        domain: &str,
+        record_type: &str,
+        prefix: &str,
+        value: &str,
+    ) -> Result<(), DnsServiceError> {
+        let zone_name = self.zone_name(domain).await?;
+        let name = fqdn(subdomain, domain);
+        let current = self
+            .find_rrset(&zone_name, &name, record_type)
+            .await?
+            .map(|rrset| rrset.rrdatas)
+            .unwrap_or_default();
+        self.replace_rrset(
+            domain,
+            &name,
+            record_type,
+            &replace_managed(record_type, &current, prefix, value),
+            None,
+        )
+        .await?;
+        Ok(())
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum GoogleCloudDnsError {
+    #[error("The service-account key is not valid: {message}")]
+    InvalidKey { message: String },
+    #[error("No project is configured and the service-account key does not name one.")]
+    MissingProject,
+    #[error("No access token was issued: {message}")]
+    TokenRequestFailed { message: String },
+    #[error("The service account is not permitted to do this: {message}")]
+    PermissionDenied { message: String },
+    #[error("The managed zone for {zone} does not exist.")]
+    ZoneNotFound { zone: String },
+    #[error("The zone or change does not exist.")]
+    NotFound,
+    #[error("Cloud DNS rejected the change: {message}")]
+    Rejected { message: String },
+    #[error("API request failed with status {status}: {message}")]
+    Api { status: u16, message: String },
+    #[error("The change {change} was not done in time.")]
+    ChangeTimeout { change: String },
+    #[error("request failed")]
+    RequestFailed {
+        #[from]
+        source: reqwest::Error,
+    },
+}
+
+impl From<GoogleCloudDnsError> for DnsServiceError {
+    fn from(e: GoogleCloudDnsError) -> Self {
+        match e {
+            GoogleCloudDnsError::ZoneNotFound { .. } | GoogleCloudDnsError::NotFound => {
+                DnsServiceError::UnknownZone
+            }
+            GoogleCloudDnsError::Rejected { .. } => DnsServiceError::UpdateRejected {
+                record: e.to_string(),
+            },
+            _ => DnsServiceError::ClientError,
+        }
+    }
+}
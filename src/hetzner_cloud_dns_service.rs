@@ -0,0 +1,521 @@
+use async_trait::async_trait;
+use log::{debug, error};
+use reqwest::{Client, Method, RequestBuilder, Response, StatusCode};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::net::Ipv4Addr;
+use std::time::Duration;
+use thiserror::Error;
+
+use crate::dns_service::{DnsService, DnsServiceError};
+use crate::record_template::{api_value, is_managed, template_value};
+
+const API_URL: &str = "https://api.hetzner.cloud/v1";
+const PER_PAGE: u32 = 100;
+const ACTION_POLL_INTERVAL: Duration = Duration::from_secs(1);
+const ACTION_POLL_ATTEMPTS: u32 = 120;
+
+#[derive(Clone, Deserialize, Serialize, Debug, Default, PartialEq, Eq)]
+pub struct CloudZone {
+    pub id: u64,
+    pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ttl: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mode: Option<String>,
+}
+
+#[derive(Clone, Deserialize, Serialize, Debug, Default, PartialEq, Eq)]
+pub struct RRSet {
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub id: String,
+    pub name: String,
+    pub r#type: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ttl: Option<u32>,
+    pub records: Vec<RRSetRecord>,
+}
+
+#[derive(Clone, Deserialize, Serialize, Debug, Default, PartialEq, Eq)]
+pub struct RRSetRecord {
+    pub value: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub comment: Option<String>,
+}
+
+#[derive(Clone, Deserialize, Serialize, Debug, Default, PartialEq, Eq)]
+pub struct Action {
+    pub id: u64,
+    pub command: String,
+    pub status: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<ActionError>,
+}
+
+#[derive(Clone, Deserialize, Serialize, Debug, Default, PartialEq, Eq)]
+pub struct ActionError {
+    pub code: String,
+    pub message: String,
+}
+
+#[derive(Clone, Deserialize, Serialize, Debug, Default, PartialEq, Eq)]
+pub struct CloudMeta {
+    pub pagination: CloudPagination,
+}
+
+#[derive(Clone, Deserialize, Serialize, Debug, Default, PartialEq, Eq)]
+pub struct CloudPagination {
+    pub page: u32,
+    pub per_page: u32,
+    #[serde(default)]
+    pub next_page: Option<u32>,
+    #[serde(default)]
+    pub last_page: Option<u32>,
+    #[serde(default)]
+    pub total_entries: Option<u32>,
+}
+
+#[derive(Deserialize, Serialize, Debug)]
+pub struct ZonesResponse {
+    pub zones: Vec<CloudZone>,
+    #[serde(default)]
+    pub meta: CloudMeta,
+}
+
+#[derive(Deserialize, Serialize, Debug)]
+pub struct RRSetsResponse {
+    pub rrsets: Vec<RRSet>,
+    #[serde(default)]
+    pub meta: CloudMeta,
+}
+
+#[derive(Deserialize, Serialize, Debug)]
+pub struct RRSetResponse {
+    pub rrset: RRSet,
+}
+
+#[derive(Deserialize, Serialize, Debug)]
+pub struct ActionResponse {
+    pub action: Action,
+}
+
+#[derive(Deserialize, Serialize, Debug)]
+pub struct CreateRRSetResponse {
+    pub rrset: RRSet,
+    pub action: Action,
+}
+
+#[derive(Deserialize, Serialize, Debug)]
+pub struct SetRecordsRequest {
+    pub records: Vec<RRSetRecord>,
+}
+
+#[derive(Deserialize, Serialize, Debug)]
+pub struct ChangeTtlRequest {
+    pub ttl: u32,
+}
+
+#[derive(Deserialize)]
+struct ErrorResponse {
+    error: ErrorBody,
+}
+
+#[derive(Deserialize)]
+struct ErrorBody {
+    code: String,
+    message: String,
+}
+
+pub struct HetznerCloudDnsClient {
+    api_url: String,
+    api_token: String,
+    action_poll_interval: Duration,
+    client: Client,
+}
+
+impl HetznerCloudDnsClient {
+    pub fn new(api_token: &str) -> Self {
+        Self::new_with_url(api_token, API_URL)
+    }
+
+    pub fn new_with_url(api_token: &str, api_url: &str) -> Self {
+        let client = Client::new();
+        Self {
+            api_url: String::from(api_url),
+            api_token: String::from(api_token),
+            action_poll_interval: ACTION_POLL_INTERVAL,
+            client,
+        }
+    }
+
+    pub fn with_action_poll_interval(mut self, action_poll_interval: Duration) -> Self {
+        self.action_poll_interval = action_poll_interval;
+        self
+    }
+
+    pub async fn list_zones(&self) -> Result<Vec<CloudZone>, HetznerCloudDnsClientError> {
+        let mut zones = Vec::new();
+        let mut page = Some(1);
+        while let Some(current) = page {
+            let request = self
+                .request(Method::GET, "/zones")
+                .query(&[("page", current), ("per_page", PER_PAGE)]);
+            let response: ZonesResponse = self.send_json(request).await?;
+            zones.extend(response.zones);
+            page = response.meta.pagination.next_page;
+        }
+        Ok(zones)
+    }
+
+    pub async fn find_zone(
+        &self,
+        zone: &str,
+    ) -> Result<Option<CloudZone>, HetznerCloudDnsClientError> {
+        let mut page = Some(1);
+        while let Some(current) = page {
+            let request = self
+                .request(Method::GET, "/zones")
+                .query(&[("name", zone)])
+                .query(&[("page", current), ("per_page", PER_PAGE)]);
+            let response: ZonesResponse = self.send_json(request).await?;
+            if let Some(zone) = response.zones.into_iter().find(|z| z.name == zone) {
+                return Ok(Some(zone));
+            }
+            page = response.meta.pagination.next_page;
+        }
+        Ok(None)
+    }
+
+    pub async fn list_rrsets(&self, zone: &str) -> Result<Vec<RRSet>, HetznerCloudDnsClientError> {
+        let mut rrsets = Vec::new();
+        let mut page = Some(1);
+        while let Some(current) = page {
+            let request = self
+                .request(Method::GET, &format!("/zones/{}/rrsets", zone))
+                .query(&[("page", current), ("per_page", PER_PAGE)]);
+            let response: RRSetsResponse = self.send_json(request).await?;
+            rrsets.extend(response.rrsets);
+            page = response.meta.pagination.next_page;
+        }
+        Ok(rrsets)
+    }
+
+    pub async fn get_rrset(
+        &self,
+        zone: &str,
+        name: &str,
+        record_type: &str,
+    ) -> Result<Option<RRSet>, HetznerCloudDnsClientError> {
+        let request = self.request(
+            Method::GET,
+            &format!("/zones/{}/rrsets/{}/{}", zone, name, record_type),
+        );
+        match self.send_json::<RRSetResponse>(request).await {
+            Ok(response) => Ok(Some(response.rrset)),
+            Err(HetznerCloudDnsClientError::Api { status: 404, .. }) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    pub async fn create_rrset(
+        &self,
+        zone: &str,
+        rrset: &RRSet,
+    ) -> Result<Action, HetznerCloudDnsClientError> {
+        let request = self
+            .request(Method::POST, &format!("/zones/{}/rrsets", zone))
+            .json(rrset);
+        let response: CreateRRSetResponse = self.send_json(request).await?;
+        Ok(response.action)
+    }
+
+    pub async fn set_records(
+        &self,
+        zone: &str,
+        name: &str,
+        record_type: &str,
+        records: Vec<RRSetRecord>,
+    ) -> Result<Action, HetznerCloudDnsClientError> {
+        let request = self
+            .request(
+                Method::POST,
+                &format!(
+                    "/zones/{}/rrsets/{}/{}/actions/set_records",
+                    zone, name, record_type
+                ),
+            )
+            .json(&SetRecordsRequest { records });
+        let response: ActionResponse = self.send_json(request).await?;
+        Ok(response.action)
+    }
+
+    pub async fn change_ttl(
+        &self,
+        zone: &str,
+        name: &str,
+        record_type: &str,
+        ttl: u32,
+    ) -> Result<Action, HetznerCloudDnsClientError> {
+        let request = self
+            .request(
+                Method::POST,
+                &format!(
+                    "/zones/{}/rrsets/{}/{}/actions/change_ttl",
+                    zone, name, record_type
+                ),
+            )
+            .json(&ChangeTtlRequest { ttl });
+        let response: ActionResponse = self.send_json(request).await?;
+        Ok(response.action)
+    }
+
+    pub async fn get_action(&self, action_id: u64) -> Result<Action, HetznerCloudDnsClientError> {
+        let request = self.request(Method::GET, &format!("/actions/{}", action_id));
+        let response: ActionResponse = self.send_json(request).await?;
+        Ok(response.action)
+    }
+
+    pub async fn wait_for_action(
+        &self,
+        action: Action,
+    ) -> Result<Action, HetznerCloudDnsClientError> {
+        let mut action = action;
+        for _ in 0..ACTION_POLL_ATTEMPTS {
+            match action.status.as_str() {
+                "running" => {
+                    debug!("Waiting for action {} ({}).", action.id, action.command);
+                    tokio::time::sleep(self.action_poll_interval).await;
+                    action = self.get_action(action.id).await?;
+                }
+                "success" => return Ok(action),
+                _ => {
+                    let message = action
+                        .error
+                        .map(|e| format!("{}: {}", e.code, e.message))
+                        .unwrap_or_else(|| action.status.clone());
+                    return Err(HetznerCloudDnsClientError::ActionFailed {
+                        action: action.id,
+                        message,
+                    });
+                }
+            }
+        }
+        Err(HetznerCloudDnsClientError::ActionTimeout { action: action.id })
+    }
+
+    fn request(&self, method: Method, path: &str) -> RequestBuilder {
+        self.client
+            .request(method, format!("{}{}", self.api_url, path))
+            .bearer_auth(&self.api_token)
+    }
+
+    async fn send(&self, request: RequestBuilder) -> Result<Response, HetznerCloudDnsClientError> {
+        let response = request.send().await?;
+        let status = response.status();
+
+        if status.is_success() {
+            return Ok(response);
+        }
+        if status == StatusCode::UNAUTHORIZED {
+            return Err(HetznerCloudDnsClientError::InvalidApiToken);
+        }
+
+        let body = response.text().await.unwrap_or_default();
+        let message = serde_json::from_str::<ErrorResponse>(&body)
+            .map(|e| format!("{}: {}", e.error.code, e.error.message))
+            .unwrap_or(body);
+        error!("Hetzner Cloud API returned {}: {}", status, message);
+        Err(HetznerCloudDnsClientError::Api {
+            status: status.as_u16(),
+            message,
+        })
+    }
+
+    async fn send_json<T: DeserializeOwned>(
+        &self,
+        request: RequestBuilder,
+    ) -> Result<T, HetznerCloudDnsClientError> {
+        Ok(self.send(request).await?.json().await?)
+    }
+}
+
+#[async_trait]
+impl DnsService for HetznerCloudDnsClient {
+    async fn resolve_ip(
+        &self,
+        subdomain: &str,
+        domain: &str,
+    ) -> Result<Option<Ipv4Addr>, DnsServiceError> {
+        debug!(
+            "Resolve ip for domain {} and subdomain {}.",
+            domain, subdomain
+        );
+        let rrset = self.get_rrset(domain, subdomain, "A").await?;
+
+        match rrset.and_then(|rrset| rrset.records.into_iter().next()) {
+            Some(record) => {
+                let ip = record
+                    .value
+                    .parse()
+                    .map_err(|_| DnsServiceError::UnknownError)?;
+                Ok(Some(ip))
+            }
+            None => Ok(None),
+        }
+    }
+
+    async fn resolve_ttl(
+        &self,
+        subdomain: &str,
+        domain: &str,
+    ) -> Result<Option<u32>, DnsServiceError> {
+        let rrset = self.get_rrset(domain, subdomain, "A").await?;
+        Ok(rrset.and_then(|rrset| rrset.ttl))
+    }
+
+    async fn update_dns(
+        &self,
+        subdomain: &str,
+        domain: &str,
+        ip: Ipv4Addr,
+    ) -> Result<(), DnsServiceError> {
+        self.update_dns_with_ttl(subdomain, domain, ip, None).await
+    }
+
+    async fn update_dns_with_ttl(
+        &self,
+        subdomain: &str,
+        domain: &str,
+        ip: Ipv4Addr,
+        ttl: Option<u32>,
+    ) -> Result<(), DnsServiceError> {
+        debug!(
+            "Update dns for domain {} and subdomain {} with IP {}.",
+            domain, subdomain, ip
+        );
+        let records = vec![RRSetRecord {
+            value: ip.to_string(),
+            comment: None,
+        }];
+
+        match self.get_rrset(domain, subdomain, "A").await? {
+            Some(rrset) => {
+                let action = self.set_records(domain, subdomain, "A", records).await?;
+                self.wait_for_action(action).await?;
+                if let Some(ttl) = ttl.filter(|ttl| rrset.ttl != Some(*ttl)) {
+                    let action = self.change_ttl(domain, subdomain, "A", ttl).await?;
+                    self.wait_for_action(action).await?;
+                }
+            }
+            None => {
+                let rrset = RRSet {
+                    name: subdomain.to_string(),
+                    r#type: String::from("A"),
+                    ttl,
+                    records,
+                    ..Default::default()
+                };
+                let action = self.create_rrset(domain, &rrset).await?;
+                self.wait_for_action(action).await?;
+            }
+        }
+        Ok(())
+    }
+
+    async fn list_zones(&self) -> Result<Vec<String>, DnsServiceError> {
+        let zones = HetznerCloudDnsClient::list_zones(self).await?;
+        Ok(zones.into_iter().map(|zone| zone.name).collect())
+    }
+
+    fn supports_record_type(&self, _record_type: &str) -> bool {
+        true
+    }
+
+    async fn resolve_record(
+        &self,
+        subdomain: &str,
+        domain: &str,
+        record_type: &str,
+        prefix: &str,
+    ) -> Result<Option<String>, DnsServiceError> {
+        let rrset = self.get_rrset(domain, subdomain, record_type).await?;
+        Ok(rrset.and_then(|rrset| {
+            rrset
+                .records
+                .into_iter()
+                .find(|record| is_managed(record_type, &record.value, prefix))
+                .map(|record| template_value(record_type, &record.value))
+        }))
+    }
+
+    async fn update_record(
+        &self,
+        subdomain: &str,
+        domain: &str,
+        record_type: &str,
+        prefix: &str,
+        value: &str,
+    ) -> Result<(), DnsServiceError> {
+        let managed = RRSetRecord {
+            value: api_value(record_type, value),
+            comment: None,
+        };
+
+        let action = match self.get_rrset(domain, subdomain, record_type).await? {
+            Some(rrset) => {
+                // `set_records` replaces the whole set, so the records we do not manage are sent
+                // back with their comments.
+                let mut records = rrset.records;
+                match records
+                    .iter()
+                    .position(|record| is_managed(record_type, &record.value, prefix))
+                {
+                    Some(position) => records[position] = managed,
+                    None => records.push(managed),
+                }
+                self.set_records(domain, subdomain, record_type, records)
+                    .await?
+            }
+            None => {
+                let records = vec![managed];
+                let rrset = RRSet {
+                    name: subdomain.to_string(),
+                    r#type: record_type.to_string(),
+                    records,
+                    ..Default::default()
+                };
+                self.create_rrset(domain, &rrset).await?
+            }
+        };
+        self.wait_for_action(action).await?;
+        Ok(())
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum HetznerCloudDnsClientError {
+    #[error("We could not authenticate against the API.")]
+    InvalidApiToken,
+    #[error("API request failed with status {status}: {message}")]
+    Api { status: u16, message: String },
+    #[error("Action {action} failed: {message}")]
+    ActionFailed { action: u64, message: String },
+    #[error("Action {action} did not finish in time")]
+    ActionTimeout { action: u64 },
+    #[error("request failed")]
+    RequestFailed {
+        #[from]
+        source: reqwest::Error,
+    },
+}
+
+impl From<HetznerCloudDnsClientError> for DnsServiceError {
+    fn from(e: HetznerCloudDnsClientError) -> Self {
+        match e {
+            HetznerCloudDnsClientError::Api { status: 404, .. } => DnsServiceError::UnknownZone,
+            HetznerCloudDnsClientError::ActionFailed { .. } => DnsServiceError::UpdateRejected {
+                record: e.to_string(),
+            },
+            _ => DnsServiceError::ClientError,
+        }
+    }
+}
@@ -1,22 +1,49 @@
-use log::error;
-use reqwest::{Client, StatusCode};
-use serde::{Deserialize, Serialize};
+use log::{error, warn};
+use reqwest::{Client, Method, RequestBuilder, Response, StatusCode};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
 use std::net::Ipv4Addr;
 use thiserror::Error;
 
+use crate::rate_limit::RateLimiter;
+
 #[cfg(test)]
 use mockall::automock;
 
 const API_URL: &str = "https://dns.hetzner.com/api/v1";
+const MAX_RATE_LIMIT_RETRIES: u32 = 5;
+const PER_PAGE: u32 = 100;
 
 #[derive(Deserialize, Serialize, Debug)]
 pub struct GetZonesResponse {
     pub zones: Vec<Zone>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub meta: Option<Meta>,
 }
 
 #[derive(Deserialize, Serialize, Debug)]
 pub struct GetRecordsResponse {
     pub records: Vec<Record>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub meta: Option<Meta>,
+}
+
+#[derive(Clone, Deserialize, Serialize, Debug, PartialEq, Eq)]
+pub struct Meta {
+    pub pagination: Pagination,
+}
+
+#[derive(Clone, Deserialize, Serialize, Debug, PartialEq, Eq)]
+pub struct Pagination {
+    pub page: u32,
+    pub per_page: u32,
+    pub last_page: u32,
+    pub total_entries: u32,
+}
+
+impl Meta {
+    fn has_next_page(meta: &Option<Meta>) -> bool {
+        matches!(meta, Some(meta) if meta.pagination.page < meta.pagination.last_page)
+    }
 }
 
 #[derive(Deserialize, Serialize, Debug)]
@@ -27,31 +54,152 @@ pub struct UpdateRecordResponse {
 #[derive(Deserialize, Serialize, Debug)]
 pub struct UpdateRecordRequest {
     pub name: String,
-    pub ttl: u16,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ttl: Option<u32>,
     pub r#type: String,
     pub value: String,
     pub zone_id: String,
 }
 
+#[derive(Deserialize, Serialize, Debug)]
+pub struct RecordResponse {
+    pub record: Record,
+}
+
+#[derive(Deserialize, Serialize, Debug)]
+pub struct ZoneResponse {
+    pub zone: Zone,
+}
+
+#[derive(Clone, Deserialize, Serialize, Debug, Default, PartialEq, Eq)]
+pub struct ZoneRequest {
+    pub name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ttl: Option<u32>,
+}
+
+#[derive(Clone, Deserialize, Serialize, Debug, Default, PartialEq, Eq)]
+pub struct RecordRequest {
+    pub name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ttl: Option<u32>,
+    pub r#type: String,
+    pub value: String,
+    pub zone_id: String,
+}
+
+#[derive(Clone, Deserialize, Serialize, Debug, Default, PartialEq, Eq)]
+pub struct BulkRecordUpdate {
+    pub id: String,
+    pub name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ttl: Option<u32>,
+    pub r#type: String,
+    pub value: String,
+    pub zone_id: String,
+}
+
+#[derive(Serialize)]
+struct BulkRequest<'a, T> {
+    records: &'a [T],
+}
+
+#[derive(Deserialize, Serialize, Debug, Default)]
+pub struct BulkCreateRecordsResponse {
+    #[serde(default)]
+    pub records: Vec<Record>,
+    #[serde(default)]
+    pub valid_records: Vec<RecordRequest>,
+    #[serde(default)]
+    pub invalid_records: Vec<RecordRequest>,
+}
+
+#[derive(Deserialize, Serialize, Debug, Default)]
+pub struct BulkUpdateRecordsResponse {
+    #[serde(default)]
+    pub records: Vec<Record>,
+    #[serde(default)]
+    pub failed_records: Vec<BulkRecordUpdate>,
+}
+
+#[derive(Deserialize, Serialize, Debug)]
+pub struct ValidateZoneFileResponse {
+    pub parsed_records: u32,
+    #[serde(default)]
+    pub valid_records: Vec<Record>,
+}
+
+#[derive(Clone, Deserialize, Serialize, Debug, PartialEq, Eq)]
+pub struct PrimaryServer {
+    pub id: String,
+    pub address: String,
+    pub port: u16,
+    pub zone_id: String,
+}
+
 #[derive(Clone, Deserialize, Serialize, Debug, PartialEq, Eq)]
+pub struct PrimaryServerRequest {
+    pub address: String,
+    pub port: u16,
+    pub zone_id: String,
+}
+
+#[derive(Deserialize, Serialize, Debug)]
+pub struct PrimaryServersResponse {
+    pub primary_servers: Vec<PrimaryServer>,
+}
+
+#[derive(Deserialize, Serialize, Debug)]
+pub struct PrimaryServerResponse {
+    pub primary_server: PrimaryServer,
+}
+
+#[derive(Deserialize, Serialize, Debug)]
+pub struct ErrorResponse {
+    pub error: ApiError,
+}
+
+#[derive(Deserialize, Serialize, Debug)]
+pub struct ApiError {
+    pub message: String,
+    #[serde(default)]
+    pub code: u16,
+}
+
+#[derive(Clone, Deserialize, Serialize, Debug, Default, PartialEq, Eq)]
 pub struct Zone {
     pub id: String,
     pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ttl: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub status: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub ns: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub records_count: Option<u32>,
 }
 
-#[derive(Clone, Deserialize, Serialize, Debug)]
+#[derive(Clone, Deserialize, Serialize, Debug, Default, PartialEq, Eq)]
 pub struct Record {
     pub id: String,
     pub zone_id: String,
     pub name: String,
     pub r#type: String,
     pub value: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ttl: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub created: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub modified: Option<String>,
 }
 
 pub struct HetznerDnsClient {
     api_url: String,
     api_token: String,
     client: Client,
+    rate_limiter: RateLimiter,
 }
 
 #[cfg_attr(test, automock)]
@@ -66,34 +214,30 @@ impl HetznerDnsClient {
             api_url: String::from(api_url),
             api_token: String::from(api_token),
             client,
+            rate_limiter: RateLimiter::new(),
         }
     }
 
     pub async fn find_zone(&self, zone: &str) -> Result<Option<Zone>, HetznerDnsClientError> {
-        let response = self
-            .client
-            .get(format!("{}/zones?name={}", self.api_url, zone))
-            .header("Auth-API-Token", &self.api_token)
-            .send()
-            .await?;
-
-        match response.status() {
-            StatusCode::OK => {
-                let response: GetZonesResponse = response.json().await?;
+        let mut page = 1;
+        loop {
+            let request = self
+                .request(Method::GET, "/zones")
+                .query(&[("name", zone)])
+                .query(&[("page", page), ("per_page", PER_PAGE)]);
+            let response: GetZonesResponse = self.send_json(request).await?;
 
-                // THere can only be one zone with a given name, therefore we just take the first
-                // result if present
-                let maybe_zone = response.zones.into_iter().next();
-
-                Ok(maybe_zone)
+            // There can only be one zone with a given name, but the name filter also matches on
+            // parts of the name, so we compare it ourselves.
+            let has_next_page = Meta::has_next_page(&response.meta);
+            if let Some(zone) = response.zones.into_iter().find(|z| z.name == zone) {
+                return Ok(Some(zone));
             }
-            StatusCode::UNAUTHORIZED => Err(HetznerDnsClientError::InvalidApiToken),
-            _ => {
-                error!("Failed to resolve zone for {}.", zone);
-                Err(HetznerDnsClientError::FailedToResolveZone {
-                    zone: zone.to_string(),
-                })
+            if !has_next_page {
+                return Ok(None);
             }
+
+            page += 1;
         }
     }
 
@@ -102,30 +246,68 @@ impl HetznerDnsClient {
         zone_id: &str,
         subdomain: &str,
     ) -> Result<Option<Record>, HetznerDnsClientError> {
-        let response = self
-            .client
-            .get(format!("{}/records?zone_id={}", self.api_url, zone_id))
-            .header("Auth-API-Token", &self.api_token)
-            .send()
-            .await?;
-
-        match response.status() {
-            StatusCode::OK => {
-                let response: GetRecordsResponse = response.json().await?;
-                if let Some(record) = response.records.into_iter().find(|r| r.name == subdomain) {
-                    return Ok(Some(record));
-                }
+        self.find_record_of_type(zone_id, subdomain, "A").await
+    }
+
+    pub async fn find_record_of_type(
+        &self,
+        zone_id: &str,
+        name: &str,
+        record_type: &str,
+    ) -> Result<Option<Record>, HetznerDnsClientError> {
+        let mut page = 1;
+        loop {
+            // The name and type filters keep the pages short, the records are still checked
+            // below in case the API ignores a filter.
+            let request = self
+                .request(Method::GET, "/records")
+                .query(&[("zone_id", zone_id), ("name", name), ("type", record_type)])
+                .query(&[("page", page), ("per_page", PER_PAGE)]);
+            let response: GetRecordsResponse = not_found(self.send_json(request).await)?;
+            let has_next_page = Meta::has_next_page(&response.meta);
+            if let Some(record) = response
+                .records
+                .into_iter()
+                .find(|r| r.name == name && r.r#type == record_type)
+            {
+                return Ok(Some(record));
             }
-            StatusCode::UNAUTHORIZED => return Err(HetznerDnsClientError::InvalidApiToken),
-            _ => {
-                error!("Failed to resolve records for zone id {}.", zone_id);
-                return Err(HetznerDnsClientError::FailedToResolveRecord {
-                    record: zone_id.to_string(),
-                });
+            if !has_next_page {
+                return Ok(None);
             }
+
+            page += 1;
         }
+    }
+
+    /// Returns all records of `record_type` named `name`, such as the TXT records of a name.
+    pub async fn find_records_of_type(
+        &self,
+        zone_id: &str,
+        name: &str,
+        record_type: &str,
+    ) -> Result<Vec<Record>, HetznerDnsClientError> {
+        let mut records = Vec::new();
+        let mut page = 1;
+        loop {
+            let request = self
+                .request(Method::GET, "/records")
+                .query(&[("zone_id", zone_id), ("name", name), ("type", record_type)])
+                .query(&[("page", page), ("per_page", PER_PAGE)]);
+            let response: GetRecordsResponse = not_found(self.send_json(request).await)?;
+            let has_next_page = Meta::has_next_page(&response.meta);
+            records.extend(
+                response
+                    .records
+                    .into_iter()
+                    .filter(|r| r.name == name && r.r#type == record_type),
+            );
+            if !has_next_page {
+                return Ok(records);
+            }
 
-        Ok(None)
+            page += 1;
+        }
     }
 
     pub async fn update_ip(
@@ -134,54 +316,330 @@ impl HetznerDnsClient {
         zone_id: &str,
         record_id: &str,
         ip: Ipv4Addr,
+        ttl: Option<u32>,
     ) -> Result<Record, HetznerDnsClientError> {
         let request_body = UpdateRecordRequest {
             name: name.into(),
-            ttl: 60,
+            ttl,
             r#type: "A".into(),
             zone_id: zone_id.into(),
             value: ip.to_string(),
         };
-        let response = self
-            .client
-            .put(format!("{}/records/{}", self.api_url, record_id))
-            .json(&request_body)
+        let request = self
+            .request(Method::PUT, &format!("/records/{}", record_id))
+            .json(&request_body);
+        let response: UpdateRecordResponse = not_found(self.send_json(request).await)?;
+        Ok(response.record)
+    }
+
+    pub async fn list_zones(&self) -> Result<Vec<Zone>, HetznerDnsClientError> {
+        let mut zones = Vec::new();
+        let mut page = 1;
+        loop {
+            let request = self
+                .request(Method::GET, "/zones")
+                .query(&[("page", page), ("per_page", PER_PAGE)]);
+            let response: GetZonesResponse = self.send_json(request).await?;
+            let has_next_page = Meta::has_next_page(&response.meta);
+            zones.extend(response.zones);
+            if !has_next_page {
+                return Ok(zones);
+            }
+            page += 1;
+        }
+    }
+
+    pub async fn get_zone(&self, zone_id: &str) -> Result<Zone, HetznerDnsClientError> {
+        let request = self.request(Method::GET, &format!("/zones/{}", zone_id));
+        let response: ZoneResponse = self.send_json(request).await?;
+        Ok(response.zone)
+    }
+
+    pub async fn create_zone(&self, zone: &ZoneRequest) -> Result<Zone, HetznerDnsClientError> {
+        let request = self.request(Method::POST, "/zones").json(zone);
+        let response: ZoneResponse = self.send_json(request).await?;
+        Ok(response.zone)
+    }
+
+    pub async fn update_zone(
+        &self,
+        zone_id: &str,
+        zone: &ZoneRequest,
+    ) -> Result<Zone, HetznerDnsClientError> {
+        let request = self
+            .request(Method::PUT, &format!("/zones/{}", zone_id))
+            .json(zone);
+        let response: ZoneResponse = self.send_json(request).await?;
+        Ok(response.zone)
+    }
+
+    pub async fn delete_zone(&self, zone_id: &str) -> Result<(), HetznerDnsClientError> {
+        let request = self.request(Method::DELETE, &format!("/zones/{}", zone_id));
+        self.send(request).await?;
+        Ok(())
+    }
+
+    pub async fn import_zone_file(
+        &self,
+        zone_id: &str,
+        zone_file: &str,
+    ) -> Result<Zone, HetznerDnsClientError> {
+        let request = self
+            .request(Method::POST, &format!("/zones/{}/import", zone_id))
+            .header("Content-Type", "text/plain")
+            .body(zone_file.to_string());
+        let response: ZoneResponse = self.send_json(request).await?;
+        Ok(response.zone)
+    }
+
+    pub async fn export_zone_file(&self, zone_id: &str) -> Result<String, HetznerDnsClientError> {
+        let request = self.request(Method::GET, &format!("/zones/{}/export", zone_id));
+        Ok(self.send(request).await?.text().await?)
+    }
+
+    pub async fn validate_zone_file(
+        &self,
+        zone_file: &str,
+    ) -> Result<ValidateZoneFileResponse, HetznerDnsClientError> {
+        let request = self
+            .request(Method::POST, "/zones/file/validate")
+            .header("Content-Type", "text/plain")
+            .body(zone_file.to_string());
+        self.send_json(request).await
+    }
+
+    pub async fn list_records(&self, zone_id: &str) -> Result<Vec<Record>, HetznerDnsClientError> {
+        let mut records = Vec::new();
+        let mut page = 1;
+        loop {
+            let request = self
+                .request(Method::GET, "/records")
+                .query(&[("zone_id", zone_id)])
+                .query(&[("page", page), ("per_page", PER_PAGE)]);
+            let response: GetRecordsResponse = self.send_json(request).await?;
+            let has_next_page = Meta::has_next_page(&response.meta);
+            records.extend(response.records);
+            if !has_next_page {
+                return Ok(records);
+            }
+            page += 1;
+        }
+    }
+
+    pub async fn get_record(&self, record_id: &str) -> Result<Record, HetznerDnsClientError> {
+        let request = self.request(Method::GET, &format!("/records/{}", record_id));
+        let response: RecordResponse = self.send_json(request).await?;
+        Ok(response.record)
+    }
+
+    pub async fn create_record(
+        &self,
+        record: &RecordRequest,
+    ) -> Result<Record, HetznerDnsClientError> {
+        let request = self.request(Method::POST, "/records").json(record);
+        let response: RecordResponse = self.send_json(request).await?;
+        Ok(response.record)
+    }
+
+    pub async fn update_record(
+        &self,
+        record_id: &str,
+        record: &RecordRequest,
+    ) -> Result<Record, HetznerDnsClientError> {
+        let request = self
+            .request(Method::PUT, &format!("/records/{}", record_id))
+            .json(record);
+        let response: RecordResponse = self.send_json(request).await?;
+        Ok(response.record)
+    }
+
+    pub async fn delete_record(&self, record_id: &str) -> Result<(), HetznerDnsClientError> {
+        let request = self.request(Method::DELETE, &format!("/records/{}", record_id));
+        self.send(request).await?;
+        Ok(())
+    }
+
+    pub async fn bulk_create_records(
+        &self,
+        records: &[RecordRequest],
+    ) -> Result<BulkCreateRecordsResponse, HetznerDnsClientError> {
+        let request = self
+            .request(Method::POST, "/records/bulk")
+            .json(&BulkRequest { records });
+        self.send_json(request).await
+    }
+
+    pub async fn bulk_update_records(
+        &self,
+        records: &[BulkRecordUpdate],
+    ) -> Result<BulkUpdateRecordsResponse, HetznerDnsClientError> {
+        let request = self
+            .request(Method::PUT, "/records/bulk")
+            .json(&BulkRequest { records });
+        self.send_json(request).await
+    }
+
+    pub async fn list_primary_servers(
+        &self,
+        zone_id: &str,
+    ) -> Result<Vec<PrimaryServer>, HetznerDnsClientError> {
+        let request = self
+            .request(Method::GET, "/primary_servers")
+            .query(&[("zone_id", zone_id)]);
+        let response: PrimaryServersResponse = self.send_json(request).await?;
+        Ok(response.primary_servers)
+    }
+
+    pub async fn get_primary_server(
+        &self,
+        primary_server_id: &str,
+    ) -> Result<PrimaryServer, HetznerDnsClientError> {
+        let request = self.request(
+            Method::GET,
+            &format!("/primary_servers/{}", primary_server_id),
+        );
+        let response: PrimaryServerResponse = self.send_json(request).await?;
+        Ok(response.primary_server)
+    }
+
+    pub async fn create_primary_server(
+        &self,
+        primary_server: &PrimaryServerRequest,
+    ) -> Result<PrimaryServer, HetznerDnsClientError> {
+        let request = self
+            .request(Method::POST, "/primary_servers")
+            .json(primary_server);
+        let response: PrimaryServerResponse = self.send_json(request).await?;
+        Ok(response.primary_server)
+    }
+
+    pub async fn update_primary_server(
+        &self,
+        primary_server_id: &str,
+        primary_server: &PrimaryServerRequest,
+    ) -> Result<PrimaryServer, HetznerDnsClientError> {
+        let request = self
+            .request(
+                Method::PUT,
+                &format!("/primary_servers/{}", primary_server_id),
+            )
+            .json(primary_server);
+        let response: PrimaryServerResponse = self.send_json(request).await?;
+        Ok(response.primary_server)
+    }
+
+    pub async fn delete_primary_server(
+        &self,
+        primary_server_id: &str,
+    ) -> Result<(), HetznerDnsClientError> {
+        let request = self.request(
+            Method::DELETE,
+            &format!("/primary_servers/{}", primary_server_id),
+        );
+        self.send(request).await?;
+        Ok(())
+    }
+}
+
+impl HetznerDnsClient {
+    fn request(&self, method: Method, path: &str) -> RequestBuilder {
+        self.client
+            .request(method, format!("{}{}", self.api_url, path))
             .header("Auth-API-Token", &self.api_token)
-            .send()
-            .await?;
+    }
 
-        match response.status() {
-            StatusCode::OK => {
-                let response: UpdateRecordResponse = response.json().await?;
+    // Every call goes through the shared rate limiter. Calls rejected with 429 wait for the
+    // limit to reset and are sent again, as long as their body can be cloned.
+    async fn execute(&self, request: RequestBuilder) -> Result<Response, HetznerDnsClientError> {
+        let mut request = request;
+        let mut attempt = 0;
+        loop {
+            let retry = request.try_clone();
+            self.rate_limiter.acquire().await;
+            let response = request.send().await?;
 
-                Ok(response.record)
+            if response.status() != StatusCode::TOO_MANY_REQUESTS {
+                self.rate_limiter.observe(response.headers()).await;
+                return Ok(response);
             }
-            StatusCode::UNAUTHORIZED => Err(HetznerDnsClientError::InvalidApiToken),
-            _ => {
-                error!("Failed to update IP for record id {}.", record_id);
-                Err(HetznerDnsClientError::FaliedToUpdateIp {
-                    record: record_id.to_string(),
-                })
+            self.rate_limiter
+                .observe_throttled(response.headers())
+                .await;
+            match retry {
+                Some(retry) if attempt < MAX_RATE_LIMIT_RETRIES => {
+                    warn!("Hetzner DNS API rate limit exceeded, retrying.");
+                    request = retry;
+                    attempt += 1;
+                }
+                _ => return Ok(response),
             }
         }
     }
+
+    async fn send(&self, request: RequestBuilder) -> Result<Response, HetznerDnsClientError> {
+        let response = self.execute(request).await?;
+        let status = response.status();
+
+        if status.is_success() {
+            return Ok(response);
+        }
+        // Not every error comes with a JSON body, so fall back to the raw text.
+        let body = response.text().await.unwrap_or_default();
+        let message = serde_json::from_str::<ErrorResponse>(&body)
+            .map(|e| e.error.message)
+            .unwrap_or(body);
+        error!("Hetzner DNS API returned {}: {}", status, message);
+        Err(match status {
+            StatusCode::UNAUTHORIZED => HetznerDnsClientError::InvalidApiToken {
+                status: status.as_u16(),
+                message,
+            },
+            StatusCode::TOO_MANY_REQUESTS => HetznerDnsClientError::RateLimited {
+                status: status.as_u16(),
+                message,
+            },
+            _ => HetznerDnsClientError::Api {
+                status: status.as_u16(),
+                message,
+            },
+        })
+    }
+
+    async fn send_json<T: DeserializeOwned>(
+        &self,
+        request: RequestBuilder,
+    ) -> Result<T, HetznerDnsClientError> {
+        Ok(self.send(request).await?.json().await?)
+    }
+}
+
+// Callers refresh cached zone and record ids when a lookup or update answers 404.
+fn not_found<T>(result: Result<T, HetznerDnsClientError>) -> Result<T, HetznerDnsClientError> {
+    match result {
+        Err(HetznerDnsClientError::Api {
+            status: 404,
+            message,
+        }) => Err(HetznerDnsClientError::NotFound {
+            status: 404,
+            message,
+        }),
+        result => result,
+    }
 }
 
 #[derive(Debug, Error)]
 pub enum HetznerDnsClientError {
-    #[error("We could not authenticate against the API.")]
-    InvalidApiToken,
-    #[error("Failed to retrieve zone: {zone}")]
-    FailedToResolveZone { zone: String },
-    #[error("Failed to update ip: {record}")]
-    FaliedToUpdateIp { record: String },
-    #[error("Failed to retrieve record: {record}")]
-    FailedToResolveRecord { record: String },
+    #[error("We could not authenticate against the API ({status}): {message}")]
+    InvalidApiToken { status: u16, message: String },
+    #[error("The zone or record does not exist ({status}): {message}")]
+    NotFound { status: u16, message: String },
+    #[error("API request failed with status {status}: {message}")]
+    Api { status: u16, message: String },
+    #[error("The API rate limit is still exceeded after retrying ({status}): {message}")]
+    RateLimited { status: u16, message: String },
     #[error("request failed")]
     RequestFailed {
         #[from]
         source: reqwest::Error,
     },
-    #[error("internal error")]
-    InternalError,
 }
@@ -0,0 +1,280 @@
+use async_trait::async_trait;
+use log::debug;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::net::{IpAddr, Ipv4Addr};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::Mutex;
+
+use crate::authoritative_dns::fqdn;
+use crate::dns_service::{DnsService, DnsServiceError};
+use crate::local_file::{
+    is_ipv6_record, parse_address, run_reload_command, write_atomically, LocalFileError,
+};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum HostsFormat {
+    /// `/etc/hosts` lines: an address followed by its canonical name and aliases.
+    Hosts,
+    /// dnsmasq `address=/name/.../address` lines.
+    Dnsmasq,
+}
+
+impl FromStr for HostsFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "hosts" => Ok(HostsFormat::Hosts),
+            "dnsmasq" => Ok(HostsFormat::Dnsmasq),
+            _ => Err(format!("unknown hosts file format: {}", s)),
+        }
+    }
+}
+
+struct HostsEntry<'a> {
+    ip: IpAddr,
+    names: Vec<&'a str>,
+    comment: &'a str,
+}
+
+impl HostsFormat {
+    fn parse_line(self, line: &str) -> Option<HostsEntry<'_>> {
+        match self {
+            HostsFormat::Hosts => {
+                let (data, comment) = match line.find('#') {
+                    Some(index) => (&line[..index], &line[index..]),
+                    None => (line, ""),
+                };
+                let mut tokens = data.split_whitespace();
+                let ip = tokens.next()?.parse().ok()?;
+                Some(HostsEntry {
+                    ip,
+                    names: tokens.collect(),
+                    comment,
+                })
+            }
+            HostsFormat::Dnsmasq => {
+                let fields: Vec<&str> = line.trim().strip_prefix("address=/")?.split('/').collect();
+                let (ip, names) = fields.split_last()?;
+                Some(HostsEntry {
+                    ip: ip.parse().ok()?,
+                    names: names.to_vec(),
+                    comment: "",
+                })
+            }
+        }
+    }
+
+    fn format_line(self, ip: IpAddr, names: &[&str], comment: &str) -> String {
+        match self {
+            HostsFormat::Hosts if comment.is_empty() => format!("{}\t{}", ip, names.join(" ")),
+            HostsFormat::Hosts => format!("{}\t{} {}", ip, names.join(" "), comment),
+            HostsFormat::Dnsmasq => format!("address=/{}/{}", names.join("/"), ip),
+        }
+    }
+}
+
+/// A hosts file or dnsmasq snippet. Lines that do not mention a managed name are kept as they
+/// are.
+pub struct HostsFile {
+    lines: Vec<String>,
+    format: HostsFormat,
+}
+
+impl HostsFile {
+    pub fn parse(contents: &str, format: HostsFormat) -> Self {
+        Self {
+            lines: contents.lines().map(String::from).collect(),
+            format,
+        }
+    }
+
+    pub fn get(&self, name: &str, ipv6: bool) -> Option<IpAddr> {
+        self.lines
+            .iter()
+            .filter_map(|line| self.format.parse_line(line))
+            .find(|entry| entry.ip.is_ipv6() == ipv6 && has_name(entry, name))
+            .map(|entry| entry.ip)
+    }
+
+    /// Points `name` at `ip`. The first line with `name` as its canonical name gets the new
+    /// address, `name` is removed from the other lines of the same address family, and a line
+    /// is appended if there was none. Returns whether the file changed.
+    pub fn set(&mut self, name: &str, ip: IpAddr) -> bool {
+        let mut updated = false;
+        let mut lines = Vec::with_capacity(self.lines.len() + 1);
+
+        for line in &self.lines {
+            let entry = match self.format.parse_line(line) {
+                Some(entry) if entry.ip.is_ipv6() == ip.is_ipv6() && has_name(&entry, name) => {
+                    entry
+                }
+                _ => {
+                    lines.push(line.clone());
+                    continue;
+                }
+            };
+
+            if !updated && entry.names[0].eq_ignore_ascii_case(name) {
+                updated = true;
+                match entry.ip == ip {
+                    true => lines.push(line.clone()),
+                    false => lines.push(self.format.format_line(ip, &entry.names, entry.comment)),
+                }
+            } else {
+                let names: Vec<&str> = entry
+                    .names
+                    .into_iter()
+                    .filter(|n| !n.eq_ignore_ascii_case(name))
+                    .collect();
+                if !names.is_empty() {
+                    lines.push(self.format.format_line(entry.ip, &names, entry.comment));
+                }
+            }
+        }
+        if !updated {
+            lines.push(self.format.format_line(ip, &[name], ""));
+        }
+
+        let changed = lines != self.lines;
+        self.lines = lines;
+        changed
+    }
+}
+
+impl fmt::Display for HostsFile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for line in &self.lines {
+            writeln!(f, "{}", line)?;
+        }
+        Ok(())
+    }
+}
+
+fn has_name(entry: &HostsEntry, name: &str) -> bool {
+    entry.names.iter().any(|n| n.eq_ignore_ascii_case(name))
+}
+
+/// Writes addresses into a hosts file or dnsmasq snippet and runs an optional reload command.
+/// A missing file is created.
+pub struct HostsFileDnsService {
+    path: PathBuf,
+    format: HostsFormat,
+    reload_command: Option<String>,
+    lock: Mutex<()>,
+}
+
+impl HostsFileDnsService {
+    pub fn new(path: impl AsRef<Path>, format: HostsFormat) -> Self {
+        Self {
+            path: path.as_ref().to_path_buf(),
+            format,
+            reload_command: None,
+            lock: Mutex::new(()),
+        }
+    }
+
+    pub fn with_reload_command(mut self, command: &str) -> Self {
+        self.reload_command = Some(String::from(command));
+        self
+    }
+
+    pub fn read_hosts(&self) -> Result<HostsFile, LocalFileError> {
+        let contents = match fs::read_to_string(&self.path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => String::new(),
+            Err(e) => return Err(e.into()),
+        };
+        Ok(HostsFile::parse(&contents, self.format))
+    }
+
+    pub async fn set_address(&self, name: &str, ip: IpAddr) -> Result<(), LocalFileError> {
+        let changed = {
+            let _guard = self.lock.lock().unwrap();
+            let mut hosts = self.read_hosts()?;
+            if hosts.set(name, ip) {
+                debug!("Write {} with {} for {}.", self.path.display(), ip, name);
+                write_atomically(&self.path, &hosts.to_string())?;
+                true
+            } else {
+                false
+            }
+        };
+
+        if let (true, Some(command)) = (changed, &self.reload_command) {
+            run_reload_command(command).await?;
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl DnsService for HostsFileDnsService {
+    async fn resolve_ip(
+        &self,
+        subdomain: &str,
+        domain: &str,
+    ) -> Result<Option<Ipv4Addr>, DnsServiceError> {
+        debug!(
+            "Resolve ip for domain {} and subdomain {}.",
+            domain, subdomain
+        );
+        match self.read_hosts()?.get(&fqdn(subdomain, domain), false) {
+            Some(IpAddr::V4(ip)) => Ok(Some(ip)),
+            _ => Ok(None),
+        }
+    }
+
+    async fn update_dns(
+        &self,
+        subdomain: &str,
+        domain: &str,
+        ip: Ipv4Addr,
+    ) -> Result<(), DnsServiceError> {
+        debug!(
+            "Update dns for domain {} and subdomain {} with IP {}.",
+            domain, subdomain, ip
+        );
+        self.set_address(&fqdn(subdomain, domain), IpAddr::V4(ip))
+            .await?;
+        Ok(())
+    }
+
+    fn is_local(&self) -> bool {
+        true
+    }
+
+    fn supports_record_type(&self, record_type: &str) -> bool {
+        is_ipv6_record(record_type).is_ok()
+    }
+
+    async fn resolve_record(
+        &self,
+        subdomain: &str,
+        domain: &str,
+        record_type: &str,
+        _prefix: &str,
+    ) -> Result<Option<String>, DnsServiceError> {
+        let ipv6 = is_ipv6_record(record_type)?;
+        let hosts = self.read_hosts()?;
+        Ok(hosts
+            .get(&fqdn(subdomain, domain), ipv6)
+            .map(|ip| ip.to_string()))
+    }
+
+    async fn update_record(
+        &self,
+        subdomain: &str,
+        domain: &str,
+        record_type: &str,
+        _prefix: &str,
+        value: &str,
+    ) -> Result<(), DnsServiceError> {
+        let ip = parse_address(record_type, value)?;
+        self.set_address(&fqdn(subdomain, domain), ip).await?;
+        Ok(())
+    }
+}
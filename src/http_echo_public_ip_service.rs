@@ -0,0 +1,37 @@
+use async_trait::async_trait;
+use reqwest::{Client, StatusCode};
+use std::net::Ipv4Addr;
+
+use crate::public_ip_service::{PublicIpService, PublicIpServiceError};
+
+pub struct HttpEchoPublicIpService {
+    url: String,
+    client: Client,
+}
+
+impl HttpEchoPublicIpService {
+    pub fn new(url: &str) -> Self {
+        let client = Client::new();
+        Self {
+            url: String::from(url),
+            client,
+        }
+    }
+}
+
+#[async_trait]
+impl PublicIpService for HttpEchoPublicIpService {
+    async fn get_ip(&self) -> Result<Ipv4Addr, PublicIpServiceError> {
+        let response = self.client.get(&self.url).send().await?;
+
+        match response.status() {
+            StatusCode::OK => {
+                let body = response.text().await?;
+                body.trim()
+                    .parse()
+                    .map_err(|_| PublicIpServiceError::InvalidIpResponse)
+            }
+            _ => Err(PublicIpServiceError::InvalidIpResponse),
+        }
+    }
+}
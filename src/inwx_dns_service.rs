@@ -0,0 +1,429 @@
+use async_trait::async_trait;
+use log::{debug, error};
+use reqwest::header::{COOKIE, SET_COOKIE};
+use reqwest::Client;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::net::Ipv4Addr;
+use std::sync::Mutex;
+use thiserror::Error;
+
+use crate::authoritative_dns::fqdn;
+use crate::dns_service::{DnsService, DnsServiceError};
+use crate::record_template::{api_value, is_managed, managed_value};
+
+const API_URL: &str = "https://api.domrobot.com/jsonrpc/";
+const DEFAULT_TTL: u32 = 3600;
+
+const CODE_OK: u32 = 1000;
+const CODE_OK_PENDING: u32 = 1001;
+const CODE_AUTHENTICATION_ERROR: u32 = 2200;
+const CODE_OBJECT_EXISTS: u32 = 2302;
+const CODE_OBJECT_DOES_NOT_EXIST: u32 = 2303;
+
+#[derive(Debug, Serialize)]
+struct InwxRequest<'a> {
+    method: &'a str,
+    params: Value,
+}
+
+#[derive(Debug, Deserialize)]
+struct InwxResponse {
+    code: u32,
+    #[serde(default)]
+    msg: String,
+    #[serde(default, rename = "resData")]
+    res_data: Value,
+}
+
+#[derive(Clone, Deserialize, Serialize, Debug, Default, PartialEq, Eq)]
+pub struct InwxRecord {
+    pub id: u64,
+    pub name: String,
+    pub r#type: String,
+    pub content: String,
+    #[serde(default)]
+    pub ttl: Option<u32>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct InfoResponse {
+    #[serde(default)]
+    record: Vec<InwxRecord>,
+}
+
+#[derive(Debug, Deserialize)]
+struct InwxDomain {
+    domain: String,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct ListResponse {
+    #[serde(default)]
+    domains: Vec<InwxDomain>,
+}
+
+/// Client for the INWX DomRobot JSON-RPC API. Like ubus, calls run in a session that is opened
+/// with `account.login`; the session cookie is kept and renewed when the server drops it.
+pub struct InwxClient {
+    api_url: String,
+    username: String,
+    password: String,
+    session: Mutex<Option<String>>,
+    client: Client,
+}
+
+impl InwxClient {
+    pub fn new(username: &str, password: &str) -> Self {
+        Self::new_with_url(username, password, API_URL)
+    }
+
+    pub fn new_with_url(username: &str, password: &str, api_url: &str) -> Self {
+        let client = Client::new();
+        Self {
+            api_url: String::from(api_url),
+            username: String::from(username),
+            password: String::from(password),
+            session: Mutex::new(None),
+            client,
+        }
+    }
+
+    pub async fn login(&self) -> Result<String, InwxError> {
+        let request = InwxRequest {
+            method: "account.login",
+            params: json!({ "user": self.username, "pass": self.password }),
+        };
+        let response = self
+            .client
+            .post(&self.api_url)
+            .json(&request)
+            .send()
+            .await?;
+        let session = response
+            .headers()
+            .get_all(SET_COOKIE)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .filter_map(|value| value.split(';').next())
+            .find(|cookie| cookie.starts_with("domrobot="))
+            .map(str::to_string);
+        let response: InwxResponse = response.json().await?;
+        check(&response)?;
+
+        let session = session.ok_or(InwxError::MissingSession)?;
+        *self.session.lock().unwrap() = Some(session.clone());
+        Ok(session)
+    }
+
+    pub async fn list_domains(&self) -> Result<Vec<String>, InwxError> {
+        let response: ListResponse = self.call("nameserver.list", json!({})).await?;
+        Ok(response.domains.into_iter().map(|d| d.domain).collect())
+    }
+
+    pub async fn find_record(
+        &self,
+        domain: &str,
+        subdomain: &str,
+        record_type: &str,
+    ) -> Result<Option<InwxRecord>, InwxError> {
+        Ok(self
+            .find_records(domain, subdomain, record_type)
+            .await?
+            .into_iter()
+            .next())
+    }
+
+    pub async fn find_records(
+        &self,
+        domain: &str,
+        subdomain: &str,
+        record_type: &str,
+    ) -> Result<Vec<InwxRecord>, InwxError> {
+        let response: InfoResponse = self
+            .call(
+                "nameserver.info",
+                json!({ "domain": domain, "name": record_name(subdomain), "type": record_type }),
+            )
+            .await?;
+        let name = fqdn(subdomain, domain);
+        Ok(response
+            .record
+            .into_iter()
+            .filter(|r| r.name.eq_ignore_ascii_case(&name) && r.r#type == record_type)
+            .collect())
+    }
+
+    pub async fn create_record(
+        &self,
+        domain: &str,
+        subdomain: &str,
+        record_type: &str,
+        content: &str,
+        ttl: u32,
+    ) -> Result<(), InwxError> {
+        let _: Value = self
+            .call(
+                "nameserver.createRecord",
+                json!({
+                    "domain": domain,
+                    "name": record_name(subdomain),
+                    "type": record_type,
+                    "content": content,
+                    "ttl": ttl,
+                }),
+            )
+            .await?;
+        Ok(())
+    }
+
+    pub async fn update_record(
+        &self,
+        id: u64,
+        content: &str,
+        ttl: Option<u32>,
+    ) -> Result<(), InwxError> {
+        let mut params = json!({ "id": id, "content": content });
+        if let Some(ttl) = ttl {
+            params["ttl"] = json!(ttl);
+        }
+        let _: Value = self.call("nameserver.updateRecord", params).await?;
+        Ok(())
+    }
+
+    async fn set_record(
+        &self,
+        domain: &str,
+        subdomain: &str,
+        record_type: &str,
+        prefix: &str,
+        content: &str,
+        ttl: Option<u32>,
+    ) -> Result<(), InwxError> {
+        let managed = self
+            .find_records(domain, subdomain, record_type)
+            .await?
+            .into_iter()
+            .find(|record| is_managed(record_type, &record.content, prefix));
+        match managed {
+            Some(record) => self.update_record(record.id, content, ttl).await,
+            None => {
+                self.create_record(
+                    domain,
+                    subdomain,
+                    record_type,
+                    content,
+                    ttl.unwrap_or(DEFAULT_TTL),
+                )
+                .await
+            }
+        }
+    }
+
+    /// Calls `method` in the current session, logging in first if there is none. A session
+    /// the server no longer knows is replaced once.
+    async fn call<T: DeserializeOwned>(&self, method: &str, params: Value) -> Result<T, InwxError> {
+        let session = self.session.lock().unwrap().clone();
+        let session = match session {
+            Some(session) => session,
+            None => self.login().await?,
+        };
+
+        let response = self.send(method, &params, &session).await?;
+        let response = if response.code == CODE_AUTHENTICATION_ERROR {
+            debug!("INWX session expired, logging in again.");
+            let session = self.login().await?;
+            self.send(method, &params, &session).await?
+        } else {
+            response
+        };
+        check(&response)?;
+
+        let res_data = match response.res_data {
+            Value::Null => json!({}),
+            res_data => res_data,
+        };
+        serde_json::from_value(res_data).map_err(|e| InwxError::InvalidResponse {
+            message: e.to_string(),
+        })
+    }
+
+    async fn send(
+        &self,
+        method: &str,
+        params: &Value,
+        session: &str,
+    ) -> Result<InwxResponse, InwxError> {
+        let request = InwxRequest {
+            method,
+            params: params.clone(),
+        };
+        Ok(self
+            .client
+            .post(&self.api_url)
+            .header(COOKIE, session)
+            .json(&request)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?)
+    }
+}
+
+fn check(response: &InwxResponse) -> Result<(), InwxError> {
+    match response.code {
+        CODE_OK | CODE_OK_PENDING => Ok(()),
+        code => {
+            error!("INWX API returned {}: {}", code, response.msg);
+            let message = response.msg.clone();
+            Err(match code {
+                CODE_AUTHENTICATION_ERROR => InwxError::InvalidCredentials,
+                CODE_OBJECT_DOES_NOT_EXIST => InwxError::NotFound,
+                CODE_OBJECT_EXISTS | 2004 | 2005 | 2306 => InwxError::Rejected { code, message },
+                _ => InwxError::Api { code, message },
+            })
+        }
+    }
+}
+
+/// INWX names the apex with an empty record name.
+fn record_name(subdomain: &str) -> &str {
+    match subdomain {
+        "@" => "",
+        _ => subdomain,
+    }
+}
+
+#[async_trait]
+impl DnsService for InwxClient {
+    async fn resolve_ip(
+        &self,
+        subdomain: &str,
+        domain: &str,
+    ) -> Result<Option<Ipv4Addr>, DnsServiceError> {
+        debug!(
+            "Resolve ip for domain {} and subdomain {}.",
+            domain, subdomain
+        );
+        let record = self.find_record(domain, subdomain, "A").await?;
+
+        match record {
+            Some(record) => {
+                let ip = record
+                    .content
+                    .parse()
+                    .map_err(|_| DnsServiceError::UnknownError)?;
+                Ok(Some(ip))
+            }
+            None => Ok(None),
+        }
+    }
+
+    async fn resolve_ttl(
+        &self,
+        subdomain: &str,
+        domain: &str,
+    ) -> Result<Option<u32>, DnsServiceError> {
+        let record = self.find_record(domain, subdomain, "A").await?;
+        Ok(record.and_then(|record| record.ttl))
+    }
+
+    async fn update_dns(
+        &self,
+        subdomain: &str,
+        domain: &str,
+        ip: Ipv4Addr,
+    ) -> Result<(), DnsServiceError> {
+        self.update_dns_with_ttl(subdomain, domain, ip, None).await
+    }
+
+    async fn update_dns_with_ttl(
+        &self,
+        subdomain: &str,
+        domain: &str,
+        ip: Ipv4Addr,
+        ttl: Option<u32>,
+    ) -> Result<(), DnsServiceError> {
+        debug!(
+            "Update dns for domain {} and subdomain {} with IP {}.",
+            domain, subdomain, ip
+        );
+        self.set_record(domain, subdomain, "A", "", &ip.to_string(), ttl)
+            .await?;
+        Ok(())
+    }
+
+    async fn list_zones(&self) -> Result<Vec<String>, DnsServiceError> {
+        Ok(self.list_domains().await?)
+    }
+
+    fn supports_record_type(&self, _record_type: &str) -> bool {
+        true
+    }
+
+    async fn resolve_record(
+        &self,
+        subdomain: &str,
+        domain: &str,
+        record_type: &str,
+        prefix: &str,
+    ) -> Result<Option<String>, DnsServiceError> {
+        let records = self.find_records(domain, subdomain, record_type).await?;
+        let values: Vec<String> = records.into_iter().map(|record| record.content).collect();
+        Ok(managed_value(record_type, &values, prefix))
+    }
+
+    async fn update_record(
+        &self,
+        subdomain: &str,
+        domain: &str,
+        record_type: &str,
+        prefix: &str,
+        value: &str,
+    ) -> Result<(), DnsServiceError> {
+        self.set_record(
+            domain,
+            subdomain,
+            record_type,
+            prefix,
+            &api_value(record_type, value),
+            None,
+        )
+        .await?;
+        Ok(())
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum InwxError {
+    #[error("We could not log in to the API.")]
+    InvalidCredentials,
+    #[error("The login did not return a session cookie.")]
+    MissingSession,
+    #[error("The domain or record does not exist.")]
+    NotFound,
+    #[error("INWX rejected the change with code {code}: {message}")]
+    Rejected { code: u32, message: String },
+    #[error("API call failed with code {code}: {message}")]
+    Api { code: u32, message: String },
+    #[error("invalid response: {message}")]
+    InvalidResponse { message: String },
+    #[error("request failed")]
+    RequestFailed {
+        #[from]
+        source: reqwest::Error,
+    },
+}
+
+impl From<InwxError> for DnsServiceError {
+    fn from(e: InwxError) -> Self {
+        match e {
+            InwxError::NotFound => DnsServiceError::UnknownZone,
+            InwxError::Rejected { .. } => DnsServiceError::UpdateRejected {
+                record: e.to_string(),
+            },
+            _ => DnsServiceError::ClientError,
+        }
+    }
+}
@@ -1,6 +1,37 @@
+pub mod address_policy;
+pub mod adguard_home_dns_service;
+pub mod authoritative_dns;
+pub mod aws_sigv4;
+pub mod cloudflare_dns_service;
 pub mod config;
+pub mod dampening;
+pub mod desec_dns_service;
+pub mod dns_message;
 pub mod dns_service;
+pub mod dyndns2_dns_service;
 pub mod dyndns_service;
+pub mod gandi_dns_service;
+pub mod google_cloud_dns_service;
+pub mod hetzner_cloud_dns_service;
 pub mod hetzner_dns_client;
+pub mod hosts_file_dns_service;
+pub mod http_echo_public_ip_service;
+pub mod inwx_dns_service;
+pub mod local_file;
+pub mod openwrt_dns_service;
+pub mod ovh_dns_service;
+pub mod pihole_dns_service;
+pub mod powerdns_dns_service;
 pub mod public_ip_service;
+pub mod rate_limit;
+pub mod record_template;
+pub mod rfc2136_dns_service;
+pub mod route53_dns_service;
+pub mod status;
+pub mod stun_public_ip_service;
+pub mod time;
+pub mod tsig;
+pub mod ttl;
 pub mod ubus_jsonrpc_public_ip_service;
+pub mod zone_detection;
+pub mod zone_file_dns_service;
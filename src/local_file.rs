@@ -0,0 +1,108 @@
+use log::{debug, error};
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::path::Path;
+use thiserror::Error;
+use tokio::process::Command;
+
+use crate::dns_service::DnsServiceError;
+
+/// Replaces `path` with `contents` by writing a temporary file next to it and renaming it over
+/// the original, so readers never see a partially written file. The permissions of an existing
+/// file are kept.
+pub fn write_atomically(path: &Path, contents: &str) -> io::Result<()> {
+    let file_name = path
+        .file_name()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "path does not name a file"))?;
+    let temp_path = path.with_file_name(format!(".{}.dyndnsd.tmp", file_name.to_string_lossy()));
+
+    let result = (|| {
+        let mut file = File::create(&temp_path)?;
+        if let Ok(metadata) = fs::metadata(path) {
+            file.set_permissions(metadata.permissions())?;
+        }
+        file.write_all(contents.as_bytes())?;
+        file.sync_all()?;
+        fs::rename(&temp_path, path)
+    })();
+    if result.is_err() {
+        let _ = fs::remove_file(&temp_path);
+    }
+    result
+}
+
+/// Address records are the only records hosts files and local DNS tables know about.
+pub fn is_ipv6_record(record_type: &str) -> Result<bool, DnsServiceError> {
+    match record_type {
+        "A" => Ok(false),
+        "AAAA" => Ok(true),
+        _ => Err(DnsServiceError::Unsupported {
+            record_type: record_type.to_string(),
+        }),
+    }
+}
+
+pub fn parse_address(record_type: &str, value: &str) -> Result<IpAddr, DnsServiceError> {
+    let ip = match is_ipv6_record(record_type)? {
+        false => value.parse::<Ipv4Addr>().map(IpAddr::V4).ok(),
+        true => value.parse::<Ipv6Addr>().map(IpAddr::V6).ok(),
+    };
+    Ok(ip.ok_or_else(|| LocalFileError::InvalidAddress {
+        value: String::from(value),
+    })?)
+}
+
+/// Runs `command` through `sh -c`, e.g. `rndc reload example.com` or
+/// `systemctl reload dnsmasq`.
+pub async fn run_reload_command(command: &str) -> Result<(), LocalFileError> {
+    debug!("Run reload command {}.", command);
+    let output = Command::new("sh").arg("-c").arg(command).output().await?;
+
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr).trim().to_string();
+        error!(
+            "Reload command {} failed with {}: {}",
+            command, output.status, stderr
+        );
+        return Err(LocalFileError::ReloadFailed {
+            command: String::from(command),
+            status: output.status.to_string(),
+            output: stderr,
+        });
+    }
+    Ok(())
+}
+
+#[derive(Debug, Error)]
+pub enum LocalFileError {
+    #[error("The zone file is not valid: {message}")]
+    InvalidZone { message: String },
+    #[error("The zone file is for {found}, not {expected}.")]
+    ZoneMismatch { expected: String, found: String },
+    #[error("The record value {value} is not an address.")]
+    InvalidAddress { value: String },
+    #[error("Reload command {command} failed with {status}: {output}")]
+    ReloadFailed {
+        command: String,
+        status: String,
+        output: String,
+    },
+    #[error("file access failed")]
+    Io {
+        #[from]
+        source: io::Error,
+    },
+}
+
+impl From<LocalFileError> for DnsServiceError {
+    fn from(e: LocalFileError) -> Self {
+        match e {
+            LocalFileError::ZoneMismatch { .. } => DnsServiceError::UnknownZone,
+            LocalFileError::InvalidAddress { .. } => DnsServiceError::UpdateRejected {
+                record: e.to_string(),
+            },
+            _ => DnsServiceError::ClientError,
+        }
+    }
+}
@@ -1,33 +1,359 @@
 use clokwerk::{Scheduler, TimeUnits};
 use dyndnsd::{
-    config::CliConfig,
-    dns_service::HetznerDnsService,
-    dyndns_service::{DynDnsService, DynDnsServiceError},
+    address_policy::AddressPolicy,
+    adguard_home_dns_service::AdGuardHomeClient,
+    authoritative_dns::AuthoritativeResolver,
+    aws_sigv4::AwsCredentials,
+    cloudflare_dns_service::CloudflareDnsClient,
+    config::{CliConfig, DnsProvider},
+    dampening::DampeningPolicy,
+    desec_dns_service::DesecClient,
+    dns_service::{DnsService, DnsServiceError, HetznerDnsService},
+    dyndns2_dns_service::Dyndns2Client,
+    dyndns_service::{render_plan, DynDnsService, DynDnsServiceError},
+    gandi_dns_service::GandiClient,
+    google_cloud_dns_service::{GoogleCloudDnsClient, ServiceAccountKey},
+    hetzner_cloud_dns_service::HetznerCloudDnsClient,
+    hosts_file_dns_service::HostsFileDnsService,
+    http_echo_public_ip_service::HttpEchoPublicIpService,
+    inwx_dns_service::InwxClient,
+    openwrt_dns_service::OpenWrtDnsService,
+    ovh_dns_service::{endpoint_url, OvhClient},
+    pihole_dns_service::PiholeClient,
+    powerdns_dns_service::PowerDnsClient,
+    public_ip_service::PublicIpService,
+    record_template::parse_record_templates,
+    rfc2136_dns_service::Rfc2136DnsService,
+    route53_dns_service::Route53Client,
+    status::{serve_metrics, Status},
+    stun_public_ip_service::StunPublicIpService,
+    tsig::TsigKey,
+    ttl::{parse_record_ttls, TtlManager},
     ubus_jsonrpc_public_ip_service::UbusJsonRpcClient,
+    zone_detection::{detect_zone, ZoneDetectionError},
+    zone_file_dns_service::ZoneFileDnsService,
 };
 use envconfig::Envconfig;
+use log::error;
+use std::sync::Arc;
 use std::time::Duration;
 use tokio::sync::mpsc::channel;
 
+fn api_token(config: &CliConfig) -> &str {
+    config
+        .api_token
+        .as_deref()
+        .expect("DYNDNSD_HETZNER_API_TOKEN must be set")
+}
+
+fn dns_service(
+    config: &CliConfig,
+    domain: Option<&str>,
+    ubus: &Arc<UbusJsonRpcClient>,
+) -> Box<dyn DnsService> {
+    match config.dns_provider {
+        DnsProvider::Hetzner => {
+            let resolver = match &config.name_servers {
+                Some(servers) => AuthoritativeResolver::new(
+                    &servers.split(',').map(str::trim).collect::<Vec<_>>(),
+                ),
+                None => AuthoritativeResolver::hetzner(),
+            };
+
+            let mut dns_service = HetznerDnsService::new(api_token(config))
+                .with_cached_values(config.cache_record_values)
+                .with_cache_max_age(Duration::from_secs(config.cache_max_age_seconds));
+            if let (Some(domain), Some(zone_id)) = (domain, &config.zone_id) {
+                dns_service = dns_service.with_zone_id(domain, zone_id);
+            }
+            if config.resolve_via_dns {
+                dns_service = dns_service.with_live_dns(resolver.clone());
+            }
+            if config.verify_update {
+                dns_service = dns_service.with_verification(
+                    resolver,
+                    Duration::from_secs(config.verify_timeout_seconds),
+                );
+            }
+            Box::new(dns_service)
+        }
+        DnsProvider::HetznerCloud => Box::new(HetznerCloudDnsClient::new(api_token(config))),
+        DnsProvider::Rfc2136 => {
+            let server = config
+                .rfc2136_server
+                .as_deref()
+                .expect("DYNDNSD_RFC2136_SERVER must be set");
+            let mut dns_service = Rfc2136DnsService::new(server);
+            if let (Some(name), Some(secret)) = (&config.tsig_key_name, &config.tsig_secret) {
+                let key = TsigKey::from_base64(name, config.tsig_algorithm, secret)
+                    .expect("DYNDNSD_TSIG_SECRET must be base64");
+                dns_service = dns_service.with_tsig_key(key);
+            }
+            Box::new(dns_service)
+        }
+        DnsProvider::Cloudflare => {
+            let api_token = config
+                .cloudflare_api_token
+                .as_deref()
+                .expect("DYNDNSD_CLOUDFLARE_API_TOKEN must be set");
+            let mut dns_service = CloudflareDnsClient::new(api_token);
+            if let Some(proxied) = config.cloudflare_proxied {
+                dns_service = dns_service.with_proxied(proxied);
+            }
+            Box::new(dns_service)
+        }
+        DnsProvider::Route53 => {
+            let credentials = AwsCredentials::load()
+                .expect("AWS credentials must be set in the environment or a profile");
+            Box::new(Route53Client::new(credentials))
+        }
+        DnsProvider::PowerDns => {
+            let api_url = config
+                .powerdns_url
+                .as_deref()
+                .expect("DYNDNSD_POWERDNS_URL must be set");
+            let api_key = config
+                .powerdns_api_key
+                .as_deref()
+                .expect("DYNDNSD_POWERDNS_API_KEY must be set");
+            let mut dns_service = PowerDnsClient::new(api_url, api_key);
+            if let Some(server_id) = &config.powerdns_server_id {
+                dns_service = dns_service.with_server_id(server_id);
+            }
+            Box::new(dns_service)
+        }
+        DnsProvider::Desec => {
+            let api_token = config
+                .desec_token
+                .as_deref()
+                .expect("DYNDNSD_DESEC_TOKEN must be set");
+            Box::new(DesecClient::new(api_token))
+        }
+        DnsProvider::Dyndns2 => {
+            let server_url = config
+                .dyndns2_url
+                .as_deref()
+                .expect("DYNDNSD_DYNDNS2_URL must be set");
+            let username = config
+                .dyndns2_username
+                .as_deref()
+                .expect("DYNDNSD_DYNDNS2_USERNAME must be set");
+            let password = config
+                .dyndns2_password
+                .as_deref()
+                .expect("DYNDNSD_DYNDNS2_PASSWORD must be set");
+            let mut dns_service = Dyndns2Client::new(server_url, username, password);
+            if let Some(state_file) = &config.dyndns2_state_file {
+                dns_service = dns_service.with_state_file(state_file);
+            }
+            if let (true, Some(servers)) = (config.resolve_via_dns, &config.name_servers) {
+                dns_service = dns_service.with_live_dns(AuthoritativeResolver::new(
+                    &servers.split(',').map(str::trim).collect::<Vec<_>>(),
+                ));
+            }
+            Box::new(dns_service)
+        }
+        DnsProvider::Ovh => {
+            let application_key = config
+                .ovh_application_key
+                .as_deref()
+                .expect("DYNDNSD_OVH_APPLICATION_KEY must be set");
+            let application_secret = config
+                .ovh_application_secret
+                .as_deref()
+                .expect("DYNDNSD_OVH_APPLICATION_SECRET must be set");
+            let consumer_key = config
+                .ovh_consumer_key
+                .as_deref()
+                .expect("DYNDNSD_OVH_CONSUMER_KEY must be set");
+            Box::new(OvhClient::new_with_url(
+                application_key,
+                application_secret,
+                consumer_key,
+                endpoint_url(&config.ovh_endpoint),
+            ))
+        }
+        DnsProvider::Gandi => {
+            let token = config
+                .gandi_token
+                .as_deref()
+                .expect("DYNDNSD_GANDI_TOKEN must be set");
+            Box::new(GandiClient::new(token))
+        }
+        DnsProvider::Inwx => {
+            let username = config
+                .inwx_username
+                .as_deref()
+                .expect("DYNDNSD_INWX_USERNAME must be set");
+            let password = config
+                .inwx_password
+                .as_deref()
+                .expect("DYNDNSD_INWX_PASSWORD must be set");
+            Box::new(InwxClient::new(username, password))
+        }
+        DnsProvider::GoogleCloud => {
+            let credentials_file = config
+                .google_credentials_file
+                .clone()
+                .or_else(|| std::env::var("GOOGLE_APPLICATION_CREDENTIALS").ok())
+                .expect("DYNDNSD_GOOGLE_CREDENTIALS_FILE must be set");
+            let key = ServiceAccountKey::from_file(&credentials_file)
+                .expect("DYNDNSD_GOOGLE_CREDENTIALS_FILE must be a service-account key");
+            let dns_service = GoogleCloudDnsClient::new(key, config.google_project.as_deref())
+                .expect("DYNDNSD_GOOGLE_PROJECT must be set for this service-account key");
+            Box::new(dns_service)
+        }
+        DnsProvider::ZoneFile => {
+            let path = config
+                .zone_file
+                .as_deref()
+                .expect("DYNDNSD_ZONE_FILE must be set");
+            let mut dns_service = ZoneFileDnsService::new(path);
+            if let Some(command) = &config.reload_command {
+                dns_service = dns_service.with_reload_command(command);
+            }
+            Box::new(dns_service)
+        }
+        DnsProvider::HostsFile => {
+            let path = config
+                .hosts_file
+                .as_deref()
+                .expect("DYNDNSD_HOSTS_FILE must be set");
+            let mut dns_service = HostsFileDnsService::new(path, config.hosts_format);
+            if let Some(command) = &config.reload_command {
+                dns_service = dns_service.with_reload_command(command);
+            }
+            Box::new(dns_service)
+        }
+        DnsProvider::Pihole => {
+            let api_url = config
+                .pihole_url
+                .as_deref()
+                .expect("DYNDNSD_PIHOLE_URL must be set");
+            let password = config.pihole_password.as_deref().unwrap_or_default();
+            Box::new(PiholeClient::new(api_url, password))
+        }
+        DnsProvider::AdGuardHome => {
+            let api_url = config
+                .adguard_url
+                .as_deref()
+                .expect("DYNDNSD_ADGUARD_URL must be set");
+            let username = config
+                .adguard_username
+                .as_deref()
+                .expect("DYNDNSD_ADGUARD_USERNAME must be set");
+            let password = config
+                .adguard_password
+                .as_deref()
+                .expect("DYNDNSD_ADGUARD_PASSWORD must be set");
+            Box::new(AdGuardHomeClient::new(api_url, username, password))
+        }
+        DnsProvider::OpenWrt => Box::new(OpenWrtDnsService::new(
+            ubus.clone(),
+            config.openwrt_dns_mode,
+        )),
+    }
+}
+
 #[tokio::main]
 async fn main() -> Result<(), DynDnsServiceError> {
     env_logger::init();
 
     let config = CliConfig::init_from_env().unwrap();
 
-    let mut scheduler = Scheduler::new();
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let plan_only = args.iter().any(|arg| arg == "plan" || arg == "--plan");
+    let dry_run = config.dry_run || plan_only || args.iter().any(|arg| arg == "--dry-run");
 
-    let dns_service = HetznerDnsService::new(&config.api_token);
-    let ubus_service =
-        UbusJsonRpcClient::new(&config.ubus_url, &config.ubus_user, &config.ubus_secret);
+    let mut scheduler = Scheduler::new();
 
-    let dyndns = DynDnsService::new(
-        &config.domain,
-        &config.subdomain,
-        Box::new(dns_service),
-        Box::new(ubus_service),
+    let address_policy = AddressPolicy {
+        mask: config.wan_mask,
+        allow_non_public: config.allow_non_public_ip,
+    };
+    // The OpenWrt provider talks to the same rpcd, so both share one client and session.
+    let ubus = Arc::new(
+        UbusJsonRpcClient::new(&config.ubus_url, &config.ubus_user, &config.ubus_secret)
+            .with_interface(&config.ubus_interface)
+            .with_address_policy(address_policy.clone()),
     );
 
+    let status = Status::new();
+    if let Some(metrics_addr) = config.metrics_addr {
+        let status = status.clone();
+        tokio::spawn(async move {
+            if let Err(e) = serve_metrics(metrics_addr, status).await {
+                error!("Metrics endpoint failed: {}", e);
+            }
+        });
+    }
+
+    let mut ttl_manager = TtlManager::new(config.ttl.clone());
+    if let Some(record_ttls) = &config.record_ttls {
+        for (record, policy) in parse_record_ttls(record_ttls).unwrap() {
+            ttl_manager = ttl_manager.with_record(&record, policy);
+        }
+    }
+
+    let service = dns_service(&config, config.domain.as_deref(), &ubus);
+    let (domain, subdomains) = match (&config.name, &config.domain, &config.subdomain) {
+        (Some(names), _, _) => {
+            let names: Vec<&str> = names.split(',').map(str::trim).collect();
+            match detect_zone(service.as_ref(), &names).await {
+                Err(ZoneDetectionError::DnsServiceError {
+                    source: DnsServiceError::ZoneListingUnsupported,
+                }) => panic!(
+                    "The {} provider cannot list zones, \
+                     set DYNDNSD_DOMAIN and DYNDNSD_SUBDOMAIN instead of DYNDNSD_NAME",
+                    config.dns_provider
+                ),
+                result => result?,
+            }
+        }
+        (None, Some(domain), Some(subdomain)) => (
+            domain.clone(),
+            subdomain.split(',').map(|s| s.trim().to_string()).collect(),
+        ),
+        _ => panic!("Either DYNDNSD_NAME or DYNDNSD_DOMAIN and DYNDNSD_SUBDOMAIN must be set"),
+    };
+    let subdomains: Vec<&str> = subdomains.iter().map(String::as_str).collect();
+    let mut dyndns = DynDnsService::new(&domain, subdomains[0], service, Box::new(ubus.clone()))
+        .with_subdomains(&subdomains)
+        .with_derived_records(
+            config
+                .derived_records
+                .as_deref()
+                .map(|templates| parse_record_templates(templates).unwrap())
+                .unwrap_or_default(),
+        )
+        .with_address_policy(address_policy)
+        .with_dampening_policy(DampeningPolicy {
+            min_observations: config.dampening_min_observations,
+            min_stable: Duration::from_secs(config.dampening_min_stable_seconds),
+            max_updates_per_hour: config.max_updates_per_hour,
+        })
+        .with_ttl_manager(ttl_manager)
+        .with_dry_run(dry_run)
+        .with_status(status);
+
+    let observed_ip_service: Option<Box<dyn PublicIpService>> =
+        match (&config.observed_ip_url, &config.observed_ip_stun_server) {
+            (Some(url), _) => Some(Box::new(HttpEchoPublicIpService::new(url))),
+            (None, Some(server)) => Some(Box::new(StunPublicIpService::new(server))),
+            (None, None) => None,
+        };
+    if let Some(observed_ip_service) = observed_ip_service {
+        dyndns = dyndns.with_observed_ip_service(observed_ip_service);
+    }
+
+    dyndns.check_derived_records()?;
+
+    if plan_only {
+        let (plan, derived) = dyndns.plan_with_derived().await?;
+        print!("{}", render_plan(&plan, &derived));
+        return Ok(());
+    }
+
     let (tx, mut rx) = channel::<()>(1);
 
     let loop_tx = tx.clone();
@@ -38,8 +364,13 @@ async fn main() -> Result<(), DynDnsServiceError> {
 
     tx.clone().send(()).await.unwrap();
 
+    // Errors during a cycle, such as a non-public WAN address behind CGNAT or an update that
+    // slow secondaries do not serve yet, are reported and the next cycle runs as scheduled.
+    // Configuration errors end the process before the loop starts.
     while rx.recv().await.is_some() {
-        dyndns.update_dns_if_required().await?;
+        if let Err(e) = dyndns.update_dns_if_required().await {
+            error!("Update cycle failed: {}", e);
+        }
     }
 
     Ok(())
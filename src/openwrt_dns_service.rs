@@ -0,0 +1,336 @@
+use async_trait::async_trait;
+use log::debug;
+use serde::Deserialize;
+use serde_json::{json, Map, Value};
+use std::collections::HashMap;
+use std::net::{IpAddr, Ipv4Addr};
+use std::str::FromStr;
+use std::sync::Arc;
+use thiserror::Error;
+
+use crate::authoritative_dns::fqdn;
+use crate::dns_service::{DnsService, DnsServiceError};
+use crate::hosts_file_dns_service::{HostsFile, HostsFormat};
+use crate::local_file::{is_ipv6_record, parse_address};
+use crate::ubus_jsonrpc_public_ip_service::{
+    UbusError, UbusJsonRpcClient, UBUS_STATUS_INVALID_ARGUMENT, UBUS_STATUS_NOT_FOUND,
+};
+
+const DHCP: &str = "dhcp";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OpenWrtDnsMode {
+    /// `config domain` sections with a `name` and an `ip` option, as on LuCI's Hostnames page.
+    Domain,
+    /// The `address` list of the first `config dnsmasq` section.
+    Address,
+}
+
+impl FromStr for OpenWrtDnsMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "domain" => Ok(OpenWrtDnsMode::Domain),
+            "address" => Ok(OpenWrtDnsMode::Address),
+            _ => Err(format!("unknown OpenWrt dns mode: {}", s)),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UciSection {
+    #[serde(rename = ".name")]
+    pub name: String,
+    #[serde(rename = ".index", default)]
+    pub index: u32,
+    #[serde(flatten)]
+    pub options: Map<String, Value>,
+}
+
+impl UciSection {
+    pub fn option(&self, name: &str) -> Option<&str> {
+        self.options.get(name).and_then(Value::as_str)
+    }
+
+    pub fn list(&self, name: &str) -> Vec<String> {
+        match self.options.get(name) {
+            Some(Value::Array(values)) => values
+                .iter()
+                .filter_map(Value::as_str)
+                .map(String::from)
+                .collect(),
+            Some(Value::String(value)) => vec![value.clone()],
+            _ => Vec::new(),
+        }
+    }
+
+    fn domain_address(&self, name: &str, ipv6: bool) -> Option<IpAddr> {
+        if !self.option("name")?.eq_ignore_ascii_case(name) {
+            return None;
+        }
+        self.option("ip")?
+            .parse::<IpAddr>()
+            .ok()
+            .filter(|ip| ip.is_ipv6() == ipv6)
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct UciGetResponse {
+    #[serde(default)]
+    values: HashMap<String, UciSection>,
+}
+
+/// Writes split-horizon entries into the router's dnsmasq configuration through the ubus `uci`
+/// object, so LAN clients resolve public names to internal addresses. The ubus client and its
+/// session are shared with the address lookup; changes are committed and dnsmasq is reloaded
+/// afterwards.
+pub struct OpenWrtDnsService {
+    ubus: Arc<UbusJsonRpcClient>,
+    mode: OpenWrtDnsMode,
+}
+
+impl OpenWrtDnsService {
+    pub fn new(ubus: Arc<UbusJsonRpcClient>, mode: OpenWrtDnsMode) -> Self {
+        Self { ubus, mode }
+    }
+
+    /// Returns the `dhcp` sections of `section_type` in file order.
+    pub async fn sections(&self, section_type: &str) -> Result<Vec<UciSection>, UbusError> {
+        let response = self
+            .ubus
+            .call(
+                "uci",
+                "get",
+                json!({ "config": DHCP, "type": section_type }),
+            )
+            .await;
+        let response = match response {
+            Ok(response) => response,
+            Err(UbusError::Status {
+                status: UBUS_STATUS_NOT_FOUND,
+                ..
+            }) => return Ok(Vec::new()),
+            Err(e) => return Err(e),
+        };
+
+        let response: UciGetResponse =
+            serde_json::from_value(response).map_err(|_| UbusError::InvalidResponse)?;
+        let mut sections: Vec<UciSection> = response.values.into_values().collect();
+        sections.sort_by_key(|section| section.index);
+        Ok(sections)
+    }
+
+    pub async fn get_address(
+        &self,
+        name: &str,
+        ipv6: bool,
+    ) -> Result<Option<IpAddr>, OpenWrtError> {
+        match self.mode {
+            OpenWrtDnsMode::Domain => Ok(self
+                .sections("domain")
+                .await?
+                .iter()
+                .find_map(|section| section.domain_address(name, ipv6))),
+            OpenWrtDnsMode::Address => {
+                let (_, addresses) = self.address_list().await?;
+                Ok(address_file(&addresses).get(name, ipv6))
+            }
+        }
+    }
+
+    /// Points `name` at `ip` and applies the change if there was one.
+    pub async fn set_address(&self, name: &str, ip: IpAddr) -> Result<(), OpenWrtError> {
+        let changed = match self.mode {
+            OpenWrtDnsMode::Domain => self.set_domain(name, ip).await?,
+            OpenWrtDnsMode::Address => self.set_address_list(name, ip).await?,
+        };
+
+        if changed {
+            debug!("Commit dhcp and reload dnsmasq.");
+            self.ubus
+                .call("uci", "commit", json!({ "config": DHCP }))
+                .await?;
+            self.ubus
+                .call(
+                    "rc",
+                    "init",
+                    json!({ "name": "dnsmasq", "action": "reload" }),
+                )
+                .await?;
+        }
+        Ok(())
+    }
+
+    async fn set_domain(&self, name: &str, ip: IpAddr) -> Result<bool, OpenWrtError> {
+        let sections = self.sections("domain").await?;
+        let current: Vec<&UciSection> = sections
+            .iter()
+            .filter(|section| section.domain_address(name, ip.is_ipv6()).is_some())
+            .collect();
+
+        let mut changed = false;
+        match current.first() {
+            Some(section) if section.domain_address(name, ip.is_ipv6()) == Some(ip) => {}
+            Some(section) => {
+                let args = json!({
+                    "config": DHCP,
+                    "section": section.name,
+                    "values": { "ip": ip.to_string() }
+                });
+                self.ubus.call("uci", "set", args).await?;
+                changed = true;
+            }
+            None => {
+                let args = json!({
+                    "config": DHCP,
+                    "type": "domain",
+                    "values": { "name": name, "ip": ip.to_string() }
+                });
+                self.ubus.call("uci", "add", args).await?;
+                changed = true;
+            }
+        }
+        for section in current.iter().skip(1) {
+            let args = json!({ "config": DHCP, "section": section.name });
+            self.ubus.call("uci", "delete", args).await?;
+            changed = true;
+        }
+        Ok(changed)
+    }
+
+    async fn set_address_list(&self, name: &str, ip: IpAddr) -> Result<bool, OpenWrtError> {
+        let (section, addresses) = self.address_list().await?;
+        let mut file = address_file(&addresses);
+        if !file.set(name, ip) {
+            return Ok(false);
+        }
+
+        let contents = file.to_string();
+        let addresses: Vec<&str> = contents
+            .lines()
+            .filter_map(|line| line.strip_prefix("address="))
+            .collect();
+        let args = json!({
+            "config": DHCP,
+            "section": section,
+            "values": { "address": addresses }
+        });
+        self.ubus.call("uci", "set", args).await?;
+        Ok(true)
+    }
+
+    async fn address_list(&self) -> Result<(String, Vec<String>), OpenWrtError> {
+        let sections = self.sections("dnsmasq").await?;
+        let section = sections
+            .into_iter()
+            .next()
+            .ok_or(OpenWrtError::NoDnsmasqSection)?;
+        let addresses = section.list("address");
+        Ok((section.name, addresses))
+    }
+}
+
+/// Entries of the `address` list are `address=` options without the option name.
+fn address_file(addresses: &[String]) -> HostsFile {
+    let lines: Vec<String> = addresses
+        .iter()
+        .map(|address| format!("address={}", address))
+        .collect();
+    HostsFile::parse(&lines.join("\n"), HostsFormat::Dnsmasq)
+}
+
+#[async_trait]
+impl DnsService for OpenWrtDnsService {
+    async fn resolve_ip(
+        &self,
+        subdomain: &str,
+        domain: &str,
+    ) -> Result<Option<Ipv4Addr>, DnsServiceError> {
+        debug!(
+            "Resolve ip for domain {} and subdomain {}.",
+            domain, subdomain
+        );
+        match self.get_address(&fqdn(subdomain, domain), false).await? {
+            Some(IpAddr::V4(ip)) => Ok(Some(ip)),
+            _ => Ok(None),
+        }
+    }
+
+    async fn update_dns(
+        &self,
+        subdomain: &str,
+        domain: &str,
+        ip: Ipv4Addr,
+    ) -> Result<(), DnsServiceError> {
+        debug!(
+            "Update dns for domain {} and subdomain {} with IP {}.",
+            domain, subdomain, ip
+        );
+        self.set_address(&fqdn(subdomain, domain), IpAddr::V4(ip))
+            .await?;
+        Ok(())
+    }
+
+    fn is_local(&self) -> bool {
+        true
+    }
+
+    fn supports_record_type(&self, record_type: &str) -> bool {
+        is_ipv6_record(record_type).is_ok()
+    }
+
+    async fn resolve_record(
+        &self,
+        subdomain: &str,
+        domain: &str,
+        record_type: &str,
+        _prefix: &str,
+    ) -> Result<Option<String>, DnsServiceError> {
+        let ipv6 = is_ipv6_record(record_type)?;
+        let ip = self.get_address(&fqdn(subdomain, domain), ipv6).await?;
+        Ok(ip.map(|ip| ip.to_string()))
+    }
+
+    async fn update_record(
+        &self,
+        subdomain: &str,
+        domain: &str,
+        record_type: &str,
+        _prefix: &str,
+        value: &str,
+    ) -> Result<(), DnsServiceError> {
+        let ip = parse_address(record_type, value)?;
+        self.set_address(&fqdn(subdomain, domain), ip).await?;
+        Ok(())
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum OpenWrtError {
+    #[error("The dhcp config has no dnsmasq section.")]
+    NoDnsmasqSection,
+    #[error("ubus call failed")]
+    Ubus {
+        #[from]
+        source: UbusError,
+    },
+}
+
+impl From<OpenWrtError> for DnsServiceError {
+    fn from(e: OpenWrtError) -> Self {
+        match e {
+            OpenWrtError::Ubus {
+                source:
+                    source @ UbusError::Status {
+                        status: UBUS_STATUS_INVALID_ARGUMENT,
+                        ..
+                    },
+            } => DnsServiceError::UpdateRejected {
+                record: source.to_string(),
+            },
+            _ => DnsServiceError::ClientError,
+        }
+    }
+}
@@ -0,0 +1,511 @@
+use async_trait::async_trait;
+use log::{debug, error};
+use reqwest::{Client, Method, StatusCode, Url};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use sha1::{Digest, Sha1};
+use std::net::Ipv4Addr;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+use thiserror::Error;
+
+use crate::dns_service::{DnsService, DnsServiceError};
+use crate::record_template::{api_value, is_managed, managed_value};
+
+const API_URL: &str = "https://eu.api.ovh.com/1.0";
+const DEFAULT_TTL: u32 = 3600;
+
+/// Returns the API URL of a named OVH endpoint. Anything else is taken as a URL.
+pub fn endpoint_url(endpoint: &str) -> &str {
+    match endpoint {
+        "ovh-eu" => API_URL,
+        "ovh-ca" => "https://ca.api.ovh.com/1.0",
+        "ovh-us" => "https://api.us.ovhcloud.com/1.0",
+        _ => endpoint,
+    }
+}
+
+#[derive(Clone, Deserialize, Serialize, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct OvhRecord {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<u64>,
+    pub field_type: String,
+    pub sub_domain: String,
+    pub target: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ttl: Option<u32>,
+}
+
+#[derive(Clone, Deserialize, Serialize, Debug, Default, PartialEq, Eq)]
+pub struct UpdateRecordRequest {
+    pub target: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ttl: Option<u32>,
+}
+
+#[derive(Deserialize)]
+struct ErrorResponse {
+    message: String,
+}
+
+/// Client for the OVH API. Requests are signed with the application secret and consumer key,
+/// using the server's clock which is fetched once from `/auth/time`.
+pub struct OvhClient {
+    api_url: String,
+    application_key: String,
+    application_secret: String,
+    consumer_key: String,
+    time_offset: Mutex<Option<i64>>,
+    client: Client,
+}
+
+impl OvhClient {
+    pub fn new(application_key: &str, application_secret: &str, consumer_key: &str) -> Self {
+        Self::new_with_url(application_key, application_secret, consumer_key, API_URL)
+    }
+
+    pub fn new_with_url(
+        application_key: &str,
+        application_secret: &str,
+        consumer_key: &str,
+        api_url: &str,
+    ) -> Self {
+        let client = Client::new();
+        Self {
+            api_url: String::from(api_url.trim_end_matches('/')),
+            application_key: String::from(application_key),
+            application_secret: String::from(application_secret),
+            consumer_key: String::from(consumer_key),
+            time_offset: Mutex::new(None),
+            client,
+        }
+    }
+
+    pub async fn list_zones(&self) -> Result<Vec<String>, OvhError> {
+        self.call(Method::GET, "/domain/zone", &[], None::<&()>)
+            .await
+    }
+
+    pub async fn find_record(
+        &self,
+        zone: &str,
+        subdomain: &str,
+        record_type: &str,
+    ) -> Result<Option<OvhRecord>, OvhError> {
+        let ids: Vec<u64> = self
+            .call(
+                Method::GET,
+                &format!("/domain/zone/{}/record", zone),
+                &[("fieldType", record_type), ("subDomain", subdomain)],
+                None::<&()>,
+            )
+            .await?;
+
+        match ids.first() {
+            Some(id) => {
+                let record = self
+                    .call(
+                        Method::GET,
+                        &format!("/domain/zone/{}/record/{}", zone, id),
+                        &[],
+                        None::<&()>,
+                    )
+                    .await?;
+                Ok(Some(record))
+            }
+            None => Ok(None),
+        }
+    }
+
+    pub async fn find_records(
+        &self,
+        zone: &str,
+        subdomain: &str,
+        record_type: &str,
+    ) -> Result<Vec<OvhRecord>, OvhError> {
+        let ids: Vec<u64> = self
+            .call(
+                Method::GET,
+                &format!("/domain/zone/{}/record", zone),
+                &[("fieldType", record_type), ("subDomain", subdomain)],
+                None::<&()>,
+            )
+            .await?;
+
+        let mut records = Vec::with_capacity(ids.len());
+        for id in ids {
+            records.push(
+                self.call(
+                    Method::GET,
+                    &format!("/domain/zone/{}/record/{}", zone, id),
+                    &[],
+                    None::<&()>,
+                )
+                .await?,
+            );
+        }
+        Ok(records)
+    }
+
+    pub async fn create_record(&self, zone: &str, record: &OvhRecord) -> Result<(), OvhError> {
+        let _: OvhRecord = self
+            .call(
+                Method::POST,
+                &format!("/domain/zone/{}/record", zone),
+                &[],
+                Some(record),
+            )
+            .await?;
+        Ok(())
+    }
+
+    pub async fn update_record(
+        &self,
+        zone: &str,
+        id: u64,
+        update: &UpdateRecordRequest,
+    ) -> Result<(), OvhError> {
+        self.call(
+            Method::PUT,
+            &format!("/domain/zone/{}/record/{}", zone, id),
+            &[],
+            Some(update),
+        )
+        .await
+    }
+
+    /// Changes only reach the name servers once the zone is refreshed.
+    pub async fn refresh_zone(&self, zone: &str) -> Result<(), OvhError> {
+        self.call(
+            Method::POST,
+            &format!("/domain/zone/{}/refresh", zone),
+            &[],
+            None::<&()>,
+        )
+        .await
+    }
+
+    async fn set_record(
+        &self,
+        zone: &str,
+        subdomain: &str,
+        record_type: &str,
+        prefix: &str,
+        target: String,
+        ttl: Option<u32>,
+    ) -> Result<(), OvhError> {
+        let subdomain = sub_domain(subdomain);
+        let managed = self
+            .find_records(zone, subdomain, record_type)
+            .await?
+            .into_iter()
+            .find(|record| is_managed(record_type, &record.target, prefix));
+        match managed {
+            Some(OvhRecord { id: Some(id), .. }) => {
+                self.update_record(zone, id, &UpdateRecordRequest { target, ttl })
+                    .await?;
+            }
+            _ => {
+                let record = OvhRecord {
+                    id: None,
+                    field_type: record_type.to_string(),
+                    sub_domain: subdomain.to_string(),
+                    target,
+                    ttl: Some(ttl.unwrap_or(DEFAULT_TTL)),
+                };
+                self.create_record(zone, &record).await?;
+            }
+        }
+        self.refresh_zone(zone).await
+    }
+
+    /// The current time of the API server, in seconds since the epoch.
+    async fn timestamp(&self) -> Result<i64, OvhError> {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs() as i64;
+        if let Some(offset) = *self.time_offset.lock().unwrap() {
+            return Ok(now + offset);
+        }
+
+        let server_time: i64 = self
+            .client
+            .get(format!("{}/auth/time", self.api_url))
+            .send()
+            .await?
+            .json()
+            .await?;
+        debug!("OVH API clock is {} seconds off.", server_time - now);
+        *self.time_offset.lock().unwrap() = Some(server_time - now);
+        Ok(server_time)
+    }
+
+    async fn call<T: DeserializeOwned, B: Serialize + ?Sized>(
+        &self,
+        method: Method,
+        path: &str,
+        query: &[(&str, &str)],
+        body: Option<&B>,
+    ) -> Result<T, OvhError> {
+        let url =
+            Url::parse_with_params(&format!("{}{}", self.api_url, path), query).map_err(|e| {
+                OvhError::InvalidUrl {
+                    message: e.to_string(),
+                }
+            })?;
+        let body = match body {
+            Some(body) => serde_json::to_string(body).unwrap_or_default(),
+            None => String::new(),
+        };
+        let timestamp = self.timestamp().await?.to_string();
+        let signature = signature(
+            &self.application_secret,
+            &self.consumer_key,
+            method.as_str(),
+            url.as_str(),
+            &body,
+            &timestamp,
+        );
+
+        let mut request = self
+            .client
+            .request(method, url)
+            .header("X-Ovh-Application", &self.application_key)
+            .header("X-Ovh-Consumer", &self.consumer_key)
+            .header("X-Ovh-Timestamp", timestamp)
+            .header("X-Ovh-Signature", signature);
+        if !body.is_empty() {
+            request = request
+                .header("Content-Type", "application/json")
+                .body(body);
+        }
+
+        let response = request.send().await?;
+        let status = response.status();
+        let text = response.text().await?;
+        if status.is_success() {
+            // Changes answer with an empty body or `null`.
+            let text = if text.is_empty() { "null" } else { &text };
+            return serde_json::from_str(text).map_err(|e| OvhError::InvalidResponse {
+                message: e.to_string(),
+            });
+        }
+
+        let message = serde_json::from_str::<ErrorResponse>(&text)
+            .map(|e| e.message)
+            .unwrap_or(text);
+        error!("OVH API returned {}: {}", status, message);
+        if message.contains("out of time") {
+            // Synchronise the clock again with the next request.
+            *self.time_offset.lock().unwrap() = None;
+        }
+        Err(match status {
+            StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => {
+                OvhError::InvalidCredentials { message }
+            }
+            StatusCode::NOT_FOUND => OvhError::NotFound,
+            StatusCode::BAD_REQUEST => OvhError::Rejected { message },
+            _ => OvhError::Api {
+                status: status.as_u16(),
+                message,
+            },
+        })
+    }
+}
+
+/// `$1$` followed by the hex SHA1 of the secret, consumer key, method, full URL, body and
+/// timestamp, joined with `+`.
+pub fn signature(
+    application_secret: &str,
+    consumer_key: &str,
+    method: &str,
+    url: &str,
+    body: &str,
+    timestamp: &str,
+) -> String {
+    let digest = Sha1::digest(
+        [
+            application_secret,
+            consumer_key,
+            method,
+            url,
+            body,
+            timestamp,
+        ]
+        .join("+")
+        .as_bytes(),
+    );
+    let hex: String = digest.iter().map(|b| format!("{:02x}", b)).collect();
+    format!("$1${}", hex)
+}
+
+/// OVH names the apex with an empty sub domain.
+fn sub_domain(subdomain: &str) -> &str {
+    match subdomain {
+        "@" => "",
+        _ => subdomain,
+    }
+}
+
+#[async_trait]
+impl DnsService for OvhClient {
+    async fn resolve_ip(
+        &self,
+        subdomain: &str,
+        domain: &str,
+    ) -> Result<Option<Ipv4Addr>, DnsServiceError> {
+        debug!(
+            "Resolve ip for domain {} and subdomain {}.",
+            domain, subdomain
+        );
+        let record = self.find_record(domain, sub_domain(subdomain), "A").await?;
+
+        match record {
+            Some(record) => {
+                let ip = record
+                    .target
+                    .parse()
+                    .map_err(|_| DnsServiceError::UnknownError)?;
+                Ok(Some(ip))
+            }
+            None => Ok(None),
+        }
+    }
+
+    async fn resolve_ttl(
+        &self,
+        subdomain: &str,
+        domain: &str,
+    ) -> Result<Option<u32>, DnsServiceError> {
+        let record = self.find_record(domain, sub_domain(subdomain), "A").await?;
+        Ok(record.and_then(|record| record.ttl))
+    }
+
+    async fn update_dns(
+        &self,
+        subdomain: &str,
+        domain: &str,
+        ip: Ipv4Addr,
+    ) -> Result<(), DnsServiceError> {
+        self.update_dns_with_ttl(subdomain, domain, ip, None).await
+    }
+
+    async fn update_dns_with_ttl(
+        &self,
+        subdomain: &str,
+        domain: &str,
+        ip: Ipv4Addr,
+        ttl: Option<u32>,
+    ) -> Result<(), DnsServiceError> {
+        debug!(
+            "Update dns for domain {} and subdomain {} with IP {}.",
+            domain, subdomain, ip
+        );
+        self.set_record(domain, subdomain, "A", "", ip.to_string(), ttl)
+            .await?;
+        Ok(())
+    }
+
+    async fn list_zones(&self) -> Result<Vec<String>, DnsServiceError> {
+        Ok(OvhClient::list_zones(self).await?)
+    }
+
+    fn supports_record_type(&self, _record_type: &str) -> bool {
+        true
+    }
+
+    async fn resolve_record(
+        &self,
+        subdomain: &str,
+        domain: &str,
+        record_type: &str,
+        prefix: &str,
+    ) -> Result<Option<String>, DnsServiceError> {
+        let records = self
+            .find_records(domain, sub_domain(subdomain), record_type)
+            .await?;
+        let values: Vec<String> = records.into_iter().map(|record| record.target).collect();
+        Ok(managed_value(record_type, &values, prefix))
+    }
+
+    async fn update_record(
+        &self,
+        subdomain: &str,
+        domain: &str,
+        record_type: &str,
+        prefix: &str,
+        value: &str,
+    ) -> Result<(), DnsServiceError> {
+        self.set_record(
+            domain,
+            subdomain,
+            record_type,
+            prefix,
+            api_value(record_type, value),
+            None,
+        )
+        .await?;
+        Ok(())
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum OvhError {
+    #[error("The application or consumer key is not valid: {message}")]
+    InvalidCredentials { message: String },
+    #[error("The zone or record does not exist.")]
+    NotFound,
+    #[error("OVH rejected the request: {message}")]
+    Rejected { message: String },
+    #[error("API request failed with status {status}: {message}")]
+    Api { status: u16, message: String },
+    #[error("invalid URL: {message}")]
+    InvalidUrl { message: String },
+    #[error("invalid response: {message}")]
+    InvalidResponse { message: String },
+    #[error("request failed")]
+    RequestFailed {
+        #[from]
+        source: reqwest::Error,
+    },
+}
+
+impl From<OvhError> for DnsServiceError {
+    fn from(e: OvhError) -> Self {
+        match e {
+            OvhError::NotFound => DnsServiceError::UnknownZone,
+            OvhError::Rejected { .. } => DnsServiceError::UpdateRejected {
+                record: e.to_string(),
+            },
+            _ => DnsServiceError::ClientError,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_signature() {
+        let signature = signature(
+            "secret",
+            "consumer",
+            "GET",
+            "https://eu.api.ovh.com/1.0/domain/zone",
+            "",
+            "1366560945",
+        );
+        assert_eq!(signature, "$1$a34af1e37822ab4d6a9fdce5e8fa3341f7dc7543");
+    }
+
+    #[test]
+    fn test_endpoint_url() {
+        assert_eq!(endpoint_url("ovh-eu"), "https://eu.api.ovh.com/1.0");
+        assert_eq!(
+            endpoint_url("http://localhost:8080"),
+            "http://localhost:8080"
+        );
+    }
+}
@@ -0,0 +1,312 @@
+use async_trait::async_trait;
+use log::{debug, error};
+use reqwest::{Client, Method, Response, StatusCode};
+use serde::{de::DeserializeOwned, Deserialize};
+use serde_json::json;
+use std::net::{IpAddr, Ipv4Addr};
+use std::sync::Mutex;
+use thiserror::Error;
+
+use crate::authoritative_dns::fqdn;
+use crate::dns_service::{DnsService, DnsServiceError};
+use crate::hosts_file_dns_service::{HostsFile, HostsFormat};
+use crate::local_file::{is_ipv6_record, parse_address};
+
+const SESSION_HEADER: &str = "X-FTL-SID";
+
+#[derive(Debug, Deserialize)]
+struct AuthResponse {
+    session: PiholeSession,
+}
+
+#[derive(Debug, Deserialize)]
+struct PiholeSession {
+    valid: bool,
+    #[serde(default)]
+    sid: Option<String>,
+    #[serde(default)]
+    message: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct HostsResponse {
+    config: HostsConfig,
+}
+
+#[derive(Debug, Deserialize)]
+struct HostsConfig {
+    dns: HostsDnsConfig,
+}
+
+#[derive(Debug, Deserialize)]
+struct HostsDnsConfig {
+    #[serde(default)]
+    hosts: Vec<String>,
+}
+
+#[derive(Deserialize)]
+struct ErrorResponse {
+    error: PiholeApiError,
+}
+
+#[derive(Deserialize)]
+struct PiholeApiError {
+    message: String,
+    #[serde(default)]
+    hint: Option<String>,
+}
+
+/// Client for the Pi-hole v6 API. Local DNS records are the `dns.hosts` setting, a list of
+/// `address name...` entries in hosts file format. The session opened with the app password is
+/// reused and renewed when Pi-hole drops it, since Pi-hole only allows a few sessions at a time.
+pub struct PiholeClient {
+    api_url: String,
+    password: String,
+    session: Mutex<Option<String>>,
+    client: Client,
+}
+
+impl PiholeClient {
+    pub fn new(api_url: &str, password: &str) -> Self {
+        let client = Client::new();
+        Self {
+            api_url: String::from(api_url.trim_end_matches('/')),
+            password: String::from(password),
+            session: Mutex::new(None),
+            client,
+        }
+    }
+
+    pub async fn login(&self) -> Result<String, PiholeError> {
+        let response = self
+            .client
+            .post(format!("{}/api/auth", self.api_url))
+            .json(&json!({ "password": self.password }))
+            .send()
+            .await?;
+        if response.status() == StatusCode::UNAUTHORIZED {
+            return Err(PiholeError::InvalidCredentials);
+        }
+
+        let response: AuthResponse = check(response).await?.json().await?;
+        if !response.session.valid {
+            error!(
+                "Pi-hole API refused the login: {}",
+                response.session.message.unwrap_or_default()
+            );
+            return Err(PiholeError::InvalidCredentials);
+        }
+
+        // Pi-hole without a password hands out no session id.
+        let session = response.session.sid.unwrap_or_default();
+        *self.session.lock().unwrap() = Some(session.clone());
+        Ok(session)
+    }
+
+    pub async fn list_hosts(&self) -> Result<Vec<String>, PiholeError> {
+        let response: HostsResponse = self.send_json(Method::GET, "/api/config/dns/hosts").await?;
+        Ok(response.config.dns.hosts)
+    }
+
+    pub async fn add_host(&self, entry: &str) -> Result<(), PiholeError> {
+        self.send(Method::PUT, &host_path(entry)).await?;
+        Ok(())
+    }
+
+    pub async fn delete_host(&self, entry: &str) -> Result<(), PiholeError> {
+        self.send(Method::DELETE, &host_path(entry)).await?;
+        Ok(())
+    }
+
+    pub async fn get_address(&self, name: &str, ipv6: bool) -> Result<Option<IpAddr>, PiholeError> {
+        let hosts = self.list_hosts().await?;
+        Ok(HostsFile::parse(&hosts.join("\n"), HostsFormat::Hosts).get(name, ipv6))
+    }
+
+    /// Points `name` at `ip` like a hosts file update. New entries are added before the old
+    /// ones are removed, so the name keeps resolving in between.
+    pub async fn set_address(&self, name: &str, ip: IpAddr) -> Result<(), PiholeError> {
+        let hosts = self.list_hosts().await?;
+        let mut file = HostsFile::parse(&hosts.join("\n"), HostsFormat::Hosts);
+        if !file.set(name, ip) {
+            return Ok(());
+        }
+
+        let entries: Vec<String> = file.to_string().lines().map(String::from).collect();
+        for entry in entries.iter().filter(|entry| !hosts.contains(entry)) {
+            self.add_host(entry).await?;
+        }
+        for entry in hosts.iter().filter(|entry| !entries.contains(entry)) {
+            self.delete_host(entry).await?;
+        }
+        Ok(())
+    }
+
+    /// Sends a request in the current session, logging in first if there is none. A session
+    /// Pi-hole no longer knows is replaced once.
+    async fn send(&self, method: Method, path: &str) -> Result<Response, PiholeError> {
+        let session = self.session.lock().unwrap().clone();
+        let session = match session {
+            Some(session) => session,
+            None => self.login().await?,
+        };
+
+        let mut response = self.request(method.clone(), path, &session).await?;
+        if response.status() == StatusCode::UNAUTHORIZED {
+            debug!("Pi-hole session expired, logging in again.");
+            let session = self.login().await?;
+            response = self.request(method, path, &session).await?;
+        }
+        check(response).await
+    }
+
+    async fn send_json<T: DeserializeOwned>(
+        &self,
+        method: Method,
+        path: &str,
+    ) -> Result<T, PiholeError> {
+        Ok(self.send(method, path).await?.json().await?)
+    }
+
+    async fn request(
+        &self,
+        method: Method,
+        path: &str,
+        session: &str,
+    ) -> Result<Response, PiholeError> {
+        let mut request = self
+            .client
+            .request(method, format!("{}{}", self.api_url, path));
+        if !session.is_empty() {
+            request = request.header(SESSION_HEADER, session);
+        }
+        Ok(request.send().await?)
+    }
+}
+
+/// Pi-hole separates the address and names of an entry with a single space, and the entry is
+/// part of the path.
+fn host_path(entry: &str) -> String {
+    let entry = entry.split_whitespace().collect::<Vec<_>>().join("%20");
+    format!("/api/config/dns/hosts/{}", entry)
+}
+
+async fn check(response: Response) -> Result<Response, PiholeError> {
+    let status = response.status();
+    if status.is_success() {
+        return Ok(response);
+    }
+
+    let body = response.text().await.unwrap_or_default();
+    let message = match serde_json::from_str::<ErrorResponse>(&body) {
+        Ok(ErrorResponse { error }) => match error.hint {
+            Some(hint) => format!("{} ({})", error.message, hint),
+            None => error.message,
+        },
+        Err(_) => body,
+    };
+    error!("Pi-hole API returned {}: {}", status, message);
+    Err(match status {
+        StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => PiholeError::InvalidCredentials,
+        StatusCode::NOT_FOUND => PiholeError::NotFound,
+        StatusCode::BAD_REQUEST => PiholeError::Rejected { message },
+        _ => PiholeError::Api {
+            status: status.as_u16(),
+            message,
+        },
+    })
+}
+
+#[async_trait]
+impl DnsService for PiholeClient {
+    async fn resolve_ip(
+        &self,
+        subdomain: &str,
+        domain: &str,
+    ) -> Result<Option<Ipv4Addr>, DnsServiceError> {
+        debug!(
+            "Resolve ip for domain {} and subdomain {}.",
+            domain, subdomain
+        );
+        match self.get_address(&fqdn(subdomain, domain), false).await? {
+            Some(IpAddr::V4(ip)) => Ok(Some(ip)),
+            _ => Ok(None),
+        }
+    }
+
+    async fn update_dns(
+        &self,
+        subdomain: &str,
+        domain: &str,
+        ip: Ipv4Addr,
+    ) -> Result<(), DnsServiceError> {
+        debug!(
+            "Update dns for domain {} and subdomain {} with IP {}.",
+            domain, subdomain, ip
+        );
+        self.set_address(&fqdn(subdomain, domain), IpAddr::V4(ip))
+            .await?;
+        Ok(())
+    }
+
+    fn is_local(&self) -> bool {
+        true
+    }
+
+    fn supports_record_type(&self, record_type: &str) -> bool {
+        is_ipv6_record(record_type).is_ok()
+    }
+
+    async fn resolve_record(
+        &self,
+        subdomain: &str,
+        domain: &str,
+        record_type: &str,
+        _prefix: &str,
+    ) -> Result<Option<String>, DnsServiceError> {
+        let ipv6 = is_ipv6_record(record_type)?;
+        let ip = self.get_address(&fqdn(subdomain, domain), ipv6).await?;
+        Ok(ip.map(|ip| ip.to_string()))
+    }
+
+    async fn update_record(
+        &self,
+        subdomain: &str,
+        domain: &str,
+        record_type: &str,
+        _prefix: &str,
+        value: &str,
+    ) -> Result<(), DnsServiceError> {
+        let ip = parse_address(record_type, value)?;
+        self.set_address(&fqdn(subdomain, domain), ip).await?;
+        Ok(())
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum PiholeError {
+    #[error("The app password is not valid.")]
+    InvalidCredentials,
+    #[error("The entry does not exist.")]
+    NotFound,
+    #[error("Pi-hole rejected the change: {message}")]
+    Rejected { message: String },
+    #[error("API request failed with status {status}: {message}")]
+    Api { status: u16, message: String },
+    #[error("request failed")]
+    RequestFailed {
+        #[from]
+        source: reqwest::Error,
+    },
+}
+
+impl From<PiholeError> for DnsServiceError {
+    fn from(e: PiholeError) -> Self {
+        match e {
+            PiholeError::Rejected { .. } => DnsServiceError::UpdateRejected {
+                record: e.to_string(),
+            },
+            _ => DnsServiceError::ClientError,
+        }
+    }
+}
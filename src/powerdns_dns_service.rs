@@ -0,0 +1,415 @@
+use async_trait::async_trait;
+use log::{debug, error};
+use reqwest::{Client, Method, RequestBuilder, Response, StatusCode};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::collections::HashMap;
+use std::net::Ipv4Addr;
+use std::sync::Mutex;
+use thiserror::Error;
+
+use crate::authoritative_dns::fqdn;
+use crate::dns_service::{DnsService, DnsServiceError};
+use crate::record_template::{api_value, is_managed, template_value};
+
+const SERVER_ID: &str = "localhost";
+const DEFAULT_TTL: u32 = 300;
+
+#[derive(Clone, Deserialize, Serialize, Debug, Default, PartialEq, Eq)]
+pub struct PowerDnsZone {
+    pub id: String,
+    pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub kind: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub rrsets: Vec<PowerDnsRRSet>,
+}
+
+#[derive(Clone, Deserialize, Serialize, Debug, Default, PartialEq, Eq)]
+pub struct PowerDnsRRSet {
+    pub name: String,
+    pub r#type: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ttl: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub changetype: Option<String>,
+    #[serde(default)]
+    pub records: Vec<PowerDnsRecord>,
+}
+
+#[derive(Clone, Deserialize, Serialize, Debug, Default, PartialEq, Eq)]
+pub struct PowerDnsRecord {
+    pub content: String,
+    #[serde(default)]
+    pub disabled: bool,
+}
+
+#[derive(Deserialize, Serialize, Debug)]
+pub struct PatchZoneRequest {
+    pub rrsets: Vec<PowerDnsRRSet>,
+}
+
+#[derive(Deserialize)]
+struct ErrorResponse {
+    error: String,
+}
+
+/// Client for the HTTP API of the PowerDNS authoritative server. Records are changed by
+/// replacing whole RRsets with `PATCH /zones/{id}`.
+pub struct PowerDnsClient {
+    api_url: String,
+    api_key: String,
+    server_id: String,
+    zone_ids: Mutex<HashMap<String, String>>,
+    client: Client,
+}
+
+impl PowerDnsClient {
+    /// `api_url` is the address of the API without the `/api/v1` path, for example
+    /// `http://ns1.example.com:8081`.
+    pub fn new(api_url: &str, api_key: &str) -> Self {
+        let client = Client::new();
+        Self {
+            api_url: String::from(api_url.trim_end_matches('/')),
+            api_key: String::from(api_key),
+            server_id: String::from(SERVER_ID),
+            zone_ids: Mutex::new(HashMap::new()),
+            client,
+        }
+    }
+
+    pub fn with_server_id(mut self, server_id: &str) -> Self {
+        self.server_id = String::from(server_id);
+        self
+    }
+
+    pub async fn list_zones(&self) -> Result<Vec<PowerDnsZone>, PowerDnsError> {
+        let request = self.request(Method::GET, "/zones");
+        self.send_json(request).await
+    }
+
+    pub async fn find_zone(&self, zone: &str) -> Result<Option<PowerDnsZone>, PowerDnsError> {
+        let name = canonical(zone);
+        let request = self
+            .request(Method::GET, "/zones")
+            .query(&[("zone", &name)]);
+        let zones: Vec<PowerDnsZone> = self.send_json(request).await?;
+        Ok(zones
+            .into_iter()
+            .find(|z| z.name.eq_ignore_ascii_case(&name)))
+    }
+
+    /// PowerDNS ids are the zone names with a trailing dot and special characters escaped,
+    /// so they are asked from the server instead of being built here, once per zone.
+    pub async fn zone_id(&self, zone: &str) -> Result<String, PowerDnsError> {
+        if let Some(id) = self.zone_ids.lock().unwrap().get(zone) {
+            return Ok(id.clone());
+        }
+
+        let id = self
+            .find_zone(zone)
+            .await?
+            .ok_or_else(|| PowerDnsError::ZoneNotFound {
+                zone: zone.to_string(),
+            })?
+            .id;
+        self.zone_ids
+            .lock()
+            .unwrap()
+            .insert(zone.to_string(), id.clone());
+        Ok(id)
+    }
+
+    pub async fn get_rrset(
+        &self,
+        zone_id: &str,
+        name: &str,
+        record_type: &str,
+    ) -> Result<Option<PowerDnsRRSet>, PowerDnsError> {
+        let name = canonical(name);
+        let request = self
+            .request(Method::GET, &format!("/zones/{}", zone_id))
+            .query(&[("rrset_name", name.as_str()), ("rrset_type", record_type)]);
+        let zone: PowerDnsZone = self.send_json(request).await?;
+
+        // Older servers ignore the filter and return the whole zone.
+        Ok(zone
+            .rrsets
+            .into_iter()
+            .find(|rrset| rrset.name.eq_ignore_ascii_case(&name) && rrset.r#type == record_type))
+    }
+
+    pub async fn patch_rrsets(
+        &self,
+        zone_id: &str,
+        rrsets: Vec<PowerDnsRRSet>,
+    ) -> Result<(), PowerDnsError> {
+        let request = self
+            .request(Method::PATCH, &format!("/zones/{}", zone_id))
+            .json(&PatchZoneRequest { rrsets });
+        self.send(request).await?;
+        Ok(())
+    }
+
+    /// Sends a `REPLACE` change with `values` as enabled records. PowerDNS needs a TTL for it,
+    /// so the one of the existing RRset is reused unless `ttl` is given.
+    pub async fn replace_rrset(
+        &self,
+        zone: &str,
+        name: &str,
+        record_type: &str,
+        values: &[String],
+        ttl: Option<u32>,
+    ) -> Result<(), PowerDnsError> {
+        let zone_id = self.zone_id(zone).await?;
+        let ttl = match ttl {
+            Some(ttl) => ttl,
+            None => self
+                .get_rrset(&zone_id, name, record_type)
+                .await?
+                .and_then(|rrset| rrset.ttl)
+                .unwrap_or(DEFAULT_TTL),
+        };
+
+        let rrset = PowerDnsRRSet {
+            name: canonical(name),
+            r#type: record_type.to_string(),
+            ttl: Some(ttl),
+            changetype: Some(String::from("REPLACE")),
+            records: values
+                .iter()
+                .map(|value| PowerDnsRecord {
+                    content: value.clone(),
+                    disabled: false,
+                })
+                .collect(),
+        };
+        self.patch_rrsets(&zone_id, vec![rrset]).await
+    }
+
+    fn request(&self, method: Method, path: &str) -> RequestBuilder {
+        self.client
+            .request(
+                method,
+                format!("{}/api/v1/servers/{}{}", self.api_url, self.server_id, path),
+            )
+            .header("X-API-Key", &self.api_key)
+    }
+
+    async fn send(&self, request: RequestBuilder) -> Result<Response, PowerDnsError> {
+        let response = request.send().await?;
+        let status = response.status();
+
+        if status.is_success() {
+            return Ok(response);
+        }
+        if status == StatusCode::UNAUTHORIZED {
+            return Err(PowerDnsError::InvalidApiKey);
+        }
+
+        let body = response.text().await.unwrap_or_default();
+        let message = serde_json::from_str::<ErrorResponse>(&body)
+            .map(|e| e.error)
+            .unwrap_or(body);
+        error!("PowerDNS API returned {}: {}", status, message);
+        Err(match status {
+            StatusCode::NOT_FOUND => PowerDnsError::NotFound,
+            StatusCode::UNPROCESSABLE_ENTITY => PowerDnsError::Rejected { message },
+            _ => PowerDnsError::Api {
+                status: status.as_u16(),
+                message,
+            },
+        })
+    }
+
+    async fn send_json<T: DeserializeOwned>(
+        &self,
+        request: RequestBuilder,
+    ) -> Result<T, PowerDnsError> {
+        Ok(self.send(request).await?.json().await?)
+    }
+}
+
+/// PowerDNS names are absolute, with a trailing dot.
+fn canonical(name: &str) -> String {
+    format!("{}.", name.trim_end_matches('.'))
+}
+
+#[async_trait]
+impl DnsService for PowerDnsClient {
+    async fn resolve_ip(
+        &self,
+        subdomain: &str,
+        domain: &str,
+    ) -> Result<Option<Ipv4Addr>, DnsServiceError> {
+        debug!(
+            "Resolve ip for domain {} and subdomain {}.",
+            domain, subdomain
+        );
+        let zone_id = self.zone_id(domain).await?;
+        let rrset = self
+            .get_rrset(&zone_id, &fqdn(subdomain, domain), "A")
+            .await?;
+
+        match rrset.and_then(|rrset| rrset.records.into_iter().find(|r| !r.disabled)) {
+            Some(record) => {
+                let ip = record
+                    .content
+                    .parse()
+                    .map_err(|_| DnsServiceError::UnknownError)?;
+                Ok(Some(ip))
+            }
+            None => Ok(None),
+        }
+    }
+
+    async fn resolve_ttl(
+        &self,
+        subdomain: &str,
+        domain: &str,
+    ) -> Result<Option<u32>, DnsServiceError> {
+        let zone_id = self.zone_id(domain).await?;
+        let rrset = self
+            .get_rrset(&zone_id, &fqdn(subdomain, domain), "A")
+            .await?;
+        Ok(rrset.and_then(|rrset| rrset.ttl))
+    }
+
+    async fn update_dns(
+        &self,
+        subdomain: &str,
+        domain: &str,
+        ip: Ipv4Addr,
+    ) -> Result<(), DnsServiceError> {
+        self.update_dns_with_ttl(subdomain, domain, ip, None).await
+    }
+
+    async fn update_dns_with_ttl(
+        &self,
+        subdomain: &str,
+        domain: &str,
+        ip: Ipv4Addr,
+        ttl: Option<u32>,
+    ) -> Result<(), DnsServiceError> {
+        debug!(
+            "Update dns for domain {} and subdomain {} with IP {}.",
+            domain, subdomain, ip
+        );
+        self.replace_rrset(
+            domain,
+            &fqdn(subdomain, domain),
+            "A",
+            &[ip.to_string()],
+            ttl,
+        )
+        .await?;
+        Ok(())
+    }
+
+    async fn list_zones(&self) -> Result<Vec<String>, DnsServiceError> {
+        let zones = PowerDnsClient::list_zones(self).await?;
+        let mut zone_ids = self.zone_ids.lock().unwrap();
+        Ok(zones
+            .into_iter()
+            .map(|zone| {
+                let name = zone.name.trim_end_matches('.').to_string();
+                zone_ids.insert(name.clone(), zone.id);
+                name
+            })
+            .collect())
+    }
+
+    fn supports_record_type(&self, _record_type: &str) -> bool {
+        true
+    }
+
+    async fn resolve_record(
+        &self,
+        subdomain: &str,
+        domain: &str,
+        record_type: &str,
+        prefix: &str,
+    ) -> Result<Option<String>, DnsServiceError> {
+        let zone_id = self.zone_id(domain).await?;
+        let rrset = self
+            .get_rrset(&zone_id, &fqdn(subdomain, domain), record_type)
+            .await?;
+        Ok(rrset.and_then(|rrset| {
+            rrset
+                .records
+                .into_iter()
+                .find(|r| !r.disabled && is_managed(record_type, &r.content, prefix))
+                .map(|record| template_value(record_type, &record.content))
+        }))
+    }
+
+    async fn update_record(
+        &self,
+        subdomain: &str,
+        domain: &str,
+        record_type: &str,
+        prefix: &str,
+        value: &str,
+    ) -> Result<(), DnsServiceError> {
+        let zone_id = self.zone_id(domain).await?;
+        let name = fqdn(subdomain, domain);
+        let rrset = self.get_rrset(&zone_id, &name, record_type).await?;
+        let ttl = rrset.as_ref().and_then(|rrset| rrset.ttl);
+
+        // `REPLACE` drops every record it is not sent, so the disabled ones go back as well.
+        let mut records = rrset.map(|rrset| rrset.records).unwrap_or_default();
+        let managed = PowerDnsRecord {
+            content: api_value(record_type, value),
+            disabled: false,
+        };
+        match records
+            .iter()
+            .position(|r| is_managed(record_type, &r.content, prefix))
+        {
+            Some(position) => records[position] = managed,
+            None => records.push(managed),
+        }
+
+        let rrset = PowerDnsRRSet {
+            name: canonical(&name),
+            r#type: record_type.to_string(),
+            ttl: Some(ttl.unwrap_or(DEFAULT_TTL)),
+            changetype: Some(String::from("REPLACE")),
+            records,
+        };
+        self.patch_rrsets(&zone_id, vec![rrset]).await?;
+        Ok(())
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum PowerDnsError {
+    #[error("We could not authenticate against the API.")]
+    InvalidApiKey,
+    #[error("The zone {zone} does not exist on the server.")]
+    ZoneNotFound { zone: String },
+    #[error("The zone or record does not exist.")]
+    NotFound,
+    #[error("The server rejected the change: {message}")]
+    Rejected { message: String },
+    #[error("API request failed with status {status}: {message}")]
+    Api { status: u16, message: String },
+    #[error("request failed")]
+    RequestFailed {
+        #[from]
+        source: reqwest::Error,
+    },
+}
+
+impl From<PowerDnsError> for DnsServiceError {
+    fn from(e: PowerDnsError) -> Self {
+        match e {
+            PowerDnsError::ZoneNotFound { .. } | PowerDnsError::NotFound => {
+                DnsServiceError::UnknownZone
+            }
+            PowerDnsError::Rejected { .. } => DnsServiceError::UpdateRejected {
+                record: e.to_string(),
+            },
+            _ => DnsServiceError::ClientError,
+        }
+    }
+}
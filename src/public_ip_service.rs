@@ -1,5 +1,6 @@
 use async_trait::async_trait;
 use std::net::Ipv4Addr;
+use std::sync::Arc;
 use thiserror::Error;
 
 #[cfg(test)]
@@ -11,6 +12,15 @@ pub trait PublicIpService {
     async fn get_ip(&self) -> Result<Ipv4Addr, PublicIpServiceError>;
 }
 
+/// Lets a client that also serves other purposes, like the ubus client of the OpenWrt
+/// provider, be shared with the DNS service.
+#[async_trait]
+impl<T: PublicIpService + Send + Sync + ?Sized> PublicIpService for Arc<T> {
+    async fn get_ip(&self) -> Result<Ipv4Addr, PublicIpServiceError> {
+        T::get_ip(self).await
+    }
+}
+
 #[derive(Debug, Error)]
 pub enum PublicIpServiceError {
     #[error("internal error")]
@@ -19,9 +29,16 @@ pub enum PublicIpServiceError {
     InvalidCredentials,
     #[error("invalid response")]
     InvalidIpResponse,
+    #[error("interface has no usable address")]
+    NoAddress,
     #[error("client request error")]
     ClientError {
         #[from]
         source: reqwest::Error,
     },
+    #[error("network error")]
+    IoError {
+        #[from]
+        source: std::io::Error,
+    },
 }
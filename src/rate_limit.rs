@@ -0,0 +1,212 @@
+use log::warn;
+use reqwest::header::HeaderMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::Mutex;
+
+// Values above this are unix timestamps rather than a number of seconds.
+const UNIX_TIMESTAMP_THRESHOLD: u64 = 1_000_000_000;
+const DEFAULT_BACKOFF: Duration = Duration::from_secs(1);
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct RateLimitBudget {
+    pub limit: Option<u32>,
+    pub remaining: Option<u32>,
+    pub reset_at: Option<Instant>,
+    pub blocked_until: Option<Instant>,
+}
+
+impl RateLimitBudget {
+    /// Returns how long the next call has to wait before it can be sent.
+    pub fn delay(&self, now: Instant) -> Option<Duration> {
+        let exhausted_until = match self.remaining {
+            Some(0) => self.reset_at,
+            _ => None,
+        };
+        [self.blocked_until, exhausted_until]
+            .into_iter()
+            .flatten()
+            .max()
+            .filter(|until| *until > now)
+            .map(|until| until - now)
+    }
+
+    pub fn observe(&mut self, headers: &HeaderMap, now: Instant) {
+        if let Some(limit) = header_value(headers, "ratelimit-limit") {
+            self.limit = Some(limit as u32);
+        }
+        if let Some(remaining) = header_value(headers, "ratelimit-remaining") {
+            self.remaining = Some(remaining as u32);
+        }
+        if let Some(reset) = header_value(headers, "ratelimit-reset") {
+            self.reset_at = Some(now + seconds_from_now(reset));
+        }
+        if let Some(retry_after) = header_value(headers, "retry-after") {
+            self.blocked_until = Some(now + Duration::from_secs(retry_after));
+        }
+    }
+
+    /// Like `observe`, but for a rejected call: without any hint from the headers we still back
+    /// off for a moment instead of retrying right away.
+    pub fn observe_throttled(&mut self, headers: &HeaderMap, now: Instant) {
+        self.observe(headers, now);
+        if !headers.contains_key("retry-after") && self.delay(now).is_none() {
+            self.blocked_until = Some(now + DEFAULT_BACKOFF);
+        }
+    }
+
+    fn consume(&mut self, now: Instant) {
+        if self.reset_at.is_some_and(|reset_at| reset_at <= now) {
+            self.remaining = self.limit;
+            self.reset_at = None;
+        }
+        if let Some(remaining) = self.remaining.as_mut() {
+            *remaining = remaining.saturating_sub(1);
+        }
+    }
+}
+
+fn header_value(headers: &HeaderMap, name: &str) -> Option<u64> {
+    headers.get(name)?.to_str().ok()?.trim().parse().ok()
+}
+
+fn seconds_from_now(reset: u64) -> Duration {
+    if reset < UNIX_TIMESTAMP_THRESHOLD {
+        return Duration::from_secs(reset);
+    }
+    let unix_now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    Duration::from_secs(reset.saturating_sub(unix_now))
+}
+
+/// A rate limit budget shared by all calls of a client. Calls queue up on the budget in the
+/// order they arrive, so a burst of updates waits for the limit to reset instead of failing.
+#[derive(Clone, Debug, Default)]
+pub struct RateLimiter {
+    budget: Arc<Mutex<RateLimitBudget>>,
+}
+
+impl RateLimiter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub async fn acquire(&self) {
+        let mut budget = self.budget.lock().await;
+        if let Some(delay) = budget.delay(Instant::now()) {
+            warn!(
+                "Rate limit reached, waiting {:?} before the next call.",
+                delay
+            );
+            tokio::time::sleep(delay).await;
+        }
+        budget.consume(Instant::now());
+    }
+
+    pub async fn observe(&self, headers: &HeaderMap) {
+        self.budget.lock().await.observe(headers, Instant::now());
+    }
+
+    pub async fn observe_throttled(&self, headers: &HeaderMap) {
+        self.budget
+            .lock()
+            .await
+            .observe_throttled(headers, Instant::now());
+    }
+
+    pub async fn budget(&self) -> RateLimitBudget {
+        self.budget.lock().await.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::header::HeaderValue;
+
+    fn headers(values: &[(&'static str, &str)]) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        for (name, value) in values {
+            headers.insert(*name, HeaderValue::from_str(value).unwrap());
+        }
+        headers
+    }
+
+    #[test]
+    fn no_delay_without_headers() {
+        let budget = RateLimitBudget::default();
+        assert_eq!(budget.delay(Instant::now()), None);
+    }
+
+    #[test]
+    fn wait_for_reset_when_budget_is_exhausted() {
+        let now = Instant::now();
+        let mut budget = RateLimitBudget::default();
+        budget.observe(
+            &headers(&[
+                ("Ratelimit-Limit", "5"),
+                ("Ratelimit-Remaining", "0"),
+                ("Ratelimit-Reset", "10"),
+            ]),
+            now,
+        );
+
+        assert_eq!(budget.limit, Some(5));
+        assert_eq!(budget.delay(now), Some(Duration::from_secs(10)));
+    }
+
+    #[test]
+    fn no_delay_while_budget_remains() {
+        let now = Instant::now();
+        let mut budget = RateLimitBudget::default();
+        budget.observe(
+            &headers(&[("Ratelimit-Remaining", "3"), ("Ratelimit-Reset", "10")]),
+            now,
+        );
+
+        assert_eq!(budget.delay(now), None);
+    }
+
+    #[test]
+    fn honor_retry_after() {
+        let now = Instant::now();
+        let mut budget = RateLimitBudget::default();
+        budget.observe(&headers(&[("Retry-After", "7")]), now);
+
+        assert_eq!(budget.delay(now), Some(Duration::from_secs(7)));
+        assert_eq!(budget.delay(now + Duration::from_secs(8)), None);
+    }
+
+    #[test]
+    fn back_off_when_throttled_without_hint() {
+        let now = Instant::now();
+        let mut budget = RateLimitBudget::default();
+        budget.observe_throttled(&HeaderMap::new(), now);
+
+        assert_eq!(budget.delay(now), Some(DEFAULT_BACKOFF));
+    }
+
+    #[test]
+    fn consume_counts_down_and_refills_after_reset() {
+        let now = Instant::now();
+        let mut budget = RateLimitBudget::default();
+        budget.observe(
+            &headers(&[
+                ("Ratelimit-Limit", "2"),
+                ("Ratelimit-Remaining", "1"),
+                ("Ratelimit-Reset", "1"),
+            ]),
+            now,
+        );
+
+        budget.consume(now);
+        assert_eq!(budget.remaining, Some(0));
+        assert_eq!(budget.delay(now), Some(Duration::from_secs(1)));
+
+        budget.consume(now + Duration::from_secs(2));
+        assert_eq!(budget.remaining, Some(1));
+        assert_eq!(budget.delay(now + Duration::from_secs(2)), None);
+    }
+}
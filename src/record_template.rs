@@ -0,0 +1,195 @@
+use std::net::Ipv4Addr;
+
+/// A record whose value is rendered from the detected address, for example an SPF record
+/// `v=spf1 ip4:{ipv4} -all` or an HTTPS record with `ipv4hint={ipv4}`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RecordTemplate {
+    pub subdomain: String,
+    pub record_type: String,
+    pub template: String,
+}
+
+impl RecordTemplate {
+    pub fn new(subdomain: &str, record_type: &str, template: &str) -> Self {
+        Self {
+            subdomain: String::from(subdomain),
+            record_type: record_type.to_uppercase(),
+            template: String::from(template),
+        }
+    }
+
+    /// The text before the first placeholder. It tells the value managed by this template apart
+    /// from other values of the same name and type, such as verification or DKIM TXT records.
+    pub fn prefix(&self) -> &str {
+        let end = self.template.find('{').unwrap_or(self.template.len());
+        &self.template[..end]
+    }
+
+    pub fn render(&self, domain: &str, ipv4: Ipv4Addr) -> String {
+        self.template
+            .replace("{ipv4}", &ipv4.to_string())
+            .replace("{domain}", domain)
+    }
+}
+
+/// Parses templates of the form `mail TXT v=spf1 ip4:{ipv4} -all; @ HTTPS 1 . ipv4hint={ipv4}`.
+pub fn parse_record_templates(s: &str) -> Result<Vec<RecordTemplate>, String> {
+    s.split(';')
+        .map(str::trim)
+        .filter(|entry| !entry.is_empty())
+        .map(|entry| {
+            let mut parts = entry.splitn(3, char::is_whitespace);
+            match (parts.next(), parts.next(), parts.next()) {
+                (Some(subdomain), Some(record_type), Some(template)) => {
+                    Ok(RecordTemplate::new(subdomain, record_type, template.trim()))
+                }
+                _ => Err(format!("invalid record template: {}", entry)),
+            }
+        })
+        .collect()
+}
+
+/// TXT values are stored as quoted strings by the DNS APIs, templates are written without.
+pub fn api_value(record_type: &str, value: &str) -> String {
+    match record_type {
+        "TXT" => quote_txt(value),
+        _ => String::from(value),
+    }
+}
+
+pub fn template_value(record_type: &str, value: &str) -> String {
+    match record_type {
+        "TXT" => unquote_txt(value),
+        _ => String::from(value),
+    }
+}
+
+/// Whether `value`, as stored by a DNS API, is the one managed by a template with `prefix`.
+pub fn is_managed(record_type: &str, value: &str, prefix: &str) -> bool {
+    template_value(record_type, value).starts_with(prefix)
+}
+
+/// Returns the value managed by a template with `prefix` among the values of a record set.
+pub fn managed_value<'a, I>(record_type: &str, values: I, prefix: &str) -> Option<String>
+where
+    I: IntoIterator<Item = &'a String>,
+{
+    values
+        .into_iter()
+        .find(|value| is_managed(record_type, value, prefix))
+        .map(|value| template_value(record_type, value))
+}
+
+/// Replaces the value managed by a template with `prefix` in a record set, or adds it if there
+/// is none. All other values are kept as they are.
+pub fn replace_managed(
+    record_type: &str,
+    values: &[String],
+    prefix: &str,
+    value: &str,
+) -> Vec<String> {
+    let value = api_value(record_type, value);
+    let mut values = values.to_vec();
+    match values
+        .iter()
+        .position(|v| is_managed(record_type, v, prefix))
+    {
+        Some(position) => values[position] = value,
+        None => values.push(value),
+    }
+    values
+}
+
+pub fn quote_txt(value: &str) -> String {
+    if value.starts_with('"') && value.ends_with('"') && value.len() > 1 {
+        String::from(value)
+    } else {
+        format!("\"{}\"", value)
+    }
+}
+
+pub fn unquote_txt(value: &str) -> String {
+    value
+        .strip_prefix('"')
+        .and_then(|v| v.strip_suffix('"'))
+        .unwrap_or(value)
+        .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn render_spf_template() {
+        let template = RecordTemplate::new("mail", "txt", "v=spf1 ip4:{ipv4} -all");
+        assert_eq!(template.record_type, "TXT");
+        assert_eq!(
+            template.render("example.com", Ipv4Addr::new(93, 184, 216, 34)),
+            "v=spf1 ip4:93.184.216.34 -all"
+        );
+    }
+
+    #[test]
+    fn parse_templates() {
+        let templates =
+            parse_record_templates("mail TXT v=spf1 ip4:{ipv4} -all; @ HTTPS 1 . ipv4hint={ipv4}")
+                .expect("parse failed");
+        assert_eq!(
+            templates,
+            vec![
+                RecordTemplate::new("mail", "TXT", "v=spf1 ip4:{ipv4} -all"),
+                RecordTemplate::new("@", "HTTPS", "1 . ipv4hint={ipv4}"),
+            ]
+        );
+        assert!(parse_record_templates("mail TXT").is_err());
+    }
+
+    #[test]
+    fn template_prefix() {
+        assert_eq!(
+            RecordTemplate::new("mail", "TXT", "v=spf1 ip4:{ipv4} -all").prefix(),
+            "v=spf1 ip4:"
+        );
+        assert_eq!(RecordTemplate::new("@", "A", "{ipv4}").prefix(), "");
+    }
+
+    #[test]
+    fn replace_only_the_managed_value() {
+        let values = vec![
+            String::from("\"google-site-verification=abc\""),
+            String::from("\"v=spf1 ip4:93.184.216.34 -all\""),
+        ];
+        assert_eq!(
+            managed_value("TXT", &values, "v=spf1 ip4:").as_deref(),
+            Some("v=spf1 ip4:93.184.216.34 -all")
+        );
+        assert_eq!(
+            replace_managed(
+                "TXT",
+                &values,
+                "v=spf1 ip4:",
+                "v=spf1 ip4:93.184.216.35 -all"
+            ),
+            vec![
+                String::from("\"google-site-verification=abc\""),
+                String::from("\"v=spf1 ip4:93.184.216.35 -all\""),
+            ]
+        );
+        assert_eq!(
+            replace_managed("TXT", &values[..1], "v=spf1 ip4:", "v=spf1 -all"),
+            vec![
+                String::from("\"google-site-verification=abc\""),
+                String::from("\"v=spf1 -all\""),
+            ]
+        );
+    }
+
+    #[test]
+    fn quote_and_unquote_txt() {
+        assert_eq!(quote_txt("v=spf1 -all"), "\"v=spf1 -all\"");
+        assert_eq!(quote_txt("\"v=spf1 -all\""), "\"v=spf1 -all\"");
+        assert_eq!(unquote_txt("\"v=spf1 -all\""), "v=spf1 -all");
+        assert_eq!(unquote_txt("v=spf1 -all"), "v=spf1 -all");
+    }
+}
@@ -0,0 +1,296 @@
+use async_trait::async_trait;
+use log::debug;
+use std::net::Ipv4Addr;
+use std::time::Duration;
+use thiserror::Error;
+use uuid::Uuid;
+
+use crate::authoritative_dns::fqdn;
+use crate::dns_message::{
+    exchange_udp, exchange_udp_raw, DnsMessageError, Message, Question, ResourceRecord, CLASS_ANY,
+    CLASS_IN, CLASS_NONE, OPCODE_UPDATE, RCODE_NOERROR, RCODE_NOTAUTH, RCODE_NXDOMAIN,
+    RCODE_NXRRSET, RCODE_REFUSED, RCODE_YXDOMAIN, RCODE_YXRRSET, TYPE_A, TYPE_SOA,
+};
+use crate::dns_service::{DnsService, DnsServiceError};
+use crate::tsig::{self, TsigError, TsigKey};
+
+const DEFAULT_TTL: u32 = 60;
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Updates zones on a primary name server such as BIND or Knot with RFC 2136 UPDATE
+/// messages. Updates are signed with TSIG when a key is configured and only apply if the
+/// record still has the value that was read before.
+pub struct Rfc2136DnsService {
+    server: String,
+    key: Option<TsigKey>,
+    timeout: Duration,
+}
+
+impl Rfc2136DnsService {
+    pub fn new(server: &str) -> Self {
+        Self {
+            server: String::from(server),
+            key: None,
+            timeout: TIMEOUT,
+        }
+    }
+
+    pub fn with_tsig_key(mut self, key: TsigKey) -> Self {
+        self.key = Some(key);
+        self
+    }
+
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    pub async fn query_a(&self, name: &str) -> Result<Vec<ResourceRecord>, Rfc2136Error> {
+        let response = exchange_udp(
+            &self.server,
+            &Message::query(next_id(), name, TYPE_A),
+            self.timeout,
+        )
+        .await?;
+
+        match response.rcode() {
+            RCODE_NOERROR => Ok(response
+                .answers
+                .into_iter()
+                .filter(|record| record.rtype == TYPE_A && record.name.eq_ignore_ascii_case(name))
+                .collect()),
+            RCODE_NXDOMAIN => Ok(Vec::new()),
+            rcode => Err(Rfc2136Error::ServerFailure { rcode }),
+        }
+    }
+
+    pub async fn update_a(
+        &self,
+        zone: &str,
+        name: &str,
+        current: &[ResourceRecord],
+        ip: Ipv4Addr,
+        ttl: u32,
+    ) -> Result<(), Rfc2136Error> {
+        let message = update_message(zone, name, current, ip, ttl);
+        let response = self.send_update(&message).await?;
+
+        match response.rcode() {
+            RCODE_NOERROR => Ok(()),
+            RCODE_YXRRSET | RCODE_NXRRSET | RCODE_YXDOMAIN | RCODE_NXDOMAIN => {
+                Err(Rfc2136Error::PrerequisiteFailed {
+                    record: name.to_string(),
+                })
+            }
+            RCODE_NOTAUTH => Err(Rfc2136Error::NotAuthorized),
+            RCODE_REFUSED => Err(Rfc2136Error::Refused),
+            rcode => Err(Rfc2136Error::ServerFailure { rcode }),
+        }
+    }
+
+    async fn send_update(&self, message: &Message) -> Result<Message, Rfc2136Error> {
+        let key = match &self.key {
+            Some(key) => key,
+            None => return Ok(exchange_udp(&self.server, message, self.timeout).await?),
+        };
+
+        let (wire, mac) = tsig::sign(message, key, tsig::now(), None);
+        let (response, raw) =
+            exchange_udp_raw(&self.server, message.id, &wire, self.timeout).await?;
+
+        // A server that cannot verify the request answers NOTAUTH with an unsigned response.
+        if response.rcode() == RCODE_NOTAUTH {
+            return Ok(response);
+        }
+        tsig::verify(&raw, key, Some(&mac), tsig::now())?;
+        Ok(response)
+    }
+}
+
+fn next_id() -> u16 {
+    u16::from_be_bytes(Uuid::new_v4().as_bytes()[..2].try_into().unwrap())
+}
+
+/// Builds an UPDATE that replaces the A records of `name`. The prerequisite section requires
+/// the records to still be `current`, or to not exist if there were none.
+pub fn update_message(
+    zone: &str,
+    name: &str,
+    current: &[ResourceRecord],
+    ip: Ipv4Addr,
+    ttl: u32,
+) -> Message {
+    let mut message = Message {
+        id: next_id(),
+        questions: vec![Question {
+            name: zone.to_string(),
+            qtype: TYPE_SOA,
+            qclass: CLASS_IN,
+        }],
+        ..Default::default()
+    };
+    message.set_opcode(OPCODE_UPDATE);
+
+    message.answers = if current.is_empty() {
+        vec![ResourceRecord {
+            name: name.to_string(),
+            rtype: TYPE_A,
+            class: CLASS_NONE,
+            ttl: 0,
+            rdata: Vec::new(),
+        }]
+    } else {
+        current
+            .iter()
+            .map(|record| ResourceRecord {
+                name: name.to_string(),
+                rtype: TYPE_A,
+                class: CLASS_IN,
+                ttl: 0,
+                rdata: record.rdata.clone(),
+            })
+            .collect()
+    };
+
+    message.authorities = vec![
+        ResourceRecord {
+            name: name.to_string(),
+            rtype: TYPE_A,
+            class: CLASS_ANY,
+            ttl: 0,
+            rdata: Vec::new(),
+        },
+        ResourceRecord::a(name, ttl, ip),
+    ];
+    message
+}
+
+#[async_trait]
+impl DnsService for Rfc2136DnsService {
+    async fn resolve_ip(
+        &self,
+        subdomain: &str,
+        domain: &str,
+    ) -> Result<Option<Ipv4Addr>, DnsServiceError> {
+        debug!(
+            "Resolve ip for domain {} and subdomain {}.",
+            domain, subdomain
+        );
+        let records = self.query_a(&fqdn(subdomain, domain)).await?;
+        Ok(records.iter().find_map(ResourceRecord::as_ipv4))
+    }
+
+    async fn resolve_ttl(
+        &self,
+        subdomain: &str,
+        domain: &str,
+    ) -> Result<Option<u32>, DnsServiceError> {
+        let records = self.query_a(&fqdn(subdomain, domain)).await?;
+        Ok(records.first().map(|record| record.ttl))
+    }
+
+    async fn update_dns(
+        &self,
+        subdomain: &str,
+        domain: &str,
+        ip: Ipv4Addr,
+    ) -> Result<(), DnsServiceError> {
+        self.update_dns_with_ttl(subdomain, domain, ip, None).await
+    }
+
+    async fn update_dns_with_ttl(
+        &self,
+        subdomain: &str,
+        domain: &str,
+        ip: Ipv4Addr,
+        ttl: Option<u32>,
+    ) -> Result<(), DnsServiceError> {
+        let name = fqdn(subdomain, domain);
+        debug!("Update {} to {} on {}.", name, ip, self.server);
+
+        let current = self.query_a(&name).await?;
+        let ttl = ttl
+            .or_else(|| current.first().map(|record| record.ttl))
+            .unwrap_or(DEFAULT_TTL);
+        self.update_a(domain, &name, &current, ip, ttl).await?;
+        Ok(())
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum Rfc2136Error {
+    #[error("the record {record} changed since it was read")]
+    PrerequisiteFailed { record: String },
+    #[error("the server did not accept the key for this zone")]
+    NotAuthorized,
+    #[error("the server refused the update")]
+    Refused,
+    #[error("the server answered with rcode {rcode}")]
+    ServerFailure { rcode: u8 },
+    #[error("invalid signature")]
+    Tsig {
+        #[from]
+        source: TsigError,
+    },
+    #[error("dns message error")]
+    Message {
+        #[from]
+        source: DnsMessageError,
+    },
+}
+
+impl From<Rfc2136Error> for DnsServiceError {
+    fn from(e: Rfc2136Error) -> Self {
+        match e {
+            Rfc2136Error::PrerequisiteFailed { record } => {
+                DnsServiceError::UpdateRejected { record }
+            }
+            _ => DnsServiceError::ClientError,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn update_requires_current_records() {
+        let current = vec![ResourceRecord::a(
+            "home.example.com",
+            300,
+            Ipv4Addr::new(93, 184, 216, 34),
+        )];
+        let message = update_message(
+            "example.com",
+            "home.example.com",
+            &current,
+            Ipv4Addr::new(93, 184, 216, 35),
+            300,
+        );
+
+        assert_eq!(message.opcode(), OPCODE_UPDATE);
+        assert_eq!(message.questions[0].qtype, TYPE_SOA);
+        assert_eq!(message.answers[0].class, CLASS_IN);
+        assert_eq!(message.answers[0].ttl, 0);
+        assert_eq!(message.authorities[0].class, CLASS_ANY);
+        assert_eq!(
+            message.authorities[1].as_ipv4(),
+            Some(Ipv4Addr::new(93, 184, 216, 35))
+        );
+    }
+
+    #[test]
+    fn create_requires_missing_rrset() {
+        let message = update_message(
+            "example.com",
+            "home.example.com",
+            &[],
+            Ipv4Addr::new(93, 184, 216, 35),
+            60,
+        );
+
+        assert_eq!(message.answers[0].class, CLASS_NONE);
+        assert!(message.answers[0].rdata.is_empty());
+    }
+}
@@ -0,0 +1,563 @@
+use async_trait::async_trait;
+use log::{debug, error};
+use reqwest::{Client, Method, StatusCode, Url};
+use std::collections::HashMap;
+use std::net::Ipv4Addr;
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use thiserror::Error;
+
+use crate::authoritative_dns::fqdn;
+use crate::aws_sigv4::{self, AwsCredentials};
+use crate::dns_service::{DnsService, DnsServiceError};
+use crate::record_template::{managed_value, replace_managed};
+
+const API_URL: &str = "https://route53.amazonaws.com";
+const API_VERSION: &str = "2013-04-01";
+const REGION: &str = "us-east-1";
+const SERVICE: &str = "route53";
+const DEFAULT_TTL: u32 = 300;
+const CHANGE_POLL_INTERVAL: Duration = Duration::from_secs(5);
+const CHANGE_POLL_ATTEMPTS: u32 = 60;
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct HostedZone {
+    pub id: String,
+    pub name: String,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ResourceRecordSet {
+    pub name: String,
+    pub r#type: String,
+    pub ttl: Option<u32>,
+    pub values: Vec<String>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ChangeInfo {
+    pub id: String,
+    pub status: String,
+}
+
+pub struct Route53Client {
+    api_url: String,
+    credentials: AwsCredentials,
+    change_poll_interval: Duration,
+    zone_ids: Mutex<HashMap<String, String>>,
+    client: Client,
+}
+
+impl Route53Client {
+    pub fn new(credentials: AwsCredentials) -> Self {
+        Self::new_with_url(credentials, API_URL)
+    }
+
+    pub fn new_with_url(credentials: AwsCredentials, api_url: &str) -> Self {
+        let client = Client::new();
+        Self {
+            api_url: String::from(api_url),
+            credentials,
+            change_poll_interval: CHANGE_POLL_INTERVAL,
+            zone_ids: Mutex::new(HashMap::new()),
+            client,
+        }
+    }
+
+    pub fn with_change_poll_interval(mut self, change_poll_interval: Duration) -> Self {
+        self.change_poll_interval = change_poll_interval;
+        self
+    }
+
+    pub async fn list_hosted_zones(&self) -> Result<Vec<HostedZone>, Route53Error> {
+        let mut zones = Vec::new();
+        let mut marker: Option<String> = None;
+        loop {
+            let mut query = vec![];
+            if let Some(marker) = &marker {
+                query.push(("marker", marker.as_str()));
+            }
+            let body = self.send(Method::GET, "/hostedzone", &query, None).await?;
+            zones.extend(hosted_zones(&body));
+
+            marker = match element(&body, "IsTruncated") {
+                Some("true") => element(&body, "NextMarker").map(unescape),
+                _ => None,
+            };
+            if marker.is_none() {
+                break;
+            }
+        }
+        Ok(zones)
+    }
+
+    pub async fn find_hosted_zone(&self, zone: &str) -> Result<Option<HostedZone>, Route53Error> {
+        let body = self
+            .send(
+                Method::GET,
+                "/hostedzonesbyname",
+                &[("dnsname", zone), ("maxitems", "1")],
+                None,
+            )
+            .await?;
+        Ok(hosted_zones(&body).into_iter().find(|z| z.name == zone))
+    }
+
+    /// Looks up the hosted zone id, asking the API only the first time a zone is used.
+    pub async fn zone_id(&self, zone: &str) -> Result<String, Route53Error> {
+        if let Some(id) = self.zone_ids.lock().unwrap().get(zone) {
+            return Ok(id.clone());
+        }
+
+        let id = self
+            .find_hosted_zone(zone)
+            .await?
+            .ok_or_else(|| Route53Error::NoSuchHostedZone {
+                zone: zone.to_string(),
+            })?
+            .id;
+        self.zone_ids
+            .lock()
+            .unwrap()
+            .insert(zone.to_string(), id.clone());
+        Ok(id)
+    }
+
+    pub async fn find_record_set(
+        &self,
+        zone_id: &str,
+        name: &str,
+        record_type: &str,
+    ) -> Result<Option<ResourceRecordSet>, Route53Error> {
+        let name = format!("{}.", name);
+        let body = self
+            .send(
+                Method::GET,
+                &format!("/hostedzone/{}/rrset", zone_id),
+                &[("name", &name), ("type", record_type), ("maxitems", "1")],
+                None,
+            )
+            .await?;
+
+        // The listing starts at the requested name and type but continues with the next ones.
+        Ok(elements(&body, "ResourceRecordSet")
+            .into_iter()
+            .map(|set| ResourceRecordSet {
+                name: element(set, "Name").map(unescape).unwrap_or_default(),
+                r#type: element(set, "Type").unwrap_or_default().to_string(),
+                ttl: element(set, "TTL").and_then(|ttl| ttl.parse().ok()),
+                values: elements(set, "Value").into_iter().map(unescape).collect(),
+            })
+            .find(|set| set.name.eq_ignore_ascii_case(&name) && set.r#type == record_type))
+    }
+
+    /// Creates or replaces a record set with a single UPSERT change.
+    pub async fn upsert_record_set(
+        &self,
+        zone_id: &str,
+        record_set: &ResourceRecordSet,
+    ) -> Result<ChangeInfo, Route53Error> {
+        let records: String = record_set
+            .values
+            .iter()
+            .map(|value| {
+                format!(
+                    "<ResourceRecord><Value>{}</Value></ResourceRecord>",
+                    escape(value)
+                )
+            })
+            .collect();
+        let body = format!(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\
+             <ChangeResourceRecordSetsRequest xmlns=\"https://route53.amazonaws.com/doc/{}/\">\
+             <ChangeBatch><Changes><Change><Action>UPSERT</Action><ResourceRecordSet>\
+             <Name>{}.</Name><Type>{}</Type><TTL>{}</TTL><ResourceRecords>{}</ResourceRecords>\
+             </ResourceRecordSet></Change></Changes></ChangeBatch>\
+             </ChangeResourceRecordSetsRequest>",
+            API_VERSION,
+            escape(record_set.name.trim_end_matches('.')),
+            record_set.r#type,
+            record_set.ttl.unwrap_or(DEFAULT_TTL),
+            records
+        );
+
+        let response = self
+            .send(
+                Method::POST,
+                &format!("/hostedzone/{}/rrset/", zone_id),
+                &[],
+                Some(body),
+            )
+            .await?;
+        change_info(&response)
+    }
+
+    pub async fn get_change(&self, change_id: &str) -> Result<ChangeInfo, Route53Error> {
+        let body = self
+            .send(Method::GET, &format!("/change/{}", change_id), &[], None)
+            .await?;
+        change_info(&body)
+    }
+
+    pub async fn wait_for_change(&self, change: ChangeInfo) -> Result<ChangeInfo, Route53Error> {
+        let mut change = change;
+        for _ in 0..CHANGE_POLL_ATTEMPTS {
+            if change.status == "INSYNC" {
+                return Ok(change);
+            }
+            debug!("Waiting for change {} ({}).", change.id, change.status);
+            tokio::time::sleep(self.change_poll_interval).await;
+            change = self.get_change(&change.id).await?;
+        }
+        Err(Route53Error::ChangeTimeout { change: change.id })
+    }
+
+    async fn set_record(
+        &self,
+        domain: &str,
+        subdomain: &str,
+        record_type: &str,
+        value: String,
+        ttl: Option<u32>,
+    ) -> Result<(), Route53Error> {
+        let zone_id = self.zone_id(domain).await?;
+        let name = fqdn(subdomain, domain);
+        let existing = self.find_record_set(&zone_id, &name, record_type).await?;
+
+        let record_set = ResourceRecordSet {
+            name,
+            r#type: record_type.to_string(),
+            ttl: ttl.or_else(|| existing.and_then(|set| set.ttl)),
+            values: vec![value],
+        };
+        let change = self.upsert_record_set(&zone_id, &record_set).await?;
+        self.wait_for_change(change).await?;
+        Ok(())
+    }
+
+    async fn send(
+        &self,
+        method: Method,
+        path: &str,
+        query: &[(&str, &str)],
+        body: Option<String>,
+    ) -> Result<String, Route53Error> {
+        let mut url = Url::parse(&format!("{}/{}{}", self.api_url, API_VERSION, path))
+            .map_err(|_| Route53Error::InvalidResponse)?;
+        if !query.is_empty() {
+            url.query_pairs_mut().extend_pairs(query);
+        }
+
+        let payload = body.unwrap_or_default();
+        let time = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        let headers = aws_sigv4::sign(
+            &self.credentials,
+            REGION,
+            SERVICE,
+            method.as_str(),
+            &url,
+            &[],
+            payload.as_bytes(),
+            time,
+        );
+
+        let mut request = self.client.request(method, url);
+        for (name, value) in headers {
+            request = request.header(name, value);
+        }
+        if !payload.is_empty() {
+            request = request
+                .header("content-type", "application/xml")
+                .body(payload);
+        }
+
+        let response = request.send().await?;
+        let status = response.status();
+        let body = response.text().await?;
+        if status.is_success() {
+            return Ok(body);
+        }
+
+        let code = element(&body, "Code").unwrap_or_default().to_string();
+        let message = element(&body, "Message").map(unescape).unwrap_or(body);
+        error!("Route 53 API returned {}: {} {}", status, code, message);
+        Err(match (status, code.as_str()) {
+            (_, "InvalidClientTokenId" | "SignatureDoesNotMatch" | "IncompleteSignature") => {
+                Route53Error::InvalidCredentials
+            }
+            (StatusCode::FORBIDDEN, _) | (_, "AccessDenied") => {
+                Route53Error::AccessDenied { message }
+            }
+            (_, "NoSuchHostedZone") => Route53Error::NoSuchHostedZone { zone: message },
+            (_, "InvalidChangeBatch") => Route53Error::InvalidChangeBatch { message },
+            (_, "Throttling" | "PriorRequestNotComplete") | (StatusCode::TOO_MANY_REQUESTS, _) => {
+                Route53Error::Throttled
+            }
+            _ => Route53Error::Api {
+                status: status.as_u16(),
+                code,
+                message,
+            },
+        })
+    }
+}
+
+fn hosted_zones(body: &str) -> Vec<HostedZone> {
+    elements(body, "HostedZone")
+        .into_iter()
+        .map(|zone| HostedZone {
+            id: element(zone, "Id")
+                .unwrap_or_default()
+                .trim_start_matches("/hostedzone/")
+                .to_string(),
+            name: unescape(element(zone, "Name").unwrap_or_default())
+                .trim_end_matches('.')
+                .to_string(),
+        })
+        .collect()
+}
+
+fn change_info(body: &str) -> Result<ChangeInfo, Route53Error> {
+    let info = element(body, "ChangeInfo").ok_or(Route53Error::InvalidResponse)?;
+    Ok(ChangeInfo {
+        id: element(info, "Id")
+            .ok_or(Route53Error::InvalidResponse)?
+            .trim_start_matches("/change/")
+            .to_string(),
+        status: element(info, "Status")
+            .ok_or(Route53Error::InvalidResponse)?
+            .to_string(),
+    })
+}
+
+/// The contents of all `<tag>` elements in `xml`. Route 53 responses are small and flat
+/// enough that this is all the XML parsing the provider needs.
+fn elements<'a>(xml: &'a str, tag: &str) -> Vec<&'a str> {
+    let open = format!("<{}>", tag);
+    let close = format!("</{}>", tag);
+    let mut out = Vec::new();
+    let mut rest = xml;
+    while let Some(start) = rest.find(&open) {
+        let content = &rest[start + open.len()..];
+        match content.find(&close) {
+            Some(end) => {
+                out.push(&content[..end]);
+                rest = &content[end + close.len()..];
+            }
+            None => break,
+        }
+    }
+    out
+}
+
+fn element<'a>(xml: &'a str, tag: &str) -> Option<&'a str> {
+    elements(xml, tag).into_iter().next()
+}
+
+fn escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn unescape(s: &str) -> String {
+    s.replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&amp;", "&")
+}
+
+#[async_trait]
+impl DnsService for Route53Client {
+    async fn resolve_ip(
+        &self,
+        subdomain: &str,
+        domain: &str,
+    ) -> Result<Option<Ipv4Addr>, DnsServiceError> {
+        debug!(
+            "Resolve ip for domain {} and subdomain {}.",
+            domain, subdomain
+        );
+        let zone_id = self.zone_id(domain).await?;
+        let record_set = self
+            .find_record_set(&zone_id, &fqdn(subdomain, domain), "A")
+            .await?;
+
+        match record_set.and_then(|set| set.values.into_iter().next()) {
+            Some(value) => {
+                let ip = value.parse().map_err(|_| DnsServiceError::UnknownError)?;
+                Ok(Some(ip))
+            }
+            None => Ok(None),
+        }
+    }
+
+    async fn resolve_ttl(
+        &self,
+        subdomain: &str,
+        domain: &str,
+    ) -> Result<Option<u32>, DnsServiceError> {
+        let zone_id = self.zone_id(domain).await?;
+        let record_set = self
+            .find_record_set(&zone_id, &fqdn(subdomain, domain), "A")
+            .await?;
+        Ok(record_set.and_then(|set| set.ttl))
+    }
+
+    async fn update_dns(
+        &self,
+        subdomain: &str,
+        domain: &str,
+        ip: Ipv4Addr,
+    ) -> Result<(), DnsServiceError> {
+        self.update_dns_with_ttl(subdomain, domain, ip, None).await
+    }
+
+    async fn update_dns_with_ttl(
+        &self,
+        subdomain: &str,
+        domain: &str,
+        ip: Ipv4Addr,
+        ttl: Option<u32>,
+    ) -> Result<(), DnsServiceError> {
+        debug!(
+            "Update dns for domain {} and subdomain {} with IP {}.",
+            domain, subdomain, ip
+        );
+        self.set_record(domain, subdomain, "A", ip.to_string(), ttl)
+            .await?;
+        Ok(())
+    }
+
+    async fn list_zones(&self) -> Result<Vec<String>, DnsServiceError> {
+        let zones = self.list_hosted_zones().await?;
+        let mut zone_ids = self.zone_ids.lock().unwrap();
+        Ok(zones
+            .into_iter()
+            .map(|zone| {
+                zone_ids.insert(zone.name.clone(), zone.id);
+                zone.name
+            })
+            .collect())
+    }
+
+    fn supports_record_type(&self, _record_type: &str) -> bool {
+        true
+    }
+
+    async fn resolve_record(
+        &self,
+        subdomain: &str,
+        domain: &str,
+        record_type: &str,
+        prefix: &str,
+    ) -> Result<Option<String>, DnsServiceError> {
+        let zone_id = self.zone_id(domain).await?;
+        let record_set = self
+            .find_record_set(&zone_id, &fqdn(subdomain, domain), record_type)
+            .await?;
+        Ok(record_set.and_then(|set| managed_value(record_type, &set.values, prefix)))
+    }
+
+    async fn update_record(
+        &self,
+        subdomain: &str,
+        domain: &str,
+        record_type: &str,
+        prefix: &str,
+        value: &str,
+    ) -> Result<(), DnsServiceError> {
+        let zone_id = self.zone_id(domain).await?;
+        let name = fqdn(subdomain, domain);
+        let existing = self.find_record_set(&zone_id, &name, record_type).await?;
+        let ttl = existing.as_ref().and_then(|set| set.ttl);
+        let current = existing.map(|set| set.values).unwrap_or_default();
+
+        let record_set = ResourceRecordSet {
+            name,
+            r#type: record_type.to_string(),
+            ttl,
+            values: replace_managed(record_type, &current, prefix, value),
+        };
+        let change = self.upsert_record_set(&zone_id, &record_set).await?;
+        self.wait_for_change(change).await?;
+        Ok(())
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum Route53Error {
+    #[error("AWS did not accept the credentials or the request signature.")]
+    InvalidCredentials,
+    #[error("The credentials are not allowed to do this: {message}")]
+    AccessDenied { message: String },
+    #[error("No hosted zone found: {zone}")]
+    NoSuchHostedZone { zone: String },
+    #[error("Route 53 rejected the change: {message}")]
+    InvalidChangeBatch { message: String },
+    #[error("The Route 53 API is throttling requests.")]
+    Throttled,
+    #[error("API request failed with status {status} and code {code}: {message}")]
+    Api {
+        status: u16,
+        code: String,
+        message: String,
+    },
+    #[error("Change {change} did not reach INSYNC in time")]
+    ChangeTimeout { change: String },
+    #[error("The API response could not be read.")]
+    InvalidResponse,
+    #[error("request failed")]
+    RequestFailed {
+        #[from]
+        source: reqwest::Error,
+    },
+}
+
+impl From<Route53Error> for DnsServiceError {
+    fn from(e: Route53Error) -> Self {
+        match e {
+            Route53Error::NoSuchHostedZone { .. } => DnsServiceError::UnknownZone,
+            Route53Error::InvalidChangeBatch { .. } => DnsServiceError::UpdateRejected {
+                record: e.to_string(),
+            },
+            _ => DnsServiceError::ClientError,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn read_record_sets() {
+        let body = "<ListResourceRecordSetsResponse><ResourceRecordSets>\
+                    <ResourceRecordSet><Name>mail.example.com.</Name><Type>TXT</Type><TTL>300</TTL>\
+                    <ResourceRecords><ResourceRecord><Value>&quot;v=spf1 -all&quot;</Value>\
+                    </ResourceRecord></ResourceRecords></ResourceRecordSet>\
+                    </ResourceRecordSets></ListResourceRecordSetsResponse>";
+
+        let sets = elements(body, "ResourceRecordSet");
+        assert_eq!(sets.len(), 1);
+        assert_eq!(element(sets[0], "TTL"), Some("300"));
+        assert_eq!(
+            elements(sets[0], "Value")
+                .into_iter()
+                .map(unescape)
+                .collect::<Vec<_>>(),
+            vec!["\"v=spf1 -all\""]
+        );
+    }
+
+    #[test]
+    fn escape_values() {
+        assert_eq!(escape("\"a<b>&c\""), "&quot;a&lt;b&gt;&amp;c&quot;");
+        assert_eq!(unescape(&escape("\"a<b>&c\"")), "\"a<b>&c\"");
+    }
+}
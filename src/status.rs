@@ -0,0 +1,132 @@
+use log::{debug, warn};
+use std::fmt::Write;
+use std::io;
+use std::net::{Ipv4Addr, SocketAddr};
+use std::sync::{Arc, Mutex};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpListener;
+
+#[derive(Clone, Debug, Default)]
+pub struct StatusSnapshot {
+    pub router_ip: Option<Ipv4Addr>,
+    pub observed_ip: Option<Ipv4Addr>,
+    /// `None` until the external address has been observed and after a failed lookup.
+    pub behind_nat: Option<bool>,
+    pub nat_mismatches_total: u64,
+    pub updates_held_back_total: u64,
+}
+
+#[derive(Clone, Default)]
+pub struct Status {
+    inner: Arc<Mutex<StatusSnapshot>>,
+}
+
+impl Status {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn snapshot(&self) -> StatusSnapshot {
+        self.inner.lock().unwrap().clone()
+    }
+
+    pub fn update<F: FnOnce(&mut StatusSnapshot)>(&self, f: F) {
+        f(&mut self.inner.lock().unwrap());
+    }
+
+    pub fn render_metrics(&self) -> String {
+        let status = self.snapshot();
+        let mut metrics = String::new();
+
+        writeln!(
+            metrics,
+            "# HELP dyndnsd_behind_nat Whether the router address differs from the externally observed address."
+        )
+        .unwrap();
+        writeln!(metrics, "# TYPE dyndnsd_behind_nat gauge").unwrap();
+        if let Some(behind_nat) = status.behind_nat {
+            writeln!(metrics, "dyndnsd_behind_nat {}", behind_nat as u8).unwrap();
+        }
+        writeln!(
+            metrics,
+            "# HELP dyndnsd_nat_mismatches_total Number of checks in which the router address differed from the observed address."
+        )
+        .unwrap();
+        writeln!(metrics, "# TYPE dyndnsd_nat_mismatches_total counter").unwrap();
+        writeln!(
+            metrics,
+            "dyndnsd_nat_mismatches_total {}",
+            status.nat_mismatches_total
+        )
+        .unwrap();
+        writeln!(
+            metrics,
+            "# HELP dyndnsd_updates_held_back_total Number of updates held back by flap dampening."
+        )
+        .unwrap();
+        writeln!(metrics, "# TYPE dyndnsd_updates_held_back_total counter").unwrap();
+        writeln!(
+            metrics,
+            "dyndnsd_updates_held_back_total {}",
+            status.updates_held_back_total
+        )
+        .unwrap();
+
+        metrics
+    }
+}
+
+pub async fn serve_metrics(addr: SocketAddr, status: Status) -> io::Result<()> {
+    let listener = TcpListener::bind(addr).await?;
+    debug!("Serving metrics on {}.", addr);
+
+    loop {
+        let (mut stream, _) = listener.accept().await?;
+        let status = status.clone();
+
+        tokio::spawn(async move {
+            // We answer every request with the metrics, so the request itself is only drained.
+            let mut request = [0u8; 1024];
+            if stream.read(&mut request).await.is_err() {
+                return;
+            }
+
+            let body = status.render_metrics();
+            let response = format!(
+                "HTTP/1.1 200 OK\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                body.len(),
+                body
+            );
+            if let Err(e) = stream.write_all(response.as_bytes()).await {
+                warn!("Failed to write metrics response: {}", e);
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn render_metrics() {
+        let status = Status::new();
+        status.update(|s| {
+            s.behind_nat = Some(true);
+            s.nat_mismatches_total = 3;
+        });
+
+        let metrics = status.render_metrics();
+        assert!(metrics.contains("dyndnsd_behind_nat 1\n"));
+        assert!(metrics.contains("dyndnsd_nat_mismatches_total 3\n"));
+    }
+
+    #[test]
+    fn omit_unknown_nat_state() {
+        let metrics = Status::new().render_metrics();
+        assert!(!metrics
+            .lines()
+            .any(|line| line.starts_with("dyndnsd_behind_nat ")));
+        assert!(metrics.contains("# TYPE dyndnsd_behind_nat gauge\n"));
+    }
+}
@@ -0,0 +1,140 @@
+use async_trait::async_trait;
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::time::Duration;
+use tokio::net::{lookup_host, UdpSocket};
+use tokio::time::timeout;
+use uuid::Uuid;
+
+use crate::public_ip_service::{PublicIpService, PublicIpServiceError};
+
+const BINDING_REQUEST: u16 = 0x0001;
+const BINDING_SUCCESS: u16 = 0x0101;
+const MAGIC_COOKIE: u32 = 0x2112_A442;
+const MAPPED_ADDRESS: u16 = 0x0001;
+const XOR_MAPPED_ADDRESS: u16 = 0x0020;
+const FAMILY_IPV4: u8 = 0x01;
+const HEADER_LEN: usize = 20;
+const TIMEOUT: Duration = Duration::from_secs(5);
+const DEFAULT_PORT: u16 = 3478;
+
+pub struct StunPublicIpService {
+    server: String,
+}
+
+impl StunPublicIpService {
+    pub fn new(server: &str) -> Self {
+        Self {
+            server: with_default_port(server),
+        }
+    }
+
+    async fn binding_request(&self) -> Result<Ipv4Addr, PublicIpServiceError> {
+        let server = lookup_host(&self.server)
+            .await?
+            .find(SocketAddr::is_ipv4)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no IPv4 STUN server"))?;
+
+        let socket = UdpSocket::bind("0.0.0.0:0").await?;
+        socket.connect(server).await?;
+
+        let transaction_id: [u8; 12] = Uuid::new_v4().as_bytes()[..12].try_into().unwrap();
+        let mut request = Vec::with_capacity(HEADER_LEN);
+        request.extend_from_slice(&BINDING_REQUEST.to_be_bytes());
+        request.extend_from_slice(&0u16.to_be_bytes());
+        request.extend_from_slice(&MAGIC_COOKIE.to_be_bytes());
+        request.extend_from_slice(&transaction_id);
+        socket.send(&request).await?;
+
+        let mut buf = [0u8; 512];
+        let len = socket.recv(&mut buf).await?;
+
+        parse_binding_response(&buf[..len], &transaction_id)
+            .ok_or(PublicIpServiceError::InvalidIpResponse)
+    }
+}
+
+/// Appends the STUN port to `server` unless it already names one.
+fn with_default_port(server: &str) -> String {
+    if server.parse::<SocketAddr>().is_ok() {
+        return String::from(server);
+    }
+    if let Ok(ip) = server.trim_matches(['[', ']']).parse::<IpAddr>() {
+        return SocketAddr::new(ip, DEFAULT_PORT).to_string();
+    }
+    match server.rsplit_once(':') {
+        Some((_, port)) if port.parse::<u16>().is_ok() => String::from(server),
+        _ => format!("{}:{}", server, DEFAULT_PORT),
+    }
+}
+
+fn parse_binding_response(message: &[u8], transaction_id: &[u8; 12]) -> Option<Ipv4Addr> {
+    if message.len() < HEADER_LEN
+        || u16::from_be_bytes([message[0], message[1]]) != BINDING_SUCCESS
+        || message[4..8] != MAGIC_COOKIE.to_be_bytes()
+        || &message[8..20] != transaction_id
+    {
+        return None;
+    }
+
+    let length = u16::from_be_bytes([message[2], message[3]]) as usize;
+    let attributes = message.get(HEADER_LEN..HEADER_LEN + length)?;
+
+    let mut mapped = None;
+    let mut offset = 0;
+    while offset + 4 <= attributes.len() {
+        let kind = u16::from_be_bytes([attributes[offset], attributes[offset + 1]]);
+        let len = u16::from_be_bytes([attributes[offset + 2], attributes[offset + 3]]) as usize;
+        let value = attributes.get(offset + 4..offset + 4 + len)?;
+
+        if value.len() >= 8 && value[1] == FAMILY_IPV4 {
+            let octets: [u8; 4] = value[4..8].try_into().unwrap();
+            match kind {
+                XOR_MAPPED_ADDRESS => {
+                    let cookie = MAGIC_COOKIE.to_be_bytes();
+                    let address = std::array::from_fn(|i| octets[i] ^ cookie[i]);
+                    return Some(Ipv4Addr::from(address));
+                }
+                MAPPED_ADDRESS => mapped = Some(Ipv4Addr::from(octets)),
+                _ => {}
+            }
+        }
+
+        // Attributes are padded to a multiple of four bytes.
+        offset += 4 + ((len + 3) & !3);
+    }
+
+    mapped
+}
+
+#[async_trait]
+impl PublicIpService for StunPublicIpService {
+    async fn get_ip(&self) -> Result<Ipv4Addr, PublicIpServiceError> {
+        timeout(TIMEOUT, self.binding_request())
+            .await
+            .map_err(|_| io::Error::from(io::ErrorKind::TimedOut))?
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn default_to_stun_port() {
+        assert_eq!(
+            with_default_port("stun.example.com"),
+            "stun.example.com:3478"
+        );
+        assert_eq!(
+            with_default_port("stun.example.com:19302"),
+            "stun.example.com:19302"
+        );
+        assert_eq!(with_default_port("192.0.2.1"), "192.0.2.1:3478");
+        assert_eq!(with_default_port("2001:db8::1"), "[2001:db8::1]:3478");
+        assert_eq!(
+            with_default_port("[2001:db8::1]:5349"),
+            "[2001:db8::1]:5349"
+        );
+    }
+}
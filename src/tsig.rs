@@ -0,0 +1,378 @@
+use base64::{engine::general_purpose::STANDARD, Engine};
+use hmac::{Hmac, Mac};
+use sha2::{Sha256, Sha512};
+use std::str::FromStr;
+use std::time::{SystemTime, UNIX_EPOCH};
+use thiserror::Error;
+
+use crate::dns_message::{
+    decode_name, encode_name, last_record_offset, DnsMessageError, Message, ResourceRecord,
+    CLASS_ANY, TYPE_TSIG,
+};
+
+pub const FUDGE: u16 = 300;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TsigAlgorithm {
+    HmacSha256,
+    HmacSha512,
+}
+
+impl TsigAlgorithm {
+    pub fn name(&self) -> &'static str {
+        match self {
+            TsigAlgorithm::HmacSha256 => "hmac-sha256",
+            TsigAlgorithm::HmacSha512 => "hmac-sha512",
+        }
+    }
+}
+
+impl FromStr for TsigAlgorithm {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim_end_matches('.').to_lowercase().as_str() {
+            "hmac-sha256" => Ok(TsigAlgorithm::HmacSha256),
+            "hmac-sha512" => Ok(TsigAlgorithm::HmacSha512),
+            _ => Err(format!("unsupported tsig algorithm: {}", s)),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TsigKey {
+    pub name: String,
+    pub algorithm: TsigAlgorithm,
+    pub secret: Vec<u8>,
+}
+
+impl TsigKey {
+    pub fn new(name: &str, algorithm: TsigAlgorithm, secret: &[u8]) -> Self {
+        Self {
+            name: name.trim_end_matches('.').to_lowercase(),
+            algorithm,
+            secret: secret.to_vec(),
+        }
+    }
+
+    /// Creates a key from the base64 secret found in BIND and Knot key files.
+    pub fn from_base64(
+        name: &str,
+        algorithm: TsigAlgorithm,
+        secret: &str,
+    ) -> Result<Self, TsigError> {
+        let secret = STANDARD
+            .decode(secret.trim())
+            .map_err(|_| TsigError::InvalidSecret)?;
+        Ok(Self::new(name, algorithm, &secret))
+    }
+
+    fn mac(&self, data: &[u8]) -> Vec<u8> {
+        match self.algorithm {
+            TsigAlgorithm::HmacSha256 => {
+                let mut mac = Hmac::<Sha256>::new_from_slice(&self.secret)
+                    .expect("HMAC accepts keys of any length");
+                mac.update(data);
+                mac.finalize().into_bytes().to_vec()
+            }
+            TsigAlgorithm::HmacSha512 => {
+                let mut mac = Hmac::<Sha512>::new_from_slice(&self.secret)
+                    .expect("HMAC accepts keys of any length");
+                mac.update(data);
+                mac.finalize().into_bytes().to_vec()
+            }
+        }
+    }
+
+    fn verify_mac(&self, data: &[u8], expected: &[u8]) -> bool {
+        match self.algorithm {
+            TsigAlgorithm::HmacSha256 => {
+                let mut mac = Hmac::<Sha256>::new_from_slice(&self.secret)
+                    .expect("HMAC accepts keys of any length");
+                mac.update(data);
+                mac.verify_slice(expected).is_ok()
+            }
+            TsigAlgorithm::HmacSha512 => {
+                let mut mac = Hmac::<Sha512>::new_from_slice(&self.secret)
+                    .expect("HMAC accepts keys of any length");
+                mac.update(data);
+                mac.verify_slice(expected).is_ok()
+            }
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TsigRecord {
+    pub algorithm: String,
+    pub time_signed: u64,
+    pub fudge: u16,
+    pub mac: Vec<u8>,
+    pub original_id: u16,
+    pub error: u16,
+    pub other: Vec<u8>,
+}
+
+impl TsigRecord {
+    fn encode(&self) -> Vec<u8> {
+        let mut rdata = Vec::new();
+        encode_name(&self.algorithm, &mut rdata);
+        rdata.extend_from_slice(&self.time_signed.to_be_bytes()[2..]);
+        rdata.extend_from_slice(&self.fudge.to_be_bytes());
+        rdata.extend_from_slice(&(self.mac.len() as u16).to_be_bytes());
+        rdata.extend_from_slice(&self.mac);
+        rdata.extend_from_slice(&self.original_id.to_be_bytes());
+        rdata.extend_from_slice(&self.error.to_be_bytes());
+        rdata.extend_from_slice(&(self.other.len() as u16).to_be_bytes());
+        rdata.extend_from_slice(&self.other);
+        rdata
+    }
+
+    fn decode(rdata: &[u8]) -> Result<Self, TsigError> {
+        let (algorithm, offset) = decode_name(rdata, 0)?;
+        let fixed = rdata
+            .get(offset..offset + 10)
+            .ok_or(DnsMessageError::Truncated)?;
+        let mut time = [0u8; 8];
+        time[2..].copy_from_slice(&fixed[..6]);
+        let fudge = u16::from_be_bytes([fixed[6], fixed[7]]);
+        let mac_len = u16::from_be_bytes([fixed[8], fixed[9]]) as usize;
+
+        let mac_start = offset + 10;
+        let mac = rdata
+            .get(mac_start..mac_start + mac_len)
+            .ok_or(DnsMessageError::Truncated)?;
+        let rest = rdata
+            .get(mac_start + mac_len..mac_start + mac_len + 6)
+            .ok_or(DnsMessageError::Truncated)?;
+        let other_len = u16::from_be_bytes([rest[4], rest[5]]) as usize;
+        let other_start = mac_start + mac_len + 6;
+        let other = rdata
+            .get(other_start..other_start + other_len)
+            .ok_or(DnsMessageError::Truncated)?;
+
+        Ok(Self {
+            algorithm: algorithm.to_lowercase(),
+            time_signed: u64::from_be_bytes(time),
+            fudge,
+            mac: mac.to_vec(),
+            original_id: u16::from_be_bytes([rest[0], rest[1]]),
+            error: u16::from_be_bytes([rest[2], rest[3]]),
+            other: other.to_vec(),
+        })
+    }
+}
+
+pub fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+/// Signs `message` and returns the encoded message together with its MAC. Responses pass the
+/// MAC of the request they answer.
+pub fn sign(
+    message: &Message,
+    key: &TsigKey,
+    time_signed: u64,
+    request_mac: Option<&[u8]>,
+) -> (Vec<u8>, Vec<u8>) {
+    let mut data = Vec::new();
+    if let Some(request_mac) = request_mac {
+        data.extend_from_slice(&(request_mac.len() as u16).to_be_bytes());
+        data.extend_from_slice(request_mac);
+    }
+    data.extend_from_slice(&message.encode());
+    data.extend_from_slice(&variables(key, time_signed, FUDGE, 0, &[]));
+    let mac = key.mac(&data);
+
+    let record = TsigRecord {
+        algorithm: key.algorithm.name().to_string(),
+        time_signed,
+        fudge: FUDGE,
+        mac: mac.clone(),
+        original_id: message.id,
+        error: 0,
+        other: Vec::new(),
+    };
+    let mut signed = message.clone();
+    signed.additionals.push(ResourceRecord {
+        name: key.name.clone(),
+        rtype: TYPE_TSIG,
+        class: CLASS_ANY,
+        ttl: 0,
+        rdata: record.encode(),
+    });
+    (signed.encode(), mac)
+}
+
+/// Checks the TSIG record at the end of `raw` and returns its MAC. Requests are verified
+/// without a request MAC, responses with the MAC of the request they answer.
+pub fn verify(
+    raw: &[u8],
+    key: &TsigKey,
+    request_mac: Option<&[u8]>,
+    now: u64,
+) -> Result<Vec<u8>, TsigError> {
+    let message = Message::decode(raw)?;
+    let tsig = message
+        .additionals
+        .last()
+        .filter(|record| record.rtype == TYPE_TSIG)
+        .ok_or(TsigError::Unsigned)?;
+    let record = TsigRecord::decode(&tsig.rdata)?;
+
+    if record.error != 0 {
+        return Err(TsigError::Rejected {
+            error: record.error,
+        });
+    }
+    if !tsig.name.eq_ignore_ascii_case(&key.name)
+        || record.algorithm.trim_end_matches('.') != key.algorithm.name()
+    {
+        return Err(TsigError::BadKey);
+    }
+
+    // The signature covers the message as it was before the TSIG record was added.
+    let offset = last_record_offset(raw)?;
+    let mut unsigned = raw[..offset].to_vec();
+    unsigned[..2].copy_from_slice(&record.original_id.to_be_bytes());
+    let additionals = u16::from_be_bytes([unsigned[10], unsigned[11]]) - 1;
+    unsigned[10..12].copy_from_slice(&additionals.to_be_bytes());
+
+    let mut data = Vec::new();
+    if let Some(request_mac) = request_mac {
+        data.extend_from_slice(&(request_mac.len() as u16).to_be_bytes());
+        data.extend_from_slice(request_mac);
+    }
+    data.extend_from_slice(&unsigned);
+    data.extend_from_slice(&variables(
+        key,
+        record.time_signed,
+        record.fudge,
+        record.error,
+        &record.other,
+    ));
+
+    if !key.verify_mac(&data, &record.mac) {
+        return Err(TsigError::BadSignature);
+    }
+    if now.abs_diff(record.time_signed) > record.fudge as u64 {
+        return Err(TsigError::BadTime);
+    }
+    Ok(record.mac)
+}
+
+fn variables(key: &TsigKey, time_signed: u64, fudge: u16, error: u16, other: &[u8]) -> Vec<u8> {
+    let mut out = Vec::new();
+    encode_name(&key.name.to_lowercase(), &mut out);
+    out.extend_from_slice(&CLASS_ANY.to_be_bytes());
+    out.extend_from_slice(&0u32.to_be_bytes());
+    encode_name(key.algorithm.name(), &mut out);
+    out.extend_from_slice(&time_signed.to_be_bytes()[2..]);
+    out.extend_from_slice(&fudge.to_be_bytes());
+    out.extend_from_slice(&error.to_be_bytes());
+    out.extend_from_slice(&(other.len() as u16).to_be_bytes());
+    out.extend_from_slice(other);
+    out
+}
+
+#[derive(Debug, Error)]
+pub enum TsigError {
+    #[error("the TSIG secret is not valid base64")]
+    InvalidSecret,
+    #[error("the message is not signed")]
+    Unsigned,
+    #[error("the message is signed with an unknown key")]
+    BadKey,
+    #[error("the signature does not match")]
+    BadSignature,
+    #[error("the signature time is outside the allowed window")]
+    BadTime,
+    #[error("the server rejected the signature with TSIG error {error}")]
+    Rejected { error: u16 },
+    #[error("invalid message")]
+    InvalidMessage {
+        #[from]
+        source: DnsMessageError,
+    },
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dns_message::{OPCODE_UPDATE, TYPE_SOA};
+
+    fn key(algorithm: TsigAlgorithm) -> TsigKey {
+        TsigKey::from_base64("DynDNS.Key.", algorithm, "c2VjcmV0LXNlY3JldC1zZWNyZXQ=").unwrap()
+    }
+
+    fn update() -> Message {
+        let mut message = Message::query(4711, "example.com", TYPE_SOA);
+        message.set_opcode(OPCODE_UPDATE);
+        message
+    }
+
+    #[test]
+    fn hmac_sha256_test_vector() {
+        // RFC 4231, test case 2.
+        let key = TsigKey::new("k", TsigAlgorithm::HmacSha256, b"Jefe");
+        let mac = key.mac(b"what do ya want for nothing?");
+        assert_eq!(
+            mac.iter().map(|b| format!("{:02x}", b)).collect::<String>(),
+            "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+        );
+    }
+
+    #[test]
+    fn sign_and_verify_request() {
+        for algorithm in [TsigAlgorithm::HmacSha256, TsigAlgorithm::HmacSha512] {
+            let key = key(algorithm);
+            let (wire, mac) = sign(&update(), &key, 1_700_000_000, None);
+
+            let verified = verify(&wire, &key, None, 1_700_000_010).expect("verify failed");
+            assert_eq!(verified, mac);
+        }
+    }
+
+    #[test]
+    fn verify_response_with_request_mac() {
+        let key = key(TsigAlgorithm::HmacSha256);
+        let (_, request_mac) = sign(&update(), &key, 1_700_000_000, None);
+        let response = Message::response_to(&update());
+        let (wire, _) = sign(&response, &key, 1_700_000_001, Some(&request_mac));
+
+        assert!(verify(&wire, &key, Some(&request_mac), 1_700_000_001).is_ok());
+        assert!(matches!(
+            verify(&wire, &key, Some(&[0u8; 32]), 1_700_000_001),
+            Err(TsigError::BadSignature)
+        ));
+    }
+
+    #[test]
+    fn reject_wrong_key_and_time() {
+        let key = key(TsigAlgorithm::HmacSha256);
+        let (wire, _) = sign(&update(), &key, 1_700_000_000, None);
+
+        let other = TsigKey::new("dynDNS.key", TsigAlgorithm::HmacSha256, b"other");
+        assert!(matches!(
+            verify(&wire, &other, None, 1_700_000_000),
+            Err(TsigError::BadSignature)
+        ));
+        assert!(matches!(
+            verify(&wire, &key, None, 1_700_001_000),
+            Err(TsigError::BadTime)
+        ));
+        assert!(matches!(
+            verify(&update().encode(), &key, None, 1_700_000_000),
+            Err(TsigError::Unsigned)
+        ));
+    }
+
+    #[test]
+    fn parse_algorithm_names() {
+        assert_eq!("HMAC-SHA512.".parse(), Ok(TsigAlgorithm::HmacSha512));
+        assert!("hmac-md5".parse::<TsigAlgorithm>().is_err());
+    }
+}
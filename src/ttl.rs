@@ -0,0 +1,251 @@
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+const ADAPTIVE_MIN_TTL: u32 = 60;
+const ADAPTIVE_MAX_TTL: u32 = 3600;
+const ADAPTIVE_STABLE_AFTER: Duration = Duration::from_secs(24 * 3600);
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub enum TtlPolicy {
+    /// Leave the TTL of the record as it is.
+    #[default]
+    Keep,
+    /// Always publish the record with this TTL.
+    Fixed(u32),
+    /// Publish `min` right after a change and raise it to `max` once the address has not
+    /// changed for `stable_after`.
+    Adaptive {
+        min: u32,
+        max: u32,
+        stable_after: Duration,
+    },
+}
+
+impl FromStr for TtlPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("invalid ttl policy: {}", s);
+        let parts: Vec<&str> = s.trim().split(':').collect();
+        match parts.as_slice() {
+            ["keep"] => Ok(TtlPolicy::Keep),
+            ["adaptive"] => Ok(TtlPolicy::Adaptive {
+                min: ADAPTIVE_MIN_TTL,
+                max: ADAPTIVE_MAX_TTL,
+                stable_after: ADAPTIVE_STABLE_AFTER,
+            }),
+            ["adaptive", min, max, stable_after] => {
+                let min = min.parse().map_err(|_| invalid())?;
+                let max = max.parse().map_err(|_| invalid())?;
+                let stable_after = stable_after.parse().map_err(|_| invalid())?;
+                if min > max {
+                    return Err(invalid());
+                }
+                Ok(TtlPolicy::Adaptive {
+                    min,
+                    max,
+                    stable_after: Duration::from_secs(stable_after),
+                })
+            }
+            [ttl] => ttl.parse().map(TtlPolicy::Fixed).map_err(|_| invalid()),
+            _ => Err(invalid()),
+        }
+    }
+}
+
+/// Parses per record policies of the form `www=300,home=adaptive`.
+pub fn parse_record_ttls(s: &str) -> Result<Vec<(String, TtlPolicy)>, String> {
+    s.split(',')
+        .filter(|entry| !entry.trim().is_empty())
+        .map(|entry| match entry.split_once('=') {
+            Some((record, policy)) => Ok((record.trim().to_string(), policy.parse()?)),
+            None => Err(format!("invalid record ttl: {}", entry)),
+        })
+        .collect()
+}
+
+struct TtlState {
+    last_change: Instant,
+    applied: Option<u32>,
+}
+
+pub struct TtlManager {
+    default: TtlPolicy,
+    records: HashMap<String, TtlPolicy>,
+    state: HashMap<String, TtlState>,
+}
+
+impl TtlManager {
+    pub fn new(default: TtlPolicy) -> Self {
+        Self {
+            default,
+            records: HashMap::new(),
+            state: HashMap::new(),
+        }
+    }
+
+    pub fn with_record(mut self, record: &str, policy: TtlPolicy) -> Self {
+        self.records.insert(record.to_string(), policy);
+        self
+    }
+
+    /// Whether the TTL the record is currently published with is still unknown and should be
+    /// passed to `seed_ttl` before planning.
+    pub fn needs_current_ttl(&self, record: &str) -> bool {
+        let policy = self.records.get(record).unwrap_or(&self.default);
+        *policy != TtlPolicy::Keep && !self.state.contains_key(record)
+    }
+
+    /// Remembers the TTL a record is published with, so that it is not sent again.
+    pub fn seed_ttl(&mut self, record: &str, ttl: Option<u32>, now: Instant) {
+        self.state.entry(record.to_string()).or_insert(TtlState {
+            last_change: now,
+            applied: ttl,
+        });
+    }
+
+    /// Returns the TTL to publish for a record, or `None` if the TTL should not be touched.
+    /// Without an address change this only returns a TTL if the policy wants to change it.
+    pub fn planned_ttl(
+        &mut self,
+        record: &str,
+        address_changed: bool,
+        now: Instant,
+    ) -> Option<u32> {
+        let policy = self.records.get(record).unwrap_or(&self.default);
+        let state = self.state.entry(record.to_string()).or_insert(TtlState {
+            last_change: now,
+            applied: None,
+        });
+
+        match *policy {
+            TtlPolicy::Keep => None,
+            TtlPolicy::Fixed(ttl) => (address_changed || state.applied != Some(ttl)).then_some(ttl),
+            TtlPolicy::Adaptive {
+                min,
+                max,
+                stable_after,
+            } => {
+                if address_changed {
+                    Some(min)
+                } else if now.duration_since(state.last_change) >= stable_after
+                    && state.applied != Some(max)
+                {
+                    Some(max)
+                } else {
+                    None
+                }
+            }
+        }
+    }
+
+    pub fn record_update(
+        &mut self,
+        record: &str,
+        ttl: Option<u32>,
+        address_changed: bool,
+        now: Instant,
+    ) {
+        let state = self.state.entry(record.to_string()).or_insert(TtlState {
+            last_change: now,
+            applied: None,
+        });
+        if address_changed {
+            state.last_change = now;
+        }
+        if ttl.is_some() {
+            state.applied = ttl;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RECORD: &str = "home";
+
+    fn adaptive() -> TtlPolicy {
+        TtlPolicy::Adaptive {
+            min: 60,
+            max: 3600,
+            stable_after: Duration::from_secs(600),
+        }
+    }
+
+    #[test]
+    fn parse_policies() {
+        assert_eq!("keep".parse(), Ok(TtlPolicy::Keep));
+        assert_eq!("300".parse(), Ok(TtlPolicy::Fixed(300)));
+        assert_eq!("adaptive:60:3600:600".parse(), Ok(adaptive()));
+        assert!("adaptive:3600:60:600".parse::<TtlPolicy>().is_err());
+        assert!("sometimes".parse::<TtlPolicy>().is_err());
+    }
+
+    #[test]
+    fn parse_per_record_policies() {
+        assert_eq!(
+            parse_record_ttls("www=300, home=keep"),
+            Ok(vec![
+                (String::from("www"), TtlPolicy::Fixed(300)),
+                (String::from("home"), TtlPolicy::Keep)
+            ])
+        );
+        assert!(parse_record_ttls("www").is_err());
+    }
+
+    #[test]
+    fn keep_never_sets_a_ttl() {
+        let mut manager = TtlManager::new(TtlPolicy::Keep);
+        assert_eq!(manager.planned_ttl(RECORD, true, Instant::now()), None);
+    }
+
+    #[test]
+    fn fixed_ttl_is_applied_once() {
+        let now = Instant::now();
+        let mut manager =
+            TtlManager::new(TtlPolicy::Keep).with_record(RECORD, TtlPolicy::Fixed(300));
+
+        assert_eq!(manager.planned_ttl(RECORD, false, now), Some(300));
+        manager.record_update(RECORD, Some(300), false, now);
+        assert_eq!(manager.planned_ttl(RECORD, false, now), None);
+        assert_eq!(manager.planned_ttl(RECORD, true, now), Some(300));
+        assert_eq!(manager.planned_ttl("other", true, now), None);
+    }
+
+    #[test]
+    fn fixed_ttl_already_published_is_not_sent_again() {
+        let now = Instant::now();
+        let mut manager = TtlManager::new(TtlPolicy::Fixed(300));
+
+        assert!(manager.needs_current_ttl(RECORD));
+        manager.seed_ttl(RECORD, Some(300), now);
+        assert!(!manager.needs_current_ttl(RECORD));
+        assert_eq!(manager.planned_ttl(RECORD, false, now), None);
+
+        manager.seed_ttl("other", Some(3600), now);
+        assert_eq!(manager.planned_ttl("other", false, now), Some(300));
+        assert!(!TtlManager::new(TtlPolicy::Keep).needs_current_ttl(RECORD));
+    }
+
+    #[test]
+    fn adaptive_lowers_after_change_and_raises_when_stable() {
+        let start = Instant::now();
+        let mut manager = TtlManager::new(adaptive());
+
+        assert_eq!(manager.planned_ttl(RECORD, true, start), Some(60));
+        manager.record_update(RECORD, Some(60), true, start);
+
+        let later = start + Duration::from_secs(300);
+        assert_eq!(manager.planned_ttl(RECORD, false, later), None);
+
+        let stable = start + Duration::from_secs(600);
+        assert_eq!(manager.planned_ttl(RECORD, false, stable), Some(3600));
+        manager.record_update(RECORD, Some(3600), false, stable);
+        assert_eq!(manager.planned_ttl(RECORD, false, stable), None);
+
+        let changed = stable + Duration::from_secs(60);
+        assert_eq!(manager.planned_ttl(RECORD, true, changed), Some(60));
+    }
+}
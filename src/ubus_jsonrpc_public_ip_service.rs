@@ -1,20 +1,29 @@
 use async_trait::async_trait;
+use log::{debug, error};
 use reqwest::Client;
 use reqwest::StatusCode;
 use serde::Deserialize;
 use serde::Serialize;
 use serde_json::Map;
 use serde_json::Value;
-use std::net::Ipv4Addr;
+use std::net::{Ipv4Addr, Ipv6Addr};
+use std::sync::Mutex;
+use thiserror::Error;
 
 use uuid::Uuid;
 
+use crate::address_policy::AddressPolicy;
 use crate::public_ip_service::PublicIpService;
 use crate::public_ip_service::PublicIpServiceError;
 
 const JSONRPC2: &str = "2.0";
 const METHOD_CALL: &str = "call";
 const NULL_SESSION: &str = "00000000000000000000000000000000";
+const ACCESS_DENIED: i64 = -32002;
+
+/// Status codes of ubus calls, see `enum ubus_msg_status` in libubus.
+pub const UBUS_STATUS_INVALID_ARGUMENT: i64 = 2;
+pub const UBUS_STATUS_NOT_FOUND: i64 = 4;
 
 #[derive(Debug)]
 pub struct SessionResponse {
@@ -24,12 +33,27 @@ pub struct SessionResponse {
 #[derive(Debug, Deserialize)]
 pub struct Ipv4AddressInfo {
     pub address: Ipv4Addr,
+    #[serde(default)]
+    pub mask: Option<u8>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Ipv6AddressInfo {
+    pub address: Ipv6Addr,
+    #[serde(default)]
+    pub mask: Option<u8>,
+    #[serde(default)]
+    pub preferred: Option<u32>,
+    #[serde(default)]
+    pub valid: Option<u32>,
 }
 
 #[derive(Debug, Deserialize)]
 #[serde(rename_all = "kebab-case")]
 pub struct NetworkInterfaceStatusResponse {
     pub ipv4_address: Vec<Ipv4AddressInfo>,
+    #[serde(default)]
+    pub ipv6_address: Vec<Ipv6AddressInfo>,
 }
 
 #[derive(Debug, Serialize, Deserialize, PartialEq, Eq)]
@@ -39,6 +63,20 @@ pub struct UbusJsonResponseContainer {
     pub result: Vec<Value>,
 }
 
+#[derive(Debug, Deserialize)]
+struct UbusCallResponse {
+    #[serde(default)]
+    result: Option<Vec<Value>>,
+    #[serde(default)]
+    error: Option<UbusRpcError>,
+}
+
+#[derive(Debug, Deserialize)]
+struct UbusRpcError {
+    code: i64,
+    message: String,
+}
+
 #[derive(Debug, Serialize, Deserialize, PartialEq, Eq)]
 pub struct UbusJsonRequestContainer {
     pub jsonrpc: String,
@@ -72,15 +110,6 @@ impl UbusJsonRequestContainer {
         )
     }
 
-    fn network_interface_status(token: String) -> Self {
-        UbusJsonRequestContainer::new(
-            Value::String(token),
-            Value::String("network.interface.wan".to_string()),
-            Value::String("status".to_string()),
-            vec![Value::Object(Map::new())],
-        )
-    }
-
     #[cfg(test)]
     fn get_command_params(&self) -> Vec<Value> {
         self.params.to_owned()
@@ -108,6 +137,9 @@ pub struct UbusJsonRpcClient {
     ubus_url: String,
     ubus_user: String,
     ubus_secret: String,
+    interface: String,
+    address_policy: AddressPolicy,
+    session: Mutex<Option<String>>,
     client: Client,
 }
 
@@ -118,10 +150,25 @@ impl UbusJsonRpcClient {
             ubus_url: String::from(ubus_url),
             ubus_user: String::from(ubus_user),
             ubus_secret: String::from(ubus_secret),
+            interface: String::from("wan"),
+            address_policy: AddressPolicy::default(),
+            session: Mutex::new(None),
             client,
         }
     }
 
+    /// Reads the address of another logical interface than `wan`, for example `lan` when the
+    /// records are only served to the local network.
+    pub fn with_interface(mut self, interface: &str) -> Self {
+        self.interface = String::from(interface);
+        self
+    }
+
+    pub fn with_address_policy(mut self, address_policy: AddressPolicy) -> Self {
+        self.address_policy = address_policy;
+        self
+    }
+
     pub async fn get_session(&self) -> Result<SessionResponse, PublicIpServiceError> {
         let login_request = UbusJsonRequestContainer::login(&self.ubus_user, &self.ubus_secret);
         let response = self
@@ -147,34 +194,145 @@ impl UbusJsonRpcClient {
         }
     }
 
+    /// Reads the status of the configured interface in the shared session.
     pub async fn get_ip(&self) -> Result<NetworkInterfaceStatusResponse, PublicIpServiceError> {
-        let session = self.get_session().await?;
-        let status_request = UbusJsonRequestContainer::network_interface_status(session.token);
-        let response = self
-            .client
-            .post(&self.ubus_url)
-            .json(&status_request)
-            .send()
-            .await?;
+        let object = format!("network.interface.{}", self.interface);
+        let status = self
+            .call(&object, "status", Value::Object(Map::new()))
+            .await
+            .map_err(|e| match e {
+                UbusError::Login { source } => source,
+                UbusError::RequestFailed { source } => PublicIpServiceError::ClientError { source },
+                _ => PublicIpServiceError::InvalidIpResponse,
+            })?;
+        serde_json::from_value(status).map_err(|_| PublicIpServiceError::InvalidIpResponse)
+    }
 
-        match response.status() {
-            StatusCode::OK => {
-                let json: Value = serde_json::from_value(response.json().await?).unwrap();
-                let response_body: UbusJsonResponseContainer = serde_json::from_value(json)
-                    .map_err(|_| PublicIpServiceError::InternalError)?;
-                let status = serde_json::from_value(response_body.result[1].clone())
-                    .map_err(|_| PublicIpServiceError::InvalidIpResponse)?;
-                Ok(status)
+    /// Calls `method` of the ubus `object` and returns its data. The session is opened on the
+    /// first call and reused, and opened again once when rpcd no longer accepts it.
+    pub async fn call(&self, object: &str, method: &str, args: Value) -> Result<Value, UbusError> {
+        let session = self.session.lock().unwrap().clone();
+        let session = match session {
+            Some(session) => session,
+            None => self.login().await?,
+        };
+
+        let response = match self.send_call(&session, object, method, &args).await? {
+            UbusCallResponse {
+                error:
+                    Some(UbusRpcError {
+                        code: ACCESS_DENIED,
+                        ..
+                    }),
+                ..
+            } => {
+                debug!("ubus session expired, logging in again.");
+                let session = self.login().await?;
+                self.send_call(&session, object, method, &args).await?
             }
-            _ => Err(PublicIpServiceError::InvalidIpResponse),
+            response => response,
+        };
+
+        if let Some(e) = response.error {
+            error!(
+                "ubus call {} {} failed with {}: {}",
+                object, method, e.code, e.message
+            );
+            return Err(match e.code {
+                ACCESS_DENIED => UbusError::AccessDenied {
+                    object: String::from(object),
+                    method: String::from(method),
+                },
+                code => UbusError::Rpc {
+                    code,
+                    message: e.message,
+                },
+            });
         }
+        let mut result = response.result.unwrap_or_default().into_iter();
+        match result.next().and_then(|status| status.as_i64()) {
+            Some(0) => Ok(result.next().unwrap_or(Value::Null)),
+            Some(status) => {
+                // Missing sections and options are expected while looking entries up.
+                if status == UBUS_STATUS_NOT_FOUND {
+                    debug!("ubus call {} {} returned status {}", object, method, status);
+                } else {
+                    error!("ubus call {} {} returned status {}", object, method, status);
+                }
+                Err(UbusError::Status {
+                    object: String::from(object),
+                    method: String::from(method),
+                    status,
+                })
+            }
+            None => Err(UbusError::InvalidResponse),
+        }
+    }
+
+    async fn login(&self) -> Result<String, UbusError> {
+        let session = self.get_session().await?.token;
+        *self.session.lock().unwrap() = Some(session.clone());
+        Ok(session)
+    }
+
+    async fn send_call(
+        &self,
+        session: &str,
+        object: &str,
+        method: &str,
+        args: &Value,
+    ) -> Result<UbusCallResponse, UbusError> {
+        let request = UbusJsonRequestContainer::new(
+            Value::String(session.to_string()),
+            Value::String(object.to_string()),
+            Value::String(method.to_string()),
+            vec![args.clone()],
+        );
+        Ok(self
+            .client
+            .post(&self.ubus_url)
+            .json(&request)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?)
     }
 }
 
+#[derive(Debug, Error)]
+pub enum UbusError {
+    #[error("We could not log in to ubus.")]
+    Login {
+        #[from]
+        source: PublicIpServiceError,
+    },
+    #[error("The ACL does not allow calling {object} {method}.")]
+    AccessDenied { object: String, method: String },
+    #[error("ubus call {object} {method} returned status {status}")]
+    Status {
+        object: String,
+        method: String,
+        status: i64,
+    },
+    #[error("JSON-RPC call failed with code {code}: {message}")]
+    Rpc { code: i64, message: String },
+    #[error("invalid response")]
+    InvalidResponse,
+    #[error("request failed")]
+    RequestFailed {
+        #[from]
+        source: reqwest::Error,
+    },
+}
+
 #[async_trait]
 impl PublicIpService for UbusJsonRpcClient {
     async fn get_ip(&self) -> Result<Ipv4Addr, PublicIpServiceError> {
-        Ok(self.get_ip().await?.ipv4_address[0].address)
+        let status = self.get_ip().await?;
+        self.address_policy
+            .select_ipv4(&status.ipv4_address)
+            .ok_or(PublicIpServiceError::NoAddress)
     }
 }
 
@@ -231,16 +389,4 @@ mod tests {
         assert_eq!(params[2].as_str().unwrap(), "login");
         assert_eq!(params[3], serde_json::to_value(login_params).unwrap())
     }
-
-    #[tokio::test]
-    async fn ubus_container_network_interface_status() {
-        let login_call = UbusJsonRequestContainer::network_interface_status("session".to_string());
-
-        let params = login_call.get_command_params();
-
-        assert_eq!(params[0].as_str().unwrap(), "session");
-        assert_eq!(params[1].as_str().unwrap(), "network.interface.wan");
-        assert_eq!(params[2].as_str().unwrap(), "status");
-        assert_eq!(params[3], serde_json::to_value(Map::new()).unwrap());
-    }
 }
@@ -0,0 +1,209 @@
+use log::debug;
+use thiserror::Error;
+
+use crate::dns_service::{DnsService, DnsServiceError};
+
+/// A record name relative to the zone it belongs to.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ZoneName {
+    pub zone: String,
+    pub subdomain: String,
+}
+
+/// Brings a name into the form the DNS APIs use: no trailing dot, lower case and
+/// internationalized labels in their `xn--` punycode form.
+pub fn normalize_name(name: &str) -> Result<String, ZoneDetectionError> {
+    let trimmed = name.trim().trim_end_matches('.');
+    if trimmed.is_empty() {
+        return Err(ZoneDetectionError::InvalidName {
+            name: name.to_string(),
+        });
+    }
+
+    // UTS #46 maps the name to lower case and NFC before the labels are punycode encoded.
+    let ascii = idna::domain_to_ascii(trimmed).map_err(|_| ZoneDetectionError::InvalidName {
+        name: name.to_string(),
+    })?;
+    if ascii
+        .split('.')
+        .any(|label| label.is_empty() || label.len() > 63)
+    {
+        return Err(ZoneDetectionError::InvalidName {
+            name: name.to_string(),
+        });
+    }
+    Ok(ascii)
+}
+
+/// Picks the longest zone that `name` belongs to and returns the name relative to it, with
+/// `@` for the apex.
+pub fn split_name(name: &str, zones: &[String]) -> Result<ZoneName, ZoneDetectionError> {
+    let name = normalize_name(name)?;
+    let mut best: Option<String> = None;
+    for zone in zones {
+        let zone = match normalize_name(zone) {
+            Ok(zone) => zone,
+            Err(e) => {
+                debug!("Skipping zone {}: {}", zone, e);
+                continue;
+            }
+        };
+        let matches = name == zone || name.ends_with(&format!(".{}", zone));
+        let longer = match &best {
+            Some(best) => zone.len() > best.len(),
+            None => true,
+        };
+        if matches && longer {
+            best = Some(zone);
+        }
+    }
+
+    let zone = best.ok_or_else(|| ZoneDetectionError::NoMatchingZone { name: name.clone() })?;
+    let subdomain = if name == zone {
+        String::from("@")
+    } else {
+        name[..name.len() - zone.len() - 1].to_string()
+    };
+    Ok(ZoneName { zone, subdomain })
+}
+
+/// Lists the zones visible to the DNS service and splits all names against them. All names
+/// have to belong to the same zone.
+pub async fn detect_zone(
+    dns_service: &dyn DnsService,
+    names: &[&str],
+) -> Result<(String, Vec<String>), ZoneDetectionError> {
+    let zones = dns_service.list_zones().await?;
+    debug!("Found {} zones: {}", zones.len(), zones.join(", "));
+
+    let mut detected: Option<String> = None;
+    let mut subdomains = Vec::with_capacity(names.len());
+    for name in names {
+        let split = split_name(name, &zones)?;
+        match &detected {
+            Some(zone) if *zone != split.zone => {
+                return Err(ZoneDetectionError::MultipleZones {
+                    zones: vec![zone.clone(), split.zone],
+                });
+            }
+            Some(_) => {}
+            None => detected = Some(split.zone),
+        }
+        subdomains.push(split.subdomain);
+    }
+
+    match detected {
+        Some(zone) => Ok((zone, subdomains)),
+        None => Err(ZoneDetectionError::InvalidName {
+            name: String::new(),
+        }),
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum ZoneDetectionError {
+    #[error("invalid name: {name}")]
+    InvalidName { name: String },
+    #[error("no zone available for {name}")]
+    NoMatchingZone { name: String },
+    #[error("names belong to more than one zone: {zones:?}")]
+    MultipleZones { zones: Vec<String> },
+    #[error("could not list zones")]
+    DnsServiceError {
+        #[from]
+        source: DnsServiceError,
+    },
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn zones(names: &[&str]) -> Vec<String> {
+        names.iter().map(|n| n.to_string()).collect()
+    }
+
+    #[test]
+    fn normalize_trailing_dot_and_case() {
+        assert_eq!(
+            normalize_name("NAS.Home.Example.co.uk.").unwrap(),
+            "nas.home.example.co.uk"
+        );
+        assert!(normalize_name(".").is_err());
+        assert!(normalize_name("a..b").is_err());
+    }
+
+    #[test]
+    fn normalize_idn_labels() {
+        assert_eq!(
+            normalize_name("bücher.example").unwrap(),
+            "xn--bcher-kva.example"
+        );
+        assert_eq!(normalize_name("München.de").unwrap(), "xn--mnchen-3ya.de");
+        assert_eq!(normalize_name("日本語.jp").unwrap(), "xn--wgv71a119e.jp");
+    }
+
+    #[test]
+    fn normalize_decomposed_idn_labels() {
+        // "bu\u{308}cher" is "bücher" with a combining diaeresis, as macOS file names spell it.
+        assert_eq!(
+            normalize_name("bu\u{308}cher.example").unwrap(),
+            "xn--bcher-kva.example"
+        );
+    }
+
+    #[test]
+    fn pick_longest_matching_zone() {
+        let zones = zones(&["example.co.uk", "home.example.co.uk", "co.uk.example"]);
+        assert_eq!(
+            split_name("nas.home.example.co.uk", &zones).unwrap(),
+            ZoneName {
+                zone: String::from("home.example.co.uk"),
+                subdomain: String::from("nas"),
+            }
+        );
+        assert_eq!(
+            split_name("www.example.co.uk", &zones).unwrap().subdomain,
+            "www"
+        );
+    }
+
+    #[test]
+    fn skip_zones_that_do_not_normalize() {
+        let zones = zones(&["a..b", "example.com"]);
+        assert_eq!(
+            split_name("www.example.com", &zones).unwrap().zone,
+            "example.com"
+        );
+    }
+
+    #[test]
+    fn match_on_label_boundaries_only() {
+        let zones = zones(&["ample.com"]);
+        assert!(split_name("www.example.com", &zones).is_err());
+    }
+
+    #[test]
+    fn apex_becomes_at() {
+        let zones = zones(&["Example.com."]);
+        assert_eq!(
+            split_name("example.COM.", &zones).unwrap(),
+            ZoneName {
+                zone: String::from("example.com"),
+                subdomain: String::from("@"),
+            }
+        );
+    }
+
+    #[test]
+    fn match_unicode_name_against_punycode_zone() {
+        let zones = zones(&["xn--bcher-kva.example"]);
+        assert_eq!(
+            split_name("nas.bücher.example", &zones).unwrap(),
+            ZoneName {
+                zone: String::from("xn--bcher-kva.example"),
+                subdomain: String::from("nas"),
+            }
+        );
+    }
+}
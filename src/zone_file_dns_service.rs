@@ -0,0 +1,601 @@
+use async_trait::async_trait;
+use log::debug;
+use std::fmt;
+use std::fs;
+use std::net::Ipv4Addr;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::authoritative_dns::fqdn;
+use crate::dns_service::{DnsService, DnsServiceError};
+use crate::local_file::{run_reload_command, write_atomically, LocalFileError};
+use crate::record_template::{managed_value, replace_managed};
+use crate::time::utc_date;
+
+const CLASSES: [&str; 4] = ["IN", "CH", "HS", "CS"];
+
+/// An RFC 1035 master file. Records are found and replaced line by line, everything else
+/// (comments, directives, other records) is kept as it is.
+pub struct ZoneFile {
+    lines: Vec<String>,
+    origin: String,
+}
+
+struct Entry {
+    line: usize,
+    name: String,
+    owner: Option<String>,
+    ttl: Option<String>,
+    class: Option<String>,
+    record_type: String,
+    rdata: String,
+    rdata_offset: usize,
+    comment: String,
+}
+
+impl ZoneFile {
+    pub fn parse(contents: &str, domain: &str) -> Result<Self, LocalFileError> {
+        let zone = Self {
+            lines: contents.lines().map(String::from).collect(),
+            origin: absolute_domain(domain),
+        };
+
+        match zone
+            .entries()
+            .iter()
+            .find(|entry| entry.record_type == "SOA")
+        {
+            Some(soa) if soa.name != zone.origin => Err(LocalFileError::ZoneMismatch {
+                expected: zone.origin.clone(),
+                found: soa.name.clone(),
+            }),
+            Some(_) => Ok(zone),
+            None => Err(LocalFileError::InvalidZone {
+                message: String::from("no SOA record"),
+            }),
+        }
+    }
+
+    pub fn get(&self, name: &str, record_type: &str) -> Vec<String> {
+        let name = absolute_domain(name);
+        self.entries()
+            .into_iter()
+            .filter(|entry| {
+                entry.name == name && entry.record_type.eq_ignore_ascii_case(record_type)
+            })
+            .map(|entry| entry.rdata)
+            .collect()
+    }
+
+    /// Returns the TTL of the first record of `name` and `record_type` if it is given in
+    /// seconds. Records without one use the `$TTL` of the file.
+    pub fn ttl(&self, name: &str, record_type: &str) -> Option<u32> {
+        let name = absolute_domain(name);
+        self.entries()
+            .into_iter()
+            .find(|entry| entry.name == name && entry.record_type.eq_ignore_ascii_case(record_type))
+            .and_then(|entry| entry.ttl)
+            .and_then(|ttl| ttl.parse().ok())
+    }
+
+    /// Replaces the records of `name` and `record_type` with `values`. The first record is
+    /// rewritten in place and keeps its TTL unless `ttl` is given, further records are removed
+    /// and missing ones are appended. Returns whether the file changed.
+    pub fn set(
+        &mut self,
+        name: &str,
+        record_type: &str,
+        values: &[String],
+        ttl: Option<u32>,
+    ) -> bool {
+        let name = absolute_domain(name);
+        let record_type = record_type.to_ascii_uppercase();
+        let ttl = ttl.map(|ttl| ttl.to_string());
+        let entries = self.entries();
+        let matching: Vec<&Entry> = entries
+            .iter()
+            .filter(|entry| {
+                entry.name == name && entry.record_type == record_type && !entry.rdata.contains('(')
+            })
+            .collect();
+
+        let mut lines = Vec::with_capacity(self.lines.len() + values.len());
+        // Records without an owner inherit it from the previous record, so the owner of a
+        // removed record moves to the next one.
+        let mut pending_owner = None;
+        for (index, line) in self.lines.iter().enumerate() {
+            let position = matching.iter().position(|entry| entry.line == index);
+            let entry = entries.iter().find(|entry| entry.line == index);
+            match (position, entry) {
+                (Some(0), Some(entry)) if !values.is_empty() => {
+                    let ttl = ttl.as_deref().or(entry.ttl.as_deref());
+                    if values.len() == 1 && values[0] == entry.rdata && ttl == entry.ttl.as_deref()
+                    {
+                        lines.push(line.clone());
+                        continue;
+                    }
+                    for (i, value) in values.iter().enumerate() {
+                        let (owner, comment) = match i {
+                            0 => (entry.owner.as_deref().unwrap_or(""), entry.comment.as_str()),
+                            _ => ("", ""),
+                        };
+                        lines.push(format_record(
+                            owner,
+                            ttl,
+                            entry.class.as_deref(),
+                            &record_type,
+                            value,
+                            comment,
+                        ));
+                    }
+                }
+                (Some(position), _) => {
+                    if let Some(owner) = &matching[position].owner {
+                        pending_owner = Some(owner.as_str());
+                    }
+                }
+                (None, Some(entry)) if entry.owner.is_none() => match pending_owner.take() {
+                    Some(owner) => lines.push(format!("{}{}", owner, line)),
+                    None => lines.push(line.clone()),
+                },
+                (None, Some(_)) => {
+                    pending_owner = None;
+                    lines.push(line.clone());
+                }
+                (None, None) => lines.push(line.clone()),
+            }
+        }
+
+        if matching.is_empty() {
+            let owner = relative(&name, &self.final_origin());
+            for value in values {
+                lines.push(format_record(
+                    &owner,
+                    ttl.as_deref(),
+                    Some("IN"),
+                    &record_type,
+                    value,
+                    "",
+                ));
+            }
+        }
+
+        let changed = lines != self.lines;
+        self.lines = lines;
+        changed
+    }
+
+    /// Increments the SOA serial and returns the new one.
+    pub fn bump_serial(&mut self, today: u32) -> Result<u32, LocalFileError> {
+        let (line, range) = self
+            .serial_position()
+            .ok_or_else(|| LocalFileError::InvalidZone {
+                message: String::from("the SOA record has no serial"),
+            })?;
+        let serial = &self.lines[line][range.clone()];
+        let serial: u32 = serial.parse().map_err(|_| LocalFileError::InvalidZone {
+            message: format!("the SOA serial {} is not a number", serial),
+        })?;
+
+        let next = next_serial(serial, today);
+        self.lines[line].replace_range(range, &next.to_string());
+        Ok(next)
+    }
+
+    fn entries(&self) -> Vec<Entry> {
+        let mut entries = Vec::new();
+        let mut origin = self.origin.clone();
+        let mut last_owner = origin.clone();
+        let mut depth = 0;
+
+        for (index, line) in self.lines.iter().enumerate() {
+            let (data, comment) = split_comment(line);
+            if depth > 0 {
+                depth += paren_depth(data);
+                continue;
+            }
+            if data.trim().is_empty() {
+                continue;
+            }
+            depth = paren_depth(data);
+
+            if data.starts_with('$') {
+                let mut tokens = data.split_whitespace();
+                if let (Some(directive), Some(value)) = (tokens.next(), tokens.next()) {
+                    if directive.eq_ignore_ascii_case("$ORIGIN") {
+                        origin = absolute(value, &origin);
+                    }
+                }
+                continue;
+            }
+
+            let mut rest = data;
+            let mut owner = None;
+            if !data.starts_with(char::is_whitespace) {
+                if let Some((token, remainder)) = next_token(rest) {
+                    last_owner = absolute(token, &origin);
+                    owner = Some(String::from(token));
+                    rest = remainder;
+                }
+            }
+
+            let (mut ttl, mut class, mut record_type) = (None, None, None);
+            while let Some((token, remainder)) = next_token(rest) {
+                rest = remainder;
+                if ttl.is_none() && token.starts_with(|c: char| c.is_ascii_digit()) {
+                    ttl = Some(String::from(token));
+                } else if class.is_none() && CLASSES.iter().any(|c| c.eq_ignore_ascii_case(token)) {
+                    class = Some(String::from(token));
+                } else {
+                    record_type = Some(token.to_ascii_uppercase());
+                    break;
+                }
+            }
+
+            if let Some(record_type) = record_type {
+                let rdata = rest.trim_start();
+                entries.push(Entry {
+                    line: index,
+                    name: last_owner.clone(),
+                    owner,
+                    ttl,
+                    class,
+                    record_type,
+                    rdata: String::from(rdata.trim_end()),
+                    rdata_offset: data.len() - rdata.len(),
+                    comment: String::from(comment),
+                });
+            }
+        }
+        entries
+    }
+
+    /// The serial is the third field of the SOA record, which may continue on the following
+    /// lines inside parentheses.
+    fn serial_position(&self) -> Option<(usize, Range<usize>)> {
+        let soa = self
+            .entries()
+            .into_iter()
+            .find(|entry| entry.record_type == "SOA")?;
+
+        let mut count = 0;
+        let mut offset = soa.rdata_offset;
+        for (index, line) in self.lines.iter().enumerate().skip(soa.line) {
+            let (data, _) = split_comment(line);
+            for range in fields(data, offset) {
+                count += 1;
+                if count == 3 {
+                    return Some((index, range));
+                }
+            }
+            offset = 0;
+        }
+        None
+    }
+
+    fn final_origin(&self) -> String {
+        let mut origin = self.origin.clone();
+        for line in &self.lines {
+            let mut tokens = split_comment(line).0.split_whitespace();
+            if let (Some(directive), Some(value)) = (tokens.next(), tokens.next()) {
+                if directive.eq_ignore_ascii_case("$ORIGIN") {
+                    origin = absolute(value, &origin);
+                }
+            }
+        }
+        origin
+    }
+}
+
+impl fmt::Display for ZoneFile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for line in &self.lines {
+            writeln!(f, "{}", line)?;
+        }
+        Ok(())
+    }
+}
+
+/// Serials in the `YYYYMMDDnn` format recommended by RFC 1912 start over at `today` on a new
+/// day, all others are incremented. A wrapping serial skips 0, which some secondaries treat
+/// as unset.
+pub fn next_serial(serial: u32, today: u32) -> u32 {
+    if (19700101..=today).contains(&(serial / 100)) {
+        (serial + 1).max(today * 100)
+    } else {
+        serial.checked_add(1).unwrap_or(1)
+    }
+}
+
+fn today() -> u32 {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    let (year, month, day) = utc_date(now);
+    year as u32 * 10000 + month * 100 + day
+}
+
+fn absolute_domain(domain: &str) -> String {
+    format!("{}.", domain.trim_end_matches('.').to_ascii_lowercase())
+}
+
+fn absolute(name: &str, origin: &str) -> String {
+    match name {
+        "@" => String::from(origin),
+        _ if name.ends_with('.') => name.to_ascii_lowercase(),
+        _ => format!("{}.{}", name.to_ascii_lowercase(), origin),
+    }
+}
+
+fn relative(name: &str, origin: &str) -> String {
+    if name == origin {
+        return String::from("@");
+    }
+    name.strip_suffix(&format!(".{}", origin))
+        .unwrap_or(name)
+        .to_string()
+}
+
+fn format_record(
+    owner: &str,
+    ttl: Option<&str>,
+    class: Option<&str>,
+    record_type: &str,
+    value: &str,
+    comment: &str,
+) -> String {
+    let mut line = String::from(owner);
+    for field in [ttl, class, Some(record_type), Some(value)]
+        .into_iter()
+        .flatten()
+    {
+        line.push('\t');
+        line.push_str(field);
+    }
+    if !comment.is_empty() {
+        line.push(' ');
+        line.push_str(comment);
+    }
+    line
+}
+
+fn next_token(s: &str) -> Option<(&str, &str)> {
+    let s = s.trim_start();
+    if s.is_empty() {
+        return None;
+    }
+    let end = s.find(char::is_whitespace).unwrap_or(s.len());
+    Some((&s[..end], &s[end..]))
+}
+
+/// Splits a line into its data and its `;` comment, ignoring semicolons in quoted strings.
+fn split_comment(line: &str) -> (&str, &str) {
+    let mut quoted = false;
+    let mut escaped = false;
+    for (index, c) in line.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' => escaped = true,
+            '"' => quoted = !quoted,
+            ';' if !quoted => return (&line[..index], &line[index..]),
+            _ => {}
+        }
+    }
+    (line, "")
+}
+
+fn paren_depth(data: &str) -> i32 {
+    let mut quoted = false;
+    let mut depth = 0;
+    for c in data.chars() {
+        match c {
+            '"' => quoted = !quoted,
+            '(' if !quoted => depth += 1,
+            ')' if !quoted => depth -= 1,
+            _ => {}
+        }
+    }
+    depth
+}
+
+fn fields(data: &str, offset: usize) -> Vec<Range<usize>> {
+    let mut fields = Vec::new();
+    let mut start = None;
+    for (index, c) in data.char_indices().skip_while(|(index, _)| *index < offset) {
+        let separator = c.is_whitespace() || c == '(' || c == ')';
+        match (separator, start) {
+            (true, Some(begin)) => {
+                fields.push(begin..index);
+                start = None;
+            }
+            (false, None) => start = Some(index),
+            _ => {}
+        }
+    }
+    if let Some(begin) = start {
+        fields.push(begin..data.len());
+    }
+    fields
+}
+
+/// Writes records into a zone file served by BIND, NSD or Knot, bumps the SOA serial and runs
+/// an optional reload command.
+pub struct ZoneFileDnsService {
+    path: PathBuf,
+    reload_command: Option<String>,
+    lock: Mutex<()>,
+}
+
+impl ZoneFileDnsService {
+    pub fn new(path: impl AsRef<Path>) -> Self {
+        Self {
+            path: path.as_ref().to_path_buf(),
+            reload_command: None,
+            lock: Mutex::new(()),
+        }
+    }
+
+    pub fn with_reload_command(mut self, command: &str) -> Self {
+        self.reload_command = Some(String::from(command));
+        self
+    }
+
+    pub fn read_zone(&self, domain: &str) -> Result<ZoneFile, LocalFileError> {
+        ZoneFile::parse(&fs::read_to_string(&self.path)?, domain)
+    }
+
+    pub async fn replace_records(
+        &self,
+        domain: &str,
+        name: &str,
+        record_type: &str,
+        values: &[String],
+        ttl: Option<u32>,
+    ) -> Result<(), LocalFileError> {
+        let changed = {
+            let _guard = self.lock.lock().unwrap();
+            let mut zone = self.read_zone(domain)?;
+            if zone.set(name, record_type, values, ttl) {
+                let serial = zone.bump_serial(today())?;
+                debug!("Write zone file for {} with serial {}.", domain, serial);
+                write_atomically(&self.path, &zone.to_string())?;
+                true
+            } else {
+                false
+            }
+        };
+
+        if let (true, Some(command)) = (changed, &self.reload_command) {
+            run_reload_command(command).await?;
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl DnsService for ZoneFileDnsService {
+    async fn resolve_ip(
+        &self,
+        subdomain: &str,
+        domain: &str,
+    ) -> Result<Option<Ipv4Addr>, DnsServiceError> {
+        debug!(
+            "Resolve ip for domain {} and subdomain {}.",
+            domain, subdomain
+        );
+        let zone = self.read_zone(domain)?;
+
+        match zone.get(&fqdn(subdomain, domain), "A").into_iter().next() {
+            Some(value) => {
+                let ip = value.parse().map_err(|_| DnsServiceError::UnknownError)?;
+                Ok(Some(ip))
+            }
+            None => Ok(None),
+        }
+    }
+
+    async fn resolve_ttl(
+        &self,
+        subdomain: &str,
+        domain: &str,
+    ) -> Result<Option<u32>, DnsServiceError> {
+        let zone = self.read_zone(domain)?;
+        Ok(zone.ttl(&fqdn(subdomain, domain), "A"))
+    }
+
+    async fn update_dns(
+        &self,
+        subdomain: &str,
+        domain: &str,
+        ip: Ipv4Addr,
+    ) -> Result<(), DnsServiceError> {
+        self.update_dns_with_ttl(subdomain, domain, ip, None).await
+    }
+
+    async fn update_dns_with_ttl(
+        &self,
+        subdomain: &str,
+        domain: &str,
+        ip: Ipv4Addr,
+        ttl: Option<u32>,
+    ) -> Result<(), DnsServiceError> {
+        debug!(
+            "Update dns for domain {} and subdomain {} with IP {}.",
+            domain, subdomain, ip
+        );
+        self.replace_records(
+            domain,
+            &fqdn(subdomain, domain),
+            "A",
+            &[ip.to_string()],
+            ttl,
+        )
+        .await?;
+        Ok(())
+    }
+
+    fn supports_record_type(&self, _record_type: &str) -> bool {
+        true
+    }
+
+    async fn resolve_record(
+        &self,
+        subdomain: &str,
+        domain: &str,
+        record_type: &str,
+        prefix: &str,
+    ) -> Result<Option<String>, DnsServiceError> {
+        let zone = self.read_zone(domain)?;
+        let values = zone.get(&fqdn(subdomain, domain), record_type);
+        Ok(managed_value(record_type, &values, prefix))
+    }
+
+    async fn update_record(
+        &self,
+        subdomain: &str,
+        domain: &str,
+        record_type: &str,
+        prefix: &str,
+        value: &str,
+    ) -> Result<(), DnsServiceError> {
+        let name = fqdn(subdomain, domain);
+        let current = self.read_zone(domain)?.get(&name, record_type);
+        self.replace_records(
+            domain,
+            &name,
+            record_type,
+            &replace_managed(record_type, &current, prefix, value),
+            None,
+        )
+        .await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_next_serial() {
+        assert_eq!(next_serial(2023010101, 20261018), 2026101800);
+        assert_eq!(next_serial(2026101800, 20261018), 2026101801);
+        assert_eq!(next_serial(2026101899, 20261018), 2026101900);
+        assert_eq!(next_serial(42, 20261018), 43);
+        assert_eq!(next_serial(u32::MAX, 20261018), 1);
+    }
+
+    #[test]
+    fn test_serial_on_following_line() {
+        let mut zone = ZoneFile::parse(
+            "@ IN SOA ns1.example.com. hostmaster.example.com. (\n    7 ; serial\n    3600 900 604800 300 )\n",
+            "example.com",
+        )
+        .expect("parse failed");
+        assert_eq!(zone.bump_serial(20261018).expect("bump failed"), 8);
+        assert!(zone.to_string().contains("    8 ; serial\n"));
+    }
+}
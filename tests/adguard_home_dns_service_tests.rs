@@ -0,0 +1,160 @@
+use dyndnsd::adguard_home_dns_service::{AdGuardError, AdGuardHomeClient};
+use dyndnsd::dns_service::{DnsService, DnsServiceError};
+use serde_json::json;
+use std::net::Ipv4Addr;
+use wiremock::matchers::{body_json, header, method, path};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+const ZONE: &str = "example.com";
+const SUBDOMAIN: &str = "test";
+const OLD_IP: &str = "192.168.1.10";
+const NEW_IP: Ipv4Addr = Ipv4Addr::new(192, 168, 1, 11);
+// admin:secret
+const AUTHORIZATION: &str = "Basic YWRtaW46c2VjcmV0";
+
+fn client(uri: &str) -> AdGuardHomeClient {
+    AdGuardHomeClient::new(uri, "admin", "secret")
+}
+
+async fn mount_rewrites(mock_server: &MockServer, rewrites: serde_json::Value) {
+    Mock::given(method("GET"))
+        .and(path("/control/rewrite/list"))
+        .and(header("Authorization", AUTHORIZATION))
+        .respond_with(ResponseTemplate::new(200).set_body_json(rewrites))
+        .mount(mock_server)
+        .await;
+}
+
+async fn mount_change(
+    mock_server: &MockServer,
+    action: &str,
+    rewrite: serde_json::Value,
+    times: u64,
+) {
+    Mock::given(method("POST"))
+        .and(path(format!("/control/rewrite/{}", action)))
+        .and(header("Authorization", AUTHORIZATION))
+        .and(body_json(rewrite))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(times)
+        .mount(mock_server)
+        .await;
+}
+
+#[tokio::test]
+async fn test_resolve_ip() {
+    let mock_server = MockServer::start().await;
+    mount_rewrites(
+        &mock_server,
+        json!([
+            { "domain": "test.example.com", "answer": "2001:db8::10" },
+            { "domain": "test.example.com", "answer": OLD_IP },
+            { "domain": "*.example.com", "answer": "192.168.1.20" }
+        ]),
+    )
+    .await;
+
+    let ip = client(&mock_server.uri())
+        .resolve_ip(SUBDOMAIN, ZONE)
+        .await
+        .expect("resolve ip failed");
+    assert_eq!(ip, Some(OLD_IP.parse().unwrap()));
+}
+
+#[tokio::test]
+async fn test_update_replaces_rewrite_of_same_family() {
+    let mock_server = MockServer::start().await;
+    mount_rewrites(
+        &mock_server,
+        json!([
+            { "domain": "test.example.com", "answer": OLD_IP },
+            { "domain": "test.example.com", "answer": "2001:db8::10" }
+        ]),
+    )
+    .await;
+    mount_change(
+        &mock_server,
+        "add",
+        json!({ "domain": "test.example.com", "answer": NEW_IP.to_string() }),
+        1,
+    )
+    .await;
+    mount_change(
+        &mock_server,
+        "delete",
+        json!({ "domain": "test.example.com", "answer": OLD_IP }),
+        1,
+    )
+    .await;
+    mount_change(
+        &mock_server,
+        "delete",
+        json!({ "domain": "test.example.com", "answer": "2001:db8::10" }),
+        0,
+    )
+    .await;
+
+    client(&mock_server.uri())
+        .update_dns(SUBDOMAIN, ZONE, NEW_IP)
+        .await
+        .expect("update failed");
+}
+
+#[tokio::test]
+async fn test_unchanged_rewrite_is_left_alone() {
+    let mock_server = MockServer::start().await;
+    mount_rewrites(
+        &mock_server,
+        json!([{ "domain": "test.example.com", "answer": NEW_IP.to_string() }]),
+    )
+    .await;
+    mount_change(
+        &mock_server,
+        "add",
+        json!({ "domain": "test.example.com", "answer": NEW_IP.to_string() }),
+        0,
+    )
+    .await;
+
+    client(&mock_server.uri())
+        .update_dns(SUBDOMAIN, ZONE, NEW_IP)
+        .await
+        .expect("update failed");
+}
+
+#[tokio::test]
+async fn test_invalid_credentials() {
+    let mock_server = MockServer::start().await;
+
+    Mock::given(method("GET"))
+        .and(path("/control/rewrite/list"))
+        .respond_with(ResponseTemplate::new(403).set_body_string("Forbidden"))
+        .mount(&mock_server)
+        .await;
+
+    let result = client(&mock_server.uri()).list_rewrites().await;
+    assert!(matches!(result, Err(AdGuardError::InvalidCredentials)));
+}
+
+#[tokio::test]
+async fn test_rejected_change() {
+    let mock_server = MockServer::start().await;
+    mount_rewrites(&mock_server, json!([])).await;
+
+    Mock::given(method("POST"))
+        .and(path("/control/rewrite/add"))
+        .respond_with(
+            ResponseTemplate::new(400)
+                .set_body_string("rewrite: domain \"test.example.com\" is invalid"),
+        )
+        .mount(&mock_server)
+        .await;
+
+    let result = client(&mock_server.uri())
+        .update_dns(SUBDOMAIN, ZONE, NEW_IP)
+        .await;
+    assert!(matches!(
+        result,
+        Err(DnsServiceError::UpdateRejected { .. })
+    ));
+}
@@ -0,0 +1,238 @@
+use dyndnsd::authoritative_dns::{AuthoritativeDnsError, AuthoritativeResolver};
+use dyndnsd::dns_message::{Message, ResourceRecord, TYPE_A};
+use dyndnsd::dns_service::{DnsService, DnsServiceError, HetznerDnsService};
+use dyndnsd::hetzner_dns_client::{
+    GetRecordsResponse, GetZonesResponse, HetznerDnsClient, Record, UpdateRecordResponse, Zone,
+};
+use std::collections::HashMap;
+use std::net::{Ipv4Addr, SocketAddr};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::net::UdpSocket;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+const ZONE: &str = "example.com";
+const SUBDOMAIN: &str = "test";
+const FQDN: &str = "test.example.com";
+const NEW_IP: Ipv4Addr = Ipv4Addr::new(93, 184, 216, 34);
+
+type ZoneData = Arc<Mutex<HashMap<String, Ipv4Addr>>>;
+
+async fn start_name_server(records: ZoneData) -> SocketAddr {
+    let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    let addr = socket.local_addr().unwrap();
+
+    tokio::spawn(async move {
+        let mut buf = [0u8; 512];
+        loop {
+            let (len, peer) = socket.recv_from(&mut buf).await.unwrap();
+            let request = Message::decode(&buf[..len]).unwrap();
+            let mut response = Message::response_to(&request);
+
+            let question = &request.questions[0];
+            if question.qtype == TYPE_A {
+                if let Some(ip) = records.lock().unwrap().get(&question.name) {
+                    response
+                        .answers
+                        .push(ResourceRecord::a(&question.name, 60, *ip));
+                }
+            }
+            socket.send_to(&response.encode(), peer).await.unwrap();
+        }
+    });
+
+    addr
+}
+
+async fn mount_hetzner_api(mock_server: &MockServer) {
+    let zone = Zone {
+        name: String::from(ZONE),
+        id: String::from("zid123"),
+        ..Default::default()
+    };
+    let record = Record {
+        id: String::from("rid123"),
+        zone_id: String::from("zid123"),
+        name: String::from(SUBDOMAIN),
+        r#type: String::from("A"),
+        value: String::from("93.184.216.35"),
+        ..Default::default()
+    };
+
+    Mock::given(method("GET"))
+        .and(path("/zones"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(GetZonesResponse {
+            zones: vec![zone],
+            meta: None,
+        }))
+        .mount(mock_server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/records"))
+        .respond_with(
+            ResponseTemplate::new(200).set_body_json(GetRecordsResponse {
+                records: vec![record.clone()],
+                meta: None,
+            }),
+        )
+        .mount(mock_server)
+        .await;
+    Mock::given(method("PUT"))
+        .and(path("/records/rid123"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(UpdateRecordResponse { record }))
+        .mount(mock_server)
+        .await;
+}
+
+#[tokio::test]
+async fn test_wait_for_record() {
+    let records = ZoneData::default();
+    records.lock().unwrap().insert(FQDN.to_string(), NEW_IP);
+    let name_server = start_name_server(records).await;
+
+    let resolver = AuthoritativeResolver::new(&[&name_server.to_string()]);
+    assert_eq!(
+        resolver.resolve_a(FQDN).await.expect("query failed"),
+        vec![NEW_IP]
+    );
+    resolver
+        .wait_for(ZONE, FQDN, NEW_IP, Duration::from_secs(1))
+        .await
+        .expect("record was not served");
+}
+
+#[tokio::test]
+async fn test_wait_for_record_times_out() {
+    let name_server = start_name_server(ZoneData::default()).await;
+
+    let resolver = AuthoritativeResolver::new(&[&name_server.to_string()])
+        .with_poll_interval(Duration::from_millis(10));
+    assert!(matches!(
+        resolver
+            .wait_for(ZONE, FQDN, NEW_IP, Duration::from_millis(50))
+            .await,
+        Err(AuthoritativeDnsError::NotPropagated { .. })
+    ));
+}
+
+#[tokio::test]
+async fn test_update_dns_with_verification() {
+    let mock_server = MockServer::start().await;
+    mount_hetzner_api(&mock_server).await;
+
+    let records = ZoneData::default();
+    let name_server = start_name_server(records.clone()).await;
+
+    // The name server picks up the new value shortly after the API call, like a real secondary.
+    let propagate = records.clone();
+    tokio::spawn(async move {
+        tokio::time::sleep(Duration::from_millis(30)).await;
+        propagate.lock().unwrap().insert(FQDN.to_string(), NEW_IP);
+    });
+
+    let resolver = AuthoritativeResolver::new(&[&name_server.to_string()])
+        .with_poll_interval(Duration::from_millis(10));
+    let service =
+        HetznerDnsService::from_client(HetznerDnsClient::new_with_url("XXX", &mock_server.uri()))
+            .with_verification(resolver, Duration::from_secs(2));
+
+    service
+        .update_dns(SUBDOMAIN, ZONE, NEW_IP)
+        .await
+        .expect("update failed");
+}
+
+#[tokio::test]
+async fn test_update_dns_not_propagated() {
+    let mock_server = MockServer::start().await;
+    mount_hetzner_api(&mock_server).await;
+
+    let name_server = start_name_server(ZoneData::default()).await;
+
+    let resolver = AuthoritativeResolver::new(&[&name_server.to_string()])
+        .with_poll_interval(Duration::from_millis(10));
+    let service =
+        HetznerDnsService::from_client(HetznerDnsClient::new_with_url("XXX", &mock_server.uri()))
+            .with_verification(resolver, Duration::from_millis(50));
+
+    assert!(matches!(
+        service.update_dns(SUBDOMAIN, ZONE, NEW_IP).await,
+        Err(DnsServiceError::NotPropagated { record }) if record == FQDN
+    ));
+}
+
+#[tokio::test]
+async fn test_not_propagated_update_drops_cached_value() {
+    let mock_server = MockServer::start().await;
+    Mock::given(method("PUT"))
+        .and(path("/records/rid123"))
+        .respond_with(
+            ResponseTemplate::new(200).set_body_json(UpdateRecordResponse {
+                record: Record {
+                    id: String::from("rid123"),
+                    zone_id: String::from("zid123"),
+                    name: String::from(SUBDOMAIN),
+                    r#type: String::from("A"),
+                    value: NEW_IP.to_string(),
+                    ..Default::default()
+                },
+            }),
+        )
+        .with_priority(1)
+        .mount(&mock_server)
+        .await;
+    mount_hetzner_api(&mock_server).await;
+
+    let name_server = start_name_server(ZoneData::default()).await;
+
+    let resolver = AuthoritativeResolver::new(&[&name_server.to_string()])
+        .with_poll_interval(Duration::from_millis(10));
+    let service =
+        HetznerDnsService::from_client(HetznerDnsClient::new_with_url("XXX", &mock_server.uri()))
+            .with_cached_values(true)
+            .with_verification(resolver, Duration::from_millis(50));
+
+    assert!(service.update_dns(SUBDOMAIN, ZONE, NEW_IP).await.is_err());
+    // The API still holds the old value, which is read again instead of the unverified one.
+    assert_eq!(
+        service
+            .resolve_ip(SUBDOMAIN, ZONE)
+            .await
+            .expect("resolve failed"),
+        Some(Ipv4Addr::new(93, 184, 216, 35))
+    );
+}
+
+#[tokio::test]
+async fn test_resolve_ip_via_live_dns() {
+    let mock_server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .respond_with(ResponseTemplate::new(500))
+        .expect(0)
+        .mount(&mock_server)
+        .await;
+
+    let records = ZoneData::default();
+    records.lock().unwrap().insert(FQDN.to_string(), NEW_IP);
+    let name_server = start_name_server(records).await;
+
+    let service =
+        HetznerDnsService::from_client(HetznerDnsClient::new_with_url("XXX", &mock_server.uri()))
+            .with_live_dns(AuthoritativeResolver::new(&[&name_server.to_string()]));
+
+    assert_eq!(
+        service
+            .resolve_ip(SUBDOMAIN, ZONE)
+            .await
+            .expect("resolve failed"),
+        Some(NEW_IP)
+    );
+    assert_eq!(
+        service
+            .resolve_ip("missing", ZONE)
+            .await
+            .expect("resolve failed"),
+        None
+    );
+}
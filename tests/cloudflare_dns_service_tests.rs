@@ -0,0 +1,423 @@
+use dyndnsd::cloudflare_dns_service::{CloudflareDnsClient, CloudflareDnsClientError};
+use dyndnsd::dns_service::{DnsService, DnsServiceError};
+use serde_json::json;
+use std::net::Ipv4Addr;
+use wiremock::matchers::{body_json, header, method, path, query_param};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+const API_TOKEN: &str = "XXX";
+const ZONE: &str = "example.com";
+const ZONE_ID: &str = "023e105f4ecef8ad9ca31a8372d0c353";
+const SUBDOMAIN: &str = "test";
+const FQDN: &str = "test.example.com";
+const RECORD_ID: &str = "372e67954025e0ba6aaa6d586b9e0b59";
+const OLD_IP: &str = "93.184.216.34";
+const NEW_IP: Ipv4Addr = Ipv4Addr::new(93, 184, 216, 35);
+
+fn success(result: serde_json::Value) -> serde_json::Value {
+    json!({ "success": true, "errors": [], "messages": [], "result": result })
+}
+
+fn failure(code: u32, message: &str) -> serde_json::Value {
+    json!({
+        "success": false,
+        "errors": [{ "code": code, "message": message }],
+        "messages": [],
+        "result": null
+    })
+}
+
+fn record(content: &str, proxied: bool) -> serde_json::Value {
+    json!({
+        "id": RECORD_ID,
+        "name": FQDN,
+        "type": "A",
+        "content": content,
+        "ttl": 300,
+        "proxied": proxied
+    })
+}
+
+async fn mount_zone(mock_server: &MockServer) {
+    Mock::given(method("GET"))
+        .and(path("/zones"))
+        .and(query_param("name", ZONE))
+        .and(header("Authorization", "Bearer XXX"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .set_body_json(success(json!([{ "id": ZONE_ID, "name": ZONE }]))),
+        )
+        .expect(1)
+        .mount(mock_server)
+        .await;
+}
+
+async fn mount_record(mock_server: &MockServer, records: serde_json::Value) {
+    Mock::given(method("GET"))
+        .and(path(format!("/zones/{}/dns_records", ZONE_ID)))
+        .and(query_param("name", FQDN))
+        .and(query_param("type", "A"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(success(records)))
+        .mount(mock_server)
+        .await;
+}
+
+#[tokio::test]
+async fn test_resolve_ip() {
+    let mock_server = MockServer::start().await;
+    mount_zone(&mock_server).await;
+    mount_record(&mock_server, json!([record(OLD_IP, false)])).await;
+
+    let client = CloudflareDnsClient::new_with_url(API_TOKEN, &mock_server.uri());
+    let ip = client
+        .resolve_ip(SUBDOMAIN, ZONE)
+        .await
+        .expect("resolve ip failed");
+    assert_eq!(ip, Some(OLD_IP.parse().unwrap()));
+
+    // The zone id is looked up only once.
+    client
+        .resolve_ip(SUBDOMAIN, ZONE)
+        .await
+        .expect("resolve ip failed");
+}
+
+#[tokio::test]
+async fn test_resolve_ttl() {
+    let mock_server = MockServer::start().await;
+    mount_zone(&mock_server).await;
+    mount_record(&mock_server, json!([record(OLD_IP, false)])).await;
+
+    let ttl = CloudflareDnsClient::new_with_url(API_TOKEN, &mock_server.uri())
+        .resolve_ttl(SUBDOMAIN, ZONE)
+        .await
+        .expect("resolve ttl failed");
+    assert_eq!(ttl, Some(300));
+}
+
+#[tokio::test]
+async fn test_resolve_missing_record() {
+    let mock_server = MockServer::start().await;
+    mount_zone(&mock_server).await;
+    mount_record(&mock_server, json!([])).await;
+
+    let ip = CloudflareDnsClient::new_with_url(API_TOKEN, &mock_server.uri())
+        .resolve_ip(SUBDOMAIN, ZONE)
+        .await
+        .expect("resolve ip failed");
+    assert_eq!(ip, None);
+}
+
+#[tokio::test]
+async fn test_update_patches_record() {
+    let mock_server = MockServer::start().await;
+    mount_zone(&mock_server).await;
+    mount_record(&mock_server, json!([record(OLD_IP, false)])).await;
+
+    Mock::given(method("PATCH"))
+        .and(path(format!(
+            "/zones/{}/dns_records/{}",
+            ZONE_ID, RECORD_ID
+        )))
+        .and(body_json(
+            json!({ "content": NEW_IP.to_string(), "ttl": 120 }),
+        ))
+        .respond_with(
+            ResponseTemplate::new(200).set_body_json(success(record(&NEW_IP.to_string(), false))),
+        )
+        .expect(1)
+        .mount(&mock_server)
+        .await;
+
+    CloudflareDnsClient::new_with_url(API_TOKEN, &mock_server.uri())
+        .update_dns_with_ttl(SUBDOMAIN, ZONE, NEW_IP, Some(120))
+        .await
+        .expect("update failed");
+}
+
+#[tokio::test]
+async fn test_update_sets_proxied_flag() {
+    let mock_server = MockServer::start().await;
+    mount_zone(&mock_server).await;
+    mount_record(&mock_server, json!([record(OLD_IP, false)])).await;
+
+    Mock::given(method("PATCH"))
+        .and(path(format!(
+            "/zones/{}/dns_records/{}",
+            ZONE_ID, RECORD_ID
+        )))
+        .and(body_json(
+            json!({ "content": NEW_IP.to_string(), "proxied": true }),
+        ))
+        .respond_with(
+            ResponseTemplate::new(200).set_body_json(success(record(&NEW_IP.to_string(), true))),
+        )
+        .expect(1)
+        .mount(&mock_server)
+        .await;
+
+    CloudflareDnsClient::new_with_url(API_TOKEN, &mock_server.uri())
+        .with_proxied(true)
+        .update_dns_with_ttl(SUBDOMAIN, ZONE, NEW_IP, Some(120))
+        .await
+        .expect("update failed");
+}
+
+#[tokio::test]
+async fn test_update_creates_missing_record() {
+    let mock_server = MockServer::start().await;
+    mount_zone(&mock_server).await;
+    mount_record(&mock_server, json!([])).await;
+
+    Mock::given(method("POST"))
+        .and(path(format!("/zones/{}/dns_records", ZONE_ID)))
+        .and(body_json(json!({
+            "name": FQDN,
+            "type": "A",
+            "content": NEW_IP.to_string(),
+            "ttl": 1
+        })))
+        .respond_with(
+            ResponseTemplate::new(200).set_body_json(success(record(&NEW_IP.to_string(), false))),
+        )
+        .expect(1)
+        .mount(&mock_server)
+        .await;
+
+    CloudflareDnsClient::new_with_url(API_TOKEN, &mock_server.uri())
+        .update_dns(SUBDOMAIN, ZONE, NEW_IP)
+        .await
+        .expect("update failed");
+}
+
+#[tokio::test]
+async fn test_unknown_zone() {
+    let mock_server = MockServer::start().await;
+
+    Mock::given(method("GET"))
+        .and(path("/zones"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(success(json!([]))))
+        .mount(&mock_server)
+        .await;
+
+    let client = CloudflareDnsClient::new_with_url(API_TOKEN, &mock_server.uri());
+    let result = client.zone_id(ZONE).await;
+    assert!(matches!(
+        result,
+        Err(CloudflareDnsClientError::ZoneNotFound { .. })
+    ));
+
+    let result = client.resolve_ip(SUBDOMAIN, ZONE).await;
+    assert!(matches!(result, Err(DnsServiceError::UnknownZone)));
+}
+
+#[tokio::test]
+async fn test_api_errors() {
+    let mock_server = MockServer::start().await;
+
+    Mock::given(method("GET"))
+        .and(path("/zones"))
+        .and(query_param("name", "unauthorized.com"))
+        .respond_with(
+            ResponseTemplate::new(401).set_body_json(failure(10000, "Authentication error")),
+        )
+        .mount(&mock_server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/zones"))
+        .and(query_param("name", "forbidden.com"))
+        .respond_with(
+            ResponseTemplate::new(403).set_body_json(failure(10000, "Authentication error")),
+        )
+        .mount(&mock_server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/zones"))
+        .and(query_param("name", "invalid.com"))
+        .respond_with(ResponseTemplate::new(400).set_body_json(failure(1001, "Invalid zone name")))
+        .mount(&mock_server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/zones"))
+        .and(query_param("name", "throttled.com"))
+        .respond_with(ResponseTemplate::new(429).set_body_string("rate limited"))
+        .mount(&mock_server)
+        .await;
+
+    let client = CloudflareDnsClient::new_with_url(API_TOKEN, &mock_server.uri());
+    assert!(matches!(
+        client.find_zone("unauthorized.com").await,
+        Err(CloudflareDnsClientError::InvalidApiToken)
+    ));
+    assert!(matches!(
+        client.find_zone("forbidden.com").await,
+        Err(CloudflareDnsClientError::PermissionDenied { .. })
+    ));
+    match client.find_zone("invalid.com").await {
+        Err(CloudflareDnsClientError::Api {
+            status,
+            code,
+            message,
+        }) => {
+            assert_eq!(status, 400);
+            assert_eq!(code, 1001);
+            assert_eq!(message, "1001: Invalid zone name");
+        }
+        other => panic!("unexpected result: {:?}", other),
+    }
+    assert!(matches!(
+        client.find_zone("throttled.com").await,
+        Err(CloudflareDnsClientError::RateLimited)
+    ));
+}
+
+#[tokio::test]
+async fn test_list_zones_follows_pages() {
+    let mock_server = MockServer::start().await;
+
+    Mock::given(method("GET"))
+        .and(path("/zones"))
+        .and(query_param("page", "1"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "success": true,
+            "result": [{ "id": "1", "name": "example.com" }],
+            "result_info": { "page": 1, "per_page": 1, "total_pages": 2 }
+        })))
+        .mount(&mock_server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/zones"))
+        .and(query_param("page", "2"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "success": true,
+            "result": [{ "id": "2", "name": "example.org" }],
+            "result_info": { "page": 2, "per_page": 1, "total_pages": 2 }
+        })))
+        .mount(&mock_server)
+        .await;
+
+    let zones = DnsService::list_zones(&CloudflareDnsClient::new_with_url(
+        API_TOKEN,
+        &mock_server.uri(),
+    ))
+    .await
+    .expect("list zones failed");
+    assert_eq!(zones, vec!["example.com", "example.org"]);
+}
+
+#[tokio::test]
+async fn test_find_records_follows_pages() {
+    let mock_server = MockServer::start().await;
+
+    for (page, content) in [(1, OLD_IP), (2, "93.184.216.36")] {
+        Mock::given(method("GET"))
+            .and(path(format!("/zones/{}/dns_records", ZONE_ID)))
+            .and(query_param("page", page.to_string()))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "success": true,
+                "result": [record(content, false)],
+                "result_info": { "page": page, "per_page": 1, "total_pages": 2 }
+            })))
+            .mount(&mock_server)
+            .await;
+    }
+
+    let records = CloudflareDnsClient::new_with_url(API_TOKEN, &mock_server.uri())
+        .find_records(ZONE_ID, FQDN, "A")
+        .await
+        .expect("find records failed");
+    let contents: Vec<&str> = records.iter().map(|r| r.content.as_str()).collect();
+    assert_eq!(contents, vec![OLD_IP, "93.184.216.36"]);
+}
+
+#[tokio::test]
+async fn test_look_up_recreated_zone_again() {
+    let mock_server = MockServer::start().await;
+    let new_zone_id = "9a7806061c88ada191ed06f989cc3dac";
+
+    Mock::given(method("GET"))
+        .and(path("/zones"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .set_body_json(success(json!([{ "id": ZONE_ID, "name": ZONE }]))),
+        )
+        .up_to_n_times(1)
+        .mount(&mock_server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/zones"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .set_body_json(success(json!([{ "id": new_zone_id, "name": ZONE }]))),
+        )
+        .expect(1)
+        .mount(&mock_server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path(format!("/zones/{}/dns_records", ZONE_ID)))
+        .respond_with(ResponseTemplate::new(404).set_body_json(failure(7003, "Could not route")))
+        .mount(&mock_server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path(format!("/zones/{}/dns_records", new_zone_id)))
+        .respond_with(
+            ResponseTemplate::new(200).set_body_json(success(json!([record(OLD_IP, false)]))),
+        )
+        .mount(&mock_server)
+        .await;
+
+    let client = CloudflareDnsClient::new_with_url(API_TOKEN, &mock_server.uri());
+    assert!(client.resolve_ip(SUBDOMAIN, ZONE).await.is_err());
+    let ip = client
+        .resolve_ip(SUBDOMAIN, ZONE)
+        .await
+        .expect("resolve ip failed");
+    assert_eq!(ip, Some(OLD_IP.parse().unwrap()));
+}
+
+#[tokio::test]
+async fn test_update_record_keeps_other_txt_records() {
+    let mock_server = MockServer::start().await;
+    mount_zone(&mock_server).await;
+
+    let txt = |id: &str, content: &str| json!({ "id": id, "name": FQDN, "type": "TXT", "content": content, "ttl": 300 });
+    Mock::given(method("GET"))
+        .and(path(format!("/zones/{}/dns_records", ZONE_ID)))
+        .and(query_param("name", FQDN))
+        .and(query_param("type", "TXT"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(success(json!([
+            txt("verification", "\"google-site-verification=abc\""),
+            txt("spf", "\"v=spf1 ip4:93.184.216.34 -all\""),
+        ]))))
+        .mount(&mock_server)
+        .await;
+    Mock::given(method("PATCH"))
+        .and(path(format!("/zones/{}/dns_records/spf", ZONE_ID)))
+        .and(body_json(
+            json!({ "content": "\"v=spf1 ip4:93.184.216.35 -all\"" }),
+        ))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .set_body_json(success(txt("spf", "\"v=spf1 ip4:93.184.216.35 -all\""))),
+        )
+        .expect(1)
+        .mount(&mock_server)
+        .await;
+
+    let client = CloudflareDnsClient::new_with_url(API_TOKEN, &mock_server.uri());
+    let value = client
+        .resolve_record(SUBDOMAIN, ZONE, "TXT", "v=spf1 ip4:")
+        .await
+        .expect("resolve record failed");
+    assert_eq!(value, Some(String::from("v=spf1 ip4:93.184.216.34 -all")));
+    client
+        .update_record(
+            SUBDOMAIN,
+            ZONE,
+            "TXT",
+            "v=spf1 ip4:",
+            "v=spf1 ip4:93.184.216.35 -all",
+        )
+        .await
+        .expect("update failed");
+}
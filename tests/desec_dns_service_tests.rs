@@ -0,0 +1,170 @@
+use dyndnsd::desec_dns_service::{DesecClient, DesecError};
+use dyndnsd::dns_service::{DnsService, DnsServiceError};
+use serde_json::json;
+use std::net::Ipv4Addr;
+use wiremock::matchers::{body_json, header, method, path};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+const API_TOKEN: &str = "XXX";
+const ZONE: &str = "example.com";
+const SUBDOMAIN: &str = "test";
+const OLD_IP: &str = "93.184.216.34";
+const NEW_IP: Ipv4Addr = Ipv4Addr::new(93, 184, 216, 35);
+
+fn rrset(subname: &str, records: &[&str]) -> serde_json::Value {
+    json!({
+        "domain": ZONE,
+        "subname": subname,
+        "name": format!("{}.{}.", subname, ZONE),
+        "type": "A",
+        "ttl": 3600,
+        "records": records
+    })
+}
+
+#[tokio::test]
+async fn test_resolve_ip() {
+    let mock_server = MockServer::start().await;
+
+    Mock::given(method("GET"))
+        .and(path("/domains/example.com/rrsets/test/A/"))
+        .and(header("Authorization", "Token XXX"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(rrset(SUBDOMAIN, &[OLD_IP])))
+        .mount(&mock_server)
+        .await;
+
+    let ip = DesecClient::new_with_url(API_TOKEN, &mock_server.uri())
+        .resolve_ip(SUBDOMAIN, ZONE)
+        .await
+        .expect("resolve ip failed");
+    assert_eq!(ip, Some(OLD_IP.parse().unwrap()));
+}
+
+#[tokio::test]
+async fn test_resolve_missing_rrset() {
+    let mock_server = MockServer::start().await;
+
+    Mock::given(method("GET"))
+        .and(path("/domains/example.com/rrsets/@/A/"))
+        .respond_with(ResponseTemplate::new(404).set_body_json(json!({ "detail": "Not found." })))
+        .mount(&mock_server)
+        .await;
+
+    let ip = DesecClient::new_with_url(API_TOKEN, &mock_server.uri())
+        .resolve_ip("@", ZONE)
+        .await
+        .expect("resolve ip failed");
+    assert_eq!(ip, None);
+}
+
+#[tokio::test]
+async fn test_update_replaces_rrset_and_keeps_ttl() {
+    let mock_server = MockServer::start().await;
+
+    Mock::given(method("GET"))
+        .and(path("/domains/example.com/rrsets/test/A/"))
+        .respond_with(
+            ResponseTemplate::new(200).set_body_json(rrset(SUBDOMAIN, &[OLD_IP, "93.184.216.36"])),
+        )
+        .mount(&mock_server)
+        .await;
+    Mock::given(method("PATCH"))
+        .and(path("/domains/example.com/rrsets/"))
+        .and(body_json(json!([{
+            "subname": SUBDOMAIN,
+            "type": "A",
+            "ttl": 3600,
+            "records": [NEW_IP.to_string()]
+        }])))
+        .respond_with(
+            ResponseTemplate::new(200).set_body_json(json!([rrset(SUBDOMAIN, &["93.184.216.35"])])),
+        )
+        .expect(1)
+        .mount(&mock_server)
+        .await;
+
+    DesecClient::new_with_url(API_TOKEN, &mock_server.uri())
+        .update_dns(SUBDOMAIN, ZONE, NEW_IP)
+        .await
+        .expect("update failed");
+}
+
+#[tokio::test]
+async fn test_create_apex_rrset_with_ttl() {
+    let mock_server = MockServer::start().await;
+
+    Mock::given(method("PATCH"))
+        .and(path("/domains/example.com/rrsets/"))
+        .and(body_json(json!([{
+            "subname": "",
+            "type": "A",
+            "ttl": 7200,
+            "records": [NEW_IP.to_string()]
+        }])))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!([])))
+        .expect(1)
+        .mount(&mock_server)
+        .await;
+
+    DesecClient::new_with_url(API_TOKEN, &mock_server.uri())
+        .update_dns_with_ttl("@", ZONE, NEW_IP, Some(7200))
+        .await
+        .expect("update failed");
+}
+
+#[tokio::test]
+async fn test_rejected_and_throttled_changes() {
+    let mock_server = MockServer::start().await;
+
+    Mock::given(method("PATCH"))
+        .and(path("/domains/example.com/rrsets/"))
+        .respond_with(ResponseTemplate::new(400).set_body_json(
+            json!([{ "ttl": ["Ensure this value is greater than or equal to 3600."] }]),
+        ))
+        .up_to_n_times(1)
+        .mount(&mock_server)
+        .await;
+    Mock::given(method("PATCH"))
+        .and(path("/domains/example.com/rrsets/"))
+        .respond_with(ResponseTemplate::new(429).insert_header("Retry-After", "17"))
+        .mount(&mock_server)
+        .await;
+
+    let client = DesecClient::new_with_url(API_TOKEN, &mock_server.uri());
+    let result = client
+        .update_dns_with_ttl(SUBDOMAIN, ZONE, NEW_IP, Some(60))
+        .await;
+    assert!(matches!(
+        result,
+        Err(DnsServiceError::UpdateRejected { .. })
+    ));
+
+    let result = client
+        .replace_rrset(ZONE, SUBDOMAIN, "A", &[NEW_IP.to_string()], Some(3600))
+        .await;
+    assert!(matches!(
+        result,
+        Err(DesecError::RateLimited {
+            retry_after: Some(17)
+        })
+    ));
+}
+
+#[tokio::test]
+async fn test_list_zones() {
+    let mock_server = MockServer::start().await;
+
+    Mock::given(method("GET"))
+        .and(path("/domains/"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!([
+            { "name": "example.com", "minimum_ttl": 3600 },
+            { "name": "example.org", "minimum_ttl": 3600 }
+        ])))
+        .mount(&mock_server)
+        .await;
+
+    let zones = DnsService::list_zones(&DesecClient::new_with_url(API_TOKEN, &mock_server.uri()))
+        .await
+        .expect("list zones failed");
+    assert_eq!(zones, vec!["example.com", "example.org"]);
+}
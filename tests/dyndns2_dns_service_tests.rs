@@ -0,0 +1,245 @@
+use dyndnsd::dns_service::{DnsService, DnsServiceError};
+use dyndnsd::dyndns2_dns_service::{Dyndns2Client, Dyndns2Error};
+use std::fs;
+use std::net::Ipv4Addr;
+use std::path::PathBuf;
+use std::time::Duration;
+use wiremock::matchers::{header, header_regex, method, path, query_param};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+const ZONE: &str = "example.com";
+const SUBDOMAIN: &str = "test";
+const NEW_IP: Ipv4Addr = Ipv4Addr::new(93, 184, 216, 35);
+// "user:secret"
+const BASIC_AUTH: &str = "Basic dXNlcjpzZWNyZXQ=";
+
+fn client(uri: &str) -> Dyndns2Client {
+    Dyndns2Client::new(uri, "user", "secret")
+}
+
+fn state_file(name: &str) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("dyndnsd-{}-{}.json", name, std::process::id()));
+    let _ = fs::remove_file(&path);
+    path
+}
+
+fn reply(body: &str) -> ResponseTemplate {
+    ResponseTemplate::new(200).set_body_string(body)
+}
+
+#[tokio::test]
+async fn test_update_remembers_confirmed_ip() {
+    let mock_server = MockServer::start().await;
+
+    Mock::given(method("GET"))
+        .and(path("/nic/update"))
+        .and(query_param("hostname", "test.example.com"))
+        .and(query_param("myip", "93.184.216.35"))
+        .and(header("Authorization", BASIC_AUTH))
+        .and(header_regex("User-Agent", "^dyndnsd/"))
+        .respond_with(reply("good 93.184.216.35"))
+        .expect(1)
+        .mount(&mock_server)
+        .await;
+
+    let client = client(&mock_server.uri());
+    assert_eq!(client.resolve_ip(SUBDOMAIN, ZONE).await.unwrap(), None);
+    client
+        .update_dns(SUBDOMAIN, ZONE, NEW_IP)
+        .await
+        .expect("update failed");
+    assert_eq!(
+        client.resolve_ip(SUBDOMAIN, ZONE).await.unwrap(),
+        Some(NEW_IP)
+    );
+}
+
+#[tokio::test]
+async fn test_nochg_is_success() {
+    let mock_server = MockServer::start().await;
+
+    Mock::given(method("GET"))
+        .and(path("/nic/update"))
+        .respond_with(reply("nochg 93.184.216.35\n"))
+        .mount(&mock_server)
+        .await;
+
+    client(&mock_server.uri())
+        .update_dns(SUBDOMAIN, ZONE, NEW_IP)
+        .await
+        .expect("update failed");
+}
+
+#[tokio::test]
+async fn test_badauth_stops_updates() {
+    let mock_server = MockServer::start().await;
+
+    Mock::given(method("GET"))
+        .and(path("/nic/update"))
+        .respond_with(reply("badauth"))
+        .expect(1)
+        .mount(&mock_server)
+        .await;
+
+    let client = client(&mock_server.uri());
+    let result = client.update("test.example.com", NEW_IP).await;
+    assert!(matches!(result, Err(Dyndns2Error::BadAuth)));
+
+    let result = client.update("www.example.com", NEW_IP).await;
+    assert!(matches!(result, Err(Dyndns2Error::Blocked { .. })));
+}
+
+#[tokio::test]
+async fn test_abuse_stops_updates() {
+    let mock_server = MockServer::start().await;
+
+    Mock::given(method("GET"))
+        .and(path("/nic/update"))
+        .respond_with(reply("abuse"))
+        .expect(1)
+        .mount(&mock_server)
+        .await;
+
+    let client = client(&mock_server.uri());
+    assert!(client.update_dns(SUBDOMAIN, ZONE, NEW_IP).await.is_err());
+    let result = client.update("test.example.com", NEW_IP).await;
+    assert!(matches!(result, Err(Dyndns2Error::Blocked { .. })));
+}
+
+#[tokio::test]
+async fn test_nohost_stops_updates_of_that_host() {
+    let mock_server = MockServer::start().await;
+
+    Mock::given(method("GET"))
+        .and(path("/nic/update"))
+        .and(query_param("hostname", "test.example.com"))
+        .respond_with(reply("nohost"))
+        .expect(1)
+        .mount(&mock_server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/nic/update"))
+        .and(query_param("hostname", "www.example.com"))
+        .respond_with(reply("good 93.184.216.35"))
+        .expect(1)
+        .mount(&mock_server)
+        .await;
+
+    let client = client(&mock_server.uri());
+    let result = client.update_dns(SUBDOMAIN, ZONE, NEW_IP).await;
+    assert!(matches!(
+        result,
+        Err(DnsServiceError::UpdateRejected { .. })
+    ));
+    assert!(client.update_dns(SUBDOMAIN, ZONE, NEW_IP).await.is_err());
+    client
+        .update_dns("www", ZONE, NEW_IP)
+        .await
+        .expect("update failed");
+}
+
+#[tokio::test]
+async fn test_911_backs_off() {
+    let mock_server = MockServer::start().await;
+
+    Mock::given(method("GET"))
+        .and(path("/nic/update"))
+        .respond_with(reply("911"))
+        .up_to_n_times(1)
+        .expect(1)
+        .mount(&mock_server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/nic/update"))
+        .respond_with(reply("good 93.184.216.35"))
+        .expect(1)
+        .mount(&mock_server)
+        .await;
+
+    let client = client(&mock_server.uri()).with_retry_interval(Duration::from_millis(200));
+    let result = client.update("test.example.com", NEW_IP).await;
+    assert!(matches!(result, Err(Dyndns2Error::ServerError { .. })));
+
+    let result = client.update("test.example.com", NEW_IP).await;
+    assert!(matches!(result, Err(Dyndns2Error::BackingOff { .. })));
+
+    tokio::time::sleep(Duration::from_millis(250)).await;
+    client
+        .update("test.example.com", NEW_IP)
+        .await
+        .expect("update failed");
+}
+
+#[tokio::test]
+async fn test_unauthorized_status_is_badauth() {
+    let mock_server = MockServer::start().await;
+
+    Mock::given(method("GET"))
+        .and(path("/nic/update"))
+        .respond_with(ResponseTemplate::new(401))
+        .mount(&mock_server)
+        .await;
+
+    let result = client(&mock_server.uri())
+        .update("test.example.com", NEW_IP)
+        .await;
+    assert!(matches!(result, Err(Dyndns2Error::BadAuth)));
+}
+
+#[tokio::test]
+async fn test_badauth_stop_survives_restart() {
+    let mock_server = MockServer::start().await;
+    let file = state_file("dyndns2-badauth");
+
+    Mock::given(method("GET"))
+        .and(path("/nic/update"))
+        .respond_with(reply("badauth"))
+        .expect(1)
+        .mount(&mock_server)
+        .await;
+
+    let result = client(&mock_server.uri())
+        .with_state_file(&file)
+        .update("test.example.com", NEW_IP)
+        .await;
+    assert!(matches!(result, Err(Dyndns2Error::BadAuth)));
+
+    // A new process with the same credentials does not contact the server.
+    let result = client(&mock_server.uri())
+        .with_state_file(&file)
+        .update("test.example.com", NEW_IP)
+        .await;
+    assert!(matches!(result, Err(Dyndns2Error::Blocked { .. })));
+}
+
+#[tokio::test]
+async fn test_changed_credentials_lift_persisted_stop() {
+    let mock_server = MockServer::start().await;
+    let file = state_file("dyndns2-credentials");
+
+    Mock::given(method("GET"))
+        .and(path("/nic/update"))
+        .and(header("Authorization", BASIC_AUTH))
+        .respond_with(reply("badauth"))
+        .expect(1)
+        .mount(&mock_server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/nic/update"))
+        .respond_with(reply("good 93.184.216.35"))
+        .expect(1)
+        .mount(&mock_server)
+        .await;
+
+    let result = client(&mock_server.uri())
+        .with_state_file(&file)
+        .update("test.example.com", NEW_IP)
+        .await;
+    assert!(matches!(result, Err(Dyndns2Error::BadAuth)));
+
+    Dyndns2Client::new(&mock_server.uri(), "user", "new-secret")
+        .with_state_file(&file)
+        .update("test.example.com", NEW_IP)
+        .await
+        .expect("update failed");
+}
@@ -0,0 +1,170 @@
+use dyndnsd::dns_service::{DnsService, DnsServiceError};
+use dyndnsd::gandi_dns_service::{GandiClient, GandiError};
+use serde_json::json;
+use std::net::Ipv4Addr;
+use wiremock::matchers::{body_json, header, method, path};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+const TOKEN: &str = "XXX";
+const ZONE: &str = "example.com";
+const SUBDOMAIN: &str = "test";
+const OLD_IP: &str = "93.184.216.34";
+const NEW_IP: Ipv4Addr = Ipv4Addr::new(93, 184, 216, 35);
+
+fn rrset(name: &str, ttl: u32, values: &[&str]) -> serde_json::Value {
+    json!({
+        "rrset_name": name,
+        "rrset_type": "A",
+        "rrset_ttl": ttl,
+        "rrset_values": values,
+        "rrset_href": format!("https://api.gandi.net/v5/livedns/domains/example.com/records/{}/A", name)
+    })
+}
+
+#[tokio::test]
+async fn test_resolve_ip() {
+    let mock_server = MockServer::start().await;
+
+    Mock::given(method("GET"))
+        .and(path("/domains/example.com/records/test/A"))
+        .and(header("Authorization", "Bearer XXX"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(rrset(SUBDOMAIN, 300, &[OLD_IP])))
+        .mount(&mock_server)
+        .await;
+
+    let ip = GandiClient::new_with_url(TOKEN, &mock_server.uri())
+        .resolve_ip(SUBDOMAIN, ZONE)
+        .await
+        .expect("resolve ip failed");
+    assert_eq!(ip, Some(OLD_IP.parse().unwrap()));
+}
+
+#[tokio::test]
+async fn test_resolve_missing_record() {
+    let mock_server = MockServer::start().await;
+
+    Mock::given(method("GET"))
+        .and(path("/domains/example.com/records/test/A"))
+        .respond_with(ResponseTemplate::new(404).set_body_json(json!({
+            "code": 404, "message": "The resource could not be found.", "object": "HTTPNotFound"
+        })))
+        .mount(&mock_server)
+        .await;
+
+    let ip = GandiClient::new_with_url(TOKEN, &mock_server.uri())
+        .resolve_ip(SUBDOMAIN, ZONE)
+        .await
+        .expect("resolve ip failed");
+    assert_eq!(ip, None);
+}
+
+#[tokio::test]
+async fn test_update_replaces_rrset_and_keeps_ttl() {
+    let mock_server = MockServer::start().await;
+
+    Mock::given(method("GET"))
+        .and(path("/domains/example.com/records/test/A"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(rrset(SUBDOMAIN, 300, &[OLD_IP])))
+        .mount(&mock_server)
+        .await;
+    Mock::given(method("PUT"))
+        .and(path("/domains/example.com/records/test/A"))
+        .and(header("Authorization", "Bearer XXX"))
+        .and(body_json(json!({
+            "rrset_ttl": 300,
+            "rrset_values": [NEW_IP.to_string()]
+        })))
+        .respond_with(
+            ResponseTemplate::new(201).set_body_json(json!({ "message": "DNS Record Created" })),
+        )
+        .expect(1)
+        .mount(&mock_server)
+        .await;
+
+    GandiClient::new_with_url(TOKEN, &mock_server.uri())
+        .update_dns(SUBDOMAIN, ZONE, NEW_IP)
+        .await
+        .expect("update failed");
+}
+
+#[tokio::test]
+async fn test_create_apex_rrset_with_ttl() {
+    let mock_server = MockServer::start().await;
+
+    Mock::given(method("PUT"))
+        .and(path("/domains/example.com/records/@/A"))
+        .and(body_json(json!({
+            "rrset_ttl": 600,
+            "rrset_values": [NEW_IP.to_string()]
+        })))
+        .respond_with(
+            ResponseTemplate::new(201).set_body_json(json!({ "message": "DNS Record Created" })),
+        )
+        .expect(1)
+        .mount(&mock_server)
+        .await;
+
+    GandiClient::new_with_url(TOKEN, &mock_server.uri())
+        .update_dns_with_ttl("@", ZONE, NEW_IP, Some(600))
+        .await
+        .expect("update failed");
+}
+
+#[tokio::test]
+async fn test_rejected_change() {
+    let mock_server = MockServer::start().await;
+
+    Mock::given(method("PUT"))
+        .and(path("/domains/example.com/records/test/A"))
+        .respond_with(ResponseTemplate::new(409).set_body_json(json!({
+            "code": 409, "message": "A CNAME record already exists", "object": "HTTPConflict"
+        })))
+        .mount(&mock_server)
+        .await;
+
+    let result = GandiClient::new_with_url(TOKEN, &mock_server.uri())
+        .update_dns_with_ttl(SUBDOMAIN, ZONE, NEW_IP, Some(300))
+        .await;
+    assert!(matches!(
+        result,
+        Err(DnsServiceError::UpdateRejected { .. })
+    ));
+}
+
+#[tokio::test]
+async fn test_invalid_token() {
+    let mock_server = MockServer::start().await;
+
+    Mock::given(method("GET"))
+        .and(path("/domains"))
+        .respond_with(ResponseTemplate::new(401).set_body_json(json!({
+            "code": 401, "message": "The server could not verify that you authenticated.",
+            "object": "HTTPUnauthorized"
+        })))
+        .mount(&mock_server)
+        .await;
+
+    let result = GandiClient::new_with_url("wrong", &mock_server.uri())
+        .list_domains()
+        .await;
+    assert!(matches!(result, Err(GandiError::InvalidToken)));
+}
+
+#[tokio::test]
+async fn test_list_zones() {
+    let mock_server = MockServer::start().await;
+
+    Mock::given(method("GET"))
+        .and(path("/domains"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!([
+            { "fqdn": "example.com", "automatic_snapshots": true },
+            { "fqdn": "example.org", "automatic_snapshots": false }
+        ])))
+        .mount(&mock_server)
+        .await;
+
+    let zones = DnsService::list_zones(&GandiClient::new_with_url(TOKEN, &mock_server.uri()))
+        .await
+        .expect("list zones failed");
+    assert_eq!(zones, vec!["example.com", "example.org"]);
+}
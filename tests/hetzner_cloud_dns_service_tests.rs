@@ -0,0 +1,315 @@
+use dyndnsd::dns_service::{DnsService, DnsServiceError};
+use dyndnsd::hetzner_cloud_dns_service::HetznerCloudDnsClient;
+use serde_json::json;
+use std::net::Ipv4Addr;
+use std::time::Duration;
+use wiremock::matchers::{body_json, header, method, path, query_param};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+const API_TOKEN: &str = "XXX";
+const ZONE: &str = "example.com";
+const SUBDOMAIN: &str = "test";
+const OLD_IP: &str = "93.184.216.34";
+const NEW_IP: Ipv4Addr = Ipv4Addr::new(93, 184, 216, 35);
+
+fn client(uri: &str) -> HetznerCloudDnsClient {
+    HetznerCloudDnsClient::new_with_url(API_TOKEN, uri)
+        .with_action_poll_interval(Duration::from_millis(10))
+}
+
+fn rrset(value: &str) -> serde_json::Value {
+    json!({
+        "rrset": {
+            "id": "test/A",
+            "name": SUBDOMAIN,
+            "type": "A",
+            "ttl": 60,
+            "records": [{ "value": value }]
+        }
+    })
+}
+
+fn action(id: u64, status: &str) -> serde_json::Value {
+    json!({
+        "action": {
+            "id": id,
+            "command": "set_records",
+            "status": status,
+            "error": null
+        }
+    })
+}
+
+#[tokio::test]
+async fn test_resolve_ip() {
+    let mock_server = MockServer::start().await;
+
+    Mock::given(method("GET"))
+        .and(path("/zones/example.com/rrsets/test/A"))
+        .and(header("Authorization", "Bearer XXX"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(rrset(OLD_IP)))
+        .mount(&mock_server)
+        .await;
+
+    let ip = client(&mock_server.uri())
+        .resolve_ip(SUBDOMAIN, ZONE)
+        .await
+        .expect("resolve ip failed");
+    assert_eq!(ip, Some(OLD_IP.parse().unwrap()));
+}
+
+#[tokio::test]
+async fn test_resolve_missing_rrset() {
+    let mock_server = MockServer::start().await;
+
+    Mock::given(method("GET"))
+        .and(path("/zones/example.com/rrsets/test/A"))
+        .respond_with(ResponseTemplate::new(404).set_body_json(json!({
+            "error": { "code": "not_found", "message": "rrset not found" }
+        })))
+        .mount(&mock_server)
+        .await;
+
+    let ip = client(&mock_server.uri())
+        .resolve_ip(SUBDOMAIN, ZONE)
+        .await
+        .expect("resolve ip failed");
+    assert_eq!(ip, None);
+}
+
+#[tokio::test]
+async fn test_update_waits_for_action() {
+    let mock_server = MockServer::start().await;
+
+    Mock::given(method("GET"))
+        .and(path("/zones/example.com/rrsets/test/A"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(rrset(OLD_IP)))
+        .mount(&mock_server)
+        .await;
+    Mock::given(method("POST"))
+        .and(path("/zones/example.com/rrsets/test/A/actions/set_records"))
+        .and(header("Authorization", "Bearer XXX"))
+        .and(body_json(
+            json!({ "records": [{ "value": "93.184.216.35" }] }),
+        ))
+        .respond_with(ResponseTemplate::new(201).set_body_json(action(42, "running")))
+        .expect(1)
+        .mount(&mock_server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/actions/42"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(action(42, "success")))
+        .expect(1)
+        .mount(&mock_server)
+        .await;
+
+    client(&mock_server.uri())
+        .update_dns(SUBDOMAIN, ZONE, NEW_IP)
+        .await
+        .expect("update failed");
+}
+
+#[tokio::test]
+async fn test_update_creates_missing_rrset() {
+    let mock_server = MockServer::start().await;
+
+    Mock::given(method("GET"))
+        .and(path("/zones/example.com/rrsets/test/A"))
+        .respond_with(ResponseTemplate::new(404))
+        .mount(&mock_server)
+        .await;
+    let mut created = rrset("93.184.216.35");
+    created["action"] = action(7, "success")["action"].clone();
+    Mock::given(method("POST"))
+        .and(path("/zones/example.com/rrsets"))
+        .and(body_json(json!({
+            "name": SUBDOMAIN,
+            "type": "A",
+            "records": [{ "value": "93.184.216.35" }]
+        })))
+        .respond_with(ResponseTemplate::new(201).set_body_json(created))
+        .expect(1)
+        .mount(&mock_server)
+        .await;
+
+    client(&mock_server.uri())
+        .update_dns(SUBDOMAIN, ZONE, NEW_IP)
+        .await
+        .expect("update failed");
+}
+
+#[tokio::test]
+async fn test_failed_action_is_rejected() {
+    let mock_server = MockServer::start().await;
+
+    Mock::given(method("GET"))
+        .and(path("/zones/example.com/rrsets/test/A"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(rrset(OLD_IP)))
+        .mount(&mock_server)
+        .await;
+    Mock::given(method("POST"))
+        .and(path("/zones/example.com/rrsets/test/A/actions/set_records"))
+        .respond_with(ResponseTemplate::new(201).set_body_json(json!({
+            "action": {
+                "id": 43,
+                "command": "set_records",
+                "status": "error",
+                "error": { "code": "invalid_input", "message": "bad value" }
+            }
+        })))
+        .mount(&mock_server)
+        .await;
+
+    let result = client(&mock_server.uri())
+        .update_dns(SUBDOMAIN, ZONE, NEW_IP)
+        .await;
+    assert!(matches!(
+        result,
+        Err(DnsServiceError::UpdateRejected { .. })
+    ));
+}
+
+#[tokio::test]
+async fn test_list_zones_follows_next_page() {
+    let mock_server = MockServer::start().await;
+
+    Mock::given(method("GET"))
+        .and(path("/zones"))
+        .and(query_param("page", "1"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "zones": [{ "id": 1, "name": "example.com" }],
+            "meta": { "pagination": { "page": 1, "per_page": 1, "next_page": 2 } }
+        })))
+        .mount(&mock_server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/zones"))
+        .and(query_param("page", "2"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "zones": [{ "id": 2, "name": "example.org" }],
+            "meta": { "pagination": { "page": 2, "per_page": 1, "next_page": null } }
+        })))
+        .mount(&mock_server)
+        .await;
+
+    let zones = client(&mock_server.uri())
+        .list_zones()
+        .await
+        .expect("list zones failed");
+    let names: Vec<&str> = zones.iter().map(|z| z.name.as_str()).collect();
+    assert_eq!(names, vec!["example.com", "example.org"]);
+}
+
+#[tokio::test]
+async fn test_find_zone_on_second_page() {
+    let mock_server = MockServer::start().await;
+
+    Mock::given(method("GET"))
+        .and(path("/zones"))
+        .and(query_param("name", ZONE))
+        .and(query_param("page", "1"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "zones": [{ "id": 1, "name": "sub.example.com" }],
+            "meta": { "pagination": { "page": 1, "per_page": 1, "next_page": 2 } }
+        })))
+        .mount(&mock_server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/zones"))
+        .and(query_param("name", ZONE))
+        .and(query_param("page", "2"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "zones": [{ "id": 2, "name": ZONE }],
+            "meta": { "pagination": { "page": 2, "per_page": 1, "next_page": null } }
+        })))
+        .mount(&mock_server)
+        .await;
+
+    let zone = client(&mock_server.uri())
+        .find_zone(ZONE)
+        .await
+        .expect("find zone failed");
+    assert_eq!(zone.map(|z| z.name), Some(String::from(ZONE)));
+}
+
+#[tokio::test]
+async fn test_update_changes_ttl() {
+    let mock_server = MockServer::start().await;
+
+    Mock::given(method("GET"))
+        .and(path("/zones/example.com/rrsets/test/A"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(rrset(OLD_IP)))
+        .mount(&mock_server)
+        .await;
+    Mock::given(method("POST"))
+        .and(path("/zones/example.com/rrsets/test/A/actions/set_records"))
+        .respond_with(ResponseTemplate::new(201).set_body_json(action(44, "success")))
+        .expect(1)
+        .mount(&mock_server)
+        .await;
+    Mock::given(method("POST"))
+        .and(path("/zones/example.com/rrsets/test/A/actions/change_ttl"))
+        .and(body_json(json!({ "ttl": 3600 })))
+        .respond_with(ResponseTemplate::new(201).set_body_json(action(45, "success")))
+        .expect(1)
+        .mount(&mock_server)
+        .await;
+
+    client(&mock_server.uri())
+        .update_dns_with_ttl(SUBDOMAIN, ZONE, NEW_IP, Some(3600))
+        .await
+        .expect("update failed");
+}
+
+#[tokio::test]
+async fn test_update_record_keeps_other_txt_records() {
+    let mock_server = MockServer::start().await;
+
+    Mock::given(method("GET"))
+        .and(path("/zones/example.com/rrsets/test/TXT"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "rrset": {
+                "id": "test/TXT",
+                "name": SUBDOMAIN,
+                "type": "TXT",
+                "ttl": 60,
+                "records": [
+                    { "value": "\"google-site-verification=abc\"", "comment": "search console" },
+                    { "value": "\"v=spf1 ip4:93.184.216.34 -all\"" }
+                ]
+            }
+        })))
+        .mount(&mock_server)
+        .await;
+    Mock::given(method("POST"))
+        .and(path(
+            "/zones/example.com/rrsets/test/TXT/actions/set_records",
+        ))
+        .and(body_json(json!({
+            "records": [
+                { "value": "\"google-site-verification=abc\"", "comment": "search console" },
+                { "value": "\"v=spf1 ip4:93.184.216.35 -all\"" }
+            ]
+        })))
+        .respond_with(ResponseTemplate::new(201).set_body_json(action(42, "success")))
+        .expect(1)
+        .mount(&mock_server)
+        .await;
+
+    let client = client(&mock_server.uri());
+    let value = client
+        .resolve_record(SUBDOMAIN, ZONE, "TXT", "v=spf1 ip4:")
+        .await
+        .expect("resolve record failed");
+    assert_eq!(value, Some(String::from("v=spf1 ip4:93.184.216.34 -all")));
+    client
+        .update_record(
+            SUBDOMAIN,
+            ZONE,
+            "TXT",
+            "v=spf1 ip4:",
+            "v=spf1 ip4:93.184.216.35 -all",
+        )
+        .await
+        .expect("update failed");
+}
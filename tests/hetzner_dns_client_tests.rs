@@ -1,7 +1,13 @@
 use dyndnsd::hetzner_dns_client::{
-    GetRecordsResponse, GetZonesResponse, HetznerDnsClient, Record, UpdateRecordResponse, Zone,
+    BulkRecordUpdate, GetRecordsResponse, GetZonesResponse, HetznerDnsClient,
+    HetznerDnsClientError, Meta, Pagination, PrimaryServerRequest, Record, RecordRequest,
+    RecordResponse, UpdateRecordResponse, Zone,
+};
+use serde_json::json;
+use std::time::{Duration, Instant};
+use wiremock::matchers::{
+    body_json, body_partial_json, body_string, header, headers, method, path, query_param,
 };
-use wiremock::matchers::{headers, method, path, query_param};
 use wiremock::{Mock, MockServer, ResponseTemplate};
 
 const API_TOKEN: &str = "XXX";
@@ -21,10 +27,12 @@ async fn test_get_zone_id() {
     let zone = Zone {
         name: String::from(ZONE),
         id: String::from(EXPECTED_ZONE_ID),
+        ..Default::default()
     };
 
     let zones_response = GetZonesResponse {
         zones: vec![zone.clone()],
+        meta: None,
     };
     Mock::given(method("GET"))
         .and(path("/zones"))
@@ -55,10 +63,12 @@ async fn test_get_record() {
         name: String::from(SUBDOMAIN),
         r#type: String::from("A"),
         value: String::from(OLD_IP),
+        ..Default::default()
     };
 
     let records_response = GetRecordsResponse {
         records: vec![record],
+        meta: None,
     };
     Mock::given(method("GET"))
         .and(path("/records"))
@@ -89,12 +99,14 @@ async fn test_update_record() {
         name: String::from(SUBDOMAIN),
         r#type: String::from("A"),
         value: String::from(OLD_IP),
+        ..Default::default()
     };
 
     let update_response = UpdateRecordResponse { record };
     Mock::given(method("PUT"))
         .and(path(format!("/records/{}", EXPECTED_RECORD_ID)))
         .and(headers("Auth-API-Token", vec![API_TOKEN]))
+        .and(body_partial_json(json!({ "ttl": 300 })))
         .respond_with(ResponseTemplate::new(200).set_body_json(update_response))
         .mount(&mock_server)
         .await;
@@ -106,8 +118,501 @@ async fn test_update_record() {
             EXPECTED_ZONE_ID,
             EXPECTED_RECORD_ID,
             NEW_IP.parse().unwrap(),
+            Some(300),
         )
         .await
         .expect("find record id failed");
     assert_eq!(record.id, EXPECTED_RECORD_ID);
 }
+
+#[tokio::test]
+async fn test_get_record_from_second_page() {
+    let mock_server = MockServer::start().await;
+
+    let uri = &mock_server.uri();
+
+    let other = Record {
+        id: String::from("other"),
+        zone_id: String::from(EXPECTED_ZONE_ID),
+        name: String::from("other"),
+        r#type: String::from("A"),
+        value: String::from(OLD_IP),
+        ..Default::default()
+    };
+    let record = Record {
+        id: String::from(EXPECTED_RECORD_ID),
+        zone_id: String::from(EXPECTED_ZONE_ID),
+        name: String::from(SUBDOMAIN),
+        r#type: String::from("A"),
+        value: String::from(OLD_IP),
+        ..Default::default()
+    };
+
+    let pagination = |page| {
+        Some(Meta {
+            pagination: Pagination {
+                page,
+                per_page: 1,
+                last_page: 2,
+                total_entries: 2,
+            },
+        })
+    };
+
+    Mock::given(method("GET"))
+        .and(path("/records"))
+        .and(query_param("zone_id", EXPECTED_ZONE_ID))
+        .and(query_param("name", SUBDOMAIN))
+        .and(query_param("type", "A"))
+        .and(query_param("page", "1"))
+        .respond_with(
+            ResponseTemplate::new(200).set_body_json(GetRecordsResponse {
+                records: vec![other],
+                meta: pagination(1),
+            }),
+        )
+        .mount(&mock_server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/records"))
+        .and(query_param("zone_id", EXPECTED_ZONE_ID))
+        .and(query_param("page", "2"))
+        .respond_with(
+            ResponseTemplate::new(200).set_body_json(GetRecordsResponse {
+                records: vec![record],
+                meta: pagination(2),
+            }),
+        )
+        .mount(&mock_server)
+        .await;
+
+    let client = HetznerDnsClient::new_with_url(API_TOKEN, uri);
+    let record = client
+        .find_record(EXPECTED_ZONE_ID, SUBDOMAIN)
+        .await
+        .expect("find record id failed")
+        .expect("no record id returned");
+    assert_eq!(record.id, EXPECTED_RECORD_ID);
+}
+
+#[tokio::test]
+async fn test_get_record_not_found_on_last_page() {
+    let mock_server = MockServer::start().await;
+
+    let uri = &mock_server.uri();
+
+    Mock::given(method("GET"))
+        .and(path("/records"))
+        .respond_with(
+            ResponseTemplate::new(200).set_body_json(GetRecordsResponse {
+                records: vec![],
+                meta: Some(Meta {
+                    pagination: Pagination {
+                        page: 1,
+                        per_page: 100,
+                        last_page: 1,
+                        total_entries: 0,
+                    },
+                }),
+            }),
+        )
+        .expect(1)
+        .mount(&mock_server)
+        .await;
+
+    let client = HetznerDnsClient::new_with_url(API_TOKEN, uri);
+    let record = client
+        .find_record(EXPECTED_ZONE_ID, SUBDOMAIN)
+        .await
+        .expect("find record id failed");
+    assert!(record.is_none());
+}
+
+#[tokio::test]
+async fn test_list_zones() {
+    let mock_server = MockServer::start().await;
+
+    let uri = &mock_server.uri();
+
+    for page in 1..=2 {
+        let zone = Zone {
+            id: format!("zid{}", page),
+            name: format!("example{}.com", page),
+            ..Default::default()
+        };
+        Mock::given(method("GET"))
+            .and(path("/zones"))
+            .and(query_param("page", page.to_string()))
+            .and(headers("Auth-API-Token", vec![API_TOKEN]))
+            .respond_with(ResponseTemplate::new(200).set_body_json(GetZonesResponse {
+                zones: vec![zone],
+                meta: Some(Meta {
+                    pagination: Pagination {
+                        page,
+                        per_page: 1,
+                        last_page: 2,
+                        total_entries: 2,
+                    },
+                }),
+            }))
+            .mount(&mock_server)
+            .await;
+    }
+
+    let client = HetznerDnsClient::new_with_url(API_TOKEN, uri);
+    let zones = client.list_zones().await.expect("list zones failed");
+    assert_eq!(
+        zones.iter().map(|z| z.id.as_str()).collect::<Vec<_>>(),
+        vec!["zid1", "zid2"]
+    );
+}
+
+#[tokio::test]
+async fn test_create_record() {
+    let mock_server = MockServer::start().await;
+
+    let uri = &mock_server.uri();
+
+    let request = RecordRequest {
+        name: String::from("mail"),
+        ttl: Some(300),
+        r#type: String::from("TXT"),
+        value: String::from("v=spf1 -all"),
+        zone_id: String::from(EXPECTED_ZONE_ID),
+    };
+    let record = Record {
+        id: String::from(EXPECTED_RECORD_ID),
+        zone_id: String::from(EXPECTED_ZONE_ID),
+        name: String::from("mail"),
+        r#type: String::from("TXT"),
+        value: String::from("v=spf1 -all"),
+        ttl: Some(300),
+        ..Default::default()
+    };
+
+    Mock::given(method("POST"))
+        .and(path("/records"))
+        .and(headers("Auth-API-Token", vec![API_TOKEN]))
+        .and(body_json(&request))
+        .respond_with(ResponseTemplate::new(200).set_body_json(RecordResponse {
+            record: record.clone(),
+        }))
+        .mount(&mock_server)
+        .await;
+
+    let client = HetznerDnsClient::new_with_url(API_TOKEN, uri);
+    let created = client
+        .create_record(&request)
+        .await
+        .expect("create record failed");
+    assert_eq!(created, record);
+}
+
+#[tokio::test]
+async fn test_bulk_update_records() {
+    let mock_server = MockServer::start().await;
+
+    let uri = &mock_server.uri();
+
+    let update = BulkRecordUpdate {
+        id: String::from(EXPECTED_RECORD_ID),
+        name: String::from(SUBDOMAIN),
+        r#type: String::from("A"),
+        value: String::from(NEW_IP),
+        zone_id: String::from(EXPECTED_ZONE_ID),
+        ..Default::default()
+    };
+
+    Mock::given(method("PUT"))
+        .and(path("/records/bulk"))
+        .and(body_json(json!({ "records": [update.clone()] })))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "records": [],
+            "failed_records": [update.clone()]
+        })))
+        .mount(&mock_server)
+        .await;
+
+    let client = HetznerDnsClient::new_with_url(API_TOKEN, uri);
+    let response = client
+        .bulk_update_records(std::slice::from_ref(&update))
+        .await
+        .expect("bulk update failed");
+    assert!(response.records.is_empty());
+    assert_eq!(response.failed_records, vec![update]);
+}
+
+#[tokio::test]
+async fn test_export_and_import_zone_file() {
+    let mock_server = MockServer::start().await;
+
+    let uri = &mock_server.uri();
+
+    let zone_file = "$ORIGIN example.com.\n@ 3600 IN A 93.184.216.34\n";
+
+    Mock::given(method("GET"))
+        .and(path(format!("/zones/{}/export", EXPECTED_ZONE_ID)))
+        .respond_with(ResponseTemplate::new(200).set_body_string(zone_file))
+        .mount(&mock_server)
+        .await;
+    Mock::given(method("POST"))
+        .and(path(format!("/zones/{}/import", EXPECTED_ZONE_ID)))
+        .and(header("Content-Type", "text/plain"))
+        .and(body_string(zone_file))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "zone": { "id": EXPECTED_ZONE_ID, "name": ZONE }
+        })))
+        .mount(&mock_server)
+        .await;
+
+    let client = HetznerDnsClient::new_with_url(API_TOKEN, uri);
+    let exported = client
+        .export_zone_file(EXPECTED_ZONE_ID)
+        .await
+        .expect("export failed");
+    assert_eq!(exported, zone_file);
+
+    let zone = client
+        .import_zone_file(EXPECTED_ZONE_ID, &exported)
+        .await
+        .expect("import failed");
+    assert_eq!(zone.id, EXPECTED_ZONE_ID);
+}
+
+#[tokio::test]
+async fn test_create_primary_server() {
+    let mock_server = MockServer::start().await;
+
+    let uri = &mock_server.uri();
+
+    let request = PrimaryServerRequest {
+        address: String::from("192.0.2.1"),
+        port: 53,
+        zone_id: String::from(EXPECTED_ZONE_ID),
+    };
+
+    Mock::given(method("POST"))
+        .and(path("/primary_servers"))
+        .and(body_json(&request))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "primary_server": {
+                "id": "psid123",
+                "address": "192.0.2.1",
+                "port": 53,
+                "zone_id": EXPECTED_ZONE_ID
+            }
+        })))
+        .mount(&mock_server)
+        .await;
+
+    let client = HetznerDnsClient::new_with_url(API_TOKEN, uri);
+    let primary_server = client
+        .create_primary_server(&request)
+        .await
+        .expect("create primary server failed");
+    assert_eq!(primary_server.id, "psid123");
+}
+
+#[tokio::test]
+async fn test_api_error_carries_status_and_message() {
+    let mock_server = MockServer::start().await;
+
+    let uri = &mock_server.uri();
+
+    Mock::given(method("DELETE"))
+        .and(path(format!("/zones/{}", EXPECTED_ZONE_ID)))
+        .respond_with(ResponseTemplate::new(404).set_body_json(json!({
+            "error": { "message": "zone not found", "code": 404 }
+        })))
+        .mount(&mock_server)
+        .await;
+
+    let client = HetznerDnsClient::new_with_url(API_TOKEN, uri);
+    assert!(matches!(
+        client.delete_zone(EXPECTED_ZONE_ID).await,
+        Err(HetznerDnsClientError::Api { status: 404, message }) if message == "zone not found"
+    ));
+}
+
+async fn mount_api_error(mock_server: &MockServer, verb: &str, api_path: &str, status: u16) {
+    Mock::given(method(verb))
+        .and(path(api_path))
+        .respond_with(ResponseTemplate::new(status).set_body_json(json!({
+            "error": { "message": "invalid input", "code": status }
+        })))
+        .mount(mock_server)
+        .await;
+}
+
+#[tokio::test]
+async fn test_find_zone_error_carries_status_and_message() {
+    let mock_server = MockServer::start().await;
+    mount_api_error(&mock_server, "GET", "/zones", 422).await;
+
+    let client = HetznerDnsClient::new_with_url(API_TOKEN, &mock_server.uri());
+    assert!(matches!(
+        client.find_zone(ZONE).await,
+        Err(HetznerDnsClientError::Api { status: 422, message }) if message == "invalid input"
+    ));
+}
+
+#[tokio::test]
+async fn test_invalid_token_carries_status_and_message() {
+    let mock_server = MockServer::start().await;
+    mount_api_error(&mock_server, "GET", "/zones", 401).await;
+
+    let client = HetznerDnsClient::new_with_url(API_TOKEN, &mock_server.uri());
+    assert!(matches!(
+        client.find_zone(ZONE).await,
+        Err(HetznerDnsClientError::InvalidApiToken { status: 401, message }) if message == "invalid input"
+    ));
+}
+
+#[tokio::test]
+async fn test_find_record_error_carries_status_and_message() {
+    let mock_server = MockServer::start().await;
+    mount_api_error(&mock_server, "GET", "/records", 400).await;
+
+    let client = HetznerDnsClient::new_with_url(API_TOKEN, &mock_server.uri());
+    assert!(matches!(
+        client.find_record_of_type(EXPECTED_ZONE_ID, "mail", "TXT").await,
+        Err(HetznerDnsClientError::Api { status: 400, message }) if message == "invalid input"
+    ));
+}
+
+#[tokio::test]
+async fn test_update_ip_error_carries_status_and_message() {
+    let mock_server = MockServer::start().await;
+    mount_api_error(
+        &mock_server,
+        "PUT",
+        &format!("/records/{}", EXPECTED_RECORD_ID),
+        422,
+    )
+    .await;
+
+    let client = HetznerDnsClient::new_with_url(API_TOKEN, &mock_server.uri());
+    let result = client
+        .update_ip(
+            SUBDOMAIN,
+            EXPECTED_ZONE_ID,
+            EXPECTED_RECORD_ID,
+            NEW_IP.parse().unwrap(),
+            None,
+        )
+        .await;
+    assert!(matches!(
+        result,
+        Err(HetznerDnsClientError::Api { status: 422, message }) if message == "invalid input"
+    ));
+}
+
+#[tokio::test]
+async fn test_update_ip_of_missing_record() {
+    let mock_server = MockServer::start().await;
+    mount_api_error(
+        &mock_server,
+        "PUT",
+        &format!("/records/{}", EXPECTED_RECORD_ID),
+        404,
+    )
+    .await;
+
+    let client = HetznerDnsClient::new_with_url(API_TOKEN, &mock_server.uri());
+    let result = client
+        .update_ip(
+            SUBDOMAIN,
+            EXPECTED_ZONE_ID,
+            EXPECTED_RECORD_ID,
+            NEW_IP.parse().unwrap(),
+            None,
+        )
+        .await;
+    assert!(matches!(
+        result,
+        Err(HetznerDnsClientError::NotFound { status: 404, message }) if message == "invalid input"
+    ));
+}
+
+#[tokio::test]
+async fn test_retry_after_rate_limit() {
+    let mock_server = MockServer::start().await;
+
+    let zone = Zone {
+        name: String::from(ZONE),
+        id: String::from(EXPECTED_ZONE_ID),
+        ..Default::default()
+    };
+    Mock::given(method("GET"))
+        .and(path("/zones"))
+        .respond_with(ResponseTemplate::new(429).insert_header("Retry-After", "1"))
+        .up_to_n_times(1)
+        .mount(&mock_server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/zones"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(GetZonesResponse {
+            zones: vec![zone.clone()],
+            meta: None,
+        }))
+        .expect(1)
+        .mount(&mock_server)
+        .await;
+
+    let client = HetznerDnsClient::new_with_url(API_TOKEN, &mock_server.uri());
+    let started = Instant::now();
+    let returned_zone = client.find_zone(ZONE).await.expect("find zone failed");
+
+    assert_eq!(returned_zone, Some(zone));
+    assert!(started.elapsed() >= Duration::from_secs(1));
+}
+
+#[tokio::test]
+async fn test_wait_for_exhausted_budget() {
+    let mock_server = MockServer::start().await;
+
+    Mock::given(method("GET"))
+        .and(path("/zones/zid123"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .insert_header("Ratelimit-Limit", "2")
+                .insert_header("Ratelimit-Remaining", "0")
+                .insert_header("Ratelimit-Reset", "1")
+                .set_body_json(json!({ "zone": { "id": "zid123", "name": ZONE } })),
+        )
+        .expect(2)
+        .mount(&mock_server)
+        .await;
+
+    let client = HetznerDnsClient::new_with_url(API_TOKEN, &mock_server.uri());
+    client
+        .get_zone(EXPECTED_ZONE_ID)
+        .await
+        .expect("first call failed");
+    let started = Instant::now();
+    client
+        .get_zone(EXPECTED_ZONE_ID)
+        .await
+        .expect("second call failed");
+
+    assert!(started.elapsed() >= Duration::from_secs(1));
+}
+
+#[tokio::test]
+async fn test_give_up_when_rate_limit_persists() {
+    let mock_server = MockServer::start().await;
+
+    Mock::given(method("GET"))
+        .and(path("/records/rid123"))
+        .respond_with(ResponseTemplate::new(429).insert_header("Retry-After", "0"))
+        .mount(&mock_server)
+        .await;
+
+    let client = HetznerDnsClient::new_with_url(API_TOKEN, &mock_server.uri());
+    let result = client.get_record(EXPECTED_RECORD_ID).await;
+
+    assert!(matches!(
+        result,
+        Err(HetznerDnsClientError::RateLimited { status: 429, .. })
+    ));
+}
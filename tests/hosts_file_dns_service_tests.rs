@@ -0,0 +1,137 @@
+use dyndnsd::dns_service::DnsService;
+use dyndnsd::hosts_file_dns_service::{HostsFileDnsService, HostsFormat};
+use std::fs;
+use std::net::Ipv4Addr;
+use std::path::PathBuf;
+
+const ZONE: &str = "example.com";
+const SUBDOMAIN: &str = "test";
+const OLD_IP: &str = "192.168.1.10";
+const NEW_IP: Ipv4Addr = Ipv4Addr::new(192, 168, 1, 11);
+
+const HOSTS_FILE: &str = "\
+127.0.0.1\tlocalhost
+# LAN
+192.168.1.10\ttest.example.com test nas # home server
+192.168.1.20\tprinter.example.com test.example.com
+::1\tlocalhost test.example.com
+";
+
+fn hosts_file(name: &str, contents: Option<&str>) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("dyndnsd-{}-{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).expect("create temp dir failed");
+    let path = dir.join("hosts");
+    if let Some(contents) = contents {
+        fs::write(&path, contents).expect("write hosts file failed");
+    }
+    path
+}
+
+#[tokio::test]
+async fn test_resolve_ip() {
+    let path = hosts_file("hosts-resolve", Some(HOSTS_FILE));
+
+    let ip = HostsFileDnsService::new(&path, HostsFormat::Hosts)
+        .resolve_ip(SUBDOMAIN, ZONE)
+        .await
+        .expect("resolve ip failed");
+    assert_eq!(ip, Some(OLD_IP.parse().unwrap()));
+}
+
+#[tokio::test]
+async fn test_update_canonical_name_and_remove_aliases() {
+    let path = hosts_file("hosts-update", Some(HOSTS_FILE));
+
+    HostsFileDnsService::new(&path, HostsFormat::Hosts)
+        .update_dns(SUBDOMAIN, ZONE, NEW_IP)
+        .await
+        .expect("update failed");
+
+    assert_eq!(
+        fs::read_to_string(&path).unwrap(),
+        "127.0.0.1\tlocalhost\n\
+         # LAN\n\
+         192.168.1.11\ttest.example.com test nas # home server\n\
+         192.168.1.20\tprinter.example.com\n\
+         ::1\tlocalhost test.example.com\n"
+    );
+}
+
+#[tokio::test]
+async fn test_append_missing_name() {
+    let path = hosts_file("hosts-append", Some(HOSTS_FILE));
+
+    HostsFileDnsService::new(&path, HostsFormat::Hosts)
+        .update_dns("nas", ZONE, NEW_IP)
+        .await
+        .expect("update failed");
+
+    assert_eq!(
+        fs::read_to_string(&path).unwrap(),
+        format!("{}192.168.1.11\tnas.example.com\n", HOSTS_FILE)
+    );
+}
+
+#[tokio::test]
+async fn test_update_ipv6_address() {
+    let path = hosts_file("hosts-ipv6", Some(HOSTS_FILE));
+
+    let dns_service = HostsFileDnsService::new(&path, HostsFormat::Hosts);
+    dns_service
+        .update_record(SUBDOMAIN, ZONE, "AAAA", "", "2001:db8::11")
+        .await
+        .expect("update failed");
+
+    let contents = fs::read_to_string(&path).unwrap();
+    assert!(contents.contains("192.168.1.10\ttest.example.com test nas # home server\n"));
+    assert!(contents.contains("::1\tlocalhost\n"));
+    let ip = dns_service
+        .resolve_record(SUBDOMAIN, ZONE, "AAAA", "")
+        .await
+        .expect("resolve record failed");
+    assert_eq!(ip, Some(String::from("2001:db8::11")));
+}
+
+#[tokio::test]
+async fn test_dnsmasq_snippet_is_created_and_updated() {
+    let path = hosts_file("hosts-dnsmasq", None);
+    let marker = path.with_file_name("reloaded");
+
+    let dns_service = HostsFileDnsService::new(&path, HostsFormat::Dnsmasq)
+        .with_reload_command(&format!("touch {}", marker.display()));
+    dns_service
+        .update_dns(SUBDOMAIN, ZONE, OLD_IP.parse().unwrap())
+        .await
+        .expect("update failed");
+    assert_eq!(
+        fs::read_to_string(&path).unwrap(),
+        "address=/test.example.com/192.168.1.10\n"
+    );
+    assert!(marker.exists());
+
+    fs::write(
+        &path,
+        "# split horizon\naddress=/test.example.com/192.168.1.10\nserver=/lan/192.168.1.1\n",
+    )
+    .unwrap();
+    dns_service
+        .update_dns(SUBDOMAIN, ZONE, NEW_IP)
+        .await
+        .expect("update failed");
+    assert_eq!(
+        fs::read_to_string(&path).unwrap(),
+        "# split horizon\naddress=/test.example.com/192.168.1.11\nserver=/lan/192.168.1.1\n"
+    );
+}
+
+#[tokio::test]
+async fn test_unsupported_record_type() {
+    let path = hosts_file("hosts-unsupported", Some(HOSTS_FILE));
+
+    let result = HostsFileDnsService::new(&path, HostsFormat::Hosts)
+        .update_record(SUBDOMAIN, ZONE, "TXT", "", "hello")
+        .await;
+    assert!(result.is_err());
+    assert_eq!(fs::read_to_string(&path).unwrap(), HOSTS_FILE);
+}
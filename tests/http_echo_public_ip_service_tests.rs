@@ -0,0 +1,38 @@
+use dyndnsd::http_echo_public_ip_service::HttpEchoPublicIpService;
+use dyndnsd::public_ip_service::{PublicIpService, PublicIpServiceError};
+use std::net::Ipv4Addr;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+#[tokio::test]
+async fn test_get_ip() {
+    let mock_server = MockServer::start().await;
+
+    Mock::given(method("GET"))
+        .and(path("/"))
+        .respond_with(ResponseTemplate::new(200).set_body_string("93.184.216.34\n"))
+        .mount(&mock_server)
+        .await;
+
+    let service = HttpEchoPublicIpService::new(&mock_server.uri());
+    assert_eq!(
+        service.get_ip().await.expect("get ip failed"),
+        "93.184.216.34".parse::<Ipv4Addr>().unwrap(),
+    );
+}
+
+#[tokio::test]
+async fn test_get_ip_invalid_response() {
+    let mock_server = MockServer::start().await;
+
+    Mock::given(method("GET"))
+        .respond_with(ResponseTemplate::new(200).set_body_string("<html></html>"))
+        .mount(&mock_server)
+        .await;
+
+    let service = HttpEchoPublicIpService::new(&mock_server.uri());
+    assert!(matches!(
+        service.get_ip().await,
+        Err(PublicIpServiceError::InvalidIpResponse)
+    ));
+}
@@ -0,0 +1,205 @@
+use dyndnsd::dns_service::{DnsService, DnsServiceError};
+use dyndnsd::inwx_dns_service::{InwxClient, InwxError};
+use serde_json::json;
+use std::net::Ipv4Addr;
+use wiremock::matchers::{body_json, body_partial_json, header, method, path};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+const ZONE: &str = "example.com";
+const SUBDOMAIN: &str = "test";
+const OLD_IP: &str = "93.184.216.34";
+const NEW_IP: Ipv4Addr = Ipv4Addr::new(93, 184, 216, 35);
+const SESSION: &str = "domrobot=31d7f1ea35da0e8c";
+
+fn client(uri: &str) -> InwxClient {
+    InwxClient::new_with_url("user", "secret", &format!("{}/jsonrpc/", uri))
+}
+
+fn ok(res_data: serde_json::Value) -> ResponseTemplate {
+    ResponseTemplate::new(200).set_body_json(json!({
+        "code": 1000,
+        "msg": "Command completed successfully",
+        "resData": res_data
+    }))
+}
+
+fn failed(code: u32, msg: &str) -> ResponseTemplate {
+    ResponseTemplate::new(200).set_body_json(json!({ "code": code, "msg": msg }))
+}
+
+async fn mount_login(mock_server: &MockServer, times: u64) {
+    Mock::given(method("POST"))
+        .and(path("/jsonrpc/"))
+        .and(body_json(json!({
+            "method": "account.login",
+            "params": { "user": "user", "pass": "secret" }
+        })))
+        .respond_with(ok(json!({ "customerId": 1 })).insert_header(
+            "Set-Cookie",
+            format!("{}; path=/; HttpOnly", SESSION).as_str(),
+        ))
+        .expect(times)
+        .mount(mock_server)
+        .await;
+}
+
+async fn mount_info(mock_server: &MockServer, records: serde_json::Value) {
+    Mock::given(method("POST"))
+        .and(path("/jsonrpc/"))
+        .and(header("Cookie", SESSION))
+        .and(body_json(json!({
+            "method": "nameserver.info",
+            "params": { "domain": ZONE, "name": SUBDOMAIN, "type": "A" }
+        })))
+        .respond_with(ok(json!({ "domain": ZONE, "record": records })))
+        .mount(mock_server)
+        .await;
+}
+
+#[tokio::test]
+async fn test_resolve_ip_reuses_session() {
+    let mock_server = MockServer::start().await;
+    mount_login(&mock_server, 1).await;
+    mount_info(
+        &mock_server,
+        json!([
+            { "id": 7, "name": "www.test.example.com", "type": "A", "content": "93.184.216.36", "ttl": 3600 },
+            { "id": 8, "name": "test.example.com", "type": "A", "content": OLD_IP, "ttl": 3600 }
+        ]),
+    )
+    .await;
+
+    let client = client(&mock_server.uri());
+    for _ in 0..2 {
+        let ip = client
+            .resolve_ip(SUBDOMAIN, ZONE)
+            .await
+            .expect("resolve ip failed");
+        assert_eq!(ip, Some(OLD_IP.parse().unwrap()));
+    }
+}
+
+#[tokio::test]
+async fn test_update_existing_record() {
+    let mock_server = MockServer::start().await;
+    mount_login(&mock_server, 1).await;
+    mount_info(
+        &mock_server,
+        json!([{ "id": 8, "name": "test.example.com", "type": "A", "content": OLD_IP, "ttl": 3600 }]),
+    )
+    .await;
+
+    Mock::given(method("POST"))
+        .and(path("/jsonrpc/"))
+        .and(header("Cookie", SESSION))
+        .and(body_json(json!({
+            "method": "nameserver.updateRecord",
+            "params": { "id": 8, "content": NEW_IP.to_string() }
+        })))
+        .respond_with(ok(json!(null)))
+        .expect(1)
+        .mount(&mock_server)
+        .await;
+
+    client(&mock_server.uri())
+        .update_dns(SUBDOMAIN, ZONE, NEW_IP)
+        .await
+        .expect("update failed");
+}
+
+#[tokio::test]
+async fn test_create_missing_record() {
+    let mock_server = MockServer::start().await;
+    mount_login(&mock_server, 1).await;
+    mount_info(&mock_server, json!([])).await;
+
+    Mock::given(method("POST"))
+        .and(path("/jsonrpc/"))
+        .and(body_json(json!({
+            "method": "nameserver.createRecord",
+            "params": {
+                "domain": ZONE,
+                "name": SUBDOMAIN,
+                "type": "A",
+                "content": NEW_IP.to_string(),
+                "ttl": 300
+            }
+        })))
+        .respond_with(ok(json!({ "id": 9 })))
+        .expect(1)
+        .mount(&mock_server)
+        .await;
+
+    client(&mock_server.uri())
+        .update_dns_with_ttl(SUBDOMAIN, ZONE, NEW_IP, Some(300))
+        .await
+        .expect("update failed");
+}
+
+#[tokio::test]
+async fn test_expired_session_logs_in_again() {
+    let mock_server = MockServer::start().await;
+    mount_login(&mock_server, 2).await;
+
+    Mock::given(method("POST"))
+        .and(path("/jsonrpc/"))
+        .and(body_partial_json(json!({ "method": "nameserver.list" })))
+        .respond_with(failed(2200, "Authentication error"))
+        .up_to_n_times(1)
+        .mount(&mock_server)
+        .await;
+    Mock::given(method("POST"))
+        .and(path("/jsonrpc/"))
+        .and(body_partial_json(json!({ "method": "nameserver.list" })))
+        .respond_with(ok(json!({
+            "count": 2,
+            "domains": [{ "domain": "example.com" }, { "domain": "example.org" }]
+        })))
+        .mount(&mock_server)
+        .await;
+
+    let client = client(&mock_server.uri());
+    client.login().await.expect("login failed");
+    let zones = DnsService::list_zones(&client)
+        .await
+        .expect("list zones failed");
+    assert_eq!(zones, vec!["example.com", "example.org"]);
+}
+
+#[tokio::test]
+async fn test_invalid_credentials() {
+    let mock_server = MockServer::start().await;
+
+    Mock::given(method("POST"))
+        .and(path("/jsonrpc/"))
+        .respond_with(failed(2200, "Authentication error"))
+        .mount(&mock_server)
+        .await;
+
+    let result = client(&mock_server.uri()).login().await;
+    assert!(matches!(result, Err(InwxError::InvalidCredentials)));
+}
+
+#[tokio::test]
+async fn test_rejected_change() {
+    let mock_server = MockServer::start().await;
+    mount_login(&mock_server, 1).await;
+    mount_info(&mock_server, json!([])).await;
+
+    Mock::given(method("POST"))
+        .and(path("/jsonrpc/"))
+        .and(body_partial_json(
+            json!({ "method": "nameserver.createRecord" }),
+        ))
+        .respond_with(failed(2302, "Object exists"))
+        .mount(&mock_server)
+        .await;
+
+    let result = client(&mock_server.uri())
+        .update_dns(SUBDOMAIN, ZONE, NEW_IP)
+        .await;
+    assert!(matches!(
+        result,
+        Err(DnsServiceError::UpdateRejected { .. })
+    ));
+}
@@ -0,0 +1,349 @@
+use dyndnsd::dns_service::{DnsService, DnsServiceError};
+use dyndnsd::openwrt_dns_service::{OpenWrtDnsMode, OpenWrtDnsService};
+use dyndnsd::public_ip_service::PublicIpService;
+use dyndnsd::ubus_jsonrpc_public_ip_service::UbusJsonRpcClient;
+use serde_json::{json, Value};
+use std::net::Ipv4Addr;
+use std::sync::Arc;
+use wiremock::matchers::{body_partial_json, method};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+const ZONE: &str = "example.com";
+const SUBDOMAIN: &str = "test";
+const OLD_IP: &str = "192.168.1.10";
+const NEW_IP: Ipv4Addr = Ipv4Addr::new(192, 168, 1, 11);
+const SESSION: &str = "b3f0c2e1a8d94f6e8c7b5a4d3e2f1a0b";
+
+fn dns_service(uri: &str, mode: OpenWrtDnsMode) -> OpenWrtDnsService {
+    OpenWrtDnsService::new(
+        Arc::new(UbusJsonRpcClient::new(uri, "dyndnsd", "secret")),
+        mode,
+    )
+}
+
+fn ubus_result(data: Option<Value>) -> ResponseTemplate {
+    let result = match data {
+        Some(data) => json!([0, data]),
+        None => json!([0]),
+    };
+    ResponseTemplate::new(200).set_body_json(json!({ "jsonrpc": "2.0", "id": 1, "result": result }))
+}
+
+async fn mount_login(mock_server: &MockServer, times: u64) {
+    Mock::given(method("POST"))
+        .and(body_partial_json(json!({
+            "params": ["00000000000000000000000000000000", "session", "login"]
+        })))
+        .respond_with(ubus_result(Some(json!({ "ubus_rpc_session": SESSION }))))
+        .expect(times)
+        .mount(mock_server)
+        .await;
+}
+
+async fn mount_call(
+    mock_server: &MockServer,
+    object: &str,
+    method_name: &str,
+    args: Value,
+    data: Option<Value>,
+    times: Option<u64>,
+) {
+    let mock = Mock::given(method("POST"))
+        .and(body_partial_json(json!({
+            "params": [SESSION, object, method_name, args]
+        })))
+        .respond_with(ubus_result(data));
+    let mock = match times {
+        Some(times) => mock.expect(times),
+        None => mock,
+    };
+    mock.mount(mock_server).await;
+}
+
+async fn mount_domains(mock_server: &MockServer, domains: Value) {
+    mount_call(
+        mock_server,
+        "uci",
+        "get",
+        json!({ "config": "dhcp", "type": "domain" }),
+        Some(json!({ "values": domains })),
+        None,
+    )
+    .await;
+}
+
+async fn mount_apply(mock_server: &MockServer, times: u64) {
+    mount_call(
+        mock_server,
+        "uci",
+        "commit",
+        json!({ "config": "dhcp" }),
+        None,
+        Some(times),
+    )
+    .await;
+    mount_call(
+        mock_server,
+        "rc",
+        "init",
+        json!({ "name": "dnsmasq", "action": "reload" }),
+        None,
+        Some(times),
+    )
+    .await;
+}
+
+fn domain(section: &str, index: u32, name: &str, ip: &str) -> Value {
+    json!({
+        ".anonymous": true,
+        ".type": "domain",
+        ".name": section,
+        ".index": index,
+        "name": name,
+        "ip": ip
+    })
+}
+
+#[tokio::test]
+async fn test_resolve_ip_reuses_session() {
+    let mock_server = MockServer::start().await;
+    mount_login(&mock_server, 1).await;
+    mount_domains(
+        &mock_server,
+        json!({
+            "cfg02f37d": domain("cfg02f37d", 2, "test.example.com", "2001:db8::10"),
+            "cfg03f37d": domain("cfg03f37d", 3, "test.example.com", OLD_IP)
+        }),
+    )
+    .await;
+
+    let dns_service = dns_service(&mock_server.uri(), OpenWrtDnsMode::Domain);
+    for _ in 0..2 {
+        let ip = dns_service
+            .resolve_ip(SUBDOMAIN, ZONE)
+            .await
+            .expect("resolve ip failed");
+        assert_eq!(ip, Some(OLD_IP.parse().unwrap()));
+    }
+}
+
+#[tokio::test]
+async fn test_update_domain_and_remove_duplicates() {
+    let mock_server = MockServer::start().await;
+    mount_login(&mock_server, 1).await;
+    mount_domains(
+        &mock_server,
+        json!({
+            "cfg04f37d": domain("cfg04f37d", 4, "test.example.com", "192.168.1.12"),
+            "cfg03f37d": domain("cfg03f37d", 3, "test.example.com", OLD_IP),
+            "cfg02f37d": domain("cfg02f37d", 2, "nas.example.com", OLD_IP)
+        }),
+    )
+    .await;
+    mount_call(
+        &mock_server,
+        "uci",
+        "set",
+        json!({ "config": "dhcp", "section": "cfg03f37d", "values": { "ip": NEW_IP.to_string() } }),
+        None,
+        Some(1),
+    )
+    .await;
+    mount_call(
+        &mock_server,
+        "uci",
+        "delete",
+        json!({ "config": "dhcp", "section": "cfg04f37d" }),
+        None,
+        Some(1),
+    )
+    .await;
+    mount_apply(&mock_server, 1).await;
+
+    dns_service(&mock_server.uri(), OpenWrtDnsMode::Domain)
+        .update_dns(SUBDOMAIN, ZONE, NEW_IP)
+        .await
+        .expect("update failed");
+}
+
+#[tokio::test]
+async fn test_add_missing_domain() {
+    let mock_server = MockServer::start().await;
+    mount_login(&mock_server, 1).await;
+    mount_domains(
+        &mock_server,
+        json!({ "cfg02f37d": domain("cfg02f37d", 2, "nas.example.com", OLD_IP) }),
+    )
+    .await;
+    mount_call(
+        &mock_server,
+        "uci",
+        "add",
+        json!({
+            "config": "dhcp",
+            "type": "domain",
+            "values": { "name": "test.example.com", "ip": NEW_IP.to_string() }
+        }),
+        Some(json!({ "section": "cfg05f37d" })),
+        Some(1),
+    )
+    .await;
+    mount_apply(&mock_server, 1).await;
+
+    dns_service(&mock_server.uri(), OpenWrtDnsMode::Domain)
+        .update_dns(SUBDOMAIN, ZONE, NEW_IP)
+        .await
+        .expect("update failed");
+}
+
+#[tokio::test]
+async fn test_unchanged_domain_is_not_committed() {
+    let mock_server = MockServer::start().await;
+    mount_login(&mock_server, 1).await;
+    mount_domains(
+        &mock_server,
+        json!({ "cfg03f37d": domain("cfg03f37d", 3, "test.example.com", &NEW_IP.to_string()) }),
+    )
+    .await;
+    mount_apply(&mock_server, 0).await;
+
+    dns_service(&mock_server.uri(), OpenWrtDnsMode::Domain)
+        .update_dns(SUBDOMAIN, ZONE, NEW_IP)
+        .await
+        .expect("update failed");
+}
+
+#[tokio::test]
+async fn test_update_dnsmasq_address_list() {
+    let mock_server = MockServer::start().await;
+    mount_login(&mock_server, 1).await;
+    mount_call(
+        &mock_server,
+        "uci",
+        "get",
+        json!({ "config": "dhcp", "type": "dnsmasq" }),
+        Some(json!({
+            "values": {
+                "cfg01411c": {
+                    ".anonymous": true,
+                    ".type": "dnsmasq",
+                    ".name": "cfg01411c",
+                    ".index": 0,
+                    "domain": "lan",
+                    "address": ["/router.lan/192.168.1.1", "/test.example.com/192.168.1.10"]
+                }
+            }
+        })),
+        None,
+    )
+    .await;
+    mount_call(
+        &mock_server,
+        "uci",
+        "set",
+        json!({
+            "config": "dhcp",
+            "section": "cfg01411c",
+            "values": { "address": ["/router.lan/192.168.1.1", "/test.example.com/192.168.1.11"] }
+        }),
+        None,
+        Some(1),
+    )
+    .await;
+    mount_apply(&mock_server, 1).await;
+
+    dns_service(&mock_server.uri(), OpenWrtDnsMode::Address)
+        .update_dns(SUBDOMAIN, ZONE, NEW_IP)
+        .await
+        .expect("update failed");
+}
+
+#[tokio::test]
+async fn test_expired_session_logs_in_again() {
+    let mock_server = MockServer::start().await;
+    mount_login(&mock_server, 2).await;
+
+    Mock::given(method("POST"))
+        .and(body_partial_json(
+            json!({ "params": [SESSION, "uci", "get"] }),
+        ))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "jsonrpc": "2.0",
+            "id": 1,
+            "error": { "code": -32002, "message": "Access denied" }
+        })))
+        .up_to_n_times(1)
+        .mount(&mock_server)
+        .await;
+    mount_domains(
+        &mock_server,
+        json!({ "cfg03f37d": domain("cfg03f37d", 3, "test.example.com", OLD_IP) }),
+    )
+    .await;
+
+    let ip = dns_service(&mock_server.uri(), OpenWrtDnsMode::Domain)
+        .resolve_ip(SUBDOMAIN, ZONE)
+        .await
+        .expect("resolve ip failed");
+    assert_eq!(ip, Some(OLD_IP.parse().unwrap()));
+}
+
+#[tokio::test]
+async fn test_rejected_change() {
+    let mock_server = MockServer::start().await;
+    mount_login(&mock_server, 1).await;
+    mount_domains(&mock_server, json!({})).await;
+
+    Mock::given(method("POST"))
+        .and(body_partial_json(
+            json!({ "params": [SESSION, "uci", "add"] }),
+        ))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .set_body_json(json!({ "jsonrpc": "2.0", "id": 1, "result": [2] })),
+        )
+        .mount(&mock_server)
+        .await;
+
+    let result = dns_service(&mock_server.uri(), OpenWrtDnsMode::Domain)
+        .update_dns(SUBDOMAIN, ZONE, NEW_IP)
+        .await;
+    assert!(matches!(
+        result,
+        Err(DnsServiceError::UpdateRejected { .. })
+    ));
+}
+
+#[tokio::test]
+async fn test_address_lookup_shares_session() {
+    let mock_server = MockServer::start().await;
+    mount_login(&mock_server, 1).await;
+    mount_domains(
+        &mock_server,
+        json!({ "cfg01f37d": domain("cfg01f37d", 1, "test.example.com", OLD_IP) }),
+    )
+    .await;
+    mount_call(
+        &mock_server,
+        "network.interface.lan",
+        "status",
+        json!({}),
+        Some(json!({ "ipv4-address": [{ "address": "192.168.1.11", "mask": 24 }] })),
+        Some(2),
+    )
+    .await;
+
+    let ubus = Arc::new(
+        UbusJsonRpcClient::new(&mock_server.uri(), "dyndnsd", "secret").with_interface("lan"),
+    );
+    let dns_service = OpenWrtDnsService::new(ubus.clone(), OpenWrtDnsMode::Domain);
+    for _ in 0..2 {
+        let ip = PublicIpService::get_ip(&ubus).await.expect("get ip failed");
+        assert_eq!(ip, NEW_IP);
+    }
+    let ip = dns_service
+        .resolve_ip(SUBDOMAIN, ZONE)
+        .await
+        .expect("resolve ip failed");
+    assert_eq!(ip, Some(OLD_IP.parse().unwrap()));
+}
@@ -0,0 +1,224 @@
+use dyndnsd::dns_service::{DnsService, DnsServiceError};
+use dyndnsd::ovh_dns_service::{signature, OvhClient, OvhError};
+use serde_json::json;
+use std::net::Ipv4Addr;
+use wiremock::matchers::{body_json, header, method, path, query_param};
+use wiremock::{Match, Mock, MockServer, Request, ResponseTemplate};
+
+const ZONE: &str = "example.com";
+const SUBDOMAIN: &str = "test";
+const OLD_IP: &str = "93.184.216.34";
+const NEW_IP: Ipv4Addr = Ipv4Addr::new(93, 184, 216, 35);
+const APPLICATION_KEY: &str = "app-key";
+const APPLICATION_SECRET: &str = "app-secret";
+const CONSUMER_KEY: &str = "consumer-key";
+const SERVER_TIME: i64 = 1_700_000_000;
+
+/// Recomputes the signature of a request from its method, URL, body and timestamp. wiremock
+/// drops the port from the request URL, so the URL is rebuilt from the server address.
+struct SignedRequest(String);
+
+impl Match for SignedRequest {
+    fn matches(&self, request: &Request) -> bool {
+        let header = |name: &str| {
+            request
+                .headers
+                .iter()
+                .find(|(key, _)| key.as_str().eq_ignore_ascii_case(name))
+                .map(|(_, values)| values.last().as_str().to_string())
+                .unwrap_or_default()
+        };
+        let url = match request.url.query() {
+            Some(query) => format!("{}{}?{}", self.0, request.url.path(), query),
+            None => format!("{}{}", self.0, request.url.path()),
+        };
+        let timestamp = header("X-Ovh-Timestamp");
+        let in_sync = timestamp
+            .parse::<i64>()
+            .map(|t| (SERVER_TIME..SERVER_TIME + 5).contains(&t))
+            .unwrap_or(false);
+        let expected = signature(
+            APPLICATION_SECRET,
+            CONSUMER_KEY,
+            request.method.as_ref(),
+            &url,
+            &String::from_utf8_lossy(&request.body),
+            &timestamp,
+        );
+        in_sync
+            && header("X-Ovh-Application") == APPLICATION_KEY
+            && header("X-Ovh-Consumer") == CONSUMER_KEY
+            && header("X-Ovh-Signature") == expected
+    }
+}
+
+fn client(uri: &str) -> OvhClient {
+    OvhClient::new_with_url(APPLICATION_KEY, APPLICATION_SECRET, CONSUMER_KEY, uri)
+}
+
+async fn mount_time(mock_server: &MockServer, times: u64) {
+    Mock::given(method("GET"))
+        .and(path("/auth/time"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(SERVER_TIME))
+        .expect(times)
+        .mount(mock_server)
+        .await;
+}
+
+async fn mount_record(mock_server: &MockServer, subdomain: &str, ids: &[u64]) {
+    Mock::given(method("GET"))
+        .and(path("/domain/zone/example.com/record"))
+        .and(query_param("fieldType", "A"))
+        .and(query_param("subDomain", subdomain))
+        .and(SignedRequest(mock_server.uri()))
+        .respond_with(ResponseTemplate::new(200).set_body_json(ids))
+        .mount(mock_server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/domain/zone/example.com/record/5118"))
+        .and(SignedRequest(mock_server.uri()))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "id": 5118,
+            "zone": ZONE,
+            "fieldType": "A",
+            "subDomain": subdomain,
+            "target": OLD_IP,
+            "ttl": 600
+        })))
+        .mount(mock_server)
+        .await;
+}
+
+async fn mount_refresh(mock_server: &MockServer) {
+    Mock::given(method("POST"))
+        .and(path("/domain/zone/example.com/refresh"))
+        .and(SignedRequest(mock_server.uri()))
+        .respond_with(ResponseTemplate::new(200).set_body_string("null"))
+        .expect(1)
+        .mount(mock_server)
+        .await;
+}
+
+#[tokio::test]
+async fn test_resolve_ip_syncs_time_once() {
+    let mock_server = MockServer::start().await;
+    mount_time(&mock_server, 1).await;
+    mount_record(&mock_server, SUBDOMAIN, &[5118]).await;
+
+    let client = client(&mock_server.uri());
+    for _ in 0..2 {
+        let ip = client
+            .resolve_ip(SUBDOMAIN, ZONE)
+            .await
+            .expect("resolve ip failed");
+        assert_eq!(ip, Some(OLD_IP.parse().unwrap()));
+    }
+}
+
+#[tokio::test]
+async fn test_update_existing_record_and_refresh() {
+    let mock_server = MockServer::start().await;
+    mount_time(&mock_server, 1).await;
+    mount_record(&mock_server, SUBDOMAIN, &[5118]).await;
+    mount_refresh(&mock_server).await;
+
+    Mock::given(method("PUT"))
+        .and(path("/domain/zone/example.com/record/5118"))
+        .and(header("Content-Type", "application/json"))
+        .and(body_json(json!({ "target": NEW_IP.to_string() })))
+        .and(SignedRequest(mock_server.uri()))
+        .respond_with(ResponseTemplate::new(200).set_body_string("null"))
+        .expect(1)
+        .mount(&mock_server)
+        .await;
+
+    client(&mock_server.uri())
+        .update_dns(SUBDOMAIN, ZONE, NEW_IP)
+        .await
+        .expect("update failed");
+}
+
+#[tokio::test]
+async fn test_create_apex_record() {
+    let mock_server = MockServer::start().await;
+    mount_time(&mock_server, 1).await;
+    mount_record(&mock_server, "", &[]).await;
+    mount_refresh(&mock_server).await;
+
+    Mock::given(method("POST"))
+        .and(path("/domain/zone/example.com/record"))
+        .and(body_json(json!({
+            "fieldType": "A",
+            "subDomain": "",
+            "target": NEW_IP.to_string(),
+            "ttl": 60
+        })))
+        .and(SignedRequest(mock_server.uri()))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "id": 5119,
+            "zone": ZONE,
+            "fieldType": "A",
+            "subDomain": "",
+            "target": NEW_IP.to_string(),
+            "ttl": 60
+        })))
+        .expect(1)
+        .mount(&mock_server)
+        .await;
+
+    client(&mock_server.uri())
+        .update_dns_with_ttl("@", ZONE, NEW_IP, Some(60))
+        .await
+        .expect("update failed");
+}
+
+#[tokio::test]
+async fn test_invalid_credentials() {
+    let mock_server = MockServer::start().await;
+    mount_time(&mock_server, 1).await;
+
+    Mock::given(method("GET"))
+        .and(path("/domain/zone"))
+        .respond_with(
+            ResponseTemplate::new(403)
+                .set_body_json(json!({ "message": "This credential is not valid" })),
+        )
+        .mount(&mock_server)
+        .await;
+
+    let result = client(&mock_server.uri()).list_zones().await;
+    assert!(matches!(result, Err(OvhError::InvalidCredentials { .. })));
+}
+
+#[tokio::test]
+async fn test_query_out_of_time_resyncs_clock() {
+    let mock_server = MockServer::start().await;
+    mount_time(&mock_server, 2).await;
+
+    Mock::given(method("GET"))
+        .and(path("/domain/zone"))
+        .respond_with(
+            ResponseTemplate::new(400).set_body_json(json!({ "message": "Query out of time" })),
+        )
+        .up_to_n_times(1)
+        .mount(&mock_server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/domain/zone"))
+        .and(SignedRequest(mock_server.uri()))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!([ZONE, "example.org"])))
+        .mount(&mock_server)
+        .await;
+
+    let client = client(&mock_server.uri());
+    let result = DnsService::list_zones(&client).await;
+    assert!(matches!(
+        result,
+        Err(DnsServiceError::UpdateRejected { .. })
+    ));
+
+    let zones = DnsService::list_zones(&client)
+        .await
+        .expect("list zones failed");
+    assert_eq!(zones, vec!["example.com", "example.org"]);
+}
@@ -0,0 +1,226 @@
+use dyndnsd::dns_service::{DnsService, DnsServiceError};
+use dyndnsd::dyndns_service::DynDnsService;
+use dyndnsd::pihole_dns_service::{PiholeClient, PiholeError};
+use dyndnsd::ubus_jsonrpc_public_ip_service::UbusJsonRpcClient;
+use serde_json::json;
+use std::net::Ipv4Addr;
+use wiremock::matchers::{body_json, body_partial_json, header, method, path};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+const ZONE: &str = "example.com";
+const SUBDOMAIN: &str = "test";
+const OLD_IP: &str = "192.168.1.10";
+const NEW_IP: Ipv4Addr = Ipv4Addr::new(192, 168, 1, 11);
+const PASSWORD: &str = "app-password";
+const SID: &str = "vFA+EP4MQ5JJvJg+3Q2Jnw=";
+
+async fn mount_login(mock_server: &MockServer, times: u64) {
+    Mock::given(method("POST"))
+        .and(path("/api/auth"))
+        .and(body_json(json!({ "password": PASSWORD })))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "session": {
+                "valid": true,
+                "totp": false,
+                "sid": SID,
+                "csrf": "Ux87YTIiMOf/GKCefVIOMw=",
+                "validity": 1800,
+                "message": "app-password correct"
+            },
+            "took": 0.04
+        })))
+        .expect(times)
+        .mount(mock_server)
+        .await;
+}
+
+async fn mount_hosts(mock_server: &MockServer, hosts: &[&str]) {
+    Mock::given(method("GET"))
+        .and(path("/api/config/dns/hosts"))
+        .and(header("X-FTL-SID", SID))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "config": { "dns": { "hosts": hosts } },
+            "took": 0.0002
+        })))
+        .mount(mock_server)
+        .await;
+}
+
+async fn mount_change(mock_server: &MockServer, verb: &str, entry: &str, times: u64) {
+    Mock::given(method(verb))
+        .and(path(format!("/api/config/dns/hosts/{}", entry)))
+        .and(header("X-FTL-SID", SID))
+        .respond_with(ResponseTemplate::new(if verb == "PUT" { 201 } else { 204 }))
+        .expect(times)
+        .mount(mock_server)
+        .await;
+}
+
+#[tokio::test]
+async fn test_resolve_ip_reuses_session() {
+    let mock_server = MockServer::start().await;
+    mount_login(&mock_server, 1).await;
+    mount_hosts(
+        &mock_server,
+        &["192.168.1.2 pi.hole", "192.168.1.10 test.example.com"],
+    )
+    .await;
+
+    let client = PiholeClient::new(&mock_server.uri(), PASSWORD);
+    for _ in 0..2 {
+        let ip = client
+            .resolve_ip(SUBDOMAIN, ZONE)
+            .await
+            .expect("resolve ip failed");
+        assert_eq!(ip, Some(OLD_IP.parse().unwrap()));
+    }
+}
+
+#[tokio::test]
+async fn test_update_adds_new_entry_and_deletes_old_one() {
+    let mock_server = MockServer::start().await;
+    mount_login(&mock_server, 1).await;
+    mount_hosts(
+        &mock_server,
+        &["192.168.1.2 pi.hole", "192.168.1.10 test.example.com"],
+    )
+    .await;
+    mount_change(&mock_server, "PUT", "192.168.1.11%20test.example.com", 1).await;
+    mount_change(&mock_server, "DELETE", "192.168.1.10%20test.example.com", 1).await;
+
+    PiholeClient::new(&mock_server.uri(), PASSWORD)
+        .update_dns(SUBDOMAIN, ZONE, NEW_IP)
+        .await
+        .expect("update failed");
+}
+
+#[tokio::test]
+async fn test_unchanged_entry_is_left_alone() {
+    let mock_server = MockServer::start().await;
+    mount_login(&mock_server, 1).await;
+    mount_hosts(&mock_server, &["192.168.1.11 test.example.com"]).await;
+    mount_change(&mock_server, "PUT", "192.168.1.11%20test.example.com", 0).await;
+
+    PiholeClient::new(&mock_server.uri(), PASSWORD)
+        .update_dns(SUBDOMAIN, ZONE, NEW_IP)
+        .await
+        .expect("update failed");
+}
+
+#[tokio::test]
+async fn test_expired_session_logs_in_again() {
+    let mock_server = MockServer::start().await;
+    mount_login(&mock_server, 2).await;
+
+    Mock::given(method("GET"))
+        .and(path("/api/config/dns/hosts"))
+        .respond_with(ResponseTemplate::new(401).set_body_json(json!({
+            "error": { "key": "unauthorized", "message": "Unauthorized", "hint": null }
+        })))
+        .up_to_n_times(1)
+        .mount(&mock_server)
+        .await;
+    mount_hosts(&mock_server, &[]).await;
+    mount_change(&mock_server, "PUT", "192.168.1.11%20test.example.com", 1).await;
+
+    let client = PiholeClient::new(&mock_server.uri(), PASSWORD);
+    client.login().await.expect("login failed");
+    client
+        .update_dns(SUBDOMAIN, ZONE, NEW_IP)
+        .await
+        .expect("update failed");
+}
+
+#[tokio::test]
+async fn test_invalid_password() {
+    let mock_server = MockServer::start().await;
+
+    Mock::given(method("POST"))
+        .and(path("/api/auth"))
+        .respond_with(ResponseTemplate::new(401).set_body_json(json!({
+            "session": {
+                "valid": false,
+                "totp": false,
+                "sid": null,
+                "validity": -1,
+                "message": "password incorrect"
+            }
+        })))
+        .mount(&mock_server)
+        .await;
+
+    let result = PiholeClient::new(&mock_server.uri(), "wrong").login().await;
+    assert!(matches!(result, Err(PiholeError::InvalidCredentials)));
+}
+
+#[tokio::test]
+async fn test_rejected_change() {
+    let mock_server = MockServer::start().await;
+    mount_login(&mock_server, 1).await;
+    mount_hosts(&mock_server, &[]).await;
+
+    Mock::given(method("PUT"))
+        .and(path(
+            "/api/config/dns/hosts/192.168.1.11%20test.example.com",
+        ))
+        .respond_with(ResponseTemplate::new(400).set_body_json(json!({
+            "error": {
+                "key": "bad_request",
+                "message": "Invalid configuration",
+                "hint": "webserver.api.app_sudo is false"
+            }
+        })))
+        .mount(&mock_server)
+        .await;
+
+    let result = PiholeClient::new(&mock_server.uri(), PASSWORD)
+        .update_dns(SUBDOMAIN, ZONE, NEW_IP)
+        .await;
+    assert!(matches!(
+        result,
+        Err(DnsServiceError::UpdateRejected { .. })
+    ));
+}
+
+#[tokio::test]
+async fn test_publish_lan_address_of_router() {
+    let router = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(body_partial_json(json!({ "params": [
+            "00000000000000000000000000000000", "session", "login"
+        ] })))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "jsonrpc": "2.0", "id": 1, "result": [0, { "ubus_rpc_session": "session" }]
+        })))
+        .mount(&router)
+        .await;
+    Mock::given(method("POST"))
+        .and(body_partial_json(json!({ "params": [
+            "session", "network.interface.lan", "status", {}
+        ] })))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "jsonrpc": "2.0",
+            "id": 1,
+            "result": [0, { "ipv4-address": [{ "address": "192.168.1.11", "mask": 24 }] }]
+        })))
+        .expect(1)
+        .mount(&router)
+        .await;
+
+    let pihole = MockServer::start().await;
+    mount_login(&pihole, 1).await;
+    mount_hosts(&pihole, &["192.168.1.10 test.example.com"]).await;
+    mount_change(&pihole, "PUT", "192.168.1.11%20test.example.com", 1).await;
+    mount_change(&pihole, "DELETE", "192.168.1.10%20test.example.com", 1).await;
+
+    // The default address policy refuses private addresses, Pi-hole only serves the LAN.
+    DynDnsService::new(
+        ZONE,
+        SUBDOMAIN,
+        Box::new(PiholeClient::new(&pihole.uri(), PASSWORD)),
+        Box::new(UbusJsonRpcClient::new(&router.uri(), "user", "pass").with_interface("lan")),
+    )
+    .update_dns_if_required()
+    .await
+    .expect("update failed");
+}
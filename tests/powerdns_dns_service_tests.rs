@@ -0,0 +1,257 @@
+use dyndnsd::dns_service::{DnsService, DnsServiceError};
+use dyndnsd::powerdns_dns_service::{PowerDnsClient, PowerDnsError};
+use serde_json::json;
+use std::net::Ipv4Addr;
+use wiremock::matchers::{body_json, header, method, path, query_param};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+const API_KEY: &str = "XXX";
+const ZONE: &str = "example.com";
+const ZONE_PATH: &str = "/api/v1/servers/localhost/zones/example.com.";
+const SUBDOMAIN: &str = "test";
+const OLD_IP: &str = "93.184.216.34";
+const NEW_IP: Ipv4Addr = Ipv4Addr::new(93, 184, 216, 35);
+
+async fn mount_zone(mock_server: &MockServer) {
+    Mock::given(method("GET"))
+        .and(path("/api/v1/servers/localhost/zones"))
+        .and(query_param("zone", "example.com."))
+        .and(header("X-API-Key", API_KEY))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!([
+            { "id": "example.com.", "name": "example.com.", "kind": "Native" }
+        ])))
+        .expect(1)
+        .mount(mock_server)
+        .await;
+}
+
+async fn mount_rrset(mock_server: &MockServer, records: serde_json::Value) {
+    Mock::given(method("GET"))
+        .and(path(ZONE_PATH))
+        .and(query_param("rrset_name", "test.example.com."))
+        .and(query_param("rrset_type", "A"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "id": "example.com.",
+            "name": "example.com.",
+            "rrsets": [
+                { "name": "test.example.com.", "type": "A", "ttl": 600, "records": records }
+            ]
+        })))
+        .mount(mock_server)
+        .await;
+}
+
+#[tokio::test]
+async fn test_resolve_ip_skips_disabled_records() {
+    let mock_server = MockServer::start().await;
+    mount_zone(&mock_server).await;
+    mount_rrset(
+        &mock_server,
+        json!([
+            { "content": "93.184.216.36", "disabled": true },
+            { "content": OLD_IP, "disabled": false }
+        ]),
+    )
+    .await;
+
+    let client = PowerDnsClient::new(&mock_server.uri(), API_KEY);
+    let ip = client
+        .resolve_ip(SUBDOMAIN, ZONE)
+        .await
+        .expect("resolve ip failed");
+    assert_eq!(ip, Some(OLD_IP.parse().unwrap()));
+
+    // `mount_zone` expects a single request, the second call uses the cached id.
+    client
+        .resolve_ip(SUBDOMAIN, ZONE)
+        .await
+        .expect("resolve ip failed");
+}
+
+#[tokio::test]
+async fn test_update_replaces_rrset_and_keeps_ttl() {
+    let mock_server = MockServer::start().await;
+    mount_zone(&mock_server).await;
+    mount_rrset(
+        &mock_server,
+        json!([{ "content": OLD_IP, "disabled": false }, { "content": "93.184.216.36" }]),
+    )
+    .await;
+
+    Mock::given(method("PATCH"))
+        .and(path(ZONE_PATH))
+        .and(body_json(json!({
+            "rrsets": [{
+                "name": "test.example.com.",
+                "type": "A",
+                "ttl": 600,
+                "changetype": "REPLACE",
+                "records": [{ "content": NEW_IP.to_string(), "disabled": false }]
+            }]
+        })))
+        .respond_with(ResponseTemplate::new(204))
+        .expect(1)
+        .mount(&mock_server)
+        .await;
+
+    PowerDnsClient::new(&mock_server.uri(), API_KEY)
+        .update_dns(SUBDOMAIN, ZONE, NEW_IP)
+        .await
+        .expect("update failed");
+}
+
+#[tokio::test]
+async fn test_replace_multi_value_rrset() {
+    let mock_server = MockServer::start().await;
+    mount_zone(&mock_server).await;
+
+    Mock::given(method("PATCH"))
+        .and(path(ZONE_PATH))
+        .and(body_json(json!({
+            "rrsets": [{
+                "name": "example.com.",
+                "type": "A",
+                "ttl": 60,
+                "changetype": "REPLACE",
+                "records": [
+                    { "content": "93.184.216.34", "disabled": false },
+                    { "content": "93.184.216.35", "disabled": false }
+                ]
+            }]
+        })))
+        .respond_with(ResponseTemplate::new(204))
+        .expect(1)
+        .mount(&mock_server)
+        .await;
+
+    PowerDnsClient::new(&mock_server.uri(), API_KEY)
+        .replace_rrset(
+            ZONE,
+            ZONE,
+            "A",
+            &[String::from("93.184.216.34"), String::from("93.184.216.35")],
+            Some(60),
+        )
+        .await
+        .expect("replace failed");
+}
+
+#[tokio::test]
+async fn test_rejected_change() {
+    let mock_server = MockServer::start().await;
+    mount_zone(&mock_server).await;
+    mount_rrset(&mock_server, json!([])).await;
+
+    Mock::given(method("PATCH"))
+        .and(path(ZONE_PATH))
+        .respond_with(ResponseTemplate::new(422).set_body_json(
+            json!({ "error": "Record test.example.com./A '93.184.216.35': Conflicts with CNAME" }),
+        ))
+        .mount(&mock_server)
+        .await;
+
+    let result = PowerDnsClient::new(&mock_server.uri(), API_KEY)
+        .update_dns(SUBDOMAIN, ZONE, NEW_IP)
+        .await;
+    assert!(matches!(
+        result,
+        Err(DnsServiceError::UpdateRejected { .. })
+    ));
+}
+
+#[tokio::test]
+async fn test_invalid_api_key() {
+    let mock_server = MockServer::start().await;
+
+    Mock::given(method("GET"))
+        .and(path("/api/v1/servers/localhost/zones"))
+        .respond_with(ResponseTemplate::new(401).set_body_string("Unauthorized"))
+        .mount(&mock_server)
+        .await;
+
+    let result = PowerDnsClient::new(&mock_server.uri(), "wrong")
+        .find_zone(ZONE)
+        .await;
+    assert!(matches!(result, Err(PowerDnsError::InvalidApiKey)));
+}
+
+#[tokio::test]
+async fn test_list_zones() {
+    let mock_server = MockServer::start().await;
+
+    Mock::given(method("GET"))
+        .and(path("/api/v1/servers/ns1/zones"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!([
+            { "id": "example.com.", "name": "example.com." },
+            { "id": "example.org.", "name": "example.org." }
+        ])))
+        .mount(&mock_server)
+        .await;
+
+    let client = PowerDnsClient::new(&mock_server.uri(), API_KEY).with_server_id("ns1");
+    let zones = DnsService::list_zones(&client)
+        .await
+        .expect("list zones failed");
+    assert_eq!(zones, vec!["example.com", "example.org"]);
+}
+
+#[tokio::test]
+async fn test_update_record_keeps_other_txt_records() {
+    let mock_server = MockServer::start().await;
+    mount_zone(&mock_server).await;
+
+    Mock::given(method("GET"))
+        .and(path(ZONE_PATH))
+        .and(query_param("rrset_name", "test.example.com."))
+        .and(query_param("rrset_type", "TXT"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "id": "example.com.",
+            "name": "example.com.",
+            "rrsets": [{
+                "name": "test.example.com.",
+                "type": "TXT",
+                "ttl": 600,
+                "records": [
+                    { "content": "\"google-site-verification=abc\"", "disabled": false },
+                    { "content": "\"v=spf1 ip4:93.184.216.34 -all\"", "disabled": false }
+                ]
+            }]
+        })))
+        .mount(&mock_server)
+        .await;
+    Mock::given(method("PATCH"))
+        .and(path(ZONE_PATH))
+        .and(body_json(json!({
+            "rrsets": [{
+                "name": "test.example.com.",
+                "type": "TXT",
+                "ttl": 600,
+                "changetype": "REPLACE",
+                "records": [
+                    { "content": "\"google-site-verification=abc\"", "disabled": false },
+                    { "content": "\"v=spf1 ip4:93.184.216.35 -all\"", "disabled": false }
+                ]
+            }]
+        })))
+        .respond_with(ResponseTemplate::new(204))
+        .expect(1)
+        .mount(&mock_server)
+        .await;
+
+    let client = PowerDnsClient::new(&mock_server.uri(), API_KEY);
+    let value = client
+        .resolve_record(SUBDOMAIN, ZONE, "TXT", "v=spf1 ip4:")
+        .await
+        .expect("resolve record failed");
+    assert_eq!(value, Some(String::from("v=spf1 ip4:93.184.216.34 -all")));
+    client
+        .update_record(
+            SUBDOMAIN,
+            ZONE,
+            "TXT",
+            "v=spf1 ip4:",
+            "v=spf1 ip4:93.184.216.35 -all",
+        )
+        .await
+        .expect("update failed");
+}
@@ -0,0 +1,183 @@
+use dyndnsd::dns_message::{
+    Message, ResourceRecord, CLASS_ANY, CLASS_IN, CLASS_NONE, OPCODE_UPDATE, RCODE_NOTAUTH,
+    RCODE_NXRRSET, RCODE_YXRRSET, TYPE_A,
+};
+use dyndnsd::dns_service::{DnsService, DnsServiceError};
+use dyndnsd::rfc2136_dns_service::{Rfc2136DnsService, Rfc2136Error};
+use dyndnsd::tsig::{self, TsigAlgorithm, TsigKey};
+use std::collections::HashMap;
+use std::net::{Ipv4Addr, SocketAddr};
+use std::sync::{Arc, Mutex};
+use tokio::net::UdpSocket;
+
+const ZONE: &str = "example.com";
+const SUBDOMAIN: &str = "home";
+const FQDN: &str = "home.example.com";
+const OLD_IP: Ipv4Addr = Ipv4Addr::new(93, 184, 216, 35);
+const NEW_IP: Ipv4Addr = Ipv4Addr::new(93, 184, 216, 34);
+const SECRET: &[u8] = b"a shared secret of the primary";
+
+type ZoneData = Arc<Mutex<HashMap<String, ResourceRecord>>>;
+
+fn key(algorithm: TsigAlgorithm) -> TsigKey {
+    TsigKey::new("dyndnsd-key", algorithm, SECRET)
+}
+
+fn zone(records: &[(&str, Ipv4Addr, u32)]) -> ZoneData {
+    Arc::new(Mutex::new(
+        records
+            .iter()
+            .map(|(name, ip, ttl)| (name.to_string(), ResourceRecord::a(name, *ttl, *ip)))
+            .collect(),
+    ))
+}
+
+fn apply_update(request: &Message, records: &ZoneData) -> u8 {
+    let mut records = records.lock().unwrap();
+    for prerequisite in &request.answers {
+        let current = records.get(&prerequisite.name);
+        match prerequisite.class {
+            CLASS_NONE if current.is_some() => return RCODE_YXRRSET,
+            CLASS_IN if current.map(|r| &r.rdata) != Some(&prerequisite.rdata) => {
+                return RCODE_NXRRSET
+            }
+            _ => {}
+        }
+    }
+    for update in &request.authorities {
+        match update.class {
+            CLASS_ANY => {
+                records.remove(&update.name);
+            }
+            _ => {
+                records.insert(update.name.clone(), update.clone());
+            }
+        }
+    }
+    0
+}
+
+/// A primary name server that answers queries from `records` and applies UPDATE messages
+/// signed with `key`.
+async fn start_primary(records: ZoneData, key: TsigKey) -> SocketAddr {
+    let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    let addr = socket.local_addr().unwrap();
+
+    tokio::spawn(async move {
+        let mut buf = [0u8; 4096];
+        loop {
+            let (len, peer) = socket.recv_from(&mut buf).await.unwrap();
+            let request = Message::decode(&buf[..len]).unwrap();
+            let mut response = Message::response_to(&request);
+
+            if request.opcode() != OPCODE_UPDATE {
+                let question = &request.questions[0];
+                if question.qtype == TYPE_A {
+                    if let Some(record) = records.lock().unwrap().get(&question.name) {
+                        response.answers.push(record.clone());
+                    }
+                }
+                socket.send_to(&response.encode(), peer).await.unwrap();
+                continue;
+            }
+
+            response.set_opcode(OPCODE_UPDATE);
+            let request_mac = match tsig::verify(&buf[..len], &key, None, tsig::now()) {
+                Ok(mac) => mac,
+                Err(_) => {
+                    response.set_rcode(RCODE_NOTAUTH);
+                    socket.send_to(&response.encode(), peer).await.unwrap();
+                    continue;
+                }
+            };
+            response.set_rcode(apply_update(&request, &records));
+            let (signed, _) = tsig::sign(&response, &key, tsig::now(), Some(&request_mac));
+            socket.send_to(&signed, peer).await.unwrap();
+        }
+    });
+
+    addr
+}
+
+#[tokio::test]
+async fn resolve_ip_with_query() {
+    let records = zone(&[(FQDN, OLD_IP, 300)]);
+    let addr = start_primary(records, key(TsigAlgorithm::HmacSha256)).await;
+    let service = Rfc2136DnsService::new(&addr.to_string());
+
+    let ip = service.resolve_ip(SUBDOMAIN, ZONE).await.unwrap();
+    assert_eq!(ip, Some(OLD_IP));
+
+    let missing = service.resolve_ip("www", ZONE).await.unwrap();
+    assert_eq!(missing, None);
+}
+
+#[tokio::test]
+async fn update_existing_record_keeps_ttl() {
+    let records = zone(&[(FQDN, OLD_IP, 300)]);
+    let addr = start_primary(records.clone(), key(TsigAlgorithm::HmacSha256)).await;
+    let service =
+        Rfc2136DnsService::new(&addr.to_string()).with_tsig_key(key(TsigAlgorithm::HmacSha256));
+
+    service.update_dns(SUBDOMAIN, ZONE, NEW_IP).await.unwrap();
+
+    let record = records.lock().unwrap().get(FQDN).cloned().unwrap();
+    assert_eq!(record.as_ipv4(), Some(NEW_IP));
+    assert_eq!(record.ttl, 300);
+}
+
+#[tokio::test]
+async fn create_missing_record_with_ttl() {
+    let records = zone(&[]);
+    let addr = start_primary(records.clone(), key(TsigAlgorithm::HmacSha512)).await;
+    let service =
+        Rfc2136DnsService::new(&addr.to_string()).with_tsig_key(key(TsigAlgorithm::HmacSha512));
+
+    service
+        .update_dns_with_ttl(SUBDOMAIN, ZONE, NEW_IP, Some(120))
+        .await
+        .unwrap();
+
+    let record = records.lock().unwrap().get(FQDN).cloned().unwrap();
+    assert_eq!(record.as_ipv4(), Some(NEW_IP));
+    assert_eq!(record.ttl, 120);
+}
+
+#[tokio::test]
+async fn reject_update_of_changed_record() {
+    let records = zone(&[(FQDN, OLD_IP, 300)]);
+    let addr = start_primary(records.clone(), key(TsigAlgorithm::HmacSha256)).await;
+    let service =
+        Rfc2136DnsService::new(&addr.to_string()).with_tsig_key(key(TsigAlgorithm::HmacSha256));
+
+    let stale = vec![ResourceRecord::a(
+        FQDN,
+        300,
+        Ipv4Addr::new(93, 184, 216, 36),
+    )];
+    let result = service.update_a(ZONE, FQDN, &stale, NEW_IP, 300).await;
+    assert!(matches!(
+        result,
+        Err(Rfc2136Error::PrerequisiteFailed { .. })
+    ));
+
+    let error = DnsServiceError::from(result.unwrap_err());
+    assert!(matches!(error, DnsServiceError::UpdateRejected { .. }));
+    let record = records.lock().unwrap().get(FQDN).cloned().unwrap();
+    assert_eq!(record.as_ipv4(), Some(OLD_IP));
+}
+
+#[tokio::test]
+async fn reject_update_with_wrong_key() {
+    let records = zone(&[(FQDN, OLD_IP, 300)]);
+    let addr = start_primary(records.clone(), key(TsigAlgorithm::HmacSha256)).await;
+    let wrong_key = TsigKey::new("dyndnsd-key", TsigAlgorithm::HmacSha256, b"another secret");
+    let service = Rfc2136DnsService::new(&addr.to_string()).with_tsig_key(wrong_key);
+
+    let current = service.query_a(FQDN).await.unwrap();
+    let result = service.update_a(ZONE, FQDN, &current, NEW_IP, 300).await;
+    assert!(matches!(result, Err(Rfc2136Error::NotAuthorized)));
+
+    let record = records.lock().unwrap().get(FQDN).cloned().unwrap();
+    assert_eq!(record.as_ipv4(), Some(OLD_IP));
+}
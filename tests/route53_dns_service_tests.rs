@@ -0,0 +1,303 @@
+use dyndnsd::aws_sigv4::AwsCredentials;
+use dyndnsd::dns_service::{DnsService, DnsServiceError};
+use dyndnsd::route53_dns_service::{Route53Client, Route53Error};
+use std::net::Ipv4Addr;
+use std::time::Duration;
+use wiremock::matchers::{body_string_contains, header_regex, method, path, query_param};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+const ZONE: &str = "example.com";
+const ZONE_ID: &str = "Z1D633PJN98FT9";
+const SUBDOMAIN: &str = "test";
+const OLD_IP: &str = "93.184.216.34";
+const NEW_IP: Ipv4Addr = Ipv4Addr::new(93, 184, 216, 35);
+// wiremock splits header values on commas, so each part of the header is matched on its own.
+const AUTHORIZATION: &str = "^\\s*(AWS4-HMAC-SHA256 Credential=AKIDEXAMPLE/[0-9]{8}/us-east-1/route53/aws4_request|SignedHeaders=host;x-amz-date|Signature=[0-9a-f]{64})$";
+
+fn client(uri: &str) -> Route53Client {
+    let credentials =
+        AwsCredentials::new("AKIDEXAMPLE", "wJalrXUtnFEMI/K7MDENG+bPxRfiCYEXAMPLEKEY");
+    Route53Client::new_with_url(credentials, uri)
+        .with_change_poll_interval(Duration::from_millis(10))
+}
+
+fn record_sets(name: &str, value: &str) -> String {
+    format!(
+        "<?xml version=\"1.0\"?>\
+         <ListResourceRecordSetsResponse xmlns=\"https://route53.amazonaws.com/doc/2013-04-01/\">\
+         <ResourceRecordSets><ResourceRecordSet><Name>{}</Name><Type>A</Type><TTL>600</TTL>\
+         <ResourceRecords><ResourceRecord><Value>{}</Value></ResourceRecord></ResourceRecords>\
+         </ResourceRecordSet></ResourceRecordSets><IsTruncated>false</IsTruncated>\
+         <MaxItems>1</MaxItems></ListResourceRecordSetsResponse>",
+        name, value
+    )
+}
+
+fn change(status: &str) -> String {
+    format!(
+        "<?xml version=\"1.0\"?>\
+         <GetChangeResponse><ChangeInfo><Id>/change/C2682N5HXP0BZ4</Id><Status>{}</Status>\
+         <SubmittedAt>2017-03-10T01:36:41.958Z</SubmittedAt></ChangeInfo></GetChangeResponse>",
+        status
+    )
+}
+
+fn error(code: &str, message: &str) -> String {
+    format!(
+        "<?xml version=\"1.0\"?>\
+         <ErrorResponse><Error><Type>Sender</Type><Code>{}</Code><Message>{}</Message></Error>\
+         <RequestId>b25f48e8-84fd-11e6-80d9-574e0c4664cb</RequestId></ErrorResponse>",
+        code, message
+    )
+}
+
+async fn mount_zone(mock_server: &MockServer) {
+    Mock::given(method("GET"))
+        .and(path("/2013-04-01/hostedzonesbyname"))
+        .and(query_param("dnsname", ZONE))
+        .and(header_regex("authorization", AUTHORIZATION))
+        .and(header_regex("x-amz-date", "^[0-9]{8}T[0-9]{6}Z$"))
+        .respond_with(ResponseTemplate::new(200).set_body_string(format!(
+            "<?xml version=\"1.0\"?>\
+             <ListHostedZonesByNameResponse><HostedZones><HostedZone>\
+             <Id>/hostedzone/{}</Id><Name>example.com.</Name><CallerReference>dyndnsd</CallerReference>\
+             </HostedZone></HostedZones><IsTruncated>false</IsTruncated>\
+             </ListHostedZonesByNameResponse>",
+            ZONE_ID
+        )))
+        .expect(1)
+        .mount(mock_server)
+        .await;
+}
+
+async fn mount_record_set(mock_server: &MockServer) {
+    Mock::given(method("GET"))
+        .and(path(format!("/2013-04-01/hostedzone/{}/rrset", ZONE_ID)))
+        .and(query_param("name", "test.example.com."))
+        .and(query_param("type", "A"))
+        .and(header_regex("authorization", AUTHORIZATION))
+        .respond_with(
+            ResponseTemplate::new(200).set_body_string(record_sets("test.example.com.", OLD_IP)),
+        )
+        .mount(mock_server)
+        .await;
+}
+
+#[tokio::test]
+async fn test_resolve_ip() {
+    let mock_server = MockServer::start().await;
+    mount_zone(&mock_server).await;
+    mount_record_set(&mock_server).await;
+
+    let client = client(&mock_server.uri());
+    let ip = client
+        .resolve_ip(SUBDOMAIN, ZONE)
+        .await
+        .expect("resolve ip failed");
+    assert_eq!(ip, Some(OLD_IP.parse().unwrap()));
+
+    let ip = client
+        .resolve_ip(SUBDOMAIN, ZONE)
+        .await
+        .expect("resolve ip failed");
+    assert_eq!(ip, Some(OLD_IP.parse().unwrap()));
+}
+
+#[tokio::test]
+async fn test_resolve_ignores_following_record_sets() {
+    let mock_server = MockServer::start().await;
+    mount_zone(&mock_server).await;
+
+    Mock::given(method("GET"))
+        .and(path(format!("/2013-04-01/hostedzone/{}/rrset", ZONE_ID)))
+        .respond_with(
+            ResponseTemplate::new(200).set_body_string(record_sets("www.example.com.", OLD_IP)),
+        )
+        .mount(&mock_server)
+        .await;
+
+    let ip = client(&mock_server.uri())
+        .resolve_ip(SUBDOMAIN, ZONE)
+        .await
+        .expect("resolve ip failed");
+    assert_eq!(ip, None);
+}
+
+#[tokio::test]
+async fn test_update_upserts_and_waits_for_insync() {
+    let mock_server = MockServer::start().await;
+    mount_zone(&mock_server).await;
+    mount_record_set(&mock_server).await;
+
+    Mock::given(method("POST"))
+        .and(path(format!("/2013-04-01/hostedzone/{}/rrset/", ZONE_ID)))
+        .and(header_regex("authorization", AUTHORIZATION))
+        .and(body_string_contains("<Action>UPSERT</Action>"))
+        .and(body_string_contains("<Name>test.example.com.</Name>"))
+        .and(body_string_contains("<TTL>600</TTL>"))
+        .and(body_string_contains("<Value>93.184.216.35</Value>"))
+        .respond_with(ResponseTemplate::new(200).set_body_string(change("PENDING")))
+        .expect(1)
+        .mount(&mock_server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/2013-04-01/change/C2682N5HXP0BZ4"))
+        .respond_with(ResponseTemplate::new(200).set_body_string(change("PENDING")))
+        .up_to_n_times(1)
+        .expect(1)
+        .mount(&mock_server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/2013-04-01/change/C2682N5HXP0BZ4"))
+        .respond_with(ResponseTemplate::new(200).set_body_string(change("INSYNC")))
+        .expect(1)
+        .mount(&mock_server)
+        .await;
+
+    client(&mock_server.uri())
+        .update_dns(SUBDOMAIN, ZONE, NEW_IP)
+        .await
+        .expect("update failed");
+}
+
+#[tokio::test]
+async fn test_update_with_ttl() {
+    let mock_server = MockServer::start().await;
+    mount_zone(&mock_server).await;
+    mount_record_set(&mock_server).await;
+
+    Mock::given(method("POST"))
+        .and(path(format!("/2013-04-01/hostedzone/{}/rrset/", ZONE_ID)))
+        .and(body_string_contains("<TTL>60</TTL>"))
+        .respond_with(ResponseTemplate::new(200).set_body_string(change("INSYNC")))
+        .expect(1)
+        .mount(&mock_server)
+        .await;
+
+    client(&mock_server.uri())
+        .update_dns_with_ttl(SUBDOMAIN, ZONE, NEW_IP, Some(60))
+        .await
+        .expect("update failed");
+}
+
+#[tokio::test]
+async fn test_rejected_change_batch() {
+    let mock_server = MockServer::start().await;
+    mount_zone(&mock_server).await;
+    mount_record_set(&mock_server).await;
+
+    Mock::given(method("POST"))
+        .and(path(format!("/2013-04-01/hostedzone/{}/rrset/", ZONE_ID)))
+        .respond_with(
+            ResponseTemplate::new(400)
+                .set_body_string(error("InvalidChangeBatch", "Invalid value")),
+        )
+        .mount(&mock_server)
+        .await;
+
+    let result = client(&mock_server.uri())
+        .update_dns(SUBDOMAIN, ZONE, NEW_IP)
+        .await;
+    assert!(matches!(
+        result,
+        Err(DnsServiceError::UpdateRejected { .. })
+    ));
+}
+
+#[tokio::test]
+async fn test_invalid_signature() {
+    let mock_server = MockServer::start().await;
+
+    Mock::given(method("GET"))
+        .and(path("/2013-04-01/hostedzonesbyname"))
+        .respond_with(ResponseTemplate::new(403).set_body_string(error(
+            "SignatureDoesNotMatch",
+            "The request signature we calculated does not match the signature you provided.",
+        )))
+        .mount(&mock_server)
+        .await;
+
+    let result = client(&mock_server.uri()).find_hosted_zone(ZONE).await;
+    assert!(matches!(result, Err(Route53Error::InvalidCredentials)));
+}
+
+#[tokio::test]
+async fn test_list_zones_follows_marker() {
+    let mock_server = MockServer::start().await;
+
+    Mock::given(method("GET"))
+        .and(path("/2013-04-01/hostedzone"))
+        .and(query_param("marker", "Z2"))
+        .respond_with(ResponseTemplate::new(200).set_body_string(
+            "<ListHostedZonesResponse><HostedZones><HostedZone><Id>/hostedzone/Z2</Id>\
+             <Name>example.org.</Name></HostedZone></HostedZones>\
+             <IsTruncated>false</IsTruncated></ListHostedZonesResponse>",
+        ))
+        .expect(1)
+        .mount(&mock_server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/2013-04-01/hostedzone"))
+        .respond_with(ResponseTemplate::new(200).set_body_string(
+            "<ListHostedZonesResponse><HostedZones><HostedZone><Id>/hostedzone/Z1</Id>\
+             <Name>example.com.</Name></HostedZone></HostedZones>\
+             <IsTruncated>true</IsTruncated><NextMarker>Z2</NextMarker></ListHostedZonesResponse>",
+        ))
+        .expect(1)
+        .mount(&mock_server)
+        .await;
+
+    let zones = DnsService::list_zones(&client(&mock_server.uri()))
+        .await
+        .expect("list zones failed");
+    assert_eq!(zones, vec!["example.com", "example.org"]);
+}
+
+#[tokio::test]
+async fn test_update_record_keeps_other_txt_records() {
+    let mock_server = MockServer::start().await;
+    mount_zone(&mock_server).await;
+
+    Mock::given(method("GET"))
+        .and(path(format!("/2013-04-01/hostedzone/{}/rrset", ZONE_ID)))
+        .and(query_param("name", "test.example.com."))
+        .and(query_param("type", "TXT"))
+        .respond_with(ResponseTemplate::new(200).set_body_string(
+            "<?xml version=\"1.0\"?>\
+             <ListResourceRecordSetsResponse><ResourceRecordSets><ResourceRecordSet>\
+             <Name>test.example.com.</Name><Type>TXT</Type><TTL>600</TTL><ResourceRecords>\
+             <ResourceRecord><Value>&quot;google-site-verification=abc&quot;</Value></ResourceRecord>\
+             <ResourceRecord><Value>&quot;v=spf1 ip4:93.184.216.34 -all&quot;</Value></ResourceRecord>\
+             </ResourceRecords></ResourceRecordSet></ResourceRecordSets>\
+             <IsTruncated>false</IsTruncated><MaxItems>1</MaxItems></ListResourceRecordSetsResponse>",
+        ))
+        .mount(&mock_server)
+        .await;
+    Mock::given(method("POST"))
+        .and(path(format!("/2013-04-01/hostedzone/{}/rrset/", ZONE_ID)))
+        .and(body_string_contains(
+            "<ResourceRecord><Value>&quot;google-site-verification=abc&quot;</Value></ResourceRecord>\
+             <ResourceRecord><Value>&quot;v=spf1 ip4:93.184.216.35 -all&quot;</Value></ResourceRecord>",
+        ))
+        .respond_with(ResponseTemplate::new(200).set_body_string(change("INSYNC")))
+        .expect(1)
+        .mount(&mock_server)
+        .await;
+
+    let client = client(&mock_server.uri());
+    let value = client
+        .resolve_record(SUBDOMAIN, ZONE, "TXT", "v=spf1 ip4:")
+        .await
+        .expect("resolve record failed");
+    assert_eq!(value, Some(String::from("v=spf1 ip4:93.184.216.34 -all")));
+    client
+        .update_record(
+            SUBDOMAIN,
+            ZONE,
+            "TXT",
+            "v=spf1 ip4:",
+            "v=spf1 ip4:93.184.216.35 -all",
+        )
+        .await
+        .expect("update failed");
+}
@@ -0,0 +1,37 @@
+use dyndnsd::public_ip_service::PublicIpService;
+use dyndnsd::stun_public_ip_service::StunPublicIpService;
+use std::net::Ipv4Addr;
+use tokio::net::UdpSocket;
+
+const MAGIC_COOKIE: [u8; 4] = [0x21, 0x12, 0xA4, 0x42];
+
+#[tokio::test]
+async fn test_get_ip() {
+    let server = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    let server_addr = server.local_addr().unwrap();
+    let mapped: Ipv4Addr = "93.184.216.34".parse().unwrap();
+
+    tokio::spawn(async move {
+        let mut buf = [0u8; 512];
+        let (len, peer) = server.recv_from(&mut buf).await.unwrap();
+        assert_eq!(len, 20);
+        assert_eq!(&buf[0..2], &[0x00, 0x01]);
+
+        let xored: Vec<u8> = mapped
+            .octets()
+            .iter()
+            .zip(MAGIC_COOKIE)
+            .map(|(a, b)| a ^ b)
+            .collect();
+
+        let mut response = vec![0x01, 0x01, 0x00, 0x0c];
+        response.extend_from_slice(&MAGIC_COOKIE);
+        response.extend_from_slice(&buf[8..20]);
+        response.extend_from_slice(&[0x00, 0x20, 0x00, 0x08, 0x00, 0x01, 0x12, 0x34]);
+        response.extend_from_slice(&xored);
+        server.send_to(&response, peer).await.unwrap();
+    });
+
+    let service = StunPublicIpService::new(&server_addr.to_string());
+    assert_eq!(service.get_ip().await.expect("get ip failed"), mapped);
+}
@@ -1,9 +1,12 @@
+use dyndnsd::public_ip_service::{PublicIpService, PublicIpServiceError};
 use dyndnsd::ubus_jsonrpc_public_ip_service::UbusJsonRpcClient;
 use serde_json::json;
 use std::net::Ipv4Addr;
 use wiremock::matchers::{body_partial_json, method};
 use wiremock::{Mock, MockServer, ResponseTemplate};
 
+const NULL_SESSION: &str = "00000000000000000000000000000000";
+
 #[tokio::test]
 async fn test_get_token() {
     let mock_server = MockServer::start().await;
@@ -165,3 +168,55 @@ async fn test_get_ip() {
         "192.168.1.100".parse::<Ipv4Addr>().unwrap(),
     );
 }
+
+#[tokio::test]
+async fn test_get_ip_without_address() {
+    let mock_server = MockServer::start().await;
+
+    let uri = &mock_server.uri();
+
+    Mock::given(method("POST"))
+        .and(body_partial_json(
+            json!({ "params": [NULL_SESSION, "session", "login"] }),
+        ))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!(
+        {
+            "jsonrpc": "2.0",
+            "id": 1,
+            "result": [
+                0,
+                {
+                    "ubus_rpc_session": "session"
+                }
+            ]
+        })))
+        .mount(&mock_server)
+        .await;
+
+    Mock::given(method("POST"))
+        .and(body_partial_json(
+            json!({ "params": ["session", "network.interface.wan", "status"] }),
+        ))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!(
+            {
+                "id": null,
+                "jsonrpc": "2.0",
+                "result": [
+                    0,
+                    {
+                        "ipv4-address": [],
+                        "ipv6-address": [],
+                        "up": false
+                    }
+                ]
+            }
+        )))
+        .mount(&mock_server)
+        .await;
+
+    let client = UbusJsonRpcClient::new(uri, "user", "pass");
+    assert!(matches!(
+        PublicIpService::get_ip(&client).await,
+        Err(PublicIpServiceError::NoAddress)
+    ));
+}
@@ -0,0 +1,106 @@
+use dyndnsd::dns_service::{DnsService, HetznerDnsService};
+use dyndnsd::hetzner_dns_client::HetznerDnsClient;
+use dyndnsd::zone_detection::{detect_zone, ZoneDetectionError};
+use serde_json::json;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+async fn mount_zones(mock_server: &MockServer) {
+    Mock::given(method("GET"))
+        .and(path("/zones"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "zones": [
+                { "id": "zid1", "name": "example.co.uk" },
+                { "id": "zid2", "name": "home.example.co.uk" },
+                { "id": "zid3", "name": "xn--bcher-kva.example" }
+            ]
+        })))
+        .mount(mock_server)
+        .await;
+}
+
+fn service(mock_server: &MockServer) -> HetznerDnsService {
+    HetznerDnsService::from_client(HetznerDnsClient::new_with_url("XXX", &mock_server.uri()))
+}
+
+#[tokio::test]
+async fn test_detect_zone_for_names() {
+    let mock_server = MockServer::start().await;
+    mount_zones(&mock_server).await;
+
+    let (zone, subdomains) = detect_zone(
+        &service(&mock_server),
+        &["nas.home.example.co.uk", "Home.Example.co.uk."],
+    )
+    .await
+    .expect("detect zone failed");
+
+    assert_eq!(zone, "home.example.co.uk");
+    assert_eq!(subdomains, vec!["nas", "@"]);
+}
+
+#[tokio::test]
+async fn test_detect_zone_for_idn() {
+    let mock_server = MockServer::start().await;
+    mount_zones(&mock_server).await;
+
+    let (zone, subdomains) = detect_zone(&service(&mock_server), &["nas.bücher.example"])
+        .await
+        .expect("detect zone failed");
+
+    assert_eq!(zone, "xn--bcher-kva.example");
+    assert_eq!(subdomains, vec!["nas"]);
+}
+
+#[tokio::test]
+async fn test_reject_names_in_several_zones() {
+    let mock_server = MockServer::start().await;
+    mount_zones(&mock_server).await;
+
+    let result = detect_zone(
+        &service(&mock_server),
+        &["nas.home.example.co.uk", "www.example.co.uk"],
+    )
+    .await;
+
+    assert!(matches!(
+        result,
+        Err(ZoneDetectionError::MultipleZones { .. })
+    ));
+}
+
+#[tokio::test]
+async fn test_list_zones_fills_zone_id_cache() {
+    let mock_server = MockServer::start().await;
+    mount_zones(&mock_server).await;
+    Mock::given(method("GET"))
+        .and(path("/records"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "records": [{
+                "id": "rid1",
+                "zone_id": "zid2",
+                "name": "nas",
+                "type": "A",
+                "value": "93.184.216.34"
+            }]
+        })))
+        .mount(&mock_server)
+        .await;
+
+    let service = service(&mock_server);
+    service.list_zones().await.expect("list zones failed");
+    let ip = service
+        .resolve_ip("nas", "home.example.co.uk")
+        .await
+        .expect("resolve failed");
+
+    assert_eq!(ip, Some("93.184.216.34".parse().unwrap()));
+    let zone_requests = mock_server
+        .received_requests()
+        .await
+        .unwrap()
+        .iter()
+        .filter(|r| r.url.path() == "/zones")
+        .count();
+    assert_eq!(zone_requests, 1);
+}
@@ -0,0 +1,165 @@
+use dyndnsd::dns_service::{DnsService, DnsServiceError};
+use dyndnsd::zone_file_dns_service::ZoneFileDnsService;
+use std::fs;
+use std::net::Ipv4Addr;
+use std::path::PathBuf;
+
+const ZONE: &str = "example.com";
+const SUBDOMAIN: &str = "test";
+const OLD_IP: &str = "93.184.216.34";
+const NEW_IP: Ipv4Addr = Ipv4Addr::new(93, 184, 216, 35);
+
+const ZONE_FILE: &str = "\
+$ORIGIN example.com.
+$TTL 3600
+; managed by hand and by dyndnsd
+@\tIN\tSOA\tns1.example.com. hostmaster.example.com. (
+\t\t42\t; serial
+\t\t7200 900 1209600 300 )
+@\tIN\tNS\tns1.example.com.
+www\t300\tIN\tA\t93.184.216.10
+test\t300\tIN\tA\t93.184.216.34 ; home router
+\tIN\tAAAA\t2001:db8::1
+";
+
+fn zone_file(name: &str, contents: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("dyndnsd-{}-{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).expect("create temp dir failed");
+    let path = dir.join("db.example.com");
+    fs::write(&path, contents).expect("write zone file failed");
+    path
+}
+
+#[tokio::test]
+async fn test_resolve_ip() {
+    let path = zone_file("zone-resolve", ZONE_FILE);
+
+    let ip = ZoneFileDnsService::new(&path)
+        .resolve_ip(SUBDOMAIN, ZONE)
+        .await
+        .expect("resolve ip failed");
+    assert_eq!(ip, Some(OLD_IP.parse().unwrap()));
+}
+
+#[tokio::test]
+async fn test_resolve_ttl() {
+    let path = zone_file("zone-resolve-ttl", ZONE_FILE);
+
+    let svc = ZoneFileDnsService::new(&path);
+    assert_eq!(svc.resolve_ttl(SUBDOMAIN, ZONE).await.unwrap(), Some(300));
+    assert_eq!(svc.resolve_ttl("missing", ZONE).await.unwrap(), None);
+}
+
+#[tokio::test]
+async fn test_update_record_in_place_and_bump_serial() {
+    let path = zone_file("zone-update", ZONE_FILE);
+
+    ZoneFileDnsService::new(&path)
+        .update_dns(SUBDOMAIN, ZONE, NEW_IP)
+        .await
+        .expect("update failed");
+
+    let contents = fs::read_to_string(&path).unwrap();
+    assert_eq!(
+        contents,
+        ZONE_FILE
+            .replace("\t\t42\t; serial", "\t\t43\t; serial")
+            .replace(
+                "test\t300\tIN\tA\t93.184.216.34 ; home router",
+                "test\t300\tIN\tA\t93.184.216.35 ; home router"
+            )
+    );
+    assert_eq!(fs::read_dir(path.parent().unwrap()).unwrap().count(), 1);
+}
+
+#[tokio::test]
+async fn test_create_missing_record_with_ttl() {
+    let path = zone_file("zone-create", ZONE_FILE);
+
+    ZoneFileDnsService::new(&path)
+        .update_dns_with_ttl("new", ZONE, NEW_IP, Some(60))
+        .await
+        .expect("update failed");
+
+    let contents = fs::read_to_string(&path).unwrap();
+    assert!(contents.ends_with("\tIN\tAAAA\t2001:db8::1\nnew\t60\tIN\tA\t93.184.216.35\n"));
+    assert!(contents.contains("\t\t43\t; serial"));
+}
+
+#[tokio::test]
+async fn test_unchanged_record_keeps_serial_and_skips_reload() {
+    let path = zone_file("zone-unchanged", ZONE_FILE);
+    let marker = path.with_file_name("reloaded");
+
+    ZoneFileDnsService::new(&path)
+        .with_reload_command(&format!("touch {}", marker.display()))
+        .update_dns(SUBDOMAIN, ZONE, OLD_IP.parse().unwrap())
+        .await
+        .expect("update failed");
+
+    assert_eq!(fs::read_to_string(&path).unwrap(), ZONE_FILE);
+    assert!(!marker.exists());
+}
+
+#[tokio::test]
+async fn test_reload_command_runs_after_update() {
+    let path = zone_file("zone-reload", ZONE_FILE);
+    let marker = path.with_file_name("reloaded");
+
+    ZoneFileDnsService::new(&path)
+        .with_reload_command(&format!("touch {}", marker.display()))
+        .update_dns(SUBDOMAIN, ZONE, NEW_IP)
+        .await
+        .expect("update failed");
+
+    assert!(marker.exists());
+}
+
+#[tokio::test]
+async fn test_failing_reload_command() {
+    let path = zone_file("zone-reload-failed", ZONE_FILE);
+
+    let result = ZoneFileDnsService::new(&path)
+        .with_reload_command("echo 'rndc: connect failed' >&2; exit 1")
+        .update_dns(SUBDOMAIN, ZONE, NEW_IP)
+        .await;
+    assert!(matches!(result, Err(DnsServiceError::ClientError)));
+    assert!(fs::read_to_string(&path).unwrap().contains("93.184.216.35"));
+}
+
+#[tokio::test]
+async fn test_zone_mismatch() {
+    let path = zone_file("zone-mismatch", ZONE_FILE);
+
+    let result = ZoneFileDnsService::new(&path)
+        .update_dns(SUBDOMAIN, "example.org", NEW_IP)
+        .await;
+    assert!(matches!(result, Err(DnsServiceError::UnknownZone)));
+}
+
+#[tokio::test]
+async fn test_replace_rrset_moves_owner_to_next_record() {
+    let path = zone_file(
+        "zone-rrset",
+        "@ 3600 IN SOA ns1 hostmaster 2023010101 7200 900 1209600 300\n\
+         test IN A 93.184.216.34\n\
+         test IN A 93.184.216.36\n\
+         \x20    IN TXT \"v=spf1 -all\"\n",
+    );
+
+    let dns_service = ZoneFileDnsService::new(&path);
+    dns_service
+        .update_dns(SUBDOMAIN, ZONE, NEW_IP)
+        .await
+        .expect("update failed");
+
+    let contents = fs::read_to_string(&path).unwrap();
+    assert!(!contents.contains("2023010101"));
+    assert!(contents.contains("test\tIN\tA\t93.184.216.35\ntest     IN TXT \"v=spf1 -all\"\n"));
+    let txt = dns_service
+        .resolve_record(SUBDOMAIN, ZONE, "TXT", "v=spf1 ")
+        .await
+        .expect("resolve record failed");
+    assert_eq!(txt, Some(String::from("v=spf1 -all")));
+}